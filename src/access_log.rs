@@ -0,0 +1,152 @@
+use crate::config::AccessLogFormat;
+use crate::request_context::{REQUEST_ID_HEADER, request_id};
+use log::info;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use serde_json::json;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Log target, so the access log can be routed/filtered separately (e.g. `RUST_LOG=access_log=off`)
+const LOG_TARGET: &str = "access_log";
+
+/// Per-request state, lives in Rocket's request-local cache
+#[derive(Debug)]
+struct AccessLogEntry {
+    received_at: Instant,
+    /// Only set by routes taking inputs, see `InputCount`
+    input_count: OnceLock<usize>,
+}
+
+impl AccessLogEntry {
+    fn new() -> Self {
+        Self {
+            received_at: Instant::now(),
+            input_count: OnceLock::new(),
+        }
+    }
+}
+
+/// Logs one line per request (`config.access_log`), also echoes the request id as `X-Request-Id`
+pub struct AccessLog {
+    format: AccessLogFormat,
+}
+
+impl AccessLog {
+    pub fn new(format: AccessLogFormat) -> Self {
+        Self { format }
+    }
+
+    fn format_line(
+        &self,
+        request: &Request<'_>,
+        status: u16,
+        entry: &AccessLogEntry,
+        duration_ms: f64,
+    ) -> String {
+        let client_ip = request.client_ip().map(|ip| ip.to_string());
+        let input_count = entry.input_count.get().copied();
+        match self.format {
+            AccessLogFormat::Common => format!(
+                "{} \"{} {}\" {status} {duration_ms:.3}ms request_id={} inputs={}",
+                client_ip.as_deref().unwrap_or("-"),
+                request.method(),
+                request.uri(),
+                request_id(request),
+                input_count.map_or_else(|| "-".to_string(), |count| count.to_string())
+            ),
+            AccessLogFormat::Json => json!({
+                "method": request.method().as_str(),
+                "path": request.uri().to_string(),
+                "status": status,
+                "duration_ms": duration_ms,
+                "client_ip": client_ip,
+                "request_id": request_id(request),
+                "inputs": input_count,
+            })
+            .to_string(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(AccessLogEntry::new);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let entry = request.local_cache(AccessLogEntry::new);
+        let duration_ms = entry.received_at.elapsed().as_secs_f64() * 1000.0;
+        info!(
+            target: LOG_TARGET,
+            "{}",
+            self.format_line(request, response.status().code, entry, duration_ms)
+        );
+        response.set_header(Header::new(
+            REQUEST_ID_HEADER,
+            request_id(request).to_string(),
+        ));
+    }
+}
+
+/// Lets routes report how many inputs a request carried, for the access log
+pub struct InputCount<'r>(&'r OnceLock<usize>);
+
+impl InputCount<'_> {
+    pub fn record(&self, count: usize) {
+        let _ = self.0.set(count);
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for InputCount<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let entry = request.local_cache(AccessLogEntry::new);
+        Outcome::Success(InputCount(&entry.input_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn test_format_line() {
+        let client = Client::debug_with(vec![]).unwrap();
+        let request = client
+            .post("/embed")
+            .header(Header::new(REQUEST_ID_HEADER, "abc"))
+            .remote("127.0.0.1:5000".parse().unwrap());
+        let entry = AccessLogEntry::new();
+        let _ = entry.input_count.set(3);
+
+        let line =
+            AccessLog::new(AccessLogFormat::Common).format_line(request.inner(), 200, &entry, 1.5);
+        assert_eq!(
+            line,
+            "127.0.0.1 \"POST /embed\" 200 1.500ms request_id=abc inputs=3"
+        );
+
+        let line =
+            AccessLog::new(AccessLogFormat::Json).format_line(request.inner(), 200, &entry, 1.5);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["method"], "POST");
+        assert_eq!(json["path"], "/embed");
+        assert_eq!(json["client_ip"], "127.0.0.1");
+        assert_eq!(json["request_id"], "abc");
+        assert_eq!(json["inputs"], 3);
+    }
+}
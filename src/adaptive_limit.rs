@@ -0,0 +1,172 @@
+use crate::config::AppConfig;
+use log::info;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Number of most recent batch latencies the p95 is computed over
+const LATENCY_WINDOW: usize = 50;
+/// Batches to observe between two adjustments, so each change has time to show its effect
+const ADJUST_EVERY: usize = 10;
+const DECREASE_FACTOR: f64 = 0.75;
+const INCREASE_STEP: f64 = 0.05;
+/// Grow again only with clear headroom, to avoid oscillating around the SLO
+const HEADROOM_RATIO: f64 = 0.8;
+
+/// Effective per-batch limits, at most the configured ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchLimits {
+    pub max_batch_size: usize,
+    pub max_inference_inputs: usize,
+}
+
+impl BatchLimits {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            max_batch_size: config.max_batch_size,
+            max_inference_inputs: config.max_inference_inputs,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    latencies: VecDeque<Duration>,
+    since_last_adjustment: usize,
+    /// (0, 1], applied to configured limits
+    scale: f64,
+}
+
+/// Feedback controller (AIMD) keeping p95 upstream batch latency under `config.latency_slo_ms`:
+/// limits shrink multiplicatively when the SLO is violated & grow back additively once there is headroom
+#[derive(Debug)]
+pub struct AdaptiveBatchLimit {
+    slo: Duration,
+    configured: BatchLimits,
+    state: Mutex<State>,
+}
+
+impl AdaptiveBatchLimit {
+    /// `None` when `config.latency_slo_ms` isn't set, i.e., static limits are used
+    pub fn new(config: &AppConfig) -> Option<Self> {
+        config.latency_slo_ms.map(|latency_slo_ms| Self {
+            slo: Duration::from_millis(latency_slo_ms),
+            configured: BatchLimits::from_config(config),
+            state: Mutex::new(State {
+                latencies: VecDeque::with_capacity(LATENCY_WINDOW),
+                since_last_adjustment: 0,
+                scale: 1.0,
+            }),
+        })
+    }
+
+    pub fn limits(&self) -> BatchLimits {
+        let scale = self.state.lock().unwrap().scale;
+        let scaled = |limit: usize| ((limit as f64 * scale).round() as usize).clamp(1, limit);
+        BatchLimits {
+            max_batch_size: scaled(self.configured.max_batch_size),
+            max_inference_inputs: scaled(self.configured.max_inference_inputs),
+        }
+    }
+
+    /// Called once per completed upstream call
+    pub fn record(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        if state.latencies.len() == LATENCY_WINDOW {
+            state.latencies.pop_front();
+        }
+        state.latencies.push_back(latency);
+        state.since_last_adjustment += 1;
+
+        if state.since_last_adjustment < ADJUST_EVERY {
+            return;
+        }
+        state.since_last_adjustment = 0;
+
+        let p95 = Self::p95(&state.latencies);
+        let previous_scale = state.scale;
+        if p95 > self.slo {
+            state.scale *= DECREASE_FACTOR;
+            // old (slow) samples shouldn't cause another decrease right away
+            state.latencies.clear();
+        } else if p95.as_secs_f64() < self.slo.as_secs_f64() * HEADROOM_RATIO {
+            state.scale = (state.scale + INCREASE_STEP).min(1.0);
+        }
+        // keep at least a batch of 1
+        state.scale = state.scale.max(1.0 / self.configured.max_batch_size as f64);
+
+        if state.scale != previous_scale {
+            info!(
+                "Adaptive batch limit: p95 {p95:?} (SLO {:?}), scale {previous_scale:.2} -> {:.2}",
+                self.slo, state.scale
+            );
+        }
+    }
+
+    pub(crate) fn p95(latencies: &VecDeque<Duration>) -> Duration {
+        let mut sorted: Vec<Duration> = latencies.iter().copied().collect();
+        sorted.sort();
+        let index = ((sorted.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
+        sorted.get(index).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_adaptive_limit() -> AdaptiveBatchLimit {
+        let config = AppConfig {
+            max_batch_size: 20,
+            max_inference_inputs: 32,
+            latency_slo_ms: Some(100),
+            ..AppConfig::default()
+        };
+        AdaptiveBatchLimit::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_disabled_without_slo() {
+        assert!(AdaptiveBatchLimit::new(&AppConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_limits_decrease_when_slo_violated_and_recover() {
+        let adaptive_limit = build_adaptive_limit();
+        let configured = adaptive_limit.limits();
+        assert_eq!(configured.max_batch_size, 20);
+        assert_eq!(configured.max_inference_inputs, 32);
+
+        for _ in 0..ADJUST_EVERY {
+            adaptive_limit.record(Duration::from_millis(300));
+        }
+        let decreased = adaptive_limit.limits();
+        assert_eq!(decreased.max_batch_size, 15);
+        assert_eq!(decreased.max_inference_inputs, 24);
+
+        for _ in 0..ADJUST_EVERY * 10 {
+            adaptive_limit.record(Duration::from_millis(10));
+        }
+        assert_eq!(adaptive_limit.limits(), configured);
+    }
+
+    #[test]
+    fn test_limits_never_drop_below_one() {
+        let adaptive_limit = build_adaptive_limit();
+        for _ in 0..ADJUST_EVERY * 50 {
+            adaptive_limit.record(Duration::from_secs(1));
+        }
+        let limits = adaptive_limit.limits();
+        assert_eq!(limits.max_batch_size, 1);
+        assert!(limits.max_inference_inputs >= 1);
+    }
+
+    #[test]
+    fn test_p95() {
+        let latencies: VecDeque<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(
+            AdaptiveBatchLimit::p95(&latencies),
+            Duration::from_millis(95)
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::config::AppConfig;
+use log::error;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One line (JSON) per `/embed` request that reached the queue, never contains input text
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    /// Unix epoch milliseconds
+    pub timestamp_ms: u64,
+    pub request_id: String,
+    /// Client IP or masked API key, same as in `GET /admin/usage`
+    pub caller: String,
+    pub input_count: usize,
+    /// `None` when the request failed before its batch was dispatched (e.g., timed out)
+    pub batch_id: Option<u64>,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn now(
+        request_id: &str,
+        caller: String,
+        input_count: usize,
+        batch_id: Option<u64>,
+        status: u16,
+        client_tag: Option<String>,
+    ) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis() as u64)
+                .unwrap_or_default(),
+            request_id: request_id.to_string(),
+            caller,
+            input_count,
+            batch_id,
+            status,
+            client_tag,
+        }
+    }
+}
+
+/// Append-only audit trail (`config.audit_log_path`), rotated by size
+///
+/// Records are written from a dedicated thread, so request handling never waits on disk I/O
+pub struct AuditLog {
+    sender: mpsc::Sender<AuditRecord>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AppConfig) -> Result<Option<Self>, String> {
+        let Some(path) = &config.audit_log_path else {
+            return Ok(None);
+        };
+        let writer = RotatingWriter::open(
+            PathBuf::from(path),
+            config.audit_log_max_size_mb * 1024 * 1024,
+            config.audit_log_max_files,
+        )
+        .map_err(|e| format!("Failed to open audit log `{path}`: {e}"))?;
+
+        Ok(Some(Self {
+            sender: writer.spawn("audit-log")?,
+        }))
+    }
+
+    pub fn record(&self, record: AuditRecord) {
+        if let Err(e) = self.sender.send(record) {
+            error!("Audit log writer is gone, lost record {:?}", e.0);
+        }
+    }
+}
+
+/// JSON lines file, `path` is the active file,
+/// older ones are shifted to `path.1` (newest) ... `path.{max_files}`
+pub(crate) struct RotatingWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    written_bytes: u64,
+}
+
+impl RotatingWriter {
+    pub(crate) fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> std::io::Result<Self> {
+        let file = Self::open_append(&path)?;
+        let written_bytes = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file: BufWriter::new(file),
+            written_bytes,
+        })
+    }
+
+    fn open_append(path: &Path) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// Records sent to the returned channel are written from a dedicated thread,
+    /// which ends once all senders are dropped
+    pub(crate) fn spawn<T: Serialize + Debug + Send + 'static>(
+        mut self,
+        name: &str,
+    ) -> Result<mpsc::Sender<T>, String> {
+        let (sender, receiver) = mpsc::channel::<T>();
+        std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                for record in receiver {
+                    if let Err(e) = self.write_record(&record) {
+                        error!("Failed to write {record:?} to {:?}: {e}", self.path);
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start {name} writer: {e}"))?;
+        Ok(sender)
+    }
+
+    fn write_record<T: Serialize>(&mut self, record: &T) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.written_bytes > 0 && self.written_bytes + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        // flushed per record, nothing is lost on a crash
+        self.file.flush()?;
+        self.written_bytes += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                std::fs::rename(from, self.rotated_path(index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = BufWriter::new(Self::open_append(&self.path)?);
+        self.written_bytes = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        PathBuf::from(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("auto-batching-proxy-audit-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("audit.log")
+    }
+
+    fn record(request_id: &str) -> AuditRecord {
+        AuditRecord::now(request_id, "key:…1234".to_string(), 3, Some(7), 200, None)
+    }
+
+    #[test]
+    fn test_write_record_appends_json_lines() {
+        let path = temp_path("append");
+        let mut writer = RotatingWriter::open(path.clone(), 1024 * 1024, 2).unwrap();
+        writer.write_record(&record("a")).unwrap();
+        writer.write_record(&record("b")).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["request_id"], "a");
+        assert_eq!(lines[1]["batch_id"], 7);
+        assert_eq!(lines[1]["caller"], "key:…1234");
+    }
+
+    #[test]
+    fn test_rotation_keeps_max_files() {
+        let path = temp_path("rotate");
+        // every record exceeds the limit, so each one ends up in its own file
+        let mut writer = RotatingWriter::open(path.clone(), 10, 2).unwrap();
+        for request_id in ["a", "b", "c", "d"] {
+            writer.write_record(&record(request_id)).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert!(read(&path).contains("\"d\""));
+        assert!(read(&writer.rotated_path(1)).contains("\"c\""));
+        assert!(read(&writer.rotated_path(2)).contains("\"b\""));
+        assert!(!writer.rotated_path(3).exists());
+    }
+}
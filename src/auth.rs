@@ -0,0 +1,72 @@
+use crate::request_context::presented_api_key;
+use crate::request_handler::RequestHandler;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+/// Compared in constant time, so response timing doesn't reveal how much of a key was guessed right
+pub fn key_matches(presented: &str, key: &str) -> bool {
+    presented.as_bytes().ct_eq(key.as_bytes()).into()
+}
+
+/// Whether `presented` is one of `api_keys`, all of them are compared (no early return)
+pub fn is_configured_api_key(api_keys: &[String], presented: &str) -> bool {
+    api_keys
+        .iter()
+        .fold(false, |found, key| key_matches(presented, key) | found)
+}
+
+/// Request guard, succeeds when no `config.api_keys` are configured,
+/// otherwise one of them must be presented (`X-Api-Key` or `Authorization: Bearer ...`).
+/// Failure is rendered as 401 by the global JSON catcher
+pub struct ApiKeyAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKeyAuth {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(handler) = request.rocket().state::<Arc<RequestHandler>>() else {
+            return Outcome::Error((Status::InternalServerError, "RequestHandler isn't managed"));
+        };
+
+        let api_keys = &handler.config.api_keys;
+        if api_keys.is_empty() {
+            return Outcome::Success(ApiKeyAuth);
+        }
+
+        match presented_api_key(request) {
+            Some(api_key) if is_configured_api_key(api_keys, api_key) => {
+                Outcome::Success(ApiKeyAuth)
+            }
+            Some(_) => Outcome::Error((Status::Unauthorized, "Invalid API key")),
+            None => Outcome::Error((Status::Unauthorized, "Missing API key")),
+        }
+    }
+}
+
+/// Request guard of the `/admin/*` routes, `config.admin_api_key` must be presented
+/// (same headers as `ApiKeyAuth`), tenants' `config.api_keys` aren't accepted.
+/// Fails for everyone without an admin key, since those routes act on all tenants' traffic
+pub struct AdminAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuth {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(handler) = request.rocket().state::<Arc<RequestHandler>>() else {
+            return Outcome::Error((Status::InternalServerError, "RequestHandler isn't managed"));
+        };
+
+        let Some(admin_api_key) = &handler.config.admin_api_key else {
+            return Outcome::Error((Status::Unauthorized, "Admin routes are disabled"));
+        };
+        match presented_api_key(request) {
+            Some(api_key) if key_matches(api_key, admin_api_key) => Outcome::Success(AdminAuth),
+            Some(_) => Outcome::Error((Status::Unauthorized, "Invalid admin API key")),
+            None => Outcome::Error((Status::Unauthorized, "Missing admin API key")),
+        }
+    }
+}
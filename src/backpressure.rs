@@ -0,0 +1,104 @@
+use crate::types::ErrorResponse;
+use rocket::Request;
+use rocket::response::status::Custom;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use std::time::Duration;
+
+/// Requests waiting in the queue the rejected request was meant for
+pub const QUEUE_DEPTH_HEADER: &str = "X-Queue-Depth";
+/// Rough time until a request queued now would be dispatched, see `QueuePressure`
+pub const ESTIMATED_WAIT_HEADER: &str = "X-Estimated-Wait-Ms";
+
+/// Queue state sent along with backpressure rejections (load shedding & rate limiting),
+/// so well-behaved clients can back off instead of hammering
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueuePressure {
+    pub depth: usize,
+    /// Queue ahead at the recent dispatch rate, at least `max_wait_time_ms`
+    pub estimated_wait: Duration,
+}
+
+impl QueuePressure {
+    /// `depth` requests at `requests_per_sec`, without recent dispatches the oldest
+    /// request's age is the best guess
+    pub fn estimate(
+        depth: usize,
+        requests_per_sec: f64,
+        oldest_age: Option<Duration>,
+        max_wait_time: Duration,
+    ) -> Self {
+        let drain_time = if requests_per_sec > 0.0 {
+            Duration::from_secs_f64(depth as f64 / requests_per_sec)
+        } else {
+            oldest_age.unwrap_or_default()
+        };
+        Self {
+            depth,
+            estimated_wait: drain_time.max(max_wait_time),
+        }
+    }
+
+    /// Rounded up, at least a second
+    pub fn retry_after_secs(&self) -> u64 {
+        self.estimated_wait.as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    pub(crate) fn add_headers(&self, response: &mut Response<'_>) {
+        response.set_raw_header(QUEUE_DEPTH_HEADER, self.depth.to_string());
+        response.set_raw_header(
+            ESTIMATED_WAIT_HEADER,
+            self.estimated_wait.as_millis().to_string(),
+        );
+    }
+}
+
+/// Load shedding rejection (503), rendered with `Retry-After` & queue state headers
+#[derive(Debug)]
+pub struct Overloaded {
+    pub error: Custom<Json<ErrorResponse>>,
+    pub pressure: QueuePressure,
+}
+
+impl Overloaded {
+    /// Error codes of `RequestHandler::check_load_shedding`
+    pub fn is_load_shedding(error: &Custom<Json<ErrorResponse>>) -> bool {
+        matches!(
+            error.1.code,
+            Some("queue_depth_exceeded" | "queue_age_exceeded")
+        )
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Overloaded {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.error.respond_to(request)?;
+        response.set_raw_header("Retry-After", self.pressure.retry_after_secs().to_string());
+        self.pressure.add_headers(&mut response);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_from_dispatch_rate() {
+        let max_wait_time = Duration::from_millis(50);
+        // 200 requests ahead at 100/s
+        let pressure = QueuePressure::estimate(200, 100.0, None, max_wait_time);
+        assert_eq!(pressure.estimated_wait, Duration::from_secs(2));
+        assert_eq!(pressure.retry_after_secs(), 2);
+
+        // nothing dispatched lately, e.g., a stalled upstream
+        let oldest_age = Some(Duration::from_millis(1500));
+        let pressure = QueuePressure::estimate(10, 0.0, oldest_age, max_wait_time);
+        assert_eq!(pressure.estimated_wait, Duration::from_millis(1500));
+        assert_eq!(pressure.retry_after_secs(), 2);
+
+        let pressure = QueuePressure::estimate(1, 1000.0, None, max_wait_time);
+        assert_eq!(pressure.estimated_wait, max_wait_time);
+        assert_eq!(pressure.retry_after_secs(), 1);
+    }
+}
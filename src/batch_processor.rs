@@ -1,34 +1,117 @@
+use crate::adaptive_limit::{AdaptiveBatchLimit, BatchLimits};
+use crate::batch_stats::BatchStats;
 use crate::config::AppConfig;
-use crate::inference_client::{InferenceError, InferenceServiceClient};
+use crate::inference_client::{InferenceBackend, InferenceError};
+use crate::latency_stats::LATENCY;
+use crate::metrics::METRICS;
+use crate::pending_queue::PendingQueue;
+use crate::postprocessing::PostprocessorChain;
+use crate::queue_state::QueueState;
+use crate::scheduler::{BatchBudget, BatchScheduler, build_scheduler};
 use crate::types::{
-    BatchInfo, BatchRequest, BatchResponse, BatchType, EmbedResponse, ErrorResponse, PendingRequest,
+    BatchInfo, BatchOutput, BatchRequest, BatchType, ControlMessage, Endpoint, ErrorResponse,
+    PendingRequest,
 };
+use crate::usage::UsageTracker;
 use log::{debug, error, info, warn};
+use rocket::http::Status;
 use rocket::response::status::Custom;
 use rocket::serde::json::Json;
-use std::collections::VecDeque;
 use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 
 pub struct BatchProcessor {
     config: AppConfig,
-    inference_client: Arc<InferenceServiceClient>,
+    /// `InferenceServiceClient`, unless a custom backend was passed in
+    inference_backend: Arc<dyn InferenceBackend>,
     /// Owned (not shared), should have no concurrent race issues
-    pending_requests: VecDeque<PendingRequest>,
+    pending_requests: PendingQueue,
+    /// Read by `RequestHandler` for load shedding decisions
+    queue_state: Arc<QueueState>,
+    /// Read by `RequestHandler` for `GET /stats`
+    batch_stats: Arc<BatchStats>,
+    /// Spawned `process_batch` tasks, tracked so a drain can wait for them to complete
+    in_flight_batches: JoinSet<()>,
+    /// Only set with `config.latency_slo_ms`, fed with upstream latency of every batch
+    adaptive_limit: Option<Arc<AdaptiveBatchLimit>>,
+    /// Max inputs per call the inference service actually accepts, learned from 413 responses,
+    /// starts as `config.max_inference_inputs`
+    upstream_max_inputs: Arc<AtomicUsize>,
+    /// Shared with `RequestHandler`, which reports it
+    usage: Arc<UsageTracker>,
+    /// Built from `config.scheduling_mode`, unless replaced via `with_scheduler`
+    scheduler: Box<dyn BatchScheduler>,
+    /// Set by `ControlMessage::Pause`, nothing is dispatched (except on drain) until resumed
+    paused: bool,
+    /// Inference service endpoint batches are sent to, `/embed` unless set via `with_endpoint`
+    endpoint: Endpoint,
+    /// Applied to dense embeddings, none unless set via `with_postprocessors`
+    postprocessors: Arc<PostprocessorChain>,
 }
 
 impl BatchProcessor {
-    pub fn new(config: AppConfig, inference_client: InferenceServiceClient) -> Self {
+    pub fn new(
+        config: AppConfig,
+        inference_backend: Arc<dyn InferenceBackend>,
+        queue_state: Arc<QueueState>,
+        batch_stats: Arc<BatchStats>,
+        usage: Arc<UsageTracker>,
+    ) -> Self {
         Self {
+            inference_backend,
+            pending_requests: PendingQueue::new(),
+            queue_state,
+            batch_stats,
+            in_flight_batches: JoinSet::new(),
+            adaptive_limit: AdaptiveBatchLimit::new(&config).map(Arc::new),
+            upstream_max_inputs: Arc::new(AtomicUsize::new(config.max_inference_inputs)),
+            usage,
+            scheduler: build_scheduler(&config),
+            paused: false,
+            endpoint: Endpoint::default(),
+            postprocessors: Arc::default(),
             config,
-            inference_client: Arc::new(inference_client),
-            pending_requests: VecDeque::new(),
         }
     }
 
+    pub fn with_postprocessors(mut self, postprocessors: Arc<PostprocessorChain>) -> Self {
+        self.postprocessors = postprocessors;
+        self
+    }
+
+    pub fn with_endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Replaces the built-in scheduler, e.g., to experiment with a custom batching policy
+    pub fn with_scheduler(mut self, scheduler: Box<dyn BatchScheduler>) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Configured limits, unless adapted to `config.latency_slo_ms`,
+    /// inputs are further capped by what the inference service has rejected before
+    fn batch_limits(&self) -> BatchLimits {
+        let mut limits = match &self.adaptive_limit {
+            Some(adaptive_limit) => adaptive_limit.limits(),
+            None => BatchLimits::from_config(&self.config),
+        };
+        limits.max_inference_inputs = limits
+            .max_inference_inputs
+            .min(self.upstream_max_inputs.load(Ordering::Relaxed));
+        limits
+    }
+
     /// Only single `run` instance is launched from `RequestHandler`
-    pub async fn run(mut self, mut request_receiver: mpsc::UnboundedReceiver<PendingRequest>) {
+    pub async fn run(
+        mut self,
+        mut request_receiver: mpsc::UnboundedReceiver<PendingRequest>,
+        mut control_receiver: mpsc::UnboundedReceiver<ControlMessage>,
+    ) {
         let mut batch_interval = self.config.get_batch_interval();
         // skip the first immediate tick call as it returns immediately (at time 0)
         batch_interval.tick().await;
@@ -38,49 +121,107 @@ impl BatchProcessor {
                 maybe_request = request_receiver.recv() => {
                     if let Some(request) = maybe_request {
                         debug!("Received new request with inputs: {:?}", request.inputs);
+                        self.batch_stats.record_arrival();
 
                         // `max_inference_inputs` check is applied inside `/embed` route (routes.rs)
-                        // & batch size limits are enforced in `build_safe_batch()`
+                        // & batch size limits are enforced in `build_safe_batch()`,
+                        // flushing on `max_batch_size` happens right after via `flush_if_due()`
                         self.pending_requests.push_back(request);
-                        if self.pending_requests.len() >= self.config.max_batch_size {
-                            self.process_pending_requests(BatchType::MaxBatchSize);
-                        }
                     }
                 }
                 // imagine only 1 request arrived, but then there are no new requests,
-                // can cause timeout without even executing `handle_max_wait_time_ms` for older requests,
-                // having ticker ensures, this branch runs & eventually processes `handle_max_wait_time_ms`
+                // can cause timeout without even executing `flush_if_due` for older requests,
+                // having ticker ensures, this branch runs & eventually processes `flush_if_due`
                 _ = batch_interval.tick() => {
                    // periodic wakeup to check pending requests
                 }
+                Some(control_message) = control_receiver.recv() => {
+                    match control_message {
+                        ControlMessage::Drain { done } => {
+                            self.drain(&mut request_receiver).await;
+                            let _ = done.send(());
+                            return;
+                        }
+                        ControlMessage::Pause => self.set_paused(true),
+                        ControlMessage::Resume => self.set_paused(false),
+                        ControlMessage::Flush { done } => {
+                            let _ = done.send(self.flush());
+                        }
+                    }
+                }
+                // reap finished batches, otherwise their results pile up inside `JoinSet`
+                Some(_) = self.in_flight_batches.join_next(), if !self.in_flight_batches.is_empty() => {}
             }
 
             // it will reach here, irrespective of which `tokio::select!` branch was picked
-            self.handle_max_wait_time_ms();
+            self.fail_stale_requests();
+            if !self.paused {
+                self.flush_if_due();
+            }
+            self.sync_queue_state();
         }
     }
 
-    /// ```Max Wait Time - maximal time user request can wait for other requests to be accumulated in a batch```
-    ///
-    /// let's assume, we have such timeline, at 500th ms, we process all requests in single batch,
-    /// (but also consider `max_inference_inputs` limitation)
-    ///
-    /// User1 request with 10 inputs arrives at 0th ms
-    /// User2 request with 20 inputs arrives at 100th ms
-    /// User3 request with 10 inputs arrives at 300th ms // exceeds max_inference_inputs of e.g., 32
-    /// User4 request with 5 inputs arrives at 500th ms
-    fn handle_max_wait_time_ms(&mut self) {
-        if let Some(oldest_request) = self.pending_requests.front() {
-            let elapsed = oldest_request.received_at.elapsed();
-            if elapsed >= self.config.max_wait_time_duration() {
-                info!(
-                    "Processing due to config.max_wait_time_ms: {} timeout",
-                    self.config.max_wait_time_ms
-                );
-                debug!("Oldest request waited {elapsed:?}");
-                // start processing ALL pending requests (in safe batches)
-                self.process_pending_requests(BatchType::MaxWaitTimeMs);
-            }
+    /// Dispatches everything pending regardless of `max_wait_time_ms`, in-flight batches aren't
+    /// waited for. Requests still in the channel are picked up by the regular loop
+    fn flush(&mut self) -> usize {
+        let pending = self.pending_requests.len();
+        if pending > 0 {
+            self.process_pending_requests(BatchType::Flush);
+        }
+        pending
+    }
+
+    /// Expired & stale requests still fail while paused, those are bounded by their own limits
+    fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            info!(
+                "Batch dispatch {} with {} pending requests",
+                if paused { "paused" } else { "resumed" },
+                self.pending_requests.len()
+            );
+        }
+        self.paused = paused;
+        self.queue_state.set_paused(paused);
+    }
+
+    /// Final flush on shutdown, `RequestHandler` stops queueing new requests before asking for it,
+    /// but some might still be sitting in the channel
+    async fn drain(&mut self, request_receiver: &mut mpsc::UnboundedReceiver<PendingRequest>) {
+        request_receiver.close();
+        while let Some(request) = request_receiver.recv().await {
+            self.pending_requests.push_back(request);
+        }
+
+        info!(
+            "Draining {} pending requests, {} batches in flight",
+            self.pending_requests.len(),
+            self.in_flight_batches.len()
+        );
+        if !self.pending_requests.is_empty() {
+            self.process_pending_requests(BatchType::Drain);
+        }
+        self.sync_queue_state();
+
+        while self.in_flight_batches.join_next().await.is_some() {}
+        info!("Drain completed");
+    }
+
+    fn sync_queue_state(&self) {
+        self.queue_state.update(
+            self.pending_requests.len(),
+            self.pending_requests.oldest_received_at(),
+        );
+    }
+
+    /// Scheduler decides whether pending requests should be flushed now
+    fn flush_if_due(&mut self) {
+        if let Some(batch_type) =
+            self.scheduler
+                .should_flush(&self.pending_requests, &self.batch_limits(), &self.config)
+        {
+            // start processing ALL pending requests (in safe batches)
+            self.process_pending_requests(batch_type);
         }
     }
 
@@ -95,103 +236,334 @@ impl BatchProcessor {
         while !self.pending_requests.is_empty() {
             let batch = self.build_safe_batch();
             if batch.is_empty() {
-                error!(
-                    "UNEXPECTED: build_safe_batch returned empty with {} pending requests",
-                    self.pending_requests.len()
-                );
+                // all remaining requests could have been expired (failed inside `build_safe_batch`)
+                if !self.pending_requests.is_empty() {
+                    error!(
+                        "UNEXPECTED: build_safe_batch returned empty with {} pending requests",
+                        self.pending_requests.len()
+                    );
+                }
                 break;
             }
 
             let batch_size = batch.len();
             info!("Processing batch size: {batch_size}");
+            let batch_inputs: usize = batch.iter().map(|request| request.inputs.len()).sum();
+            self.batch_stats
+                .record_batch(batch_type, batch_size, batch_inputs);
+            METRICS.batch_size.observe(batch_size as f64);
+            METRICS.batch_inputs.observe(batch_inputs as f64);
+            if let Some(oldest_received_at) = batch.iter().map(|request| request.received_at).min()
+            {
+                METRICS
+                    .batch_wait_seconds
+                    .observe(oldest_received_at.elapsed().as_secs_f64());
+            }
+            for request in &batch {
+                LATENCY.queue.record(request.received_at.elapsed());
+            }
 
             let batch_info = BatchInfo::new(&self.config, batch_type, batch_size);
-            tokio::spawn(Self::process_batch(
+            self.in_flight_batches.spawn(Self::process_batch(
                 batch,
-                self.inference_client.clone(),
+                self.endpoint,
+                self.config.dedup_batch_inputs,
+                self.inference_backend.clone(),
                 batch_info,
+                self.adaptive_limit.clone(),
+                self.upstream_max_inputs.clone(),
+                self.usage.clone(),
+                self.postprocessors.clone(),
+                self.config.slow_log_threshold(),
             ));
         }
     }
 
     /// It will build a batch while respecting `config.max_batch_size` & `config.max_inference_inputs`
-    /// Some requests might come with MANY inputs
+    /// (& `config.max_batch_tokens` if set). Some requests might come with MANY inputs,
+    /// which of the pending requests are picked is up to the scheduler
+    ///
+    /// Requests whose deadline already passed are skipped & failed immediately,
+    /// there is no point spending inference time on them
     fn build_safe_batch(&mut self) -> Vec<PendingRequest> {
-        let mut batch_size = 0;
-        let mut inputs_count = 0;
+        self.fail_expired_requests();
+        self.fail_stale_requests();
 
-        // `.iter()` - front-to-back
-        for request in self.pending_requests.iter() {
-            if batch_size >= self.config.max_batch_size
-                || (inputs_count + request.inputs.len()) > self.config.max_inference_inputs
-            {
-                break;
+        let mut budget = BatchBudget::new(&self.config, self.batch_limits());
+        let selected = self
+            .scheduler
+            .select_batch(&self.pending_requests, &mut budget);
+        self.pending_requests.take(&selected)
+    }
+
+    fn fail_expired_requests(&mut self) {
+        if !self
+            .pending_requests
+            .iter()
+            .any(|request| request.is_expired())
+        {
+            return;
+        }
+
+        let expired = self
+            .pending_requests
+            .remove_where(|request| request.is_expired());
+
+        warn!("Failing {} requests with expired deadline", expired.len());
+        for request in expired {
+            let error_response = Custom(
+                Status::GatewayTimeout,
+                Json(ErrorResponse {
+                    error: "Request deadline exceeded before it could be batched".to_string(),
+                    code: Some("deadline_exceeded"),
+                }),
+            );
+            if request.response_sender.send(Err(error_response)).is_err() {
+                warn!("Failed to send deadline error to client (may have disconnected)");
+            }
+        }
+    }
+
+    /// Requests queued longer than `config.max_queue_age_ms` are failed, rather than batched
+    /// for clients that are about to (or already did) time out
+    fn fail_stale_requests(&mut self) {
+        let Some(max_queue_age) = self.config.max_queue_age() else {
+            return;
+        };
+        if self
+            .pending_requests
+            .oldest_received_at()
+            .is_none_or(|received_at| received_at.elapsed() < max_queue_age)
+        {
+            return;
+        }
+
+        let stale = self
+            .pending_requests
+            .remove_where(|request| request.received_at.elapsed() >= max_queue_age);
+
+        warn!(
+            "Failing {} requests queued longer than {}ms",
+            stale.len(),
+            max_queue_age.as_millis()
+        );
+        for request in stale {
+            let error_response = Custom(
+                Status::GatewayTimeout,
+                Json(ErrorResponse {
+                    error: "Request waited too long in the queue".to_string(),
+                    code: Some("queue_ttl_exceeded"),
+                }),
+            );
+            if request.response_sender.send(Err(error_response)).is_err() {
+                warn!("Failed to send queue TTL error to client (may have disconnected)");
             }
-            inputs_count += request.inputs.len();
-            batch_size += 1;
         }
+    }
 
-        self.pending_requests.drain(..batch_size).collect()
+    /// Upstream timeout for the batch is bounded by its tightest client deadline
+    fn remaining_budget(batch: &[PendingRequest]) -> Option<Duration> {
+        batch
+            .iter()
+            .filter_map(|request| request.deadline)
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
     }
 
+    /// A batch rejected with 413 (too large for the model) is split in half & both halves are
+    /// retried (one after another), the learned inputs limit is applied to future batches
+    ///
+    /// Other input rejections (e.g. a single too long input) are bisected the same way,
+    /// until only the offending request(s) fail & everyone else batched with them is served
+    #[allow(clippy::too_many_arguments)]
     async fn process_batch(
         batch: Vec<PendingRequest>,
-        inference_client: Arc<InferenceServiceClient>,
-        mut batch_info: Option<BatchInfo>,
+        endpoint: Endpoint,
+        dedup_inputs: bool,
+        inference_backend: Arc<dyn InferenceBackend>,
+        batch_info: Option<BatchInfo>,
+        adaptive_limit: Option<Arc<AdaptiveBatchLimit>>,
+        upstream_max_inputs: Arc<AtomicUsize>,
+        usage: Arc<UsageTracker>,
+        postprocessors: Arc<PostprocessorChain>,
+        slow_log_threshold: Option<Duration>,
     ) {
-        let start_time = Instant::now();
-        let inference_response = inference_client
-            .call_service(BatchRequest::prepare_request(&batch))
-            .await;
+        // spawned right after the batch was built, bisected halves keep the original dispatch time
+        let dispatched_at = Instant::now();
+        let mut slow_log = slow_log_threshold.map(|_| SlowBatchLog::new(&batch, dispatched_at));
 
-        if let Some(ref mut info) = batch_info {
-            info.inference_time_ms = Some(start_time.elapsed().as_millis() as f64);
-        }
+        let mut batches = vec![batch];
+        while let Some(batch) = batches.pop() {
+            let mut batch_info = batch_info.clone();
+            let prepare_start_time = Instant::now();
+            let mut batch_request = BatchRequest::prepare_request(&batch);
+            let positions = dedup_inputs.then(|| batch_request.dedup()).flatten();
+            if let Some(positions) = &positions {
+                METRICS.deduplicated_inputs.fetch_add(
+                    (positions.len() - batch_request.inputs.len()) as u64,
+                    Ordering::Relaxed,
+                );
+            }
+            let sent_inputs = batch_request.inputs.len();
+            let prepare_time = prepare_start_time.elapsed();
 
-        match inference_response {
-            Ok(embeddings) => {
-                Self::handle_batch_success(batch, embeddings, batch_info, start_time);
+            let start_time = Instant::now();
+            let timeout = Self::remaining_budget(&batch);
+            let inference_response = match endpoint {
+                Endpoint::Embed => inference_backend
+                    .embed(batch_request, timeout)
+                    .await
+                    .map(BatchOutput::Dense),
+                Endpoint::EmbedSparse => inference_backend
+                    .embed_sparse(batch_request, timeout)
+                    .await
+                    .map(BatchOutput::Sparse),
+                Endpoint::EmbedAll => inference_backend
+                    .embed_all(batch_request, timeout)
+                    .await
+                    .map(BatchOutput::Tokens),
+            };
+            if let Some(slow_log) = &mut slow_log {
+                slow_log.record_call(prepare_time, start_time.elapsed());
             }
-            Err(e) => {
-                Self::handle_batch_error(batch, e);
+
+            if let Some(ref mut info) = batch_info {
+                info.batch_size = Some(batch.len());
+                info.inference_time_ms = Some(start_time.elapsed().as_millis() as f64);
+                info.serialization_time_ms = Some(prepare_time.as_secs_f64() * 1000.0);
+            }
+            // a fast failure (open circuit) says nothing about upstream latency
+            if let Some(adaptive_limit) = &adaptive_limit
+                && !matches!(inference_response, Err(InferenceError::CircuitOpen))
+            {
+                adaptive_limit.record(start_time.elapsed());
+            }
+
+            match inference_response {
+                Ok(output) => {
+                    LATENCY.upstream.record(start_time.elapsed());
+                    let output = match &positions {
+                        Some(positions) => output.expand(positions),
+                        None => output,
+                    };
+                    Self::handle_batch_success(
+                        batch,
+                        output,
+                        batch_info,
+                        dispatched_at,
+                        start_time,
+                        &usage,
+                        &postprocessors,
+                    );
+                }
+                Err(InferenceError::HttpError { status, body })
+                    if InferenceError::is_input_rejection(status) && batch.len() > 1 =>
+                {
+                    // TEI also answers 413 for a single too long input, only the batch size
+                    // rejection says something about future batches
+                    if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE
+                        && body.contains("batch size")
+                    {
+                        let learned_max_inputs = (sent_inputs / 2).max(1);
+                        let previous =
+                            upstream_max_inputs.fetch_min(learned_max_inputs, Ordering::Relaxed);
+                        if learned_max_inputs < previous {
+                            warn!(
+                                "Inference service rejected batch of {sent_inputs} inputs, limiting batches to {learned_max_inputs} inputs"
+                            );
+                        }
+                    } else {
+                        info!(
+                            "Bisecting rejected batch of {} requests ({status}): {body}",
+                            batch.len()
+                        );
+                    }
+
+                    let mut first_half = batch;
+                    let second_half = first_half.split_off(first_half.len() / 2);
+                    batches.push(second_half);
+                    batches.push(first_half);
+                }
+                Err(e) => {
+                    Self::handle_batch_error(batch, e);
+                }
             }
         }
+
+        if let (Some(threshold), Some(slow_log)) = (slow_log_threshold, slow_log) {
+            slow_log.warn_if_slower_than(threshold, batch_info.as_ref());
+        }
     }
 
     /// Sends inference service returned embeddings to each client as per given input(s)
+    ///
+    /// Embeddings are mapped to requests by position only, so any count mismatch
+    /// fails the whole batch (502), rather than handing out someone else's embeddings.
+    /// Dense embeddings go through `postprocessors` once for the whole batch, before they're split
     fn handle_batch_success(
         batch: Vec<PendingRequest>,
-        embeddings: BatchResponse,
+        mut output: BatchOutput,
         batch_info: Option<BatchInfo>,
+        dispatched_at: Instant,
         start_time: Instant,
+        usage: &UsageTracker,
+        postprocessors: &PostprocessorChain,
     ) {
-        let mut start_idx = 0;
-        for pending_request in batch {
-            let end_idx = start_idx + pending_request.inputs.len();
+        let expected: usize = batch.iter().map(|request| request.inputs.len()).sum();
+        if output.len() != expected {
+            METRICS
+                .embedding_count_mismatches
+                .fetch_add(1, Ordering::Relaxed);
+            return Self::handle_batch_error(
+                batch,
+                InferenceError::EmbeddingCountMismatch {
+                    expected,
+                    actual: output.len(),
+                },
+            );
+        }
 
-            // check ```assert_eq!(embeddings.len(), inputs.len())``` in test_utils to verify logic
-            let individual_embeddings = embeddings
-                .get(start_idx..end_idx)
-                .map(|slice| slice.to_vec())
-                .unwrap_or_default();
+        if let BatchOutput::Dense(embeddings) = &mut output
+            && let Err(e) = postprocessors.apply(embeddings)
+        {
+            return Self::handle_batch_error(batch, InferenceError::Postprocessing(e));
+        }
 
-            let response = EmbedResponse {
-                embeddings: individual_embeddings,
-                batch_info: batch_info.clone(),
-            };
+        let inference_time = start_time.elapsed();
+        let split_start_time = Instant::now();
+        // each request takes ownership of its embeddings, the floats themselves are never copied
+        // check ```assert_eq!(embeddings.len(), inputs.len())``` in test_utils to verify logic
+        let outputs = output.split(batch.iter().map(|request| request.inputs.len()));
+        for (pending_request, output) in batch.into_iter().zip(outputs) {
+            usage.record(
+                pending_request.client_id.as_deref(),
+                pending_request.inputs.len(),
+                inference_time.mul_f64(pending_request.inputs.len() as f64 / expected as f64),
+            );
+
+            let mut response = output.into_response();
+            response.batch_info = batch_info.clone().map(|mut info| {
+                let queue_time =
+                    dispatched_at.saturating_duration_since(pending_request.received_at);
+                info.queue_time_ms = Some(queue_time.as_secs_f64() * 1000.0);
+                info.serialization_time_ms = Some(
+                    info.serialization_time_ms.unwrap_or_default()
+                        + split_start_time.elapsed().as_secs_f64() * 1000.0,
+                );
+                info.processing_time_ms =
+                    Some(pending_request.received_at.elapsed().as_secs_f64() * 1000.0);
+                info
+            });
 
             // check `EmbedResponse` in `timeout_result` (process_request)
             if pending_request.response_sender.send(Ok(response)).is_err() {
                 warn!("Failed to send response to client (may have disconnected)");
             }
-
-            start_idx = end_idx;
         }
 
         info!(
-            "Batch processed successfully in {:?}ms, {} embeddings returned",
-            start_time.elapsed().as_millis() as f64,
-            embeddings.len()
+            "Batch processed successfully in {:?}ms, {expected} embeddings returned",
+            start_time.elapsed().as_millis() as f64
         );
     }
 
@@ -204,6 +576,7 @@ impl BatchProcessor {
             error.to_rocket_status(),
             Json(ErrorResponse {
                 error: error.message(),
+                code: error.code(),
             }),
         );
 
@@ -219,17 +592,94 @@ impl BatchProcessor {
     }
 }
 
+/// Timings of a single dispatched batch (across bisected retries) for `config.slow_log_threshold_ms`
+struct SlowBatchLog {
+    requests: usize,
+    inputs: usize,
+    /// Of the oldest request
+    queue_time: Duration,
+    dispatched_at: Instant,
+    serialization_time: Duration,
+    inference_time: Duration,
+    upstream_calls: usize,
+}
+
+impl SlowBatchLog {
+    fn new(batch: &[PendingRequest], dispatched_at: Instant) -> Self {
+        Self {
+            requests: batch.len(),
+            inputs: batch.iter().map(|request| request.inputs.len()).sum(),
+            queue_time: batch
+                .iter()
+                .map(|request| dispatched_at.saturating_duration_since(request.received_at))
+                .max()
+                .unwrap_or_default(),
+            dispatched_at,
+            serialization_time: Duration::ZERO,
+            inference_time: Duration::ZERO,
+            upstream_calls: 0,
+        }
+    }
+
+    fn record_call(&mut self, serialization_time: Duration, inference_time: Duration) {
+        self.serialization_time += serialization_time;
+        self.inference_time += inference_time;
+        self.upstream_calls += 1;
+    }
+
+    /// Queue time counts too, a batch flushed late is as slow for its callers as a slow upstream
+    fn warn_if_slower_than(&self, threshold: Duration, batch_info: Option<&BatchInfo>) {
+        let processing_time = self.dispatched_at.elapsed();
+        if self.queue_time + processing_time <= threshold {
+            return;
+        }
+        warn!(
+            "Slow batch {} ({:?}): {} requests, {} inputs, queue {:?}, serialization {:?}, inference {:?} ({} upstream calls), total {:?}",
+            batch_info.map_or(0, |info| info.batch_id),
+            batch_info.map(|info| info.batch_type),
+            self.requests,
+            self.inputs,
+            self.queue_time,
+            self.serialization_time,
+            self.inference_time,
+            self.upstream_calls,
+            self.queue_time + processing_time
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::batch_processor::BatchProcessor;
-    use crate::config::AppConfig;
-    use crate::inference_client::InferenceServiceClient;
-    use crate::types::{PendingRequest, ResponseSender};
+    use crate::batch_stats::BatchStats;
+    use crate::config::{AppConfig, SchedulingMode};
+    use crate::inference_client::{InferenceBackend, InferenceError, InferenceServiceClient};
+    use crate::metrics::METRICS;
+    use crate::postprocessing::{Normalize, PostprocessorChain, Projection};
+    use crate::queue_state::QueueState;
+    use crate::stub_upstream;
+    use crate::types::{
+        BatchInfo, BatchOutput, BatchRequest, BatchResponse, BatchType, Endpoint, PendingRequest,
+        Priority, ResponseSender, SparseValue,
+    };
+    use crate::usage::UsageTracker;
+    use rocket::http::Status;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
     use tokio::sync::oneshot;
 
     fn build_batch_processor(config: AppConfig) -> BatchProcessor {
-        let inference_client = InferenceServiceClient::new(&config).unwrap();
-        BatchProcessor::new(config, inference_client)
+        let inference_client = Arc::new(InferenceServiceClient::new(&config).unwrap());
+        let usage = Arc::new(UsageTracker::new(&config));
+        BatchProcessor::new(
+            config,
+            inference_client,
+            Arc::new(QueueState::new()),
+            Arc::new(BatchStats::new()),
+            usage,
+        )
     }
 
     #[test]
@@ -271,4 +721,608 @@ mod tests {
         let batch = batch_processor.build_safe_batch();
         assert_eq!(batch.len(), 2);
     }
+
+    #[test]
+    fn test_build_safe_batch_max_batch_tokens() {
+        let config = AppConfig {
+            max_batch_tokens: Some(100),
+            ..AppConfig::default()
+        };
+        let mut batch_processor = build_batch_processor(config);
+
+        // single input each, but 3 * 40 tokens > 100
+        for _ in 1..=3 {
+            let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+            let pending_request = PendingRequest::new(vec!["Hello".to_string()], response_sender)
+                .with_token_count(40);
+            batch_processor.pending_requests.push_back(pending_request);
+        }
+
+        let batch = batch_processor.build_safe_batch();
+        assert_eq!(batch.len(), 2);
+    }
+
+    #[test]
+    fn test_build_safe_batch_packs_high_priority_first() {
+        let config = AppConfig {
+            max_batch_size: 2,
+            ..AppConfig::default()
+        };
+        let mut batch_processor = build_batch_processor(config);
+
+        for (input, priority) in [
+            ("backfill 1", Priority::Normal),
+            ("backfill 2", Priority::Normal),
+            ("search", Priority::High),
+        ] {
+            let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+            let pending_request = PendingRequest::new(vec![input.to_string()], response_sender)
+                .with_priority(priority);
+            batch_processor.pending_requests.push_back(pending_request);
+        }
+
+        let batch = batch_processor.build_safe_batch();
+        let inputs: Vec<&str> = batch.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(inputs, vec!["search", "backfill 1"]);
+    }
+
+    #[test]
+    fn test_build_safe_batch_fair_scheduling_round_robin_across_clients() {
+        let config = AppConfig {
+            max_batch_size: 4,
+            fair_scheduling: true,
+            ..AppConfig::default()
+        };
+        let mut batch_processor = build_batch_processor(config);
+
+        // "flooder" queues 5 requests before the others arrive
+        let mut arrivals: Vec<(&str, &str)> = vec![("flooder", "f"); 5];
+        arrivals.extend([("alice", "a"), ("bob", "b")]);
+        for (i, (client_id, input)) in arrivals.into_iter().enumerate() {
+            let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+            let pending_request = PendingRequest::new(vec![format!("{input}{i}")], response_sender)
+                .with_client_id(Some(client_id.to_string()));
+            batch_processor.pending_requests.push_back(pending_request);
+        }
+
+        let batch = batch_processor.build_safe_batch();
+        let inputs: Vec<&str> = batch.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(inputs, vec!["f0", "a5", "b6", "f1"]);
+        assert_eq!(batch_processor.pending_requests.len(), 3);
+    }
+
+    #[test]
+    fn test_build_safe_batch_size_class_groups_similar_lengths() {
+        let config = AppConfig {
+            max_batch_size: 3,
+            scheduling_mode: SchedulingMode::SizeClass,
+            ..AppConfig::default()
+        };
+        let mut batch_processor = build_batch_processor(config);
+
+        let long = "What is Vector search ? ".repeat(20);
+        for input in [
+            "short 1",
+            long.as_str(),
+            "short 2",
+            long.as_str(),
+            "short 3",
+        ] {
+            let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+            let pending_request = PendingRequest::new(vec![input.to_string()], response_sender);
+            batch_processor.pending_requests.push_back(pending_request);
+        }
+
+        let batch = batch_processor.build_safe_batch();
+        let inputs: Vec<&str> = batch.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(inputs, vec!["short 1", "short 2", "short 3"]);
+
+        let batch = batch_processor.build_safe_batch();
+        assert_eq!(batch.len(), 2);
+        assert!(batch.iter().all(|r| *r.inputs[0] == *long));
+    }
+
+    #[test]
+    fn test_build_safe_batch_fails_expired_requests() {
+        let mut batch_processor = build_batch_processor(AppConfig::default());
+
+        let (response_sender, mut expired_receiver): (ResponseSender, _) = oneshot::channel();
+        let expired = PendingRequest::new(vec!["Hello".to_string()], response_sender)
+            .with_deadline(Some(Instant::now()));
+        batch_processor.pending_requests.push_back(expired);
+
+        let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+        let valid = PendingRequest::new(vec!["World".to_string()], response_sender)
+            .with_deadline(Some(Instant::now() + Duration::from_secs(60)));
+        batch_processor.pending_requests.push_back(valid);
+
+        let batch = batch_processor.build_safe_batch();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].inputs, vec![Arc::from("World")]);
+
+        let error = expired_receiver.try_recv().unwrap().unwrap_err();
+        assert_eq!(error.0, Status::GatewayTimeout);
+        assert_eq!(error.1.code, Some("deadline_exceeded"));
+    }
+
+    #[test]
+    fn test_fail_stale_requests() {
+        let mut batch_processor = build_batch_processor(AppConfig {
+            max_queue_age_ms: Some(1000),
+            ..AppConfig::default()
+        });
+
+        let (response_sender, mut stale_receiver): (ResponseSender, _) = oneshot::channel();
+        let mut stale = PendingRequest::new(vec!["Hello".to_string()], response_sender);
+        stale.received_at = Instant::now() - Duration::from_secs(2);
+        batch_processor.pending_requests.push_back(stale);
+
+        let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+        let fresh = PendingRequest::new(vec!["World".to_string()], response_sender);
+        batch_processor.pending_requests.push_back(fresh);
+
+        batch_processor.fail_stale_requests();
+        assert_eq!(batch_processor.pending_requests.len(), 1);
+
+        let error = stale_receiver.try_recv().unwrap().unwrap_err();
+        assert_eq!(error.0, Status::GatewayTimeout);
+        assert_eq!(error.1.code, Some("queue_ttl_exceeded"));
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_splits_on_payload_too_large() {
+        // proxy allows more inputs than the inference service (32)
+        let inference_client = limited_upstream(32, 512).await;
+        let upstream_max_inputs = Arc::new(AtomicUsize::new(64));
+
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = (0..3)
+            .map(|i| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                let inputs = (0..20).map(|j| format!("{i}-{j}")).collect();
+                PendingRequest::new(inputs, response_sender)
+            })
+            .collect();
+
+        // 60 inputs -> [20] + [40] -> [20] + [20] + [20]
+        BatchProcessor::process_batch(
+            batch,
+            Endpoint::Embed,
+            true,
+            inference_client,
+            None,
+            None,
+            upstream_max_inputs.clone(),
+            Arc::new(UsageTracker::new(&AppConfig::default())),
+            Arc::default(),
+            None,
+        )
+        .await;
+
+        for response_receiver in receivers {
+            let response = response_receiver.await.unwrap().unwrap();
+            assert_eq!(response.embeddings.len(), 20);
+        }
+        assert_eq!(upstream_max_inputs.load(Ordering::Relaxed), 20);
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_isolates_rejected_request() {
+        let inference_client = limited_upstream(32, 512).await;
+        let upstream_max_inputs = Arc::new(AtomicUsize::new(32));
+
+        // too long for the model (without truncation)
+        let poison = "word ".repeat(1000);
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = ["Hello", poison.as_str(), "World", "Again"]
+            .iter()
+            .map(|input| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                PendingRequest::new(vec![input.to_string()], response_sender)
+            })
+            .collect();
+
+        BatchProcessor::process_batch(
+            batch,
+            Endpoint::Embed,
+            true,
+            inference_client,
+            None,
+            None,
+            upstream_max_inputs.clone(),
+            Arc::new(UsageTracker::new(&AppConfig::default())),
+            Arc::default(),
+            None,
+        )
+        .await;
+
+        let mut results = Vec::new();
+        for response_receiver in receivers {
+            results.push(response_receiver.await.unwrap().is_ok());
+        }
+        assert_eq!(results, vec![true, false, true, true]);
+        // single input rejection doesn't affect future batches
+        assert_eq!(upstream_max_inputs.load(Ordering::Relaxed), 32);
+    }
+
+    /// Inference service stand-in rejecting (413) like TEI does batches above `max_inputs`
+    /// & inputs above `max_input_len`, embeds each input as its length
+    async fn limited_upstream(
+        max_inputs: usize,
+        max_input_len: usize,
+    ) -> Arc<InferenceServiceClient> {
+        let url = stub_upstream::spawn(move |request| {
+            let inputs = request.inputs();
+            let error = if inputs.len() > max_inputs {
+                let batch_size = inputs.len();
+                format!("batch size {batch_size} > maximum allowed batch size {max_inputs}")
+            } else if inputs.iter().any(|input| input.len() > max_input_len) {
+                format!("Input validation error: inputs must have less than {max_input_len} tokens")
+            } else {
+                let embeddings: Vec<[f32; 1]> =
+                    inputs.iter().map(|input| [input.len() as f32]).collect();
+                return Some((Status::Ok, json!(embeddings).to_string()));
+            };
+            Some((
+                Status::PayloadTooLarge,
+                json!({ "error": error }).to_string(),
+            ))
+        })
+        .await;
+        let config = AppConfig {
+            inference_urls: vec![url],
+            ..AppConfig::default()
+        };
+        Arc::new(InferenceServiceClient::new(&config).unwrap())
+    }
+
+    /// Embeds every input as its length
+    struct InputLengthBackend;
+
+    #[rocket::async_trait]
+    impl InferenceBackend for InputLengthBackend {
+        async fn embed(
+            &self,
+            request: BatchRequest,
+            _timeout: Option<Duration>,
+        ) -> Result<BatchResponse, InferenceError> {
+            if request.inputs.iter().any(|input| input.is_empty()) {
+                return Err(InferenceError::BackendError("empty input".to_string()));
+            }
+            Ok(request
+                .inputs
+                .iter()
+                .map(|input| vec![input.len() as f32])
+                .collect())
+        }
+    }
+
+    /// Records the inputs of every call, embeds each as its length
+    #[derive(Default)]
+    struct RecordingBackend {
+        calls: std::sync::Mutex<Vec<Vec<String>>>,
+    }
+
+    #[rocket::async_trait]
+    impl InferenceBackend for RecordingBackend {
+        async fn embed(
+            &self,
+            request: BatchRequest,
+            timeout: Option<Duration>,
+        ) -> Result<BatchResponse, InferenceError> {
+            let inputs = request.inputs.iter().map(|input| input.to_string());
+            self.calls.lock().unwrap().push(inputs.collect());
+            InputLengthBackend.embed(request, timeout).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_dedups_repeated_inputs() {
+        for dedup_inputs in [true, false] {
+            let backend = Arc::new(RecordingBackend::default());
+            let mut receivers = Vec::new();
+            let batch: Vec<PendingRequest> = [vec!["a", "bb", "a"], vec!["bb"]]
+                .iter()
+                .map(|inputs| {
+                    let (response_sender, response_receiver): (ResponseSender, _) =
+                        oneshot::channel();
+                    receivers.push(response_receiver);
+                    let inputs = inputs.iter().map(|input| input.to_string()).collect();
+                    PendingRequest::new(inputs, response_sender)
+                })
+                .collect();
+
+            BatchProcessor::process_batch(
+                batch,
+                Endpoint::Embed,
+                dedup_inputs,
+                backend.clone(),
+                None,
+                None,
+                Arc::new(AtomicUsize::new(32)),
+                Arc::new(UsageTracker::new(&AppConfig::default())),
+                Arc::default(),
+                None,
+            )
+            .await;
+
+            let mut embeddings = Vec::new();
+            for response_receiver in receivers {
+                embeddings.push(response_receiver.await.unwrap().unwrap().embeddings);
+            }
+            assert_eq!(
+                embeddings,
+                vec![vec![vec![1.0], vec![2.0], vec![1.0]], vec![vec![2.0]]]
+            );
+            let sent_inputs = backend.calls.lock().unwrap()[0].len();
+            assert_eq!(sent_inputs, if dedup_inputs { 2 } else { 4 });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_with_custom_backend() {
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = [vec!["a", "bb"], vec!["ccc"]]
+            .iter()
+            .map(|inputs| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                let inputs = inputs.iter().map(|input| input.to_string()).collect();
+                PendingRequest::new(inputs, response_sender)
+            })
+            .collect();
+
+        BatchProcessor::process_batch(
+            batch,
+            Endpoint::Embed,
+            true,
+            Arc::new(InputLengthBackend),
+            None,
+            None,
+            Arc::new(AtomicUsize::new(32)),
+            Arc::new(UsageTracker::new(&AppConfig::default())),
+            Arc::default(),
+            None,
+        )
+        .await;
+
+        let mut embeddings = Vec::new();
+        for response_receiver in receivers {
+            embeddings.push(response_receiver.await.unwrap().unwrap().embeddings);
+        }
+        assert_eq!(
+            embeddings,
+            vec![vec![vec![1.0], vec![2.0]], vec![vec![3.0]]]
+        );
+
+        let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+        BatchProcessor::process_batch(
+            vec![PendingRequest::new(vec![String::new()], response_sender)],
+            Endpoint::Embed,
+            true,
+            Arc::new(InputLengthBackend),
+            None,
+            None,
+            Arc::new(AtomicUsize::new(32)),
+            Arc::new(UsageTracker::new(&AppConfig::default())),
+            Arc::default(),
+            None,
+        )
+        .await;
+        let error = response_receiver.await.unwrap().unwrap_err();
+        assert_eq!(error.0, Status::BadGateway);
+    }
+
+    #[test]
+    fn test_handle_batch_success_fails_on_embedding_count_mismatch() {
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = [2, 1]
+            .iter()
+            .map(|inputs| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                PendingRequest::new(vec!["Hello".to_string(); *inputs], response_sender)
+            })
+            .collect();
+
+        let mismatches_before = METRICS.embedding_count_mismatches.load(Ordering::Relaxed);
+        // 3 inputs, but only 2 embeddings
+        BatchProcessor::handle_batch_success(
+            batch,
+            BatchOutput::Dense(vec![vec![0.1], vec![0.2]]),
+            None,
+            Instant::now(),
+            Instant::now(),
+            &UsageTracker::new(&AppConfig::default()),
+            &PostprocessorChain::default(),
+        );
+
+        for mut response_receiver in receivers {
+            let error = response_receiver.try_recv().unwrap().unwrap_err();
+            assert_eq!(error.0, Status::BadGateway);
+            assert_eq!(error.1.code, Some("embedding_count_mismatch"));
+        }
+        assert!(METRICS.embedding_count_mismatches.load(Ordering::Relaxed) > mismatches_before);
+    }
+
+    #[test]
+    fn test_handle_batch_success_postprocesses_whole_batch() {
+        let postprocessors = PostprocessorChain::default();
+        postprocessors.push(Box::new(Normalize));
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = [1, 1]
+            .iter()
+            .map(|inputs| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                PendingRequest::new(vec!["Hello".to_string(); *inputs], response_sender)
+            })
+            .collect();
+
+        BatchProcessor::handle_batch_success(
+            batch,
+            BatchOutput::Dense(vec![vec![3.0, 4.0], vec![0.0, 2.0]]),
+            None,
+            Instant::now(),
+            Instant::now(),
+            &UsageTracker::new(&AppConfig::default()),
+            &postprocessors,
+        );
+        let embeddings: Vec<_> = receivers
+            .iter_mut()
+            .map(|receiver| receiver.try_recv().unwrap().unwrap().embeddings)
+            .collect();
+        assert_eq!(embeddings, vec![vec![vec![0.6, 0.8]], vec![vec![0.0, 1.0]]]);
+
+        // a 3 dimensional projection of 2 dimensional embeddings fails the batch
+        postprocessors.push(Box::new(Projection::new(vec![vec![1.0; 3]]).unwrap()));
+        let (response_sender, mut response_receiver): (ResponseSender, _) = oneshot::channel();
+        BatchProcessor::handle_batch_success(
+            vec![PendingRequest::new(
+                vec!["Hello".to_string()],
+                response_sender,
+            )],
+            BatchOutput::Dense(vec![vec![3.0, 4.0]]),
+            None,
+            Instant::now(),
+            Instant::now(),
+            &UsageTracker::new(&AppConfig::default()),
+            &postprocessors,
+        );
+        let error = response_receiver.try_recv().unwrap().unwrap_err();
+        assert_eq!(error.0, Status::InternalServerError);
+        assert_eq!(error.1.code, Some("postprocessing_failed"));
+    }
+
+    #[test]
+    fn test_handle_batch_success_splits_sparse_embeddings() {
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = [1, 2]
+            .iter()
+            .map(|inputs| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                PendingRequest::new(vec!["Hello".to_string(); *inputs], response_sender)
+            })
+            .collect();
+        let sparse_embedding = |index| vec![SparseValue { index, value: 0.5 }];
+
+        BatchProcessor::handle_batch_success(
+            batch,
+            BatchOutput::Sparse((0..3).map(sparse_embedding).collect()),
+            None,
+            Instant::now(),
+            Instant::now(),
+            &UsageTracker::new(&AppConfig::default()),
+            &PostprocessorChain::default(),
+        );
+
+        let responses: Vec<_> = receivers
+            .iter_mut()
+            .map(|receiver| receiver.try_recv().unwrap().unwrap())
+            .collect();
+        assert!(responses[0].embeddings.is_empty());
+        assert_eq!(responses[0].sparse_embeddings, vec![sparse_embedding(0)]);
+        assert_eq!(
+            responses[1].sparse_embeddings,
+            vec![sparse_embedding(1), sparse_embedding(2)]
+        );
+    }
+
+    #[test]
+    fn test_handle_batch_success_splits_token_embeddings_of_varying_length() {
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = [2, 1]
+            .iter()
+            .map(|inputs| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                PendingRequest::new(vec!["Hello".to_string(); *inputs], response_sender)
+            })
+            .collect();
+        // 3 inputs of 1, 3 & 2 tokens
+        let token_embeddings = vec![
+            vec![vec![0.1]],
+            vec![vec![0.2], vec![0.3], vec![0.4]],
+            vec![vec![0.5], vec![0.6]],
+        ];
+
+        BatchProcessor::handle_batch_success(
+            batch,
+            BatchOutput::Tokens(token_embeddings.clone()),
+            None,
+            Instant::now(),
+            Instant::now(),
+            &UsageTracker::new(&AppConfig::default()),
+            &PostprocessorChain::default(),
+        );
+
+        let responses: Vec<_> = receivers
+            .iter_mut()
+            .map(|receiver| receiver.try_recv().unwrap().unwrap())
+            .collect();
+        assert_eq!(responses[0].token_embeddings, token_embeddings[..2]);
+        assert_eq!(responses[1].token_embeddings, token_embeddings[2..]);
+    }
+
+    #[test]
+    fn test_handle_batch_success_reports_latency_breakdown_per_request() {
+        let config = AppConfig {
+            include_batch_info: true,
+            ..AppConfig::default()
+        };
+        let dispatched_at = Instant::now();
+        let mut receivers = Vec::new();
+        let batch: Vec<PendingRequest> = [300, 100]
+            .iter()
+            .map(|queued_ms| {
+                let (response_sender, response_receiver): (ResponseSender, _) = oneshot::channel();
+                receivers.push(response_receiver);
+                let mut request = PendingRequest::new(vec!["Hello".to_string()], response_sender);
+                request.received_at = dispatched_at - Duration::from_millis(*queued_ms);
+                request
+            })
+            .collect();
+
+        BatchProcessor::handle_batch_success(
+            batch,
+            BatchOutput::Dense(vec![vec![0.1], vec![0.2]]),
+            BatchInfo::new(&config, BatchType::MaxWaitTimeMs, 2),
+            dispatched_at,
+            Instant::now(),
+            &UsageTracker::new(&config),
+            &PostprocessorChain::default(),
+        );
+
+        let batch_infos: Vec<BatchInfo> = receivers
+            .iter_mut()
+            .map(|receiver| receiver.try_recv().unwrap().unwrap().batch_info.unwrap())
+            .collect();
+        assert_eq!(batch_infos[0].queue_time_ms, Some(300.0));
+        assert_eq!(batch_infos[1].queue_time_ms, Some(100.0));
+        for (batch_info, queued_ms) in batch_infos.iter().zip([300.0, 100.0]) {
+            assert!(batch_info.serialization_time_ms.unwrap() >= 0.0);
+            assert!(batch_info.processing_time_ms.unwrap() >= queued_ms);
+        }
+    }
+
+    #[test]
+    fn test_remaining_budget_uses_tightest_deadline() {
+        let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+        let no_deadline = PendingRequest::new(vec!["Hello".to_string()], response_sender);
+        assert!(BatchProcessor::remaining_budget(&[no_deadline]).is_none());
+
+        let batch: Vec<PendingRequest> = [10, 2]
+            .iter()
+            .map(|secs| {
+                let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+                PendingRequest::new(vec!["Hello".to_string()], response_sender)
+                    .with_deadline(Some(Instant::now() + Duration::from_secs(*secs)))
+            })
+            .collect();
+        let budget = BatchProcessor::remaining_budget(&batch).unwrap();
+        assert!(budget <= Duration::from_secs(2));
+    }
 }
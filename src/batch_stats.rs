@@ -0,0 +1,336 @@
+use crate::queue_state::QueueState;
+use crate::tuning::TuningWindow;
+use crate::types::BatchType;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of most recent batches the batch size distribution is computed over
+const BATCH_SIZE_WINDOW: usize = 1000;
+/// Throughput is averaged over batches dispatched within this window
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Batching counters, maintained by `BatchProcessor` on every dispatched batch,
+/// reported by `GET /stats`
+#[derive(Debug, Default)]
+pub struct BatchStats {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    batches_by_type: BatchesByType,
+    /// Requests per batch, most recent last
+    recent_batch_sizes: VecDeque<usize>,
+    /// Batches dispatched within `THROUGHPUT_WINDOW`
+    recent_dispatches: VecDeque<Dispatch>,
+    /// (second started at, requests) that reached the batch loop within `THROUGHPUT_WINDOW`,
+    /// counted per second, arrivals can be far more frequent than batches
+    recent_arrivals: VecDeque<(Instant, u64)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Dispatch {
+    dispatched_at: Instant,
+    batch_type: BatchType,
+    requests: usize,
+    inputs: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchesByType {
+    pub max_batch_size: u64,
+    pub max_wait_time_ms: u64,
+    pub deadline: u64,
+    pub drain: u64,
+    pub flush: u64,
+}
+
+/// `GET /stats` response
+#[derive(Serialize, Debug, Clone)]
+pub struct Stats {
+    pub queue_depth: usize,
+    /// Batch dispatch paused via `POST /admin/queue/pause`
+    pub paused: bool,
+    /// `None` when the queue is empty
+    pub oldest_pending_age_ms: Option<f64>,
+    pub batches_total: u64,
+    pub batches_by_type: BatchesByType,
+    /// Requests per batch, over the last `BATCH_SIZE_WINDOW` batches
+    pub batch_size: BatchSizeStats,
+    pub throughput: Throughput,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchSizeStats {
+    pub avg: f64,
+    pub p50: usize,
+    pub p95: usize,
+    pub p99: usize,
+    pub max: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Throughput {
+    pub window_secs: u64,
+    pub batches_per_sec: f64,
+    pub requests_per_sec: f64,
+    pub inputs_per_sec: f64,
+}
+
+impl BatchStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_batch(&self, batch_type: BatchType, requests: usize, inputs: usize) {
+        self.record_batch_at(batch_type, requests, inputs, Instant::now());
+    }
+
+    fn record_batch_at(&self, batch_type: BatchType, requests: usize, inputs: usize, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        let counter = match batch_type {
+            BatchType::MaxBatchSize => &mut inner.batches_by_type.max_batch_size,
+            BatchType::MaxWaitTimeMs => &mut inner.batches_by_type.max_wait_time_ms,
+            BatchType::Deadline => &mut inner.batches_by_type.deadline,
+            BatchType::Drain => &mut inner.batches_by_type.drain,
+            BatchType::Flush => &mut inner.batches_by_type.flush,
+        };
+        *counter += 1;
+
+        if inner.recent_batch_sizes.len() == BATCH_SIZE_WINDOW {
+            inner.recent_batch_sizes.pop_front();
+        }
+        inner.recent_batch_sizes.push_back(requests);
+
+        inner.recent_dispatches.push_back(Dispatch {
+            dispatched_at: now,
+            batch_type,
+            requests,
+            inputs,
+        });
+        Self::prune(&mut inner, now);
+    }
+
+    /// Called by `BatchProcessor` for every request it receives
+    pub fn record_arrival(&self) {
+        self.record_arrival_at(Instant::now());
+    }
+
+    fn record_arrival_at(&self, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.recent_arrivals.back_mut() {
+            Some((second_started_at, requests))
+                if now.duration_since(*second_started_at) < Duration::from_secs(1) =>
+            {
+                *requests += 1
+            }
+            _ => inner.recent_arrivals.push_back((now, 1)),
+        }
+        Self::prune(&mut inner, now);
+    }
+
+    /// Requests dispatched per second within `THROUGHPUT_WINDOW`
+    pub fn requests_per_sec(&self) -> f64 {
+        let mut inner = self.inner.lock().unwrap();
+        Self::prune(&mut inner, Instant::now());
+        let requests: usize = inner
+            .recent_dispatches
+            .iter()
+            .map(|dispatch| dispatch.requests)
+            .sum();
+        requests as f64 / THROUGHPUT_WINDOW.as_secs_f64()
+    }
+
+    /// Arrivals & dispatches within `THROUGHPUT_WINDOW`, for `GET /stats/tuning`
+    pub fn tuning_window(&self) -> TuningWindow {
+        self.tuning_window_at(Instant::now())
+    }
+
+    fn tuning_window_at(&self, now: Instant) -> TuningWindow {
+        let mut inner = self.inner.lock().unwrap();
+        Self::prune(&mut inner, now);
+
+        let count = |batch_type: BatchType| {
+            inner
+                .recent_dispatches
+                .iter()
+                .filter(|dispatch| dispatch.batch_type == batch_type)
+                .count()
+        };
+        TuningWindow {
+            window_secs: THROUGHPUT_WINDOW.as_secs(),
+            arrivals: inner
+                .recent_arrivals
+                .iter()
+                .map(|(_, requests)| requests)
+                .sum(),
+            batches: inner.recent_dispatches.len(),
+            requests: inner
+                .recent_dispatches
+                .iter()
+                .map(|dispatch| dispatch.requests)
+                .sum(),
+            size_flushes: count(BatchType::MaxBatchSize),
+            wait_flushes: count(BatchType::MaxWaitTimeMs),
+        }
+    }
+
+    pub fn snapshot(&self, queue_state: &QueueState) -> Stats {
+        self.snapshot_at(queue_state, Instant::now())
+    }
+
+    fn snapshot_at(&self, queue_state: &QueueState, now: Instant) -> Stats {
+        let mut inner = self.inner.lock().unwrap();
+        Self::prune(&mut inner, now);
+
+        let by_type = &inner.batches_by_type;
+        let window_secs = THROUGHPUT_WINDOW.as_secs_f64();
+        let (requests, inputs) = inner
+            .recent_dispatches
+            .iter()
+            .fold((0, 0), |(requests, inputs), dispatch| {
+                (requests + dispatch.requests, inputs + dispatch.inputs)
+            });
+
+        Stats {
+            queue_depth: queue_state.depth(),
+            paused: queue_state.is_paused(),
+            oldest_pending_age_ms: queue_state
+                .oldest_age()
+                .map(|age| age.as_secs_f64() * 1000.0),
+            batches_total: by_type.max_batch_size
+                + by_type.max_wait_time_ms
+                + by_type.deadline
+                + by_type.drain
+                + by_type.flush,
+            batches_by_type: by_type.clone(),
+            batch_size: BatchSizeStats::from_sizes(&inner.recent_batch_sizes),
+            throughput: Throughput {
+                window_secs: THROUGHPUT_WINDOW.as_secs(),
+                batches_per_sec: inner.recent_dispatches.len() as f64 / window_secs,
+                requests_per_sec: requests as f64 / window_secs,
+                inputs_per_sec: inputs as f64 / window_secs,
+            },
+        }
+    }
+
+    fn prune(inner: &mut Inner, now: Instant) {
+        while let Some(dispatch) = inner.recent_dispatches.front()
+            && now.duration_since(dispatch.dispatched_at) > THROUGHPUT_WINDOW
+        {
+            inner.recent_dispatches.pop_front();
+        }
+        while let Some((second_started_at, _)) = inner.recent_arrivals.front()
+            && now.duration_since(*second_started_at) > THROUGHPUT_WINDOW
+        {
+            inner.recent_arrivals.pop_front();
+        }
+    }
+}
+
+impl BatchSizeStats {
+    fn from_sizes(sizes: &VecDeque<usize>) -> Self {
+        if sizes.is_empty() {
+            return Self::default();
+        }
+        let mut sorted: Vec<usize> = sizes.iter().copied().collect();
+        sorted.sort_unstable();
+
+        Self {
+            avg: sorted.iter().sum::<usize>() as f64 / sorted.len() as f64,
+            p50: percentile(&sorted, 0.50),
+            p95: percentile(&sorted, 0.95),
+            p99: percentile(&sorted, 0.99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Nearest-rank percentile (`p` in 0..=1) of a non-empty, ascending slice
+pub(crate) fn percentile<T: Copy>(sorted: &[T], p: f64) -> T {
+    let index = ((sorted.len() as f64 * p).ceil() as usize).saturating_sub(1);
+    sorted[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_without_batches() {
+        let stats = BatchStats::new().snapshot(&QueueState::new());
+        assert_eq!(stats.queue_depth, 0);
+        assert!(stats.oldest_pending_age_ms.is_none());
+        assert_eq!(stats.batches_total, 0);
+        assert_eq!(stats.batch_size, BatchSizeStats::default());
+        assert_eq!(stats.throughput.inputs_per_sec, 0.0);
+    }
+
+    #[test]
+    fn test_record_batch_counts_types_sizes_and_throughput() {
+        let batch_stats = BatchStats::new();
+        let now = Instant::now();
+        for requests in 1..=10 {
+            batch_stats.record_batch_at(BatchType::MaxWaitTimeMs, requests, requests * 2, now);
+        }
+        batch_stats.record_batch_at(BatchType::MaxBatchSize, 20, 60, now);
+
+        let queue_state = QueueState::new();
+        queue_state.update(4, Some(now));
+        let stats = batch_stats.snapshot_at(&queue_state, now);
+
+        assert_eq!(stats.queue_depth, 4);
+        assert_eq!(stats.batches_total, 11);
+        assert_eq!(stats.batches_by_type.max_wait_time_ms, 10);
+        assert_eq!(stats.batches_by_type.max_batch_size, 1);
+        assert_eq!(stats.batch_size.p50, 6);
+        assert_eq!(stats.batch_size.max, 20);
+        assert_eq!(stats.batch_size.avg, 75.0 / 11.0);
+        // (1 + ... + 10) * 2 + 60 inputs over 60s
+        assert_eq!(stats.throughput.inputs_per_sec, 170.0 / 60.0);
+    }
+
+    #[test]
+    fn test_tuning_window_counts_arrivals_and_flush_triggers() {
+        let batch_stats = BatchStats::new();
+        let now = Instant::now();
+        for millis in [0, 10, 20, 1500] {
+            batch_stats.record_arrival_at(now + Duration::from_millis(millis));
+        }
+        batch_stats.record_batch_at(BatchType::MaxBatchSize, 3, 3, now);
+        batch_stats.record_batch_at(BatchType::MaxWaitTimeMs, 1, 1, now);
+        assert_eq!(batch_stats.inner.lock().unwrap().recent_arrivals.len(), 2);
+
+        let window = batch_stats.tuning_window_at(now + Duration::from_secs(2));
+        assert_eq!(
+            window,
+            TuningWindow {
+                window_secs: 60,
+                arrivals: 4,
+                batches: 2,
+                requests: 4,
+                size_flushes: 1,
+                wait_flushes: 1,
+            }
+        );
+
+        let later = now + THROUGHPUT_WINDOW + Duration::from_secs(2);
+        assert_eq!(batch_stats.tuning_window_at(later).arrivals, 0);
+    }
+
+    #[test]
+    fn test_throughput_excludes_batches_outside_window() {
+        let batch_stats = BatchStats::new();
+        let now = Instant::now();
+        batch_stats.record_batch_at(BatchType::MaxWaitTimeMs, 5, 5, now);
+
+        let later = now + THROUGHPUT_WINDOW + Duration::from_secs(1);
+        let stats = batch_stats.snapshot_at(&QueueState::new(), later);
+        assert_eq!(stats.throughput.batches_per_sec, 0.0);
+        // totals & sizes aren't windowed by time
+        assert_eq!(stats.batches_total, 1);
+        assert_eq!(stats.batch_size.max, 5);
+    }
+}
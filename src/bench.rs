@@ -0,0 +1,293 @@
+use crate::batch_stats::percentile;
+use crate::build_rocket;
+use crate::config::AppConfig;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// `bench` subcommand, e.g. `auto-batching-proxy --mock-upstream true bench --concurrency 1,10,100`
+#[derive(clap::Args, Debug, Clone, PartialEq)]
+pub struct BenchArgs {
+    /// `/embed` URL of a running proxy (e.g. `http://127.0.0.1:3000/embed`),
+    /// otherwise an in-process instance is started from the server options
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// Requests per round
+    #[arg(long, default_value_t = 200)]
+    pub requests: usize,
+
+    /// Concurrent clients, one round per value
+    #[arg(long, value_delimiter = ',', default_value = "1,10,50")]
+    pub concurrency: Vec<usize>,
+
+    /// Inputs per request, one round per value (and concurrency)
+    #[arg(long, value_delimiter = ',', default_value = "1,8")]
+    pub inputs: Vec<usize>,
+
+    /// Sent as `Authorization: Bearer <key>`, defaults to the first of `--api-keys` in-process
+    #[arg(long)]
+    pub api_key: Option<String>,
+}
+
+enum Target {
+    Remote {
+        client: reqwest::Client,
+        url: String,
+    },
+    InProcess {
+        client: Box<Client>,
+        path: String,
+    },
+}
+
+impl Target {
+    async fn in_process(mut config: AppConfig) -> Result<Self, String> {
+        config.quiet_mode = true;
+        // batch sizes are read from the responses
+        config.include_batch_info = true;
+        let path = format!("{}/embed", config.route_prefix.trim_end_matches('/'));
+        let client = Client::untracked(build_rocket(config).await)
+            .await
+            .map_err(|e| format!("Failed to start in-process proxy: {e}"))?;
+        Ok(Self::InProcess {
+            client: Box::new(client),
+            path,
+        })
+    }
+
+    /// Batch size of the request's batch, as long as the proxy reports `batch_info`
+    async fn embed(&self, body: String, api_key: Option<&str>) -> Result<Option<usize>, String> {
+        let json: Value = match self {
+            Self::Remote { client, url } => {
+                let mut request = client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body);
+                if let Some(api_key) = api_key {
+                    request = request.bearer_auth(api_key);
+                }
+                let response = request.send().await.map_err(|e| e.to_string())?;
+                if !response.status().is_success() {
+                    return Err(format!("HTTP {}", response.status()));
+                }
+                response.json().await.map_err(|e| e.to_string())?
+            }
+            Self::InProcess { client, path } => {
+                let mut request = client.post(path.as_str()).header(ContentType::JSON);
+                if let Some(api_key) = api_key {
+                    request =
+                        request.header(Header::new("Authorization", format!("Bearer {api_key}")));
+                }
+                let response = request.body(body).dispatch().await;
+                if response.status() != Status::Ok {
+                    return Err(format!("HTTP {}", response.status()));
+                }
+                response
+                    .into_json()
+                    .await
+                    .ok_or_else(|| "Invalid JSON response".to_string())?
+            }
+        };
+        Ok(json["batch_info"]["batch_size"]
+            .as_u64()
+            .map(|batch_size| batch_size as usize))
+    }
+}
+
+#[derive(Debug, Default)]
+struct RoundResult {
+    /// Successful requests only
+    latencies: Vec<Duration>,
+    batch_sizes: Vec<usize>,
+    errors: usize,
+    last_error: Option<String>,
+    elapsed: Duration,
+}
+
+impl RoundResult {
+    fn format_row(&mut self, concurrency: usize, inputs: usize) -> String {
+        self.latencies.sort_unstable();
+        self.batch_sizes.sort_unstable();
+        let requests_per_sec = self.latencies.len() as f64 / self.elapsed.as_secs_f64();
+        let latency = |p: f64| match self.latencies.is_empty() {
+            true => "-".to_string(),
+            false => format!(
+                "{:.1}",
+                percentile(&self.latencies, p).as_secs_f64() * 1000.0
+            ),
+        };
+        let batch_sizes = match self.batch_sizes.is_empty() {
+            true => "-".to_string(),
+            false => format!(
+                "{:.1} / {} / {} / {}",
+                self.batch_sizes.iter().sum::<usize>() as f64 / self.batch_sizes.len() as f64,
+                percentile(&self.batch_sizes, 0.50),
+                percentile(&self.batch_sizes, 0.95),
+                percentile(&self.batch_sizes, 1.0),
+            ),
+        };
+
+        format!(
+            "{concurrency:>11} {inputs:>6} {requests_per_sec:>9.1} {:>6} {:>8} {:>8} {:>8} {:>8}   {batch_sizes}",
+            self.errors,
+            latency(0.50),
+            latency(0.95),
+            latency(0.99),
+            latency(1.0),
+        )
+    }
+}
+
+/// Fires `requests` requests with `inputs` inputs each, from `concurrency` concurrent clients
+async fn run_round(
+    target: Arc<Target>,
+    api_key: Option<Arc<str>>,
+    requests: usize,
+    concurrency: usize,
+    inputs: usize,
+) -> RoundResult {
+    let next_request = Arc::new(AtomicUsize::new(0));
+    let started_at = Instant::now();
+    let mut clients = JoinSet::new();
+    for _ in 0..concurrency.min(requests) {
+        let (target, api_key, next_request) =
+            (target.clone(), api_key.clone(), next_request.clone());
+        clients.spawn(async move {
+            let mut samples = Vec::new();
+            loop {
+                let request = next_request.fetch_add(1, Ordering::Relaxed);
+                if request >= requests {
+                    break samples;
+                }
+                // distinct inputs, so nothing can be served from a cache
+                let body = json!({
+                    "inputs": (0..inputs)
+                        .map(|input| format!("benchmark request {request} input {input}"))
+                        .collect::<Vec<_>>()
+                })
+                .to_string();
+                let sent_at = Instant::now();
+                let result = target.embed(body, api_key.as_deref()).await;
+                samples.push((sent_at.elapsed(), result));
+            }
+        });
+    }
+
+    let mut round = RoundResult::default();
+    for samples in clients.join_all().await {
+        for (latency, result) in samples {
+            match result {
+                Ok(batch_size) => {
+                    round.latencies.push(latency);
+                    round.batch_sizes.extend(batch_size);
+                }
+                Err(e) => {
+                    round.errors += 1;
+                    round.last_error = Some(e);
+                }
+            }
+        }
+    }
+    round.elapsed = started_at.elapsed();
+    round
+}
+
+/// Runs a round per concurrency & input count combination, prints latency percentiles
+/// and achieved batch sizes (requests per batch) of each
+pub async fn run(config: AppConfig, args: BenchArgs) -> Result<(), String> {
+    if args.requests == 0 {
+        return Err("requests must be > 0".to_string());
+    }
+    if args.concurrency.contains(&0) || args.inputs.contains(&0) {
+        return Err("concurrency & inputs values must be > 0".to_string());
+    }
+
+    let (target, api_key, description) = match &args.target {
+        Some(url) => (
+            Target::Remote {
+                client: reqwest::Client::new(),
+                url: url.clone(),
+            },
+            args.api_key.clone(),
+            url.clone(),
+        ),
+        None => (
+            Target::in_process(config.clone()).await?,
+            args.api_key.clone().or(config.api_keys.first().cloned()),
+            format!(
+                "in-process proxy (max_batch_size {}, max_wait_time_ms {}, inference_urls {:?})",
+                config.max_batch_size, config.max_wait_time_ms, config.inference_urls
+            ),
+        ),
+    };
+    let target = Arc::new(target);
+    let api_key: Option<Arc<str>> = api_key.map(Arc::from);
+
+    println!(
+        "Benchmarking {description}, {} requests per round\n",
+        args.requests
+    );
+    println!(
+        "concurrency inputs     req/s errors   p50 ms   p95 ms   p99 ms   max ms   batch size avg / p50 / p95 / max"
+    );
+    let mut last_error = None;
+    for &concurrency in &args.concurrency {
+        for &inputs in &args.inputs {
+            let mut round = run_round(
+                target.clone(),
+                api_key.clone(),
+                args.requests,
+                concurrency,
+                inputs,
+            )
+            .await;
+            println!("{}", round.format_row(concurrency, inputs));
+            last_error = round.last_error.or(last_error);
+        }
+    }
+
+    if let Some(e) = last_error {
+        println!("\nLast error: {e}");
+    }
+    if args.target.is_some() {
+        println!(
+            "Batch sizes are only shown when the target runs with `--include-batch-info true`"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_round_against_in_process_mock_upstream() {
+        let config = AppConfig {
+            inference_urls: vec!["mock://dims=4".to_string()],
+            max_batch_size: 5,
+            max_wait_time_ms: 10,
+            ..AppConfig::default()
+        };
+        let target = Arc::new(Target::in_process(config).await.unwrap());
+
+        let mut round = run_round(target, None, 20, 5, 2).await;
+        assert_eq!(round.errors, 0);
+        assert_eq!(round.latencies.len(), 20);
+        assert_eq!(round.batch_sizes.len(), 20);
+        assert!(
+            round
+                .batch_sizes
+                .iter()
+                .all(|&size| (1..=5).contains(&size))
+        );
+
+        let row = round.format_row(5, 2);
+        assert!(row.starts_with("          5      2"), "{row}");
+    }
+}
@@ -0,0 +1,199 @@
+use crate::config::InputOverflow;
+use crate::rate_limiter::RateLimited;
+use crate::request_context::RequestContext;
+use crate::request_handler::RequestHandler;
+use crate::types::{EmbedRequest, Priority};
+use rocket::futures::stream::Stream;
+use rocket::response::status::Custom;
+use rocket::response::stream::stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::task::JoinHandle;
+
+/// Name of the Rocket data limit (`config.max_bulk_body_mb`) of `POST /embed/bulk` bodies
+pub const BULK_LIMIT: &str = "bulk";
+/// Chunks queued at once per bulk request: enough to fill batches,
+/// while memory stays bounded for whole corpora
+const MAX_IN_FLIGHT_CHUNKS: usize = 8;
+
+/// One line of a `POST /embed/bulk` body
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BulkRecord {
+    /// Any JSON value, echoed back as-is
+    pub id: Value,
+    pub input: String,
+}
+
+/// One line of the `POST /embed/bulk` response
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BulkResult {
+    /// `null` for lines that couldn't be parsed
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Same codes as `ErrorResponse::code`, e.g. `rate_limited` for the last line of a throttled body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+impl BulkResult {
+    pub fn failed(id: Value, error: String) -> Self {
+        Self {
+            id,
+            embedding: None,
+            error: Some(error),
+            code: None,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
+
+type ChunkResult = Result<Vec<Vec<f32>>, String>;
+
+/// Reads JSONL records from `body`, queues them in chunks of `config.max_inference_inputs`
+/// (like regular `/embed` requests) and yields results in input order, as chunks complete.
+/// Invalid lines & failed chunks yield `error` results (the former right away),
+/// the rest of the body is still processed. Each chunk's inputs are charged against the caller's
+/// `rate_limit_inputs_per_sec` (see `embed_chunk`), once that's exhausted, the rest of the body
+/// isn't read & the stream ends with a `rate_limited` (429) line
+pub fn embed_lines<'r>(
+    handler: Arc<RequestHandler>,
+    context: RequestContext,
+    priority: Priority,
+    body: impl AsyncBufRead + Unpin + Send + 'r,
+) -> impl Stream<Item = BulkResult> + Send + 'r {
+    stream! {
+        let mut lines = body.lines();
+        let mut in_flight: VecDeque<(Vec<Value>, JoinHandle<ChunkResult>)> = VecDeque::new();
+        let mut chunk: Vec<BulkRecord> = Vec::new();
+        let mut line_number = 0;
+        let mut finished = false;
+        let mut rate_limited = None;
+
+        while !finished {
+            match lines.next_line().await {
+                Ok(Some(line)) if line.trim().is_empty() => line_number += 1,
+                Ok(Some(line)) => {
+                    line_number += 1;
+                    match serde_json::from_str::<BulkRecord>(&line) {
+                        Ok(record) => match handler.config.max_input_chars {
+                            Some(max_input_chars)
+                                if handler.config.input_overflow == InputOverflow::Reject
+                                    && record.input.chars().nth(max_input_chars).is_some() =>
+                            {
+                                yield BulkResult::failed(
+                                    record.id,
+                                    format!("`input` exceeds {max_input_chars} characters"),
+                                );
+                            }
+                            _ => chunk.push(record),
+                        },
+                        Err(e) => {
+                            yield BulkResult::failed(Value::Null, format!("Line {line_number}: {e}"));
+                        }
+                    }
+                }
+                Ok(None) => finished = true,
+                Err(e) => {
+                    yield BulkResult::failed(Value::Null, format!("Failed to read body: {e}"));
+                    finished = true;
+                }
+            }
+
+            if chunk.len() >= handler.config.max_inference_inputs
+                || (finished && !chunk.is_empty())
+            {
+                let (ids, inputs) = std::mem::take(&mut chunk)
+                    .into_iter()
+                    .map(|record| (record.id, record.input))
+                    .unzip();
+                match embed_chunk(handler.clone(), context.clone(), priority, inputs) {
+                    Ok(embedding) => in_flight.push_back((ids, tokio::spawn(embedding))),
+                    Err(limited) => {
+                        rate_limited = Some(limited);
+                        finished = true;
+                    }
+                }
+            }
+
+            while in_flight.len() >= MAX_IN_FLIGHT_CHUNKS || (finished && !in_flight.is_empty()) {
+                let Some((ids, task)) = in_flight.pop_front() else {
+                    break;
+                };
+                let result = task
+                    .await
+                    .unwrap_or_else(|e| Err(format!("Chunk task failed: {e}")));
+                match result {
+                    Ok(embeddings) => {
+                        for (id, embedding) in ids.into_iter().zip(embeddings) {
+                            yield BulkResult {
+                                id,
+                                embedding: Some(embedding),
+                                error: None,
+                                code: None,
+                            };
+                        }
+                    }
+                    Err(error) => {
+                        for id in ids {
+                            yield BulkResult::failed(id, error.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(limited) = rate_limited {
+            let Custom(_, error) = limited.into();
+            yield BulkResult {
+                code: error.code.map(String::from),
+                ..BulkResult::failed(Value::Null, error.into_inner().error)
+            };
+        }
+    }
+}
+
+/// The chunk's inputs are charged against the caller's rate right away (in body order),
+/// its quota once the returned future runs, i.e., a bulk request runs until the quota is exhausted
+fn embed_chunk(
+    handler: Arc<RequestHandler>,
+    context: RequestContext,
+    priority: Priority,
+    inputs: Vec<String>,
+) -> Result<impl Future<Output = ChunkResult> + Send + 'static, RateLimited> {
+    handler.check_input_rate_limit(&context, inputs.len())?;
+    Ok(async move {
+        let input_count = inputs.len();
+        handler
+            .charge_quota(&context, input_count)
+            .map_err(|e| e.message())?;
+
+        let mut request = EmbedRequest {
+            inputs,
+            priority,
+            ..Default::default()
+        };
+        // over-long inputs only got this far with `InputOverflow::Truncate`
+        if let Some(max_input_chars) = handler.config.max_input_chars {
+            request.truncate_inputs(max_input_chars);
+        }
+        handler
+            .process_request(request, context.clone())
+            .await
+            .map(|response| response.embeddings)
+            .map_err(|Custom(_, error)| {
+                handler.refund_quota(&context, input_count);
+                error.into_inner().error
+            })
+    })
+}
@@ -0,0 +1,130 @@
+use crate::config::AppConfig;
+use crate::inference_client::{InferenceBackend, InferenceError, InferenceServiceClient};
+use crate::metrics::{METRICS, TargetMetrics};
+use crate::types::{BatchRequest, BatchResponse};
+use log::warn;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// Routes `config.canary_percent` of the batches to `config.canary_inference_url`,
+/// the rest to the primary backend. A failed canary batch is retried on the primary,
+/// so a broken rollout shows up in the `proxy_target_*` metrics rather than as client errors
+pub struct CanaryBackend {
+    primary: Arc<dyn InferenceBackend>,
+    canary: InferenceServiceClient,
+    canary_percent: f64,
+}
+
+impl CanaryBackend {
+    /// `None` unless `config.canary_inference_url` is set
+    pub fn new(
+        config: &AppConfig,
+        primary: Arc<dyn InferenceBackend>,
+    ) -> Result<Option<Self>, InferenceError> {
+        let Some(canary_inference_url) = &config.canary_inference_url else {
+            return Ok(None);
+        };
+        // the primary is the fallback, no point in retrying or hedging the canary
+        let canary = InferenceServiceClient::new(&AppConfig {
+            inference_urls: vec![canary_inference_url.clone()],
+            inference_max_retries: 0,
+            hedge_requests: false,
+            ..config.clone()
+        })?;
+
+        Ok(Some(Self {
+            primary,
+            canary,
+            canary_percent: config.canary_percent,
+        }))
+    }
+
+    fn pick_canary(&self) -> bool {
+        // random enough for traffic splitting, without pulling in a RNG dependency
+        let random = RandomState::new().build_hasher().finish() % 10_000;
+        (random as f64) < self.canary_percent * 100.0
+    }
+
+    async fn call(
+        target: &TargetMetrics,
+        backend: &dyn InferenceBackend,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        target.batches.fetch_add(1, Ordering::Relaxed);
+        let start_time = Instant::now();
+        let result = backend.embed(request, timeout).await;
+        match &result {
+            Ok(_) => target
+                .latency_seconds
+                .observe(start_time.elapsed().as_secs_f64()),
+            Err(_) => {
+                target.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+}
+
+#[rocket::async_trait]
+impl InferenceBackend for CanaryBackend {
+    async fn embed(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        if !self.pick_canary() {
+            return Self::call(&METRICS.canary_primary, &*self.primary, request, timeout).await;
+        }
+
+        let start_time = Instant::now();
+        match Self::call(&METRICS.canary, &self.canary, request.clone(), timeout).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                warn!("Canary batch failed, retrying on primary: {}", e.message());
+                let remaining = timeout.map(|timeout| timeout.saturating_sub(start_time.elapsed()));
+                Self::call(&METRICS.canary_primary, &*self.primary, request, remaining).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canary_backend(canary_percent: f64) -> CanaryBackend {
+        let config = AppConfig {
+            inference_urls: vec!["mock://dims=4".to_string()],
+            canary_inference_url: Some("mock://dims=8".to_string()),
+            canary_percent,
+            ..AppConfig::default()
+        };
+        let primary = Arc::new(InferenceServiceClient::new(&config).unwrap());
+        CanaryBackend::new(&config, primary).unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_percentage() {
+        let request = BatchRequest {
+            inputs: vec!["Hello".into()],
+        };
+        let response = canary_backend(0.0)
+            .embed(request.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(response[0].len(), 4);
+        let response = canary_backend(100.0).embed(request, None).await.unwrap();
+        assert_eq!(response[0].len(), 8);
+    }
+
+    #[test]
+    fn test_pick_canary_share() {
+        let canary_backend = canary_backend(25.0);
+        let picked = (0..10_000).filter(|_| canary_backend.pick_canary()).count();
+        assert!((2_000..3_000).contains(&picked), "{picked}");
+    }
+}
@@ -0,0 +1,233 @@
+//! In-process sentence-transformers (BERT family, e.g. `all-MiniLM-L6-v2`) embeddings on CPU,
+//! for `candle:///path/to/model` inference URLs. The model directory needs `config.json`,
+//! `tokenizer.json` & `model.safetensors`, as found in the model's HuggingFace repository
+use crate::inference_client::{CANDLE_SCHEME, InferenceError};
+use crate::types::{BatchRequest, BatchResponse};
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// Loaded model & tokenizer, shared by all batches
+pub struct CandleModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    pad_token_id: u32,
+    /// Model directory name
+    model_id: String,
+    dims: usize,
+    max_input_length: usize,
+}
+
+impl CandleModel {
+    /// `url` is `candle://` followed by the model directory
+    pub fn load(url: &str) -> Result<Self, InferenceError> {
+        let dir = Path::new(url.strip_prefix(CANDLE_SCHEME).unwrap_or(url));
+        let invalid = |e: String| InferenceError::InvalidConfig(format!("{}: {e}", dir.display()));
+
+        let config = std::fs::read_to_string(dir.join("config.json"))
+            .map_err(|e| format!("config.json: {e}"))
+            .and_then(|config| {
+                serde_json::from_str::<Config>(&config).map_err(|e| format!("config.json: {e}"))
+            })
+            .map_err(invalid)?;
+        let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| invalid(format!("tokenizer.json: {e}")))?;
+        // inputs beyond the model's positions are truncated, like TEI with `truncate: true`
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: config.max_position_embeddings,
+                ..TruncationParams::default()
+            }))
+            .map_err(|e| invalid(format!("tokenizer.json: {e}")))?;
+        tokenizer.with_padding(None);
+
+        let device = Device::Cpu;
+        let weights = candle_core::safetensors::load(dir.join("model.safetensors"), &device)
+            .map_err(|e| invalid(format!("model.safetensors: {e}")))?;
+        let model = BertModel::load(VarBuilder::from_tensors(weights, DTYPE, &device), &config)
+            .map_err(|e| invalid(format!("model.safetensors: {e}")))?;
+
+        Ok(Self {
+            model,
+            tokenizer,
+            pad_token_id: config.pad_token_id as u32,
+            model_id: dir.file_name().map_or_else(
+                || dir.display().to_string(),
+                |name| name.to_string_lossy().to_string(),
+            ),
+            dims: config.hidden_size,
+            max_input_length: config.max_position_embeddings,
+        })
+    }
+
+    /// Runs on the blocking thread pool, a forward pass keeps a CPU core busy for a while.
+    /// Calls aren't cancelled on timeout, the batch is simply computed to the end
+    pub async fn embed(
+        self: Arc<Self>,
+        request: &BatchRequest,
+    ) -> Result<BatchResponse, InferenceError> {
+        let inputs = request.inputs.clone();
+        tokio::task::spawn_blocking(move || self.embed_blocking(&inputs))
+            .await
+            .map_err(|e| InferenceError::BackendError(e.to_string()))?
+            .map_err(InferenceError::BackendError)
+    }
+
+    /// Mean pooling over the (non-padding) token embeddings, L2 normalized, like TEI defaults
+    fn embed_blocking(&self, inputs: &[Arc<str>]) -> Result<BatchResponse, String> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let encodings = inputs
+            .iter()
+            .map(|input| self.tokenizer.encode(&**input, true))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Tokenization failed: {e}"))?;
+        let max_length = encodings
+            .iter()
+            .map(|encoding| encoding.get_ids().len())
+            .max()
+            .unwrap_or_default();
+
+        let device = &self.model.device;
+        let mut input_ids = Vec::with_capacity(encodings.len());
+        let mut attention_mask = Vec::with_capacity(encodings.len());
+        for encoding in &encodings {
+            let mut ids = encoding.get_ids().to_vec();
+            let mut mask = encoding.get_attention_mask().to_vec();
+            ids.resize(max_length, self.pad_token_id);
+            mask.resize(max_length, 0);
+            input_ids.push(Tensor::new(ids, device).map_err(|e| e.to_string())?);
+            attention_mask.push(Tensor::new(mask, device).map_err(|e| e.to_string())?);
+        }
+
+        let embeddings = (|| {
+            let input_ids = Tensor::stack(&input_ids, 0)?;
+            let attention_mask = Tensor::stack(&attention_mask, 0)?;
+            let token_type_ids = input_ids.zeros_like()?;
+            // (batch, tokens, hidden)
+            let hidden = self
+                .model
+                .forward(&input_ids, &token_type_ids, Some(&attention_mask))?;
+
+            let mask = attention_mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+            let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+            let pooled = summed.broadcast_div(&mask.sum(1)?)?;
+            let norm = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+            pooled.broadcast_div(&norm)?.to_vec2::<f32>()
+        })()
+        .map_err(|e: candle_core::Error| format!("Inference failed: {e}"))?;
+        Ok(embeddings)
+    }
+
+    /// Mimics TEI `/info`
+    pub fn info(&self) -> serde_json::Value {
+        json!({
+            "model_id": self.model_id,
+            "model_type": "candle",
+            "dims": self.dims,
+            "max_input_length": self.max_input_length,
+        })
+    }
+}
+
+impl std::fmt::Debug for CandleModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CandleModel")
+            .field("model_id", &self.model_id)
+            .field("dims", &self.dims)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_fails_without_model_files() {
+        let dir = std::env::temp_dir().join("auto-batching-proxy-candle-missing");
+        let _ = std::fs::create_dir_all(&dir);
+
+        let Err(InferenceError::InvalidConfig(error)) =
+            CandleModel::load(&format!("{CANDLE_SCHEME}{}", dir.display()))
+        else {
+            panic!("load must fail");
+        };
+        assert!(error.contains("config.json"), "{error}");
+    }
+
+    /// Tiny randomly initialized BERT with a word level tokenizer, exercises the whole pipeline
+    fn write_tiny_model(dir: &Path) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join("config.json"),
+            json!({
+                "vocab_size": 5, "hidden_size": 8, "num_hidden_layers": 1, "num_attention_heads": 2,
+                "intermediate_size": 16, "hidden_act": "gelu", "hidden_dropout_prob": 0.0,
+                "max_position_embeddings": 16, "type_vocab_size": 2, "initializer_range": 0.02,
+                "layer_norm_eps": 1e-12, "pad_token_id": 0, "classifier_dropout": null,
+                "model_type": "bert"
+            })
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("tokenizer.json"),
+            json!({
+                "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+                "normalizer": null, "pre_tokenizer": {"type": "Whitespace"},
+                "post_processor": null, "decoder": null,
+                "model": {
+                    "type": "WordLevel",
+                    "vocab": {"[PAD]": 0, "[UNK]": 1, "hello": 2, "big": 3, "world": 4},
+                    "unk_token": "[UNK]"
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let config: Config =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("config.json")).unwrap())
+                .unwrap();
+        let var_map = candle_nn::VarMap::new();
+        BertModel::load(
+            VarBuilder::from_varmap(&var_map, DTYPE, &Device::Cpu),
+            &config,
+        )
+        .unwrap();
+        var_map.save(dir.join("model.safetensors")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_embed_with_tiny_model() {
+        let dir = std::env::temp_dir().join("auto-batching-proxy-candle-tiny");
+        write_tiny_model(&dir);
+        let model =
+            Arc::new(CandleModel::load(&format!("{CANDLE_SCHEME}{}", dir.display())).unwrap());
+        assert_eq!(model.info()["dims"], 8);
+
+        let request = BatchRequest {
+            inputs: vec!["hello world".into(), "hello big big world".into()],
+        };
+        let embeddings = model.clone().embed(&request).await.unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert!(embeddings.iter().all(|embedding| embedding.len() == 8));
+        let norm: f32 = embeddings[1].iter().map(|value| value * value).sum();
+        assert!((norm - 1.0).abs() < 1e-4, "{norm}");
+
+        // padding (to the longest input of the batch) doesn't change the embedding
+        let request = BatchRequest {
+            inputs: vec!["hello world".into()],
+        };
+        let alone = model.embed(&request).await.unwrap();
+        for (padded, alone) in embeddings[0].iter().zip(&alone[0]) {
+            assert!((padded - alone).abs() < 1e-4, "{padded} vs {alone}");
+        }
+    }
+}
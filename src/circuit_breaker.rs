@@ -0,0 +1,173 @@
+use crate::config::AppConfig;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of most recent upstream calls the error rate is computed over
+const OUTCOME_WINDOW: usize = 20;
+/// Don't trip on a couple of early failures
+const MIN_CALLS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    Closed,
+    /// Calls fail fast until `cooldown` passes
+    Open {
+        since: Instant,
+    },
+    /// Single trial call decides whether to close or re-open
+    HalfOpen {
+        trial_in_flight: bool,
+    },
+}
+
+#[derive(Debug)]
+struct State {
+    circuit: CircuitState,
+    /// `true` for failed calls
+    outcomes: VecDeque<bool>,
+}
+
+/// Stops calling the inference service once its error rate reaches `config.circuit_breaker_error_rate`,
+/// so batches fail fast (503) instead of each one waiting for connect errors or timeouts
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    error_rate: f64,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// `None` when `config.circuit_breaker_error_rate` isn't set
+    pub fn new(config: &AppConfig) -> Option<Self> {
+        config.circuit_breaker_error_rate.map(|error_rate| Self {
+            error_rate,
+            cooldown: Duration::from_secs(config.circuit_breaker_cooldown_secs),
+            state: Mutex::new(State {
+                circuit: CircuitState::Closed,
+                outcomes: VecDeque::with_capacity(OUTCOME_WINDOW),
+            }),
+        })
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state.lock().unwrap().circuit
+    }
+
+    /// Whether the next call may go upstream, after `cooldown` exactly one trial call is let through
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.circuit {
+            CircuitState::Closed => true,
+            CircuitState::Open { since } if since.elapsed() >= self.cooldown => {
+                info!("Circuit breaker half-open, sending trial call");
+                state.circuit = CircuitState::HalfOpen {
+                    trial_in_flight: true,
+                };
+                true
+            }
+            CircuitState::Open { .. } => false,
+            CircuitState::HalfOpen { trial_in_flight } => {
+                if trial_in_flight {
+                    return false;
+                }
+                state.circuit = CircuitState::HalfOpen {
+                    trial_in_flight: true,
+                };
+                true
+            }
+        }
+    }
+
+    /// Called once per upstream call let through by `allow`
+    pub fn record(&self, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        match state.circuit {
+            CircuitState::HalfOpen { .. } => {
+                if failed {
+                    warn!("Circuit breaker trial call failed, opening again");
+                    state.circuit = CircuitState::Open {
+                        since: Instant::now(),
+                    };
+                } else {
+                    info!("Circuit breaker closed");
+                    state.circuit = CircuitState::Closed;
+                    state.outcomes.clear();
+                }
+            }
+            CircuitState::Closed => {
+                if state.outcomes.len() == OUTCOME_WINDOW {
+                    state.outcomes.pop_front();
+                }
+                state.outcomes.push_back(failed);
+
+                let failures = state.outcomes.iter().filter(|failed| **failed).count();
+                let error_rate = failures as f64 / state.outcomes.len() as f64;
+                if state.outcomes.len() >= MIN_CALLS && error_rate >= self.error_rate {
+                    warn!(
+                        "Circuit breaker opened, error rate {error_rate:.2} over last {} calls",
+                        state.outcomes.len()
+                    );
+                    state.circuit = CircuitState::Open {
+                        since: Instant::now(),
+                    };
+                    state.outcomes.clear();
+                }
+            }
+            // in-flight calls started before the circuit opened
+            CircuitState::Open { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_circuit_breaker(cooldown_secs: u64) -> CircuitBreaker {
+        let config = AppConfig {
+            circuit_breaker_error_rate: Some(0.5),
+            circuit_breaker_cooldown_secs: cooldown_secs,
+            ..AppConfig::default()
+        };
+        CircuitBreaker::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_disabled_without_error_rate() {
+        assert!(CircuitBreaker::new(&AppConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_opens_once_error_rate_reached() {
+        let circuit_breaker = build_circuit_breaker(60);
+        for i in 0..MIN_CALLS - 1 {
+            assert!(circuit_breaker.allow());
+            circuit_breaker.record(i % 2 == 0);
+        }
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+
+        circuit_breaker.record(true);
+        assert!(matches!(circuit_breaker.state(), CircuitState::Open { .. }));
+        assert!(!circuit_breaker.allow());
+    }
+
+    #[test]
+    fn test_half_open_trial_decides_next_state() {
+        let circuit_breaker = build_circuit_breaker(0);
+        for _ in 0..MIN_CALLS {
+            circuit_breaker.record(true);
+        }
+
+        // zero cooldown, trial call is allowed right away, but only one
+        assert!(circuit_breaker.allow());
+        assert!(!circuit_breaker.allow());
+        circuit_breaker.record(true);
+        assert!(matches!(circuit_breaker.state(), CircuitState::Open { .. }));
+
+        assert!(circuit_breaker.allow());
+        circuit_breaker.record(false);
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::batch_stats::percentile;
+use crate::config::AppConfig;
+use crate::inference_client::{InferenceBackend, InferenceError, InferenceServiceClient};
+use crate::types::{BatchRequest, BatchResponse};
+use log::debug;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Semaphore, oneshot};
+
+/// Number of most recent input comparisons the similarity percentiles are computed over
+const SIMILARITY_WINDOW: usize = 10_000;
+/// Comparison calls in flight at once, batches beyond that aren't compared
+const MAX_IN_FLIGHT_COMPARISONS: usize = 64;
+
+/// `GET /admin/drift` response
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DriftReport {
+    pub compare_inference_url: String,
+    pub batches_compared: u64,
+    pub inputs_compared: u64,
+    /// Not compared, too many comparison calls were in flight
+    pub batches_skipped: u64,
+    /// Comparison backend calls that failed
+    pub compare_errors: u64,
+    /// Batches where both backends returned a different number of embeddings
+    pub count_mismatches: u64,
+    /// Inputs whose embeddings differ in dimension, not included in `cosine_similarity`
+    pub dimension_mismatches: u64,
+    /// Over the last `SIMILARITY_WINDOW` compared inputs, `None` until there are any
+    pub cosine_similarity: Option<SimilarityStats>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimilarityStats {
+    pub mean: f32,
+    pub min: f32,
+    /// Low percentiles, as drift shows up in the worst matching inputs
+    pub p1: f32,
+    pub p5: f32,
+    pub p50: f32,
+}
+
+/// Comparison results, shared between `ComparisonBackend` & `RequestHandler` (which reports them)
+#[derive(Debug)]
+pub struct DriftTracker {
+    compare_inference_url: String,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    batches_compared: u64,
+    inputs_compared: u64,
+    batches_skipped: u64,
+    compare_errors: u64,
+    count_mismatches: u64,
+    dimension_mismatches: u64,
+    /// Most recent last
+    recent_similarities: VecDeque<f32>,
+}
+
+impl DriftTracker {
+    fn new(compare_inference_url: String) -> Self {
+        Self {
+            compare_inference_url,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    fn record(&self, primary: &BatchResponse, compared: Result<BatchResponse, InferenceError>) {
+        let mut inner = self.inner.lock().unwrap();
+        let compared = match compared {
+            Ok(compared) => compared,
+            Err(e) => {
+                debug!("Comparison inference call failed: {}", e.message());
+                inner.compare_errors += 1;
+                return;
+            }
+        };
+        inner.batches_compared += 1;
+        if primary.len() != compared.len() {
+            inner.count_mismatches += 1;
+            return;
+        }
+
+        for (primary, compared) in primary.iter().zip(&compared) {
+            inner.inputs_compared += 1;
+            if primary.len() != compared.len() {
+                inner.dimension_mismatches += 1;
+                continue;
+            }
+            if inner.recent_similarities.len() == SIMILARITY_WINDOW {
+                inner.recent_similarities.pop_front();
+            }
+            inner
+                .recent_similarities
+                .push_back(cosine_similarity(primary, compared));
+        }
+    }
+
+    fn record_skipped(&self) {
+        self.inner.lock().unwrap().batches_skipped += 1;
+    }
+
+    pub fn report(&self) -> DriftReport {
+        let inner = self.inner.lock().unwrap();
+        let mut sorted: Vec<f32> = inner.recent_similarities.iter().copied().collect();
+        sorted.sort_unstable_by(f32::total_cmp);
+        let cosine_similarity = (!sorted.is_empty()).then(|| SimilarityStats {
+            mean: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            min: sorted[0],
+            p1: percentile(&sorted, 0.01),
+            p5: percentile(&sorted, 0.05),
+            p50: percentile(&sorted, 0.50),
+        });
+
+        DriftReport {
+            compare_inference_url: self.compare_inference_url.clone(),
+            batches_compared: inner.batches_compared,
+            inputs_compared: inner.inputs_compared,
+            batches_skipped: inner.batches_skipped,
+            compare_errors: inner.compare_errors,
+            count_mismatches: inner.count_mismatches,
+            dimension_mismatches: inner.dimension_mismatches,
+            cosine_similarity,
+        }
+    }
+}
+
+/// 0 for zero vectors
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Sends every batch to the primary backend & to `config.compare_inference_url`,
+/// only the primary's response is served. Both responses are compared in the background,
+/// the comparison call never delays (or fails) a batch
+pub struct ComparisonBackend {
+    primary: Arc<dyn InferenceBackend>,
+    compared: Arc<InferenceServiceClient>,
+    tracker: Arc<DriftTracker>,
+    in_flight: Arc<Semaphore>,
+}
+
+impl ComparisonBackend {
+    /// `None` unless `config.compare_inference_url` is set
+    pub fn new(
+        config: &AppConfig,
+        primary: Arc<dyn InferenceBackend>,
+    ) -> Result<Option<Self>, InferenceError> {
+        let Some(compare_inference_url) = &config.compare_inference_url else {
+            return Ok(None);
+        };
+        let compared = InferenceServiceClient::new(&AppConfig {
+            inference_urls: vec![compare_inference_url.clone()],
+            inference_max_retries: 0,
+            hedge_requests: false,
+            circuit_breaker_error_rate: None,
+            ..config.clone()
+        })?;
+
+        Ok(Some(Self {
+            primary,
+            compared: Arc::new(compared),
+            tracker: Arc::new(DriftTracker::new(compare_inference_url.clone())),
+            in_flight: Arc::new(Semaphore::new(MAX_IN_FLIGHT_COMPARISONS)),
+        }))
+    }
+
+    pub fn tracker(&self) -> Arc<DriftTracker> {
+        self.tracker.clone()
+    }
+
+    /// Calls the comparison backend right away, compares once the primary's response is sent
+    fn compare(&self, request: BatchRequest) -> Option<oneshot::Sender<BatchResponse>> {
+        let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+            self.tracker.record_skipped();
+            return None;
+        };
+
+        let (primary_sender, primary_receiver) = oneshot::channel();
+        let compared = self.compared.clone();
+        let tracker = self.tracker.clone();
+        tokio::spawn(async move {
+            let result = compared.call_service(request, None).await;
+            // nothing to compare against when the primary failed
+            if let Ok(primary) = primary_receiver.await {
+                tracker.record(&primary, result);
+            }
+            drop(permit);
+        });
+        Some(primary_sender)
+    }
+}
+
+#[rocket::async_trait]
+impl InferenceBackend for ComparisonBackend {
+    async fn embed(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        let primary_sender = self.compare(request.clone());
+        let result = self.primary.embed(request, timeout).await;
+        if let (Some(primary_sender), Ok(response)) = (primary_sender, &result) {
+            let _ = primary_sender.send(response.clone());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]), -1.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_record_mismatches_and_similarities() {
+        let tracker = DriftTracker::new("mock://".to_string());
+        tracker.record(
+            &vec![vec![1.0, 0.0], vec![1.0, 0.0]],
+            Ok(vec![vec![1.0, 0.0], vec![1.0, 0.0, 0.0]]),
+        );
+        tracker.record(&vec![vec![1.0, 0.0]], Ok(vec![]));
+        tracker.record(
+            &vec![vec![1.0, 0.0]],
+            Err(InferenceError::BackendError("down".to_string())),
+        );
+
+        let report = tracker.report();
+        assert_eq!(report.batches_compared, 2);
+        assert_eq!(report.inputs_compared, 2);
+        assert_eq!(report.count_mismatches, 1);
+        assert_eq!(report.dimension_mismatches, 1);
+        assert_eq!(report.compare_errors, 1);
+        assert_eq!(report.cosine_similarity.unwrap().min, 1.0);
+    }
+}
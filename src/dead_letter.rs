@@ -0,0 +1,209 @@
+use crate::audit_log::RotatingWriter;
+use crate::config::AppConfig;
+use crate::mirror::MirrorRecord;
+use log::error;
+use rocket::http::Status;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Instant;
+
+/// Why a request was lost
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterReason {
+    /// Rejected by load shedding (`config.load_shed_*`)
+    Shed,
+    /// Queued longer than `config.max_queue_age_ms`
+    QueueTtlExceeded,
+    /// Client supplied deadline passed
+    DeadlineExceeded,
+    /// `config.request_timeout_secs` (or `X-Request-Timeout-Ms`) passed
+    TimedOut,
+    ShuttingDown,
+    /// Client disconnected before its response was ready
+    ClientGone,
+    /// Inference failed (after retries) or any other server side error
+    Failed,
+}
+
+impl DeadLetterReason {
+    /// `None` for results that aren't a loss, i.e., successes & client errors
+    pub fn of(status: Status, code: Option<&str>) -> Option<Self> {
+        match code {
+            Some("queue_depth_exceeded" | "queue_age_exceeded") => Some(Self::Shed),
+            Some("queue_ttl_exceeded") => Some(Self::QueueTtlExceeded),
+            Some("deadline_exceeded") => Some(Self::DeadlineExceeded),
+            Some("shutting_down") => Some(Self::ShuttingDown),
+            _ if status == Status::RequestTimeout => Some(Self::TimedOut),
+            _ if status.code >= 500 => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// One JSON line per dropped or failed `/embed` request, never contains input text
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DeadLetterRecord {
+    /// Unix epoch milliseconds, when the request was given up on
+    pub timestamp_ms: u64,
+    /// Unix epoch milliseconds
+    pub received_at_ms: u64,
+    pub request_id: String,
+    /// Client IP or masked API key, same as in `GET /admin/usage`
+    pub caller: String,
+    pub model: Option<String>,
+    pub input_count: usize,
+    pub reason: DeadLetterReason,
+    /// `None` with `DeadLetterReason::ClientGone`, nothing was responded
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Writes dead-letter records to `config.dead_letter_path`, for quantifying loss during incidents
+pub struct DeadLetter {
+    sender: mpsc::Sender<DeadLetterRecord>,
+}
+
+impl DeadLetter {
+    pub fn new(config: &AppConfig) -> Result<Option<Self>, String> {
+        let Some(path) = &config.dead_letter_path else {
+            return Ok(None);
+        };
+        let writer = RotatingWriter::open(
+            PathBuf::from(path),
+            config.dead_letter_max_size_mb * 1024 * 1024,
+            1,
+        )
+        .map_err(|e| format!("Failed to open dead-letter file `{path}`: {e}"))?;
+
+        Ok(Some(Self {
+            sender: writer.spawn("dead-letter")?,
+        }))
+    }
+
+    pub fn record(&self, record: DeadLetterRecord) {
+        if let Err(e) = self.sender.send(record) {
+            error!("Dead-letter writer is gone, lost record {:?}", e.0);
+        }
+    }
+
+    /// Records `DeadLetterReason::ClientGone` when dropped before `finish`,
+    /// i.e., when Rocket cancels the request because its client disconnected
+    pub fn watch(&self, record: DeadLetterRecord, received_at: Instant) -> DeadLetterWatch<'_> {
+        DeadLetterWatch {
+            dead_letter: self,
+            record: Some(record),
+            received_at,
+        }
+    }
+}
+
+pub struct DeadLetterWatch<'a> {
+    dead_letter: &'a DeadLetter,
+    /// Taken once the request finished
+    record: Option<DeadLetterRecord>,
+    received_at: Instant,
+}
+
+impl DeadLetterWatch<'_> {
+    /// Records the request if `status` (& error `code`) mean it was lost
+    pub fn finish(mut self, status: Status, code: Option<&str>, error: Option<&str>) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        if let Some(reason) = DeadLetterReason::of(status, code) {
+            record.reason = reason;
+            record.status = Some(status.code);
+            record.error = error.map(str::to_string);
+            record.timestamp_ms = MirrorRecord::timestamp_ms();
+            self.dead_letter.record(record);
+        }
+    }
+}
+
+impl Drop for DeadLetterWatch<'_> {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            record.timestamp_ms = MirrorRecord::timestamp_ms();
+            record.error = Some(format!(
+                "Client disconnected after {}ms",
+                self.received_at.elapsed().as_millis()
+            ));
+            self.dead_letter.record(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_of_result() {
+        let reason = |status: u16, code: Option<&str>| {
+            DeadLetterReason::of(Status::from_code(status).unwrap(), code)
+        };
+        assert_eq!(
+            reason(503, Some("queue_depth_exceeded")),
+            Some(DeadLetterReason::Shed)
+        );
+        assert_eq!(
+            reason(504, Some("queue_ttl_exceeded")),
+            Some(DeadLetterReason::QueueTtlExceeded)
+        );
+        assert_eq!(reason(408, None), Some(DeadLetterReason::TimedOut));
+        assert_eq!(reason(502, None), Some(DeadLetterReason::Failed));
+        assert_eq!(reason(200, None), None);
+        assert_eq!(reason(413, Some("too_many_tokens")), None);
+    }
+
+    #[test]
+    fn test_unfinished_watch_records_client_gone() {
+        let dir = std::env::temp_dir().join("auto-batching-proxy-dead-letter");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dead-letter.jsonl");
+        let dead_letter = DeadLetter::new(&AppConfig {
+            dead_letter_path: Some(path.to_string_lossy().to_string()),
+            ..AppConfig::default()
+        })
+        .unwrap()
+        .unwrap();
+
+        let record = |request_id: &str| DeadLetterRecord {
+            timestamp_ms: 0,
+            received_at_ms: 0,
+            request_id: request_id.to_string(),
+            caller: "anonymous".to_string(),
+            model: None,
+            input_count: 1,
+            reason: DeadLetterReason::ClientGone,
+            status: None,
+            error: None,
+        };
+        dead_letter
+            .watch(record("served"), Instant::now())
+            .finish(Status::Ok, None, None);
+        drop(dead_letter.watch(record("gone"), Instant::now()));
+        // the writer thread ends once all senders are dropped
+        drop(dead_letter);
+
+        let mut content = String::new();
+        for _ in 0..50 {
+            content = std::fs::read_to_string(&path).unwrap_or_default();
+            if !content.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["request_id"], "gone");
+        assert_eq!(records[0]["reason"], "client_gone");
+        assert!(records[0]["status"].is_null());
+    }
+}
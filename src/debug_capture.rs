@@ -0,0 +1,172 @@
+use crate::audit_log::RotatingWriter;
+use crate::config::AppConfig;
+use crate::types::{BatchInfo, EmbedResponse, ErrorResponse};
+use log::error;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Debug captures are for troubleshooting, not retention, a single rotated file is kept
+const DEBUG_CAPTURE_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Applied to every captured input before it's written, e.g., to drop PII
+pub trait Redactor: Send + Sync {
+    fn redact(&self, input: &str) -> String;
+}
+
+/// Default with `config.debug_capture_redact_pii`: masks e-mail addresses & digits
+/// (phone, card & account numbers), keeps everything else for troubleshooting
+pub struct PiiRedactor;
+
+impl Redactor for PiiRedactor {
+    fn redact(&self, input: &str) -> String {
+        input
+            .split(' ')
+            .map(|word| {
+                if word.contains('@') {
+                    "<email>".to_string()
+                } else {
+                    word.chars()
+                        .map(|c| if c.is_ascii_digit() { '#' } else { c })
+                        .collect()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// One JSON line per sampled `/embed` request
+#[derive(Serialize, Debug)]
+pub struct DebugCaptureRecord {
+    /// Unix epoch milliseconds
+    pub timestamp_ms: u64,
+    pub request_id: String,
+    /// After redaction
+    pub inputs: Vec<String>,
+    pub status: u16,
+    pub embedding_count: Option<usize>,
+    pub embedding_dims: Option<usize>,
+    pub batch_info: Option<BatchInfo>,
+    pub error: Option<String>,
+}
+
+/// Captures `config.debug_capture_sample_rate` of full request/response pairs
+/// to `config.debug_capture_path`, for offline troubleshooting of quality complaints
+pub struct DebugCapture {
+    sample_rate: f64,
+    redactor: Option<Box<dyn Redactor>>,
+    sender: mpsc::Sender<DebugCaptureRecord>,
+}
+
+impl DebugCapture {
+    pub fn new(config: &AppConfig) -> Result<Option<Self>, String> {
+        let Some(path) = &config.debug_capture_path else {
+            return Ok(None);
+        };
+        let writer = RotatingWriter::open(PathBuf::from(path), DEBUG_CAPTURE_MAX_BYTES, 1)
+            .map_err(|e| format!("Failed to open debug capture file `{path}`: {e}"))?;
+
+        Ok(Some(Self {
+            sample_rate: config.debug_capture_sample_rate,
+            redactor: config
+                .debug_capture_redact_pii
+                .then(|| Box::new(PiiRedactor) as Box<dyn Redactor>),
+            sender: writer.spawn("debug-capture")?,
+        }))
+    }
+
+    /// Replaces the built-in redaction (`None` captures inputs as-is)
+    pub fn with_redactor(mut self, redactor: Option<Box<dyn Redactor>>) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Decided up front, so inputs are only cloned for sampled requests
+    pub fn should_sample(&self) -> bool {
+        // random enough for sampling, without pulling in a RNG dependency
+        let random = RandomState::new().build_hasher().finish();
+        ((random % 1_000_000) as f64) < self.sample_rate * 1_000_000.0
+    }
+
+    pub fn capture(
+        &self,
+        request_id: &str,
+        inputs: &[String],
+        result: &Result<EmbedResponse, Custom<Json<ErrorResponse>>>,
+    ) {
+        let inputs = inputs
+            .iter()
+            .map(|input| match &self.redactor {
+                Some(redactor) => redactor.redact(input),
+                None => input.clone(),
+            })
+            .collect();
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+
+        let record = match result {
+            Ok(response) => DebugCaptureRecord {
+                timestamp_ms,
+                request_id: request_id.to_string(),
+                inputs,
+                status: 200,
+                embedding_count: Some(response.embeddings.len()),
+                embedding_dims: response.embeddings.first().map(Vec::len),
+                batch_info: response.batch_info.clone(),
+                error: None,
+            },
+            Err(Custom(status, error)) => DebugCaptureRecord {
+                timestamp_ms,
+                request_id: request_id.to_string(),
+                inputs,
+                status: status.code,
+                embedding_count: None,
+                embedding_dims: None,
+                batch_info: None,
+                error: Some(error.error.clone()),
+            },
+        };
+        if let Err(e) = self.sender.send(record) {
+            error!("Debug capture writer is gone, lost {:?}", e.0.request_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pii_redactor() {
+        assert_eq!(
+            PiiRedactor.redact("call me at 555-1234 or mail jane@example.com"),
+            "call me at ###-#### or mail <email>"
+        );
+    }
+
+    #[test]
+    fn test_sample_rate_bounds() {
+        let path = std::env::temp_dir().join("auto-batching-proxy-debug-capture-sampling.jsonl");
+        let config = AppConfig {
+            debug_capture_path: Some(path.to_string_lossy().to_string()),
+            debug_capture_sample_rate: 1.0,
+            ..AppConfig::default()
+        };
+        let debug_capture = DebugCapture::new(&config).unwrap().unwrap();
+        assert!((0..100).all(|_| debug_capture.should_sample()));
+
+        let debug_capture = DebugCapture {
+            sample_rate: 0.0,
+            ..debug_capture
+        };
+        assert!((0..100).all(|_| !debug_capture.should_sample()));
+    }
+}
@@ -0,0 +1,202 @@
+//! TEI gRPC API (`tei.v1`), only the messages/fields the proxy needs, so no `protoc`/build script
+//! is required. See https://github.com/huggingface/text-embeddings-inference/blob/main/proto/tei.proto
+use crate::inference_client::InferenceError;
+use crate::types::{BatchRequest, BatchResponse};
+use std::collections::HashMap;
+use std::time::Duration;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::codegen::tokio_stream;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+
+const EMBED_STREAM_PATH: &str = "/tei.v1.Embed/EmbedStream";
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+/// `grpc.health.v1.HealthCheckResponse.ServingStatus.SERVING`
+const SERVING: i32 = 1;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EmbedRequest {
+    #[prost(string, tag = "1")]
+    pub inputs: String,
+    #[prost(bool, tag = "2")]
+    pub truncate: bool,
+    #[prost(bool, tag = "3")]
+    pub normalize: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EmbedResponse {
+    #[prost(float, repeated, tag = "1")]
+    pub embeddings: Vec<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HealthCheckRequest {
+    #[prost(string, tag = "1")]
+    pub service: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HealthCheckResponse {
+    #[prost(int32, tag = "1")]
+    pub status: i32,
+}
+
+/// gRPC transport of `InferenceServiceClient` (`config.inference_protocol = grpc`), one lazily
+/// connected HTTP/2 channel per backend. Each batch is sent over a single `EmbedStream` call
+pub struct GrpcClient {
+    channels: HashMap<String, Channel>,
+}
+
+impl GrpcClient {
+    pub fn new(urls: &[String], connect_timeout: Duration) -> Result<Self, InferenceError> {
+        let mut channels = HashMap::new();
+        for url in urls {
+            let channel = Endpoint::from_shared(url.clone())
+                .map_err(|e| InferenceError::InvalidConfig(format!("{url}: {e}")))?
+                .connect_timeout(connect_timeout)
+                .connect_lazy();
+            channels.insert(url.clone(), channel);
+        }
+        Ok(Self { channels })
+    }
+
+    fn client(&self, url: &str) -> Result<tonic::client::Grpc<Channel>, InferenceError> {
+        self.channels
+            .get(url)
+            .cloned()
+            .map(tonic::client::Grpc::new)
+            .ok_or_else(|| InferenceError::InvalidConfig(format!("Unknown gRPC backend {url}")))
+    }
+
+    /// Embeddings are streamed back in the same order as inputs were sent
+    pub async fn embed(
+        &self,
+        url: &str,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        let mut client = self.client(url)?;
+        client.ready().await.map_err(|e| {
+            Self::to_inference_error(Status::unavailable(format!("Connection failed: {e}")))
+        })?;
+
+        let messages: Vec<EmbedRequest> = request
+            .inputs
+            .iter()
+            .map(|input| EmbedRequest {
+                inputs: input.to_string(),
+                truncate: false,
+                // same default as TEI HTTP `/embed`
+                normalize: true,
+            })
+            .collect();
+        let mut grpc_request = tonic::Request::new(tokio_stream::iter(messages));
+        if let Some(timeout) = timeout {
+            grpc_request.set_timeout(timeout);
+        }
+
+        let mut response_stream = client
+            .streaming(
+                grpc_request,
+                PathAndQuery::from_static(EMBED_STREAM_PATH),
+                ProstCodec::<EmbedRequest, EmbedResponse>::default(),
+            )
+            .await
+            .map_err(Self::to_inference_error)?
+            .into_inner();
+
+        let mut embeddings = Vec::with_capacity(request.inputs.len());
+        while let Some(response) = response_stream
+            .message()
+            .await
+            .map_err(Self::to_inference_error)?
+        {
+            embeddings.push(response.embeddings);
+        }
+        Ok(embeddings)
+    }
+
+    /// Standard gRPC health checking protocol, which TEI serves next to its own API
+    pub async fn check_health(&self, url: &str, timeout: Duration) -> Result<(), String> {
+        let mut client = self.client(url).map_err(|e| e.message())?;
+        client
+            .ready()
+            .await
+            .map_err(|e| format!("Connection failed: {e}"))?;
+
+        let mut grpc_request = tonic::Request::new(HealthCheckRequest::default());
+        grpc_request.set_timeout(timeout);
+        let response = client
+            .unary(
+                grpc_request,
+                PathAndQuery::from_static(HEALTH_CHECK_PATH),
+                ProstCodec::<HealthCheckRequest, HealthCheckResponse>::default(),
+            )
+            .await
+            .map_err(|status| format!("gRPC error: {status}"))?;
+
+        match response.into_inner().status {
+            SERVING => Ok(()),
+            status => Err(format!("gRPC health status {status}")),
+        }
+    }
+
+    /// Mapped to the HTTP status TEI would answer with, so retries, circuit breaker
+    /// & batch bisection treat both protocols the same
+    fn to_inference_error(status: Status) -> InferenceError {
+        let http_status = match status.code() {
+            Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+                reqwest::StatusCode::BAD_REQUEST
+            }
+            Code::Unauthenticated => reqwest::StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => reqwest::StatusCode::FORBIDDEN,
+            Code::NotFound => reqwest::StatusCode::NOT_FOUND,
+            Code::ResourceExhausted => reqwest::StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => reqwest::StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            Code::DeadlineExceeded | Code::Cancelled => reqwest::StatusCode::GATEWAY_TIMEOUT,
+            _ => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        InferenceError::HttpError {
+            status: http_status,
+            body: status.message().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        let error = GrpcClient::to_inference_error(Status::invalid_argument("too long"));
+        assert!(matches!(
+            error,
+            InferenceError::HttpError { status, ref body }
+                if status == reqwest::StatusCode::BAD_REQUEST && body == "too long"
+        ));
+
+        let error = GrpcClient::to_inference_error(Status::unavailable("down"));
+        assert_eq!(
+            error.to_rocket_status(),
+            rocket::http::Status::ServiceUnavailable
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embed_fails_with_unavailable_backend() {
+        let url = "http://127.0.0.1:9".to_string();
+        let client = GrpcClient::new(std::slice::from_ref(&url), Duration::from_secs(1)).unwrap();
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+        let error = client.embed(&url, &request, None).await.unwrap_err();
+        assert_eq!(
+            error.to_rocket_status(),
+            rocket::http::Status::ServiceUnavailable
+        );
+    }
+}
@@ -1,9 +1,48 @@
+use crate::adaptive_limit::AdaptiveBatchLimit;
+#[cfg(feature = "candle")]
+use crate::candle_backend::CandleModel;
+use crate::circuit_breaker::CircuitBreaker;
 use crate::config::AppConfig;
-use crate::types::{BatchRequest, BatchResponse};
-use log::debug;
+#[cfg(feature = "grpc")]
+use crate::config::InferenceProtocol;
+#[cfg(feature = "grpc")]
+use crate::grpc_client::GrpcClient;
+use crate::metrics::METRICS;
+use crate::mock_upstream::MockUpstream;
+#[cfg(feature = "onnx")]
+use crate::onnx_backend::OnnxModel;
+use crate::retry_budget::RetryBudget;
+use crate::types::{
+    BatchOutput, BatchRequest, BatchResponse, Endpoint, SparseEmbedding, TokenEmbeddings,
+};
+use log::{debug, info, warn};
 use reqwest::Error;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
 use rocket::http::Status;
-use std::time::Duration;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Number of most recent successful calls the hedging delay (p95) is computed over
+const HEDGE_LATENCY_WINDOW: usize = 100;
+/// No hedging until the p95 estimate is somewhat meaningful
+const HEDGE_MIN_SAMPLES: usize = 20;
+/// Content of `config.warmup_calls` batches
+const WARM_UP_INPUT: &str = "Warm-up request to load the model";
+/// How often backends are polled on startup with `config.wait_for_upstream`
+const WAIT_FOR_UPSTREAM_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// `candle:///path/to/model` URLs are served in-process by `CandleModel` (`candle` cargo feature)
+pub const CANDLE_SCHEME: &str = "candle://";
+/// `onnx:///path/to/model` URLs are served in-process by `OnnxModel` (`onnx` cargo feature)
+pub const ONNX_SCHEME: &str = "onnx://";
+/// Upstream `/info` rarely changes (only on model redeploys), no need to fetch it on every `GET /info`
+const UPSTREAM_INFO_TTL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum InferenceError {
@@ -13,15 +52,83 @@ pub enum InferenceError {
         body: String,
     },
     ParseError(Error),
+    /// Failed fast, without calling the inference service
+    CircuitOpen,
+    /// Every backend stayed at `config.max_in_flight_per_backend` until the call's timeout
+    BackendsSaturated,
+    /// Client couldn't be built from `AppConfig`
+    InvalidConfig(String),
+    /// Embeddings can't be mapped back to inputs, detected by `BatchProcessor`
+    EmbeddingCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// Reported by a custom `InferenceBackend`
+    BackendError(String),
+    /// Endpoint the backend doesn't serve, e.g. `/embed_sparse` of a local model
+    Unsupported(String),
+    /// Embeddings were returned, but a `ResponsePostprocessor` failed on them
+    Postprocessing(String),
 }
 impl InferenceError {
     pub fn to_rocket_status(&self) -> Status {
         match self {
+            InferenceError::NetworkError(e) if e.is_timeout() => Status::GatewayTimeout,
             InferenceError::NetworkError(_) => Status::ServiceUnavailable,
             InferenceError::HttpError { status, .. } => {
                 Status::from_code(status.as_u16()).unwrap_or(Status::InternalServerError)
             }
             InferenceError::ParseError(_) => Status::InternalServerError,
+            InferenceError::CircuitOpen => Status::ServiceUnavailable,
+            InferenceError::BackendsSaturated => Status::ServiceUnavailable,
+            InferenceError::InvalidConfig(_) => Status::InternalServerError,
+            InferenceError::EmbeddingCountMismatch { .. } => Status::BadGateway,
+            InferenceError::BackendError(_) => Status::BadGateway,
+            InferenceError::Unsupported(_) => Status::NotImplemented,
+            InferenceError::Postprocessing(_) => Status::InternalServerError,
+        }
+    }
+
+    /// Machine readable `ErrorResponse.code`
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            InferenceError::CircuitOpen => Some("circuit_open"),
+            InferenceError::BackendsSaturated => Some("backends_saturated"),
+            InferenceError::EmbeddingCountMismatch { .. } => Some("embedding_count_mismatch"),
+            InferenceError::Unsupported(_) => Some("unsupported_endpoint"),
+            InferenceError::Postprocessing(_) => Some("postprocessing_failed"),
+            InferenceError::HttpError { status, .. } if Self::is_input_rejection(*status) => {
+                Some("input_rejected")
+            }
+            _ => None,
+        }
+    }
+
+    /// 4xx caused by the batch content, not by how/when or where it was sent
+    /// (e.g. 401/404 of a misconfigured upstream, which bisecting the batch wouldn't fix)
+    pub fn is_input_rejection(status: reqwest::StatusCode) -> bool {
+        matches!(
+            status,
+            reqwest::StatusCode::BAD_REQUEST
+                | reqwest::StatusCode::PAYLOAD_TOO_LARGE
+                | reqwest::StatusCode::UNPROCESSABLE_ENTITY
+        )
+    }
+
+    /// Whether the error indicates the inference service itself is in trouble (counted by the
+    /// circuit breaker & worth a retry), as opposed to e.g. a rejected batch (4xx)
+    fn is_upstream_failure(&self) -> bool {
+        match self {
+            InferenceError::NetworkError(_) => true,
+            InferenceError::HttpError { status, .. } => status.is_server_error(),
+            InferenceError::ParseError(_)
+            | InferenceError::CircuitOpen
+            | InferenceError::BackendsSaturated
+            | InferenceError::InvalidConfig(_)
+            | InferenceError::EmbeddingCountMismatch { .. }
+            | InferenceError::BackendError(_)
+            | InferenceError::Unsupported(_)
+            | InferenceError::Postprocessing(_) => false,
         }
     }
 
@@ -32,47 +139,1064 @@ impl InferenceError {
                 format!("HTTP error: {status}: {body}")
             }
             InferenceError::ParseError(e) => format!("Parse error: {e}"),
+            InferenceError::CircuitOpen => {
+                "Circuit breaker open, inference service is unavailable".to_string()
+            }
+            InferenceError::BackendsSaturated => {
+                "Every inference backend is at its in-flight batch limit".to_string()
+            }
+            InferenceError::InvalidConfig(e) => format!("Invalid configuration: {e}"),
+            InferenceError::EmbeddingCountMismatch { expected, actual } => {
+                format!("Inference service returned {actual} embeddings for {expected} inputs")
+            }
+            InferenceError::BackendError(e) => format!("Inference backend error: {e}"),
+            InferenceError::Unsupported(e) => format!("Not supported: {e}"),
+            InferenceError::Postprocessing(e) => format!("Postprocessing failed: {e}"),
         }
     }
 }
 
-#[derive(Clone)]
+/// Where batches are sent to, `InferenceServiceClient` (TEI replicas over HTTP or gRPC) by default,
+/// custom implementations (local models, other providers) can be passed to `BatchProcessor::new`
+#[rocket::async_trait]
+pub trait InferenceBackend: Send + Sync {
+    /// One embedding per input, in input order. `timeout` (if any) bounds the whole call,
+    /// i.e., the remaining budget of the tightest client deadline in the batch
+    async fn embed(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError>;
+
+    /// One sparse embedding per input, in input order, for `/embed_sparse`.
+    /// Backends without sparse models keep the default
+    async fn embed_sparse(
+        &self,
+        _request: BatchRequest,
+        _timeout: Option<Duration>,
+    ) -> Result<Vec<SparseEmbedding>, InferenceError> {
+        Err(InferenceError::Unsupported(
+            "sparse embeddings by this inference backend".to_string(),
+        ))
+    }
+
+    /// Token level embeddings per input, in input order, for `/embed_all`
+    async fn embed_all(
+        &self,
+        _request: BatchRequest,
+        _timeout: Option<Duration>,
+    ) -> Result<Vec<TokenEmbeddings>, InferenceError> {
+        Err(InferenceError::Unsupported(
+            "token embeddings by this inference backend".to_string(),
+        ))
+    }
+}
+
+/// Per-backend health, as exposed by `GET /health/backends`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BackendStatus {
+    pub url: String,
+    pub healthy: bool,
+    /// Successful probes in a row, an unhealthy backend is re-admitted at `config.health_check_healthy_threshold`
+    pub consecutive_successes: u32,
+    pub last_error: Option<String>,
+    /// Batches currently sent to it
+    pub in_flight: usize,
+}
+
+/// Outcome of an on-demand probe, as exposed by `GET /health/deep`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BackendProbe {
+    pub url: String,
+    pub reachable: bool,
+    pub latency_ms: f64,
+    pub error: Option<String>,
+}
+
+/// Single upstream replica, see `config.inference_urls`
+#[derive(Debug)]
+pub struct Backend {
+    pub url: String,
+    /// TEI `/health` on the same host
+    health_url: String,
+    /// TEI `/info` on the same host
+    info_url: String,
+    /// TEI `/embed_sparse` on the same host
+    sparse_url: String,
+    /// TEI `/embed_all` on the same host
+    embed_all_url: String,
+    /// Set for `mock://` URLs, answered in-process
+    mock: Option<MockUpstream>,
+    /// Set for `candle://` URLs, loaded once on startup
+    #[cfg(feature = "candle")]
+    candle_model: Option<Arc<CandleModel>>,
+    /// Set for `onnx://` URLs, loaded once on startup
+    #[cfg(feature = "onnx")]
+    onnx_model: Option<Arc<OnnxModel>>,
+    status: Mutex<BackendStatus>,
+    in_flight: AtomicUsize,
+}
+
+/// Counts a call towards `Backend::in_flight` until dropped
+struct InFlightCall<'a> {
+    backend: &'a Backend,
+    /// Of `InferenceServiceClient::backend_slots`, released after `in_flight` is decremented
+    slot: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for InFlightCall<'_> {
+    fn drop(&mut self) {
+        self.backend.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Backend {
+    fn new(url: &str) -> Result<Self, InferenceError> {
+        let sibling_url = |path: &str| {
+            reqwest::Url::parse(url)
+                .and_then(|url| url.join(path))
+                .map_or_else(|_| url.to_string(), String::from)
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            health_url: sibling_url("/health"),
+            info_url: sibling_url("/info"),
+            sparse_url: sibling_url(Endpoint::EmbedSparse.path()),
+            embed_all_url: sibling_url(Endpoint::EmbedAll.path()),
+            // validated in `AppConfig::build`
+            mock: MockUpstream::parse(url).and_then(Result::ok),
+            #[cfg(feature = "candle")]
+            candle_model: match url.starts_with(CANDLE_SCHEME) {
+                true => Some(Arc::new(CandleModel::load(url)?)),
+                false => None,
+            },
+            #[cfg(feature = "onnx")]
+            onnx_model: match url.starts_with(ONNX_SCHEME) {
+                true => Some(Arc::new(OnnxModel::load(url)?)),
+                false => None,
+            },
+            // optimistic, so traffic flows before the first probe completes
+            status: Mutex::new(BackendStatus {
+                url: url.to_string(),
+                healthy: true,
+                consecutive_successes: 0,
+                last_error: None,
+                in_flight: 0,
+            }),
+            in_flight: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.status.lock().unwrap().healthy
+    }
+
+    /// Where batches of `endpoint` are posted to
+    fn endpoint_url(&self, endpoint: Endpoint) -> &str {
+        match endpoint {
+            Endpoint::Embed => &self.url,
+            Endpoint::EmbedSparse => &self.sparse_url,
+            Endpoint::EmbedAll => &self.embed_all_url,
+        }
+    }
+
+    pub fn status(&self) -> BackendStatus {
+        BackendStatus {
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            ..self.status.lock().unwrap().clone()
+        }
+    }
+
+    /// Takes an in-flight slot, unless `max_in_flight` are already taken
+    fn try_start_call(&self, max_in_flight: Option<usize>) -> Option<InFlightCall<'_>> {
+        self.in_flight
+            .fetch_update(
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+                |in_flight| match max_in_flight {
+                    Some(max_in_flight) if in_flight >= max_in_flight => None,
+                    _ => Some(in_flight + 1),
+                },
+            )
+            .ok()
+            .map(|_| InFlightCall {
+                backend: self,
+                slot: None,
+            })
+    }
+
+    /// Any failure (probe or network error of a batch) takes the backend out of rotation right away,
+    /// it takes `healthy_threshold` successful probes in a row to get it back
+    fn record_probe(&self, result: Result<(), String>, healthy_threshold: u32) {
+        let mut status = self.status.lock().unwrap();
+        match result {
+            Ok(()) => {
+                status.consecutive_successes = status.consecutive_successes.saturating_add(1);
+                if !status.healthy && status.consecutive_successes >= healthy_threshold {
+                    info!("Backend {} is healthy again", self.url);
+                    status.healthy = true;
+                    status.last_error = None;
+                }
+            }
+            Err(error) => {
+                if status.healthy {
+                    warn!("Backend {} marked unhealthy: {error}", self.url);
+                }
+                status.healthy = false;
+                status.consecutive_successes = 0;
+                status.last_error = Some(error);
+            }
+        }
+    }
+}
+
+/// Batches are rotated (round-robin) across healthy backends
 pub struct InferenceServiceClient {
-    client: reqwest::Client,
-    base_url: String,
+    /// Swapped for a fresh one (& connection pool) when upstream addresses change, see `run_dns_refresh`
+    client: RwLock<reqwest::Client>,
+    /// Only set with `config.upstream_dns_refresh_secs`
+    dns_refresh: Option<DnsRefresh>,
+    backends: Vec<Backend>,
+    next_backend: AtomicUsize,
+    max_in_flight_per_backend: Option<usize>,
+    /// `max_in_flight_per_backend` x backends, only set with `config.max_in_flight_per_backend`.
+    /// Batches wait for a slot instead of piling onto a single backend
+    backend_slots: Option<Semaphore>,
+    health_check_interval: Duration,
+    health_check_healthy_threshold: u32,
+    /// Only set with `config.circuit_breaker_error_rate`
+    circuit_breaker: Option<CircuitBreaker>,
+    max_retries: u32,
+    /// Only set with `config.retry_budget_percent`
+    retry_budget: Option<RetryBudget>,
+    /// Per attempt, see `config.upstream_timeout`
+    upstream_request_timeout: Option<Duration>,
+    upstream_request_timeout_per_input: Duration,
+    retry_backoff: Duration,
+    hedge_requests: bool,
+    /// Only fed with `config.hedge_requests`
+    recent_latencies: Mutex<VecDeque<Duration>>,
+    /// Last upstream `/info` payload & when it was fetched
+    upstream_info: Mutex<Option<(Instant, serde_json::Value)>>,
+    /// Replaces the HTTP client (`client`) for `config.inference_protocol = grpc`
+    #[cfg(feature = "grpc")]
+    grpc_client: Option<GrpcClient>,
+}
+
+/// Periodic re-resolution of upstream hostnames, reqwest's pool would otherwise keep
+/// using connections to the addresses resolved first
+struct DnsRefresh {
+    interval: Duration,
+    /// To build the replacement HTTP client with
+    config: AppConfig,
 }
 
 impl InferenceServiceClient {
     pub fn new(config: &AppConfig) -> Result<Self, InferenceError> {
-        let client = reqwest::Client::builder()
+        Ok(Self {
+            client: RwLock::new(Self::build_http_client(config)?),
+            dns_refresh: config
+                .upstream_dns_refresh_secs
+                .map(|refresh_secs| DnsRefresh {
+                    interval: Duration::from_secs(refresh_secs),
+                    config: config.clone(),
+                }),
+            backends: config
+                .inference_urls
+                .iter()
+                .map(|url| Backend::new(url))
+                .collect::<Result<_, _>>()?,
+            next_backend: AtomicUsize::new(0),
+            max_in_flight_per_backend: config.max_in_flight_per_backend,
+            backend_slots: config
+                .max_in_flight_per_backend
+                .map(|max_in_flight| Semaphore::new(max_in_flight * config.inference_urls.len())),
+            health_check_interval: Duration::from_secs(config.health_check_interval_secs),
+            health_check_healthy_threshold: config.health_check_healthy_threshold,
+            circuit_breaker: CircuitBreaker::new(config),
+            retry_budget: RetryBudget::new(config),
+            max_retries: config.inference_max_retries,
+            upstream_request_timeout: config
+                .upstream_request_timeout_ms
+                .map(Duration::from_millis),
+            upstream_request_timeout_per_input: Duration::from_millis(
+                config.upstream_request_timeout_per_input_ms,
+            ),
+            retry_backoff: Duration::from_millis(config.inference_retry_backoff_ms),
+            hedge_requests: config.hedge_requests,
+            recent_latencies: Mutex::new(VecDeque::with_capacity(HEDGE_LATENCY_WINDOW)),
+            upstream_info: Mutex::new(None),
+            #[cfg(feature = "grpc")]
+            grpc_client: match config.inference_protocol {
+                InferenceProtocol::Grpc => Some(GrpcClient::new(
+                    &config.inference_urls,
+                    Duration::from_secs(config.inference_timeout_secs),
+                )?),
+                InferenceProtocol::Http => None,
+            },
+        })
+    }
+
+    fn http_client(&self) -> reqwest::Client {
+        // cheap, `reqwest::Client` is a handle to a shared pool
+        self.client.read().unwrap().clone()
+    }
+
+    /// Background tasks of the client, they stop once it's dropped
+    pub fn spawn_background_tasks(self: &Arc<Self>) {
+        tokio::spawn(self.clone().run_health_checks());
+        if self.dns_refresh.is_some() {
+            tokio::spawn(self.clone().run_dns_refresh());
+        }
+    }
+
+    /// `host:port` of HTTP backends addressed by hostname, IP addresses have nothing to re-resolve
+    fn dns_hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = self
+            .backends
+            .iter()
+            .filter_map(|backend| reqwest::Url::parse(&backend.url).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .filter_map(|url| {
+                Some(format!(
+                    "{}:{}",
+                    url.domain()?,
+                    url.port_or_known_default()?
+                ))
+            })
+            .collect();
+        hosts.sort();
+        hosts.dedup();
+        hosts
+    }
+
+    /// Resolves upstream hostnames each `config.upstream_dns_refresh_secs`, once some resolve to
+    /// different addresses, the HTTP client is replaced, so new calls open connections to the
+    /// current ones. In-flight calls finish on the previous client
+    pub async fn run_dns_refresh(self: Arc<Self>) {
+        let Some(dns_refresh) = &self.dns_refresh else {
+            return;
+        };
+        let hosts = self.dns_hosts();
+        if hosts.is_empty() {
+            return;
+        }
+        let mut interval = tokio::time::interval(dns_refresh.interval);
+        let client = Arc::downgrade(&self);
+        drop(self);
+
+        let mut resolved: HashMap<String, BTreeSet<IpAddr>> = HashMap::new();
+        loop {
+            interval.tick().await;
+            let Some(client) = client.upgrade() else {
+                return;
+            };
+            let mut changed_hosts = Vec::new();
+            for host in &hosts {
+                let addresses = match tokio::net::lookup_host(host.as_str()).await {
+                    Ok(addresses) => addresses.map(|address| address.ip()).collect(),
+                    Err(e) => {
+                        warn!("Failed to re-resolve {host}: {e}");
+                        continue;
+                    }
+                };
+                if let Some(previous) = resolved.insert(host.clone(), addresses)
+                    && previous != resolved[host]
+                {
+                    changed_hosts.push(host.as_str());
+                }
+            }
+            if !changed_hosts.is_empty() {
+                client.recycle_connections(&changed_hosts);
+            }
+        }
+    }
+
+    fn recycle_connections(&self, changed_hosts: &[&str]) {
+        let Some(dns_refresh) = &self.dns_refresh else {
+            return;
+        };
+        match Self::build_http_client(&dns_refresh.config) {
+            Ok(http_client) => {
+                info!(
+                    "Upstream addresses of {} changed, recycling connections",
+                    changed_hosts.join(", ")
+                );
+                *self.client.write().unwrap() = http_client;
+            }
+            Err(e) => warn!("Failed to rebuild the upstream client: {}", e.message()),
+        }
+    }
+
+    fn build_http_client(config: &AppConfig) -> Result<reqwest::Client, InferenceError> {
+        let mut client_builder = reqwest::Client::builder()
             .timeout(Duration::from_secs(config.inference_timeout_secs))
+            .default_headers(Self::default_headers(config)?);
+        if let Some(connect_timeout_ms) = config.upstream_connect_timeout_ms {
+            client_builder =
+                client_builder.connect_timeout(Duration::from_millis(connect_timeout_ms));
+        }
+        let client_builder = Self::configure_connections(client_builder, config);
+        let client_builder = Self::configure_proxy(client_builder, config)?;
+        Self::configure_tls(client_builder, config)?
             .build()
-            .map_err(InferenceError::NetworkError)?;
+            .map_err(InferenceError::NetworkError)
+    }
 
-        Ok(Self {
-            client,
-            base_url: config.inference_url.clone(),
-        })
+    /// Connection reuse tuning, avoids connection churn to the inference service
+    /// under high batch concurrency
+    fn configure_connections(
+        mut client_builder: reqwest::ClientBuilder,
+        config: &AppConfig,
+    ) -> reqwest::ClientBuilder {
+        if config.inference_http2_prior_knowledge {
+            client_builder = client_builder.http2_prior_knowledge();
+        }
+        if let Some(max_idle) = config.inference_pool_max_idle_per_host {
+            client_builder = client_builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout_secs) = config.inference_pool_idle_timeout_secs {
+            client_builder =
+                client_builder.pool_idle_timeout(Duration::from_secs(idle_timeout_secs));
+        }
+        if let Some(keepalive_secs) = config.inference_tcp_keepalive_secs {
+            client_builder = client_builder.tcp_keepalive(Duration::from_secs(keepalive_secs));
+        }
+        client_builder
+    }
+
+    /// Without `config.inference_proxy_url`, reqwest picks up `HTTP(S)_PROXY` / `NO_PROXY` by itself
+    fn configure_proxy(
+        client_builder: reqwest::ClientBuilder,
+        config: &AppConfig,
+    ) -> Result<reqwest::ClientBuilder, InferenceError> {
+        let Some(proxy_url) = &config.inference_proxy_url else {
+            return Ok(client_builder);
+        };
+        let proxy = reqwest::Proxy::all(proxy_url.as_str())
+            .map_err(|e| InferenceError::InvalidConfig(format!("inference_proxy_url: {e}")))?
+            .no_proxy(reqwest::NoProxy::from_env());
+        Ok(client_builder.proxy(proxy))
     }
 
+    /// Client certificate (mutual TLS) & extra trusted CAs, for inference services
+    /// behind service meshes or with privately issued certificates.
+    /// `config.inference_tls_insecure` skips verification altogether
+    fn configure_tls(
+        mut client_builder: reqwest::ClientBuilder,
+        config: &AppConfig,
+    ) -> Result<reqwest::ClientBuilder, InferenceError> {
+        let read = |path: &str| {
+            std::fs::read(path)
+                .map_err(|e| InferenceError::InvalidConfig(format!("Failed to read {path}: {e}")))
+        };
+
+        if let (Some(cert_path), Some(key_path)) = (
+            &config.inference_client_cert_path,
+            &config.inference_client_key_path,
+        ) {
+            let identity = reqwest::Identity::from_pkcs8_pem(&read(cert_path)?, &read(key_path)?)
+                .map_err(|e| {
+                InferenceError::InvalidConfig(format!("Client certificate: {e}"))
+            })?;
+            client_builder = client_builder.identity(identity);
+        }
+
+        if let Some(ca_cert_path) = &config.inference_ca_cert_path {
+            let certificates = reqwest::Certificate::from_pem_bundle(&read(ca_cert_path)?)
+                .map_err(|e| InferenceError::InvalidConfig(format!("CA certificate: {e}")))?;
+            for certificate in certificates {
+                client_builder = client_builder.add_root_certificate(certificate);
+            }
+        }
+
+        if config.inference_tls_insecure {
+            warn!("TLS certificate verification of the inference service is DISABLED");
+            client_builder = client_builder.danger_accept_invalid_certs(true);
+        }
+        Ok(client_builder)
+    }
+
+    /// Sent with every upstream call, health probes included
+    fn default_headers(config: &AppConfig) -> Result<HeaderMap, InferenceError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.inference_headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| InferenceError::InvalidConfig(format!("{name}: {e}")))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| InferenceError::InvalidConfig(format!("{name}: {e}")))?;
+            headers.insert(name, value);
+        }
+        // takes precedence over a custom `Authorization` header
+        if let Some(inference_api_key) = &config.inference_api_key {
+            let mut value = HeaderValue::from_str(&format!("Bearer {inference_api_key}"))
+                .map_err(|e| InferenceError::InvalidConfig(format!("inference_api_key: {e}")))?;
+            // keeps it out of debug logs
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+
+    pub fn backend_statuses(&self) -> Vec<BackendStatus> {
+        self.backends.iter().map(Backend::status).collect()
+    }
+
+    /// Probes every backend each `config.health_check_interval_secs`,
+    /// stops once the client itself is dropped (e.g., Rocket instance in tests)
+    pub async fn run_health_checks(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.health_check_interval);
+        let client = Arc::downgrade(&self);
+        drop(self);
+
+        loop {
+            interval.tick().await;
+            let Some(client) = client.upgrade() else {
+                return;
+            };
+            for backend in &client.backends {
+                let result = client.probe(backend).await;
+                backend.record_probe(result, client.health_check_healthy_threshold);
+            }
+        }
+    }
+
+    async fn probe(&self, backend: &Backend) -> Result<(), String> {
+        if backend.mock.is_some() {
+            return Ok(());
+        }
+        #[cfg(feature = "candle")]
+        if backend.candle_model.is_some() {
+            return Ok(());
+        }
+        #[cfg(feature = "onnx")]
+        if backend.onnx_model.is_some() {
+            return Ok(());
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(grpc_client) = &self.grpc_client {
+            return grpc_client
+                .check_health(&backend.url, self.health_check_interval)
+                .await;
+        }
+
+        let response = self
+            .http_client()
+            .get(&backend.health_url)
+            .timeout(self.health_check_interval)
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {e}"))?;
+
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()));
+        }
+        Ok(())
+    }
+
+    /// Probes every backend right now (one after another), independent of the periodic health checks
+    pub async fn probe_all(&self) -> Vec<BackendProbe> {
+        let mut probes = Vec::with_capacity(self.backends.len());
+        for backend in &self.backends {
+            let start_time = Instant::now();
+            let result = self.probe(backend).await;
+            probes.push(BackendProbe {
+                url: backend.url.clone(),
+                reachable: result.is_ok(),
+                latency_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+                error: result.err(),
+            });
+        }
+        probes
+    }
+
+    /// Polls all backends until one of them passes its health check, `false` on `timeout`
+    pub async fn wait_until_reachable(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.probe_all().await.iter().any(|probe| probe.reachable) {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            info!("Waiting for an inference backend to become ready...");
+            tokio::time::sleep(WAIT_FOR_UPSTREAM_POLL_INTERVAL.min(remaining)).await;
+        }
+    }
+
+    /// Sends `calls` dummy batches of `inputs` inputs to every backend, so the first real batches
+    /// don't pay for cold caches / lazy model compilation. Failures are only logged
+    pub async fn warm_up(&self, calls: usize, inputs: usize) {
+        let request = BatchRequest {
+            inputs: vec![Arc::from(WARM_UP_INPUT); inputs],
+        };
+        for backend in &self.backends {
+            for call in 1..=calls {
+                let start_time = Instant::now();
+                match self.send_to(backend, Endpoint::Embed, &request, None).await {
+                    Ok(_) => info!(
+                        "Warm-up call {call}/{calls} to {} took {:?}",
+                        backend.url,
+                        start_time.elapsed()
+                    ),
+                    Err(e) => warn!(
+                        "Warm-up call {call}/{calls} to {} failed after {:?}: {}",
+                        backend.url,
+                        start_time.elapsed(),
+                        e.message()
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Upstream `/info` (model id, batch limits, etc.), cached for `UPSTREAM_INFO_TTL`.
+    /// Fetched from the next healthy backend, replicas are expected to serve the same model
+    pub async fn upstream_info(&self) -> Result<serde_json::Value, String> {
+        if let Some((fetched_at, info)) = self.upstream_info.lock().unwrap().as_ref()
+            && fetched_at.elapsed() < UPSTREAM_INFO_TTL
+        {
+            return Ok(info.clone());
+        }
+
+        let backend = self.pick_backend();
+        if let Some(mock) = &backend.mock {
+            return Ok(mock.info());
+        }
+        #[cfg(feature = "candle")]
+        if let Some(candle_model) = &backend.candle_model {
+            return Ok(candle_model.info());
+        }
+        #[cfg(feature = "onnx")]
+        if let Some(onnx_model) = &backend.onnx_model {
+            return Ok(onnx_model.info());
+        }
+        #[cfg(feature = "grpc")]
+        if self.grpc_client.is_some() {
+            return Err("Upstream info is only available over HTTP".to_string());
+        }
+
+        let response = self
+            .http_client()
+            .get(&backend.info_url)
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {e}"))?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()));
+        }
+        let info: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse error: {e}"))?;
+
+        *self.upstream_info.lock().unwrap() = Some((Instant::now(), info.clone()));
+        Ok(info)
+    }
+
+    pub fn backends(&self) -> &[Backend] {
+        &self.backends
+    }
+
+    /// Next healthy backend in round-robin order,
+    /// if none is healthy, rotates across all of them (better than failing right away)
+    fn pick_backend(&self) -> &Backend {
+        let start = self.next_backend.fetch_add(1, Ordering::Relaxed);
+        let count = self.backends.len();
+        (0..count)
+            .map(|offset| &self.backends[(start + offset) % count])
+            .find(|backend| backend.is_healthy())
+            .unwrap_or(&self.backends[start % count])
+    }
+
+    /// Next backend in rotation with a free in-flight slot, with `config.max_in_flight_per_backend`
+    /// waits (up to `timeout`) until some backend has one
+    async fn start_call(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<InFlightCall<'_>, InferenceError> {
+        let Some(backend_slots) = &self.backend_slots else {
+            let backend = self.pick_backend();
+            return Ok(backend
+                .try_start_call(None)
+                .expect("backend without in-flight limit"));
+        };
+        let acquire = backend_slots.acquire();
+        let slot = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, acquire)
+                .await
+                .map_err(|_| InferenceError::BackendsSaturated)?,
+            None => acquire.await,
+        }
+        .expect("backend slots are never closed");
+
+        let start = self.next_backend.fetch_add(1, Ordering::Relaxed);
+        let count = self.backends.len();
+        let rotation = || (0..count).map(|offset| &self.backends[(start + offset) % count]);
+        let mut call = rotation()
+            .filter(|backend| backend.is_healthy())
+            .chain(rotation())
+            .find_map(|backend| backend.try_start_call(self.max_in_flight_per_backend))
+            // a slot is only released after its backend's `in_flight` is decremented
+            .expect("fewer batches in flight than backend slots");
+        call.slot = Some(slot);
+        Ok(call)
+    }
+
+    /// Healthy backend other than `primary` with a free in-flight slot, hedges don't wait for one
+    /// (nor fall back to unhealthy backends), `None` skips hedging
+    fn try_start_hedge_call(&self, primary: &Backend) -> Option<InFlightCall<'_>> {
+        let slot = match &self.backend_slots {
+            Some(backend_slots) => Some(backend_slots.try_acquire().ok()?),
+            None => None,
+        };
+        let start = self.next_backend.fetch_add(1, Ordering::Relaxed);
+        let count = self.backends.len();
+        let mut call = (0..count)
+            .map(|offset| &self.backends[(start + offset) % count])
+            .filter(|backend| !std::ptr::eq(*backend, primary) && backend.is_healthy())
+            .find_map(|backend| backend.try_start_call(self.max_in_flight_per_backend))?;
+        call.slot = slot;
+        Some(call)
+    }
+
+    /// `timeout` bounds this call, e.g., to respect the remaining budget of client deadlines,
+    /// retries have to fit in it too. Every attempt is further bounded by `attempt_timeout`
+    ///
+    /// Transient failures (network errors, timeouts, 5xx) are retried up to `config.inference_max_retries`
+    /// times, each retry goes to the next backend in rotation
     pub async fn call_service(
         &self,
         request: BatchRequest,
+        timeout: Option<Duration>,
     ) -> Result<BatchResponse, InferenceError> {
+        match self
+            .call_endpoint(Endpoint::Embed, request, timeout)
+            .await?
+        {
+            BatchOutput::Dense(embeddings) => Ok(embeddings),
+            output => Err(Self::unexpected_output(Endpoint::Embed, &output)),
+        }
+    }
+
+    /// `call_service` for `/embed_sparse`
+    pub async fn call_sparse_service(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<SparseEmbedding>, InferenceError> {
+        match self
+            .call_endpoint(Endpoint::EmbedSparse, request, timeout)
+            .await?
+        {
+            BatchOutput::Sparse(embeddings) => Ok(embeddings),
+            output => Err(Self::unexpected_output(Endpoint::EmbedSparse, &output)),
+        }
+    }
+
+    /// `call_service` for `/embed_all`
+    pub async fn call_embed_all_service(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<TokenEmbeddings>, InferenceError> {
+        match self
+            .call_endpoint(Endpoint::EmbedAll, request, timeout)
+            .await?
+        {
+            BatchOutput::Tokens(embeddings) => Ok(embeddings),
+            output => Err(Self::unexpected_output(Endpoint::EmbedAll, &output)),
+        }
+    }
+
+    /// `send_to` parses responses by endpoint, so this only guards against future endpoints
+    fn unexpected_output(endpoint: Endpoint, output: &BatchOutput) -> InferenceError {
+        InferenceError::BackendError(format!(
+            "{} returned {} embeddings of another kind",
+            endpoint.path(),
+            output.len()
+        ))
+    }
+
+    async fn call_endpoint(
+        &self,
+        endpoint: Endpoint,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        let start_time = Instant::now();
+        let mut attempt = 0;
+        if let Some(retry_budget) = &self.retry_budget {
+            retry_budget.deposit();
+        }
+        loop {
+            let remaining = timeout.map(|timeout| timeout.saturating_sub(start_time.elapsed()));
+            let attempt_timeout = match (remaining, self.attempt_timeout(request.inputs.len())) {
+                (Some(remaining), Some(attempt_timeout)) => Some(remaining.min(attempt_timeout)),
+                (remaining, attempt_timeout) => remaining.or(attempt_timeout),
+            };
+            let result = self.call_once(endpoint, &request, attempt_timeout).await;
+
+            let error = match result {
+                Err(error) if error.is_upstream_failure() && attempt < self.max_retries => error,
+                result => return result,
+            };
+
+            let backoff = self.backoff(attempt);
+            if remaining.is_some_and(|remaining| remaining <= backoff) {
+                return Err(error);
+            }
+            if self
+                .retry_budget
+                .as_ref()
+                .is_some_and(|retry_budget| !retry_budget.try_withdraw())
+            {
+                METRICS
+                    .retry_budget_exhausted
+                    .fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Inference call failed ({}), retry budget exhausted",
+                    error.message()
+                );
+                return Err(error);
+            }
+            METRICS.inference_retries.fetch_add(1, Ordering::Relaxed);
+            attempt += 1;
+            warn!(
+                "Inference call failed ({}), retry {attempt}/{} in {backoff:?}",
+                error.message(),
+                self.max_retries
+            );
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// `config.upstream_timeout` of a batch of `inputs`, `None` leaves it to the client wide
+    /// `config.inference_timeout_secs`
+    fn attempt_timeout(&self, inputs: usize) -> Option<Duration> {
+        self.upstream_request_timeout
+            .map(|timeout| timeout + self.upstream_request_timeout_per_input * inputs as u32)
+    }
+
+    /// Exponential (`config.inference_retry_backoff_ms` * 2^attempt) with +-50% jitter,
+    /// so batches failed at the same moment don't hit the inference service again all at once
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .retry_backoff
+            .saturating_mul(2u32.saturating_pow(attempt));
+        // random enough for jitter, without pulling in a RNG dependency
+        let random = RandomState::new().build_hasher().finish();
+        let jitter = 0.5 + (random % 1000) as f64 / 1000.0;
+        exponential.mul_f64(jitter)
+    }
+
+    /// Single attempt, guarded by the circuit breaker (if enabled)
+    async fn call_once(
+        &self,
+        endpoint: Endpoint,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        let Some(circuit_breaker) = &self.circuit_breaker else {
+            return self.send_hedged(endpoint, request, timeout).await;
+        };
+        if !circuit_breaker.allow() {
+            return Err(InferenceError::CircuitOpen);
+        }
+
+        let result = self.send_hedged(endpoint, request, timeout).await;
+        circuit_breaker.record(
+            result
+                .as_ref()
+                .is_err_and(InferenceError::is_upstream_failure),
+        );
+        result
+    }
+
+    /// With `config.hedge_requests`, a call still running after the p95 latency estimate is
+    /// duplicated to another healthy backend, the first successful response wins
+    /// & the other call is cancelled (dropped)
+    async fn send_hedged(
+        &self,
+        endpoint: Endpoint,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        let Some(hedge_delay) = self.hedge_delay() else {
+            return self.send_batch(endpoint, request, timeout).await;
+        };
+
+        let waiting_since = Instant::now();
+        let call = self.start_call(timeout).await?;
+        let timeout = timeout.map(|timeout| timeout.saturating_sub(waiting_since.elapsed()));
+        let primary_backend = call.backend;
+        let primary = self.send_call(call, endpoint, request, timeout);
+        tokio::pin!(primary);
+        tokio::select! {
+            result = &mut primary => return result,
+            _ = tokio::time::sleep(hedge_delay) => {}
+        }
+
+        let Some(hedge_call) = self.try_start_hedge_call(primary_backend) else {
+            debug!("No response after {hedge_delay:?}, but no other backend to hedge to");
+            return primary.await;
+        };
+        debug!("No response after {hedge_delay:?}, sending hedged request");
+        let hedged = self.send_call(
+            hedge_call,
+            endpoint,
+            request,
+            timeout.map(|timeout| timeout.saturating_sub(hedge_delay)),
+        );
+        tokio::pin!(hedged);
+        tokio::select! {
+            result = &mut primary => if result.is_ok() { result } else { hedged.await },
+            result = &mut hedged => if result.is_ok() { result } else { primary.await },
+        }
+    }
+
+    /// p95 of recent successful calls, `None` when hedging is disabled, there is no other backend
+    /// to hedge to, or not enough samples yet
+    fn hedge_delay(&self) -> Option<Duration> {
+        if !self.hedge_requests || self.backends.len() < 2 {
+            return None;
+        }
+        let recent_latencies = self.recent_latencies.lock().unwrap();
+        (recent_latencies.len() >= HEDGE_MIN_SAMPLES)
+            .then(|| AdaptiveBatchLimit::p95(&recent_latencies))
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        let mut recent_latencies = self.recent_latencies.lock().unwrap();
+        if recent_latencies.len() == HEDGE_LATENCY_WINDOW {
+            recent_latencies.pop_front();
+        }
+        recent_latencies.push_back(latency);
+    }
+
+    async fn send_batch(
+        &self,
+        endpoint: Endpoint,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        let waiting_since = Instant::now();
+        let call = self.start_call(timeout).await?;
+        let timeout = timeout.map(|timeout| timeout.saturating_sub(waiting_since.elapsed()));
+        self.send_call(call, endpoint, request, timeout).await
+    }
+
+    /// Holds `call`'s in-flight slot until the response (or error) is in
+    async fn send_call(
+        &self,
+        call: InFlightCall<'_>,
+        endpoint: Endpoint,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        let backend = call.backend;
         debug!(
             "Making request to inference service: {} with {} inputs: {:?}",
-            self.base_url,
+            backend.endpoint_url(endpoint),
             request.inputs.len(),
             request.inputs
         );
 
-        let response = self
-            .client
-            .post(&self.base_url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(InferenceError::NetworkError)?;
+        let start_time = Instant::now();
+        let result = self.send_to(backend, endpoint, request, timeout).await;
+        if result.is_ok() && self.hedge_requests {
+            self.record_latency(start_time.elapsed());
+        }
+        result
+    }
+
+    /// Over the configured protocol, bypassing backend selection, circuit breaker & retries
+    async fn send_to(
+        &self,
+        backend: &Backend,
+        endpoint: Endpoint,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        if let Some(mock) = &backend.mock {
+            return Ok(match endpoint {
+                Endpoint::Embed => BatchOutput::Dense(mock.embed(request).await),
+                Endpoint::EmbedSparse => BatchOutput::Sparse(mock.embed_sparse(request).await),
+                Endpoint::EmbedAll => BatchOutput::Tokens(mock.embed_all(request).await),
+            });
+        }
+        #[cfg(feature = "candle")]
+        if let Some(candle_model) = &backend.candle_model {
+            Self::dense_only(endpoint, "candle:// backends")?;
+            return candle_model
+                .clone()
+                .embed(request)
+                .await
+                .map(BatchOutput::Dense);
+        }
+        #[cfg(feature = "onnx")]
+        if let Some(onnx_model) = &backend.onnx_model {
+            Self::dense_only(endpoint, "onnx:// backends")?;
+            return onnx_model
+                .clone()
+                .embed(request)
+                .await
+                .map(BatchOutput::Dense);
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(grpc_client) = &self.grpc_client {
+            Self::dense_only(endpoint, "the gRPC protocol")?;
+            return grpc_client
+                .embed(&backend.url, request, timeout)
+                .await
+                .map(BatchOutput::Dense)
+                .inspect_err(|e| {
+                    if e.to_rocket_status() == Status::ServiceUnavailable {
+                        backend.record_probe(Err(e.message()), self.health_check_healthy_threshold);
+                    }
+                });
+        }
+        self.send_http(backend, endpoint, request, timeout).await
+    }
+
+    /// Local models & gRPC only serve `/embed`
+    #[cfg(any(feature = "candle", feature = "onnx", feature = "grpc"))]
+    fn dense_only(endpoint: Endpoint, served_by: &str) -> Result<(), InferenceError> {
+        match endpoint {
+            Endpoint::Embed => Ok(()),
+            _ => Err(InferenceError::Unsupported(format!(
+                "{} over {served_by}",
+                endpoint.path()
+            ))),
+        }
+    }
+
+    async fn send_http(
+        &self,
+        backend: &Backend,
+        endpoint: Endpoint,
+        request: &BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchOutput, InferenceError> {
+        let mut request_builder = self
+            .http_client()
+            .post(backend.endpoint_url(endpoint))
+            .json(request);
+        if let Some(timeout) = timeout {
+            request_builder = request_builder.timeout(timeout);
+        }
+
+        let response = request_builder.send().await.map_err(|e| {
+            // no need to wait for the next probe to stop routing batches there
+            backend.record_probe(
+                Err(format!("Network error: {e}")),
+                self.health_check_healthy_threshold,
+            );
+            InferenceError::NetworkError(e)
+        })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -80,10 +1204,92 @@ impl InferenceServiceClient {
             return Err(InferenceError::HttpError { status, body });
         }
 
-        let batch_response: BatchResponse =
-            response.json().await.map_err(InferenceError::ParseError)?;
+        match endpoint {
+            Endpoint::Embed => response.json().await.map(BatchOutput::Dense),
+            Endpoint::EmbedSparse => response.json().await.map(BatchOutput::Sparse),
+            Endpoint::EmbedAll => response.json().await.map(BatchOutput::Tokens),
+        }
+        .map_err(InferenceError::ParseError)
+    }
+}
 
-        Ok(batch_response)
+/// The `InferenceServiceClient` batches are currently sent to, replaced at runtime
+/// by `PUT /admin/backend`. Batches already in flight finish on the client they started with,
+/// the replaced client (incl. its health checks) goes away with the last of them
+pub struct ActiveClient {
+    current: RwLock<Arc<InferenceServiceClient>>,
+}
+
+impl ActiveClient {
+    pub fn new(client: Arc<InferenceServiceClient>) -> Self {
+        Self {
+            current: RwLock::new(client),
+        }
+    }
+
+    pub fn current(&self) -> Arc<InferenceServiceClient> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Returns the previous client
+    pub fn replace(&self, client: Arc<InferenceServiceClient>) -> Arc<InferenceServiceClient> {
+        std::mem::replace(&mut *self.current.write().unwrap(), client)
+    }
+}
+
+#[rocket::async_trait]
+impl InferenceBackend for ActiveClient {
+    async fn embed(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        self.current().call_service(request, timeout).await
+    }
+
+    async fn embed_sparse(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<SparseEmbedding>, InferenceError> {
+        self.current().call_sparse_service(request, timeout).await
+    }
+
+    async fn embed_all(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<TokenEmbeddings>, InferenceError> {
+        self.current()
+            .call_embed_all_service(request, timeout)
+            .await
+    }
+}
+
+#[rocket::async_trait]
+impl InferenceBackend for InferenceServiceClient {
+    async fn embed(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        self.call_service(request, timeout).await
+    }
+
+    async fn embed_sparse(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<SparseEmbedding>, InferenceError> {
+        self.call_sparse_service(request, timeout).await
+    }
+
+    async fn embed_all(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<TokenEmbeddings>, InferenceError> {
+        self.call_embed_all_service(request, timeout).await
     }
 }
 
@@ -91,23 +1297,425 @@ impl InferenceServiceClient {
 mod tests {
     use super::*;
     use crate::config::AppConfig;
+    use crate::stub_upstream::{self, StubRequest};
+    use tokio::sync::mpsc::UnboundedReceiver;
 
     #[test]
     fn test_new_success() {
         let config = AppConfig::default();
         let result = InferenceServiceClient::new(&config);
-        assert_eq!(result.unwrap().base_url, config.inference_url.to_string());
+        assert_eq!(result.unwrap().backends[0].url, config.inference_urls[0]);
+    }
+
+    #[test]
+    fn test_is_input_rejection() {
+        use reqwest::StatusCode;
+        for status in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ] {
+            assert!(InferenceError::is_input_rejection(status));
+        }
+        for status in [
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+            StatusCode::REQUEST_TIMEOUT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ] {
+            assert!(!InferenceError::is_input_rejection(status));
+        }
+    }
+
+    #[test]
+    fn test_dns_hosts_skip_ip_addresses() {
+        let config = AppConfig {
+            inference_urls: vec![
+                "http://tei.internal:8080/embed".to_string(),
+                "https://tei.internal/embed".to_string(),
+                "http://10.0.0.2:8080/embed".to_string(),
+                "http://tei.internal:8080/embed".to_string(),
+                "mock://upstream".to_string(),
+            ],
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        assert_eq!(
+            client.dns_hosts(),
+            vec![
+                "tei.internal:443".to_string(),
+                "tei.internal:8080".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_limit_per_backend() {
+        let config = AppConfig {
+            inference_urls: vec![
+                "http://10.0.0.1:8080/embed".to_string(),
+                "http://10.0.0.2:8080/embed".to_string(),
+            ],
+            max_in_flight_per_backend: Some(1),
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let first = client.start_call(None).await.unwrap();
+        let second = client.start_call(None).await.unwrap();
+        assert_ne!(first.backend.url, second.backend.url);
+        let in_flight: Vec<usize> = client
+            .backend_statuses()
+            .iter()
+            .map(|status| status.in_flight)
+            .collect();
+        assert_eq!(in_flight, vec![1, 1]);
+
+        let timeout = Some(Duration::from_millis(50));
+        assert!(matches!(
+            client.start_call(timeout).await,
+            Err(InferenceError::BackendsSaturated)
+        ));
+
+        let freed_url = second.backend.url.clone();
+        drop(second);
+        let third = client.start_call(timeout).await.unwrap();
+        assert_eq!(third.backend.url, freed_url);
+    }
+
+    #[test]
+    fn test_attempt_timeout_scales_with_batch_size() {
+        let client = InferenceServiceClient::new(&AppConfig::default()).unwrap();
+        assert_eq!(client.attempt_timeout(8), None);
+
+        let config = AppConfig {
+            upstream_request_timeout_ms: Some(500),
+            upstream_request_timeout_per_input_ms: 10,
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        assert_eq!(client.attempt_timeout(8), Some(Duration::from_millis(580)));
+    }
+
+    /// Upstream answering with 1 embedding per input, passing on every request it receives
+    async fn capture_requests() -> (String, UnboundedReceiver<StubRequest>) {
+        let (sender, requests) = tokio::sync::mpsc::unbounded_channel();
+        let url = stub_upstream::spawn(move |request| {
+            let embeddings = vec![[0.1]; request.inputs().len()];
+            let _ = sender.send(request);
+            Some((Status::Ok, serde_json::json!(embeddings).to_string()))
+        })
+        .await;
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_call_service_sends_bearer_token() {
+        let (url, mut requests) = capture_requests().await;
+        let config = AppConfig {
+            inference_urls: vec![url],
+            inference_api_key: Some("secret".to_string()),
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+        assert!(client.call_service(request, None).await.is_ok());
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.header("authorization"), Some("Bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn test_call_service_through_proxy() {
+        let (proxy_url, mut requests) = capture_requests().await;
+        let config = AppConfig {
+            inference_urls: vec!["http://tei.internal:8080/embed".to_string()],
+            inference_proxy_url: Some(proxy_url),
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+        assert!(client.call_service(request, None).await.is_ok());
+        // absolute-form request target, i.e., forwarded by the proxy
+        let request = requests.recv().await.unwrap();
+        assert!(
+            request
+                .head
+                .starts_with("POST http://tei.internal:8080/embed HTTP/1.1\r\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_service_without_connection_reuse() {
+        let config = AppConfig {
+            inference_urls: vec![stub_upstream::spawn_embedding().await],
+            inference_pool_max_idle_per_host: Some(0),
+            inference_pool_idle_timeout_secs: Some(1),
+            inference_tcp_keepalive_secs: Some(30),
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        for _ in 0..2 {
+            let request = BatchRequest {
+                inputs: vec!["hello".into()],
+            };
+            assert_eq!(client.call_service(request, None).await.unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_new_with_client_certificate_and_ca() {
+        let fixture = |name: &str| Some(format!("tests/fixtures/tls/{name}"));
+        let config = AppConfig {
+            inference_client_cert_path: fixture("client.pem"),
+            inference_client_key_path: fixture("client.key"),
+            inference_ca_cert_path: fixture("ca.pem"),
+            ..AppConfig::default()
+        };
+        assert!(InferenceServiceClient::new(&config).is_ok());
+
+        let config = AppConfig {
+            inference_ca_cert_path: fixture("missing.pem"),
+            ..AppConfig::default()
+        };
+        let result = InferenceServiceClient::new(&config);
+        assert!(matches!(result, Err(InferenceError::InvalidConfig(_))));
+
+        let config = AppConfig {
+            inference_tls_insecure: true,
+            ..AppConfig::default()
+        };
+        assert!(InferenceServiceClient::new(&config).is_ok());
+    }
+
+    #[test]
+    fn test_pick_backend_round_robin_skips_unhealthy() {
+        let config = AppConfig {
+            inference_urls: vec![
+                "http://a/embed".to_string(),
+                "http://b/embed".to_string(),
+                "http://c/embed".to_string(),
+            ],
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+
+        let picked: Vec<&str> = (0..4).map(|_| client.pick_backend().url.as_str()).collect();
+        assert_eq!(
+            picked,
+            vec![
+                "http://a/embed",
+                "http://b/embed",
+                "http://c/embed",
+                "http://a/embed"
+            ]
+        );
+
+        client.backends[1].record_probe(Err("down".to_string()), 2);
+        let picked: Vec<&str> = (0..3).map(|_| client.pick_backend().url.as_str()).collect();
+        assert!(!picked.contains(&"http://b/embed"));
+    }
+
+    #[test]
+    fn test_backend_readmitted_after_consecutive_successful_probes() {
+        let backend = Backend::new("http://127.0.0.1:8080/embed").unwrap();
+        assert_eq!(backend.health_url, "http://127.0.0.1:8080/health");
+        assert_eq!(backend.info_url, "http://127.0.0.1:8080/info");
+        assert!(backend.is_healthy());
+
+        backend.record_probe(Err("Network error".to_string()), 2);
+        assert!(!backend.is_healthy());
+        assert_eq!(
+            backend.status().last_error,
+            Some("Network error".to_string())
+        );
+
+        backend.record_probe(Ok(()), 2);
+        assert!(!backend.is_healthy());
+        backend.record_probe(Ok(()), 2);
+        assert!(backend.is_healthy());
+        assert_eq!(backend.status().last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_call_service_marks_unreachable_backend_unhealthy() {
+        let config = AppConfig {
+            // nothing listens on port 9
+            inference_urls: vec!["http://127.0.0.1:9/embed".to_string()],
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+        assert!(client.call_service(request, None).await.is_err());
+        assert!(!client.backends[0].is_healthy());
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_with_jitter() {
+        let config = AppConfig {
+            inference_retry_backoff_ms: 100,
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        for attempt in 0..4 {
+            let base = Duration::from_millis(100 * 2u64.pow(attempt));
+            let backoff = client.backoff(attempt);
+            assert!(
+                backoff >= base / 2 && backoff <= base * 3 / 2,
+                "{backoff:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_service_retries_on_next_backend() {
+        let config = AppConfig {
+            inference_urls: vec![
+                "http://127.0.0.1:9/embed".to_string(),
+                stub_upstream::spawn_embedding().await,
+            ],
+            inference_max_retries: 1,
+            inference_retry_backoff_ms: 10,
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+        // first attempt goes to the unreachable backend
+        let response = client.call_service(request, None).await;
+        assert_eq!(response.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_call_service_hedges_slow_backend() {
+        let slow_calls = Arc::new(AtomicUsize::new(0));
+        let received = slow_calls.clone();
+        // never responds
+        let slow_url = stub_upstream::spawn(move |_| {
+            received.fetch_add(1, Ordering::Relaxed);
+            None
+        })
+        .await;
+
+        let config = AppConfig {
+            inference_urls: vec![slow_url, stub_upstream::spawn_embedding().await],
+            hedge_requests: true,
+            inference_max_retries: 0,
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        assert!(client.hedge_delay().is_none());
+        for _ in 0..HEDGE_MIN_SAMPLES {
+            client.record_latency(Duration::from_millis(20));
+        }
+        assert_eq!(client.hedge_delay(), Some(Duration::from_millis(20)));
+
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+        // only the slow backend is healthy, the hedge must not be duplicated to it
+        client.backends[1].record_probe(Err("down".to_string()), 1);
+        let response = client
+            .call_service(request.clone(), Some(Duration::from_millis(300)))
+            .await;
+        assert!(response.is_err());
+        assert_eq!(slow_calls.load(Ordering::Relaxed), 1);
+
+        // primary goes to the slow backend, would otherwise wait for the full timeout
+        for backend in &client.backends {
+            backend.record_probe(Ok(()), 1);
+        }
+        client.next_backend.store(0, Ordering::Relaxed);
+        let response =
+            tokio::time::timeout(Duration::from_secs(5), client.call_service(request, None))
+                .await
+                .expect("hedged request should win");
+        assert_eq!(response.unwrap().len(), 1);
+        assert_eq!(slow_calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_call_service_fails_fast_once_circuit_open() {
+        let config = AppConfig {
+            inference_urls: vec!["http://127.0.0.1:9/embed".to_string()],
+            circuit_breaker_error_rate: Some(0.5),
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let request = BatchRequest {
+            inputs: vec!["hello".into()],
+        };
+
+        let mut errors = Vec::new();
+        for _ in 0..15 {
+            errors.push(
+                client
+                    .call_service(request.clone(), None)
+                    .await
+                    .unwrap_err(),
+            );
+        }
+        let last = errors.last().unwrap();
+        assert!(matches!(last, InferenceError::CircuitOpen));
+        assert_eq!(last.to_rocket_status(), Status::ServiceUnavailable);
+        assert_eq!(last.code(), Some("circuit_open"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_reachable() {
+        let config = AppConfig {
+            inference_urls: vec![stub_upstream::spawn_embedding().await],
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        assert!(client.wait_until_reachable(Duration::from_secs(1)).await);
+
+        let config = AppConfig {
+            // nothing listens on port 9
+            inference_urls: vec!["http://127.0.0.1:9/embed".to_string()],
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        let start_time = Instant::now();
+        assert!(
+            !client
+                .wait_until_reachable(Duration::from_millis(1500))
+                .await
+        );
+        assert!(start_time.elapsed() >= Duration::from_millis(1500));
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_calls_every_backend() {
+        let (url, mut requests) = capture_requests().await;
+        let config = AppConfig {
+            inference_urls: vec![url],
+            ..AppConfig::default()
+        };
+        let client = InferenceServiceClient::new(&config).unwrap();
+        client.warm_up(1, 1).await;
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.inputs(), vec![WARM_UP_INPUT.to_string()]);
     }
 
     #[tokio::test]
     async fn test_call_service_success() {
-        let config = AppConfig::default();
+        let config = AppConfig {
+            inference_urls: vec![stub_upstream::spawn_embedding().await],
+            ..AppConfig::default()
+        };
         let result = InferenceServiceClient::new(&config);
         let client = result.unwrap();
         let request = BatchRequest {
-            inputs: vec!["hello".to_string(), "world".to_string()],
+            inputs: vec!["hello".into(), "world".into()],
         };
-        let response = client.call_service(request).await;
+        let response = client.call_service(request, None).await;
         assert_eq!(response.unwrap().len(), 2);
     }
 }
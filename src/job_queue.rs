@@ -0,0 +1,122 @@
+//! Queued async jobs (`POST /jobs/embed`, `POST /jobs/embed/bulk`) persisted to an embedded database
+//! (`config.job_queue_path`, `persistent-queue` cargo feature), so jobs that weren't processed yet
+//! survive a restart. They're resumed on startup under the same job id
+use crate::types::EmbedRequest;
+use log::warn;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+
+/// Job id -> JSON `PersistedJob`
+const JOBS: TableDefinition<&str, &[u8]> = TableDefinition::new("jobs");
+
+/// Everything needed to run a job again. Deadline & timeout headers aren't kept,
+/// they're meaningless after a restart
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PersistedJob {
+    Embed {
+        request: EmbedRequest,
+        callback_url: Option<String>,
+        client_id: Option<String>,
+        request_id: String,
+    },
+    Bulk {
+        input_uri: String,
+        output_uri: String,
+        callback_url: Option<String>,
+        client_id: Option<String>,
+        request_id: String,
+    },
+}
+
+pub struct JobQueue {
+    db: Database,
+}
+
+impl JobQueue {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let db = Database::create(path)
+            .map_err(|e| format!("Failed to open job queue `{path}`: {e}"))?;
+        Ok(Self { db })
+    }
+
+    /// Durable once this returns, i.e., before the job is acknowledged to the client
+    pub fn insert(&self, job_id: &str, job: &PersistedJob) -> Result<(), String> {
+        let value = serde_json::to_vec(job).map_err(|e| e.to_string())?;
+        self.write(|table| table.insert(job_id, value.as_slice()).map(|_| ()))
+    }
+
+    pub fn remove(&self, job_id: &str) -> Result<(), String> {
+        self.write(|table| table.remove(job_id).map(|_| ()))
+    }
+
+    /// Jobs that weren't finished before the last shutdown, unreadable ones are skipped
+    pub fn pending(&self) -> Result<Vec<(String, PersistedJob)>, String> {
+        let read = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = match read.open_table(JOBS) {
+            Ok(table) => table,
+            // nothing was ever queued
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut jobs = Vec::new();
+        for entry in table.iter().map_err(|e| e.to_string())? {
+            let (job_id, value) = entry.map_err(|e| e.to_string())?;
+            match serde_json::from_slice(value.value()) {
+                Ok(job) => jobs.push((job_id.value().to_string(), job)),
+                Err(e) => warn!("Skipping unreadable queued job {}: {e}", job_id.value()),
+            }
+        }
+        Ok(jobs)
+    }
+
+    fn write(
+        &self,
+        update: impl FnOnce(&mut redb::Table<&str, &[u8]>) -> Result<(), redb::StorageError>,
+    ) -> Result<(), String> {
+        let write = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = write.open_table(JOBS).map_err(|e| e.to_string())?;
+            update(&mut table).map_err(|e| e.to_string())?;
+        }
+        write.commit().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs_survive_reopening() {
+        let dir = std::env::temp_dir().join("auto-batching-proxy-job-queue");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("jobs.redb");
+        let path = path.to_str().unwrap();
+
+        let job = |input: &str| PersistedJob::Embed {
+            request: EmbedRequest {
+                inputs: vec![input.to_string()],
+                ..Default::default()
+            },
+            callback_url: None,
+            client_id: Some("key:…1234".to_string()),
+            request_id: format!("request-{input}"),
+        };
+        {
+            let job_queue = JobQueue::open(path).unwrap();
+            assert!(job_queue.pending().unwrap().is_empty());
+            job_queue.insert("job-1", &job("Hello")).unwrap();
+            job_queue.insert("job-2", &job("World")).unwrap();
+            job_queue.remove("job-1").unwrap();
+        }
+
+        let pending = JobQueue::open(path).unwrap().pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        let (job_id, PersistedJob::Embed { request, .. }) = &pending[0] else {
+            panic!("Expected an embed job, got {pending:?}");
+        };
+        assert_eq!(job_id, "job-2");
+        assert_eq!(request.inputs, vec!["World".to_string()]);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::config::AppConfig;
+use crate::types::{EmbedRequest, EmbedResponse, ErrorResponse};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// `POST /jobs/embed` request body
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmbedJobRequest {
+    #[serde(flatten)]
+    pub request: EmbedRequest,
+    /// The finished job is POSTed here (see `WebhookSender`)
+    #[serde(default)]
+    pub callback_url: Option<String>,
+}
+
+/// `POST /jobs/embed/bulk` request body (`object-store` cargo feature)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BulkJobRequest {
+    /// JSONL corpus, same format as `POST /embed/bulk` bodies, e.g. `s3://bucket/corpus.jsonl`
+    pub input_uri: String,
+    /// JSONL results are written here, e.g. `s3://bucket/embeddings.jsonl`
+    pub output_uri: String,
+    #[serde(default)]
+    pub callback_url: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting in (or being processed by) the batching queue
+    Queued,
+    Completed,
+    Failed,
+}
+
+/// `POST /jobs/embed` & `GET /jobs/<job_id>` response
+#[derive(Serialize, Debug, Clone)]
+pub struct Job {
+    pub job_id: String,
+    pub status: JobStatus,
+    /// Set once `completed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<Vec<Vec<f32>>>,
+    /// Set once `failed`, same error `/embed` would have responded with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+    /// Bulk jobs only, where results are written to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_uri: Option<String>,
+    /// Bulk jobs only, set once `completed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub records: Option<usize>,
+    /// Bulk jobs only, records with an `error` result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_records: Option<usize>,
+    /// Webhook payloads only, replaces `embeddings` when `config.webhook_include_embeddings` is off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_url: Option<String>,
+    /// `RequestContext::client_id` of the submitter, the only caller the job is visible to
+    #[serde(skip)]
+    client_id: Option<String>,
+    /// Expiry starts once the job is finished
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+}
+
+/// In-memory job results, for callers (e.g., backfill pipelines) that shouldn't hold
+/// an HTTP connection open per request. Finished jobs expire after `config.job_ttl_secs`,
+/// jobs are lost on restart (unfinished ones are kept with `config.job_queue_path`, see `job_queue`)
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, Job>>,
+    ttl: Duration,
+    max_jobs: usize,
+}
+
+impl JobStore {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            ttl: Duration::from_secs(config.job_ttl_secs),
+            max_jobs: config.max_jobs,
+        }
+    }
+
+    /// Registers a new `queued` job of `client_id`, unless `config.max_jobs` are already kept
+    pub fn create(
+        &self,
+        output_uri: Option<String>,
+        client_id: Option<String>,
+    ) -> Result<Job, Custom<Json<ErrorResponse>>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        // expired jobs are only purged here, so the store can't grow without new submissions
+        jobs.retain(|_, job| {
+            job.finished_at
+                .is_none_or(|finished_at| finished_at.elapsed() < self.ttl)
+        });
+        if jobs.len() >= self.max_jobs {
+            return Err(Custom(
+                Status::ServiceUnavailable,
+                Json(ErrorResponse {
+                    error: format!("{} jobs are already queued or awaiting pickup", jobs.len()),
+                    code: Some("too_many_jobs"),
+                }),
+            ));
+        }
+
+        let mut id = [0u8; 16];
+        getrandom::fill(&mut id).map_err(|e| {
+            Custom(
+                Status::InternalServerError,
+                Json(ErrorResponse {
+                    error: format!("Failed to generate a job id: {e}"),
+                    code: None,
+                }),
+            )
+        })?;
+        let job_id = id.iter().map(|byte| format!("{byte:02x}")).collect();
+        let job = Self::queued(job_id, output_uri, client_id);
+        jobs.insert(job.job_id.clone(), job.clone());
+        Ok(job)
+    }
+
+    /// Registers a `queued` job persisted before a restart, regardless of `config.max_jobs`
+    pub fn restore(&self, job_id: &str, output_uri: Option<String>, client_id: Option<String>) {
+        let job = Self::queued(job_id.to_string(), output_uri, client_id);
+        self.jobs.lock().unwrap().insert(job.job_id.clone(), job);
+    }
+
+    fn queued(job_id: String, output_uri: Option<String>, client_id: Option<String>) -> Job {
+        Job {
+            job_id,
+            status: JobStatus::Queued,
+            embeddings: None,
+            error: None,
+            output_uri,
+            records: None,
+            failed_records: None,
+            result_url: None,
+            client_id,
+            finished_at: None,
+        }
+    }
+
+    /// Returns the finished job
+    pub fn finish(
+        &self,
+        job_id: &str,
+        result: Result<EmbedResponse, Custom<Json<ErrorResponse>>>,
+    ) -> Option<Job> {
+        self.update_finished(job_id, |job| match result {
+            Ok(response) => {
+                job.status = JobStatus::Completed;
+                job.embeddings = Some(response.embeddings);
+            }
+            Err(Custom(_, error)) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(error.into_inner());
+            }
+        })
+    }
+
+    /// Bulk job counterpart of `finish`, with `(records, failed_records)` on success
+    pub fn finish_bulk(&self, job_id: &str, result: Result<(usize, usize), String>) -> Option<Job> {
+        self.update_finished(job_id, |job| match result {
+            Ok((records, failed_records)) => {
+                job.status = JobStatus::Completed;
+                job.records = Some(records);
+                job.failed_records = Some(failed_records);
+            }
+            Err(error) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(ErrorResponse { error, code: None });
+            }
+        })
+    }
+
+    fn update_finished(&self, job_id: &str, update: impl FnOnce(&mut Job)) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(job_id)?;
+        update(job);
+        job.finished_at = Some(Instant::now());
+        Some(job.clone())
+    }
+
+    /// `None` for unknown & expired jobs, and for jobs submitted by another `client_id`
+    pub fn get(&self, job_id: &str, client_id: Option<&str>) -> Option<Job> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(job_id)
+            .filter(|job| {
+                job.client_id.as_deref() == client_id
+                    && job
+                        .finished_at
+                        .is_none_or(|finished_at| finished_at.elapsed() < self.ttl)
+            })
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job_store(job_ttl_secs: u64, max_jobs: usize) -> JobStore {
+        JobStore::new(&AppConfig {
+            job_ttl_secs,
+            max_jobs,
+            ..AppConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let job_store = job_store(60, 10);
+        let job = job_store.create(None, None).unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.job_id.len(), 32);
+        assert!(job_store.get("unknown", None).is_none());
+
+        job_store.finish(
+            &job.job_id,
+            Ok(EmbedResponse {
+                embeddings: vec![vec![0.5, 0.5]],
+                ..EmbedResponse::default()
+            }),
+        );
+        let job = job_store.get(&job.job_id, None).unwrap();
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.embeddings, Some(vec![vec![0.5, 0.5]]));
+    }
+
+    #[test]
+    fn test_max_jobs_counts_unexpired_jobs_only() {
+        let job_store = job_store(60, 1);
+        let job = job_store.create(None, None).unwrap();
+        assert!(job_store.create(None, None).is_err());
+
+        job_store.finish(
+            &job.job_id,
+            Err(Custom(
+                Status::RequestTimeout,
+                Json(ErrorResponse {
+                    error: "Request timed out".to_string(),
+                    code: None,
+                }),
+            )),
+        );
+        // finished, but not expired yet
+        assert_eq!(
+            job_store.get(&job.job_id, None).unwrap().status,
+            JobStatus::Failed
+        );
+        assert!(job_store.create(None, None).is_err());
+
+        let job_store = JobStore {
+            ttl: Duration::ZERO,
+            ..job_store
+        };
+        assert!(job_store.get(&job.job_id, None).is_none());
+        assert!(job_store.create(None, None).is_ok());
+    }
+
+    #[test]
+    fn test_jobs_are_only_visible_to_their_submitter() {
+        let job_store = job_store(60, 10);
+        let job = job_store.create(None, Some("team-a".to_string())).unwrap();
+        assert!(job_store.get(&job.job_id, Some("team-a")).is_some());
+        assert!(job_store.get(&job.job_id, Some("team-b")).is_none());
+        assert!(job_store.get(&job.job_id, None).is_none());
+
+        let other = job_store.create(None, Some("team-a".to_string())).unwrap();
+        assert_ne!(job.job_id, other.job_id);
+    }
+}
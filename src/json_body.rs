@@ -0,0 +1,129 @@
+//! `/embed` request body guard: Rocket's `Json` by default, simd-json with the `simd-json`
+//! cargo feature (worth it for large multi-input bodies, where parsing shows up in profiles)
+#[cfg(not(feature = "simd-json"))]
+pub type JsonBody<T> = rocket::serde::json::Json<T>;
+#[cfg(feature = "simd-json")]
+pub type JsonBody<T> = SimdJson<T>;
+
+#[cfg(feature = "simd-json")]
+pub use simd::SimdJson;
+
+#[cfg(feature = "simd-json")]
+mod simd {
+    use rocket::data::{self, Data, FromData, Limits};
+    use rocket::http::Status;
+    use rocket::outcome::Outcome;
+    use rocket::request::Request;
+    use serde::de::DeserializeOwned;
+    use simd_json::ErrorType;
+    use std::ops::Deref;
+
+    /// Drop-in for `Json<T>` as data guard, same `json` limit & failure statuses (413 above
+    /// the limit, 400 for malformed JSON, 422 for JSON not matching `T`), rendered by
+    /// the global JSON catcher
+    #[derive(Debug)]
+    pub struct SimdJson<T>(pub T);
+
+    impl<T> SimdJson<T> {
+        pub fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    impl<T> Deref for SimdJson<T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.0
+        }
+    }
+
+    #[rocket::async_trait]
+    impl<'r, T: DeserializeOwned> FromData<'r> for SimdJson<T> {
+        type Error = String;
+
+        async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+            let limit = request.limits().get("json").unwrap_or(Limits::JSON);
+            let mut body = match data.open(limit).into_bytes().await {
+                Ok(body) if body.is_complete() => body.into_inner(),
+                Ok(_) => {
+                    return Outcome::Error((
+                        Status::PayloadTooLarge,
+                        "Body exceeds the json limit".to_string(),
+                    ));
+                }
+                Err(e) => return Outcome::Error((Status::BadRequest, e.to_string())),
+            };
+            // parses in place, hence the owned (mutable) body
+            match simd_json::serde::from_slice(&mut body) {
+                Ok(value) => Outcome::Success(SimdJson(value)),
+                Err(e) if is_data_error(e.error()) => {
+                    Outcome::Error((Status::UnprocessableEntity, e.to_string()))
+                }
+                Err(e) => Outcome::Error((Status::BadRequest, e.to_string())),
+            }
+        }
+    }
+
+    /// Syntax is validated before deserialization starts, i.e., these come from valid JSON
+    fn is_data_error(error: &ErrorType) -> bool {
+        matches!(
+            error,
+            ErrorType::Serde(_)
+                | ErrorType::Unexpected(..)
+                | ErrorType::BadKeyType
+                | ErrorType::ExpectedArray
+                | ErrorType::ExpectedBoolean
+                | ErrorType::ExpectedEnum
+                | ErrorType::ExpectedFloat
+                | ErrorType::ExpectedInteger
+                | ErrorType::ExpectedMap
+                | ErrorType::ExpectedNull
+                | ErrorType::ExpectedNumber
+                | ErrorType::ExpectedSigned
+                | ErrorType::ExpectedString
+                | ErrorType::ExpectedUnsigned
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::types::EmbedRequest;
+        use rocket::local::asynchronous::Client;
+        use rocket::post;
+
+        #[post("/", data = "<request>")]
+        fn echo_inputs(request: SimdJson<EmbedRequest>) -> String {
+            request.inputs.join(",")
+        }
+
+        #[tokio::test]
+        async fn test_simd_json_guard() {
+            let rocket = rocket::build().mount("/", rocket::routes![echo_inputs]);
+            let client = Client::untracked(rocket).await.unwrap();
+
+            let response = client
+                .post("/")
+                .body(r#"{"inputs": ["Hello", "World"]}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.into_string().await.unwrap(), "Hello,World");
+
+            let response = client
+                .post("/")
+                .body(r#"{"inputs": "Hello"}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::UnprocessableEntity);
+
+            let response = client
+                .post("/")
+                .body(r#"{"inputs": ["Hello}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Kafka ingestion mode (`kafka` cargo feature): `{"id": ..., "input": "..."}` records
+//! (same as `POST /embed/bulk` lines) are consumed from `config.kafka_input_topic`, batched
+//! with HTTP traffic at `Priority::Low` & results are produced to `config.kafka_output_topic`
+//! (with the record's key). Offsets are only committed once a result is produced, so records
+//! in flight on a crash or an upstream outage are consumed again (at-least-once)
+use crate::bulk::{BulkRecord, BulkResult};
+use crate::config::{AppConfig, InputOverflow};
+use crate::request_context::RequestContext;
+use crate::request_handler::RequestHandler;
+use crate::types::{EmbedRequest, Priority};
+use log::{error, info, warn};
+use rdkafka::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rocket::futures::StreamExt;
+use rocket::futures::stream::FuturesOrdered;
+use rocket::response::status::Custom;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Records processed at once, enough to fill batches while a failing record
+/// (which blocks later offsets from being committed) doesn't pile up unbounded work
+const MAX_IN_FLIGHT_RECORDS: usize = 1024;
+/// First retry delay of failed embeddings & produce calls, doubled up to `MAX_RETRY_BACKOFF`
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+/// How often an idle consumer checks whether the proxy is draining
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a consumed record came from, its offset is stored once its result is produced
+struct RecordPosition {
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+pub struct KafkaIngestion {
+    consumer: StreamConsumer,
+    producer: Arc<FutureProducer>,
+    output_topic: String,
+}
+
+impl KafkaIngestion {
+    /// `None` unless `config.kafka_brokers` is set
+    pub fn new(config: &AppConfig) -> Result<Option<Self>, String> {
+        let (Some(brokers), Some(input_topic), Some(output_topic)) = (
+            &config.kafka_brokers,
+            &config.kafka_input_topic,
+            &config.kafka_output_topic,
+        ) else {
+            return Ok(None);
+        };
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", &config.kafka_group_id)
+            // offsets are stored per processed record below, librdkafka commits them periodically
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(|e| format!("Failed to create Kafka consumer: {e}"))?;
+        consumer
+            .subscribe(&[input_topic])
+            .map_err(|e| format!("Failed to subscribe to {input_topic}: {e}"))?;
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .create()
+            .map_err(|e| format!("Failed to create Kafka producer: {e}"))?;
+
+        Ok(Some(Self {
+            consumer,
+            producer: Arc::new(producer),
+            output_topic: output_topic.clone(),
+        }))
+    }
+
+    /// Runs until the proxy starts draining, records in flight by then are finished first
+    pub async fn run(self, handler: Arc<RequestHandler>) {
+        info!("Consuming embedding records from Kafka");
+        let mut in_flight: FuturesOrdered<JoinHandle<Option<RecordPosition>>> =
+            FuturesOrdered::new();
+        loop {
+            let draining = handler.is_draining();
+            if draining && in_flight.is_empty() {
+                break;
+            }
+
+            tokio::select! {
+                message = self.consumer.recv(), if !draining && in_flight.len() < MAX_IN_FLIGHT_RECORDS => {
+                    match message {
+                        Ok(message) => {
+                            let position = RecordPosition {
+                                topic: message.topic().to_string(),
+                                partition: message.partition(),
+                                offset: message.offset(),
+                            };
+                            let payload = message.payload().unwrap_or_default().to_vec();
+                            let key = message.key().map(<[u8]>::to_vec);
+                            in_flight.push_back(tokio::spawn(process_record(
+                                handler.clone(),
+                                self.producer.clone(),
+                                self.output_topic.clone(),
+                                position,
+                                key,
+                                payload,
+                            )));
+                        }
+                        Err(e) => warn!("Kafka consumer error: {e}"),
+                    }
+                }
+                // completed in consumption order, so a stored offset never skips an unfinished record
+                Some(finished) = in_flight.next(), if !in_flight.is_empty() => {
+                    match finished {
+                        Ok(Some(position)) => {
+                            if let Err(e) = self.consumer.store_offset(
+                                &position.topic,
+                                position.partition,
+                                position.offset,
+                            ) {
+                                warn!("Failed to store Kafka offset: {e}");
+                            }
+                        }
+                        // not stored, redelivered after a restart
+                        Ok(None) => {}
+                        Err(e) => error!("Kafka record task failed: {e}"),
+                    }
+                }
+                _ = tokio::time::sleep(DRAIN_CHECK_INTERVAL) => {}
+            }
+        }
+
+        if let Err(e) = self
+            .consumer
+            .commit_consumer_state(rdkafka::consumer::CommitMode::Sync)
+        {
+            warn!("Failed to commit Kafka offsets: {e}");
+        }
+        info!("Kafka consumer stopped");
+    }
+}
+
+/// Embeds & produces one record, retrying until both succeed. Invalid records get an `error`
+/// result instead (retrying can't fix them). `None` once the proxy drains before that
+async fn process_record(
+    handler: Arc<RequestHandler>,
+    producer: Arc<FutureProducer>,
+    output_topic: String,
+    position: RecordPosition,
+    key: Option<Vec<u8>>,
+    payload: Vec<u8>,
+) -> Option<RecordPosition> {
+    let context = RequestContext {
+        client_id: Some("kafka".to_string()),
+        request_id: format!(
+            "kafka-{}-{}-{}",
+            position.topic, position.partition, position.offset
+        ),
+        ..RequestContext::default()
+    };
+
+    let mut backoff = RETRY_BACKOFF;
+    let result = loop {
+        match embed_record(&handler, &payload, context.clone()).await {
+            Ok(result) => break result,
+            Err(e) => {
+                if handler.is_draining() {
+                    return None;
+                }
+                warn!(
+                    "{} failed ({e}), retrying in {backoff:?}",
+                    context.request_id
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+            }
+        }
+    };
+
+    let line = serde_json::to_vec(&result).unwrap_or_default();
+    let mut backoff = RETRY_BACKOFF;
+    loop {
+        let mut record = FutureRecord::to(&output_topic).payload(&line);
+        if let Some(key) = &key {
+            record = record.key(key);
+        }
+        match producer.send(record, Duration::from_secs(30)).await {
+            Ok(_) => return Some(position),
+            Err((e, _)) => {
+                if handler.is_draining() {
+                    return None;
+                }
+                warn!(
+                    "Failed to produce {} to {output_topic} ({e}), retrying in {backoff:?}",
+                    context.request_id
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+            }
+        }
+    }
+}
+
+/// `Err` only for (retryable) upstream failures
+async fn embed_record(
+    handler: &RequestHandler,
+    payload: &[u8],
+    context: RequestContext,
+) -> Result<BulkResult, String> {
+    let record = match serde_json::from_slice::<BulkRecord>(payload) {
+        Ok(record) => record,
+        Err(e) => return Ok(BulkResult::failed(Value::Null, e.to_string())),
+    };
+    let mut request = EmbedRequest {
+        inputs: vec![record.input],
+        priority: Priority::Low,
+        ..Default::default()
+    };
+    if let Some(max_input_chars) = handler.config.max_input_chars {
+        if handler.config.input_overflow == InputOverflow::Reject
+            && request.inputs[0].chars().nth(max_input_chars).is_some()
+        {
+            return Ok(BulkResult::failed(
+                record.id,
+                format!("`input` exceeds {max_input_chars} characters"),
+            ));
+        }
+        request.truncate_inputs(max_input_chars);
+    }
+
+    let response = handler
+        .process_request(request, context)
+        .await
+        .map_err(|Custom(_, error)| error.into_inner().error)?;
+    Ok(BulkResult {
+        id: record.id,
+        embedding: response.embeddings.into_iter().next(),
+        error: None,
+        code: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embed_record() {
+        let handler = RequestHandler::new(AppConfig {
+            inference_urls: vec!["mock://dims=4".to_string()],
+            max_wait_time_ms: 10,
+            max_input_chars: Some(5),
+            ..AppConfig::default()
+        })
+        .await
+        .unwrap();
+
+        let payload = br#"{"id": "doc-1", "input": "Hello"}"#;
+        let result = embed_record(&handler, payload, RequestContext::default())
+            .await
+            .unwrap();
+        assert_eq!(result.id, "doc-1");
+        assert_eq!(result.embedding.unwrap().len(), 4);
+
+        // invalid records are answered, not retried
+        let result = embed_record(&handler, b"not json", RequestContext::default())
+            .await
+            .unwrap();
+        assert!(result.error.is_some());
+        let payload = br#"{"id": 2, "input": "Hello World"}"#;
+        let result = embed_record(&handler, payload, RequestContext::default())
+            .await
+            .unwrap();
+        assert_eq!(result.id, 2);
+        assert!(result.error.is_some());
+    }
+}
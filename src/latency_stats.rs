@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Process wide latency percentiles since startup, reported by `GET /stats/latency`
+pub static LATENCY: LatencyStats = LatencyStats::new();
+
+/// Upper bound of the first bucket, in microseconds
+const MIN_MICROS: f64 = 10.0;
+/// Each bucket is 5% wider than the previous one, so estimates are at most 5% too high
+const GROWTH: f64 = 1.05;
+/// Up to ~48min, slower observations land in the last bucket
+const BUCKETS: usize = 400;
+
+/// Streaming percentile estimator over exponentially growing buckets,
+/// constant memory & lock free to record
+#[derive(Debug)]
+pub struct LatencyEstimator {
+    bucket_counts: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl Default for LatencyEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyEstimator {
+    pub const fn new() -> Self {
+        Self {
+            bucket_counts: [const { AtomicU64::new(0) }; BUCKETS],
+            count: AtomicU64::new(0),
+            max_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let micros = latency.as_micros() as f64;
+        let index = if micros <= MIN_MICROS {
+            0
+        } else {
+            ((micros / MIN_MICROS).ln() / GROWTH.ln()).ceil() as usize
+        };
+        self.bucket_counts[index.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max_micros
+            .fetch_max(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// `None` before the first observation
+    pub fn summary(&self) -> Option<LatencySummary> {
+        let counts: Vec<u64> = self
+            .bucket_counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        // bucket counts, not `count`, which might be ahead of them while recording
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let max_ms = self.max_micros.load(Ordering::Relaxed) as f64 / 1000.0;
+        let quantile_ms = |p: f64| {
+            let rank = ((total as f64 * p).ceil() as u64).max(1);
+            let mut cumulative = 0;
+            let index = counts
+                .iter()
+                .position(|count| {
+                    cumulative += count;
+                    cumulative >= rank
+                })
+                .unwrap_or(BUCKETS - 1);
+            let upper_bound_ms = MIN_MICROS * GROWTH.powi(index as i32) / 1000.0;
+            upper_bound_ms.min(max_ms)
+        };
+
+        Some(LatencySummary {
+            count: total,
+            p50_ms: quantile_ms(0.50),
+            p95_ms: quantile_ms(0.95),
+            p99_ms: quantile_ms(0.99),
+            max_ms,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    /// Successful `/embed` requests, from arrival until their response was ready
+    pub end_to_end: LatencyEstimator,
+    /// Per request, from arrival until its batch was dispatched
+    pub queue: LatencyEstimator,
+    /// Per successful upstream call, a bisected batch makes several
+    pub upstream: LatencyEstimator,
+}
+
+/// `GET /stats/latency` response, every summary is `None` until it got its first observation
+#[derive(Serialize, Debug, Clone)]
+pub struct LatencyReport {
+    pub end_to_end: Option<LatencySummary>,
+    pub queue: Option<LatencySummary>,
+    pub upstream: Option<LatencySummary>,
+}
+
+impl LatencyStats {
+    pub const fn new() -> Self {
+        Self {
+            end_to_end: LatencyEstimator::new(),
+            queue: LatencyEstimator::new(),
+            upstream: LatencyEstimator::new(),
+        }
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        LatencyReport {
+            end_to_end: self.end_to_end.summary(),
+            queue: self.queue.summary(),
+            upstream: self.upstream.summary(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_estimator_has_no_summary() {
+        assert!(LatencyEstimator::new().summary().is_none());
+    }
+
+    #[test]
+    fn test_percentiles_within_bucket_error() {
+        let estimator = LatencyEstimator::new();
+        // 1ms..=1000ms, uniformly
+        for ms in 1..=1000 {
+            estimator.record(Duration::from_millis(ms));
+        }
+        let summary = estimator.summary().unwrap();
+        assert_eq!(summary.count, 1000);
+        assert_eq!(summary.max_ms, 1000.0);
+        for (estimate, exact) in [
+            (summary.p50_ms, 500.0),
+            (summary.p95_ms, 950.0),
+            (summary.p99_ms, 990.0),
+        ] {
+            assert!(
+                estimate >= exact && estimate <= exact * GROWTH,
+                "{estimate} not within 5% above {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_outliers_land_in_the_edge_buckets() {
+        let estimator = LatencyEstimator::new();
+        estimator.record(Duration::ZERO);
+        estimator.record(Duration::from_secs(24 * 3600));
+        let summary = estimator.summary().unwrap();
+        assert_eq!(summary.p50_ms, MIN_MICROS / 1000.0);
+        // capped by the actual max, not the last bucket's bound
+        assert_eq!(summary.max_ms, 24.0 * 3600.0 * 1000.0);
+        assert!(summary.p99_ms <= summary.max_ms);
+    }
+}
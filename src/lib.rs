@@ -1,16 +1,71 @@
+pub mod access_log;
+pub mod adaptive_limit;
+pub mod audit_log;
+pub mod auth;
+pub mod backpressure;
 pub mod batch_processor;
+pub mod batch_stats;
+pub mod bench;
+pub mod bulk;
+pub mod canary;
+#[cfg(feature = "candle")]
+pub mod candle_backend;
+pub mod circuit_breaker;
+pub mod comparison;
 pub mod config;
+pub mod dead_letter;
+pub mod debug_capture;
+#[cfg(feature = "grpc")]
+pub mod grpc_client;
 pub mod inference_client;
+#[cfg(feature = "persistent-queue")]
+pub mod job_queue;
+pub mod jobs;
+pub mod json_body;
+#[cfg(feature = "kafka")]
+pub mod kafka_consumer;
+pub mod latency_stats;
+pub mod log_level;
+pub mod metrics;
+pub mod mirror;
+pub mod mock_upstream;
+pub mod models;
+#[cfg(feature = "object-store")]
+pub mod object_storage;
+#[cfg(feature = "onnx")]
+pub mod onnx_backend;
+pub mod pending_queue;
+pub mod postprocessing;
+pub mod preprocessing;
+pub mod queue_state;
+pub mod quota;
+pub mod rate_limiter;
+pub mod replay;
+pub mod request_context;
 pub mod request_handler;
+pub mod retry_budget;
 pub mod routes;
+pub mod scheduler;
+pub mod shadow;
+pub mod streamed_response;
+#[cfg(test)]
+mod stub_upstream;
+pub mod token_counter;
+pub mod tuning;
 pub mod types;
+pub mod upstream_limits;
+pub mod usage;
+pub mod webhooks;
 
+use crate::access_log::AccessLog;
 use crate::config::AppConfig;
 use crate::request_handler::RequestHandler;
 use crate::types::ErrorResponse;
-use rocket::config::LogLevel;
+use rocket::config::{LogLevel, Shutdown, TlsConfig};
+use rocket::data::{ByteUnit, Limits};
+use rocket::fairing::AdHoc;
 use rocket::serde::json::Json;
-use rocket::{Build, Request, Rocket, catch, http::Status};
+use rocket::{Build, Request, Rocket, Route, catch, http::Status};
 use std::sync::Arc;
 
 /// Only catches errors that aren't explicitly handled,
@@ -20,35 +75,179 @@ use std::sync::Arc;
 fn json_error_catcher(status: Status, _req: &Request) -> Json<ErrorResponse> {
     Json(ErrorResponse {
         error: status.reason().unwrap_or("Unknown Error").to_string(),
+        code: None,
     })
 }
 
 /// Builds and configures a Rocket application instance
 /// Accessible from application as well as tests
 pub async fn build_rocket(app_config: AppConfig) -> Rocket<Build> {
+    build_rockets(app_config).await.remove(0)
+}
+
+/// One Rocket instance per listener, since Rocket 0.5 binds a single address: `config.address`
+/// & `config.port` first, then `config.listen`, then `config.admin_listen`.
+/// All of them share the same `RequestHandler`
+pub async fn build_rockets(app_config: AppConfig) -> Vec<Rocket<Build>> {
+    // it's OK to fail earlier in this case, since it's App startup code
+    let handler = RequestHandler::new(app_config)
+        .await
+        .expect("Failed to create RequestHandler");
+    build_rockets_with_handler(handler)
+}
+
+/// `build_rockets` around a customized `RequestHandler`, e.g. one `with_preprocessor`,
+/// so library users can plug in their own hooks without forking
+pub fn build_rockets_with_handler(handler: RequestHandler) -> Vec<Rocket<Build>> {
+    let app_config = &handler.config;
+    let address = app_config.address;
     let port = app_config.port;
+    let listen = app_config.listen.clone();
+    let admin_listen = app_config.admin_listen;
+    let route_prefix = app_config.route_prefix.clone();
+    let limits = app_config.data_limits.iter().fold(
+        Limits::default()
+            .limit("json", ByteUnit::Kibibyte(app_config.max_request_body_kb))
+            .limit(
+                bulk::BULK_LIMIT,
+                ByteUnit::Mebibyte(app_config.max_bulk_body_mb),
+            ),
+        |limits, (name, limit)| limits.limit(name.clone(), ByteUnit::Byte(*limit)),
+    );
+    let keep_alive = app_config.keep_alive_secs;
+    // only informational here, the runtime is sized in `main`
+    let workers = app_config
+        .workers
+        .unwrap_or_else(|| rocket::Config::default().workers);
+    let tls = match (&app_config.tls_cert_path, &app_config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => Some(TlsConfig::from_paths(cert_path, key_path)),
+        _ => None,
+    };
+    // Rocket's grace period starts with the shutdown request, so it must cover our own drain,
+    // otherwise connections waiting for drained batches get cancelled
+    let shutdown = Shutdown {
+        grace: app_config.shutdown_drain_timeout_secs as u32 + Shutdown::default().grace,
+        ..Shutdown::default()
+    };
+    let access_log = app_config.access_log;
     let log_level = if app_config.quiet_mode {
         LogLevel::Off // Silent Rocket (no startup messages)
     } else {
         LogLevel::Normal // Standard Rocket startup messages
     };
 
-    // it's OK to fail earlier in this case, since it's App startup code
-    let handler = Arc::new(
-        RequestHandler::new(app_config)
-            .await
-            .expect("Failed to create RequestHandler"),
-    );
+    let handler = Arc::new(handler);
+    #[cfg(feature = "persistent-queue")]
+    handler
+        .resume_jobs()
+        .expect("Failed to resume persisted jobs");
+
+    #[cfg(feature = "kafka")]
+    let kafka_ingestion = kafka_consumer::KafkaIngestion::new(&handler.config)
+        .expect("Failed to set up Kafka ingestion");
+    #[cfg(feature = "kafka")]
+    let kafka_handler = handler.clone();
+
+    let rocket_config = rocket::Config {
+        address,
+        port,
+        workers,
+        log_level,
+        shutdown,
+        tls,
+        limits,
+        keep_alive,
+        ..rocket::Config::default()
+    };
+
+    let health_routes = rocket::routes![
+        routes::health,
+        routes::livez,
+        routes::readyz,
+        routes::health_deep,
+        routes::health_backends
+    ];
+    let admin_routes = rocket::routes![
+        routes::stats,
+        routes::latency_stats,
+        routes::tuning_stats,
+        routes::metrics,
+        routes::admin_usage,
+        routes::admin_drift,
+        routes::admin_switch_backend,
+        routes::admin_pause_queue,
+        routes::admin_resume_queue,
+        routes::admin_flush_queue,
+        routes::admin_drain,
+        routes::admin_log_level
+    ];
+    let mut routes = rocket::routes![
+        routes::info,
+        routes::embed,
+        routes::embed_sparse,
+        routes::embed_all,
+        routes::embed_bulk,
+        routes::submit_embed_job,
+        routes::embed_job
+    ];
+    #[cfg(feature = "object-store")]
+    routes.extend(rocket::routes![routes::submit_bulk_embed_job]);
+    routes.extend(health_routes.clone());
+    if admin_listen.is_none() {
+        routes.extend(admin_routes.clone());
+    }
+
+    let listener = |config: rocket::Config, routes: Vec<Route>| {
+        let rocket = rocket::build()
+            // available to any route handler via `State<T>` param
+            // same instance is shared across all requests (& listeners)
+            .manage(handler.clone())
+            .mount(route_prefix.as_str(), routes)
+            .register("/", rocket::catchers![json_error_catcher])
+            // Rocket stops accepting new connections before running shutdown fairings.
+            // Every listener waits for the same (single) drain
+            .attach(AdHoc::on_shutdown("Drain pending requests", |rocket| {
+                Box::pin(async move {
+                    if let Some(handler) = rocket.state::<Arc<RequestHandler>>() {
+                        handler.drain().await;
+                    }
+                })
+            }))
+            .configure(config);
+        match access_log {
+            Some(access_log) => rocket.attach(AccessLog::new(access_log)),
+            None => rocket,
+        }
+    };
+
+    let rocket = listener(rocket_config.clone(), routes.clone());
+
+    #[cfg(feature = "kafka")]
+    let rocket = match kafka_ingestion {
+        Some(kafka_ingestion) => rocket.attach(AdHoc::on_liftoff("Kafka consumer", |_| {
+            Box::pin(async move {
+                tokio::spawn(kafka_ingestion.run(kafka_handler));
+            })
+        })),
+        None => rocket,
+    };
 
-    rocket::build()
-        // available to any route handler via `State<T>` param
-        // same instance is shared across all requests
-        .manage(handler)
-        .mount("/", rocket::routes![routes::health, routes::embed])
-        .register("/", rocket::catchers![json_error_catcher])
-        .configure(rocket::Config {
-            port,
-            log_level,
-            ..rocket::Config::default()
-        })
+    let mut rockets = vec![rocket];
+    for address in listen {
+        let config = rocket::Config {
+            address: address.ip(),
+            port: address.port(),
+            ..rocket_config.clone()
+        };
+        rockets.push(listener(config, routes.clone()));
+    }
+    if let Some(address) = admin_listen {
+        let config = rocket::Config {
+            address: address.ip(),
+            port: address.port(),
+            ..rocket_config
+        };
+        rockets.push(listener(config, [health_routes, admin_routes].concat()));
+    }
+    rockets
 }
@@ -0,0 +1,132 @@
+//! `env_logger` with a filter that can be replaced at runtime (`PUT /admin/log-level`),
+//! e.g. bumped to debug during an incident without losing the queue to a restart
+use env_logger::Env;
+use log::{Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::sync::{OnceLock, RwLock};
+
+/// (filter it was built from, logger), only set once `RELOADABLE_LOGGER` is the global logger
+static LOGGER: OnceLock<RwLock<(String, env_logger::Logger)>> = OnceLock::new();
+
+static RELOADABLE_LOGGER: ReloadableLogger = ReloadableLogger;
+
+struct ReloadableLogger;
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        LOGGER
+            .get()
+            .is_some_and(|inner| inner.read().unwrap().1.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(inner) = LOGGER.get() {
+            inner.read().unwrap().1.log(record)
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = LOGGER.get() {
+            inner.read().unwrap().1.flush()
+        }
+    }
+}
+
+/// Only the filter is overridden, `RUST_LOG_STYLE` still applies
+fn build_logger(filter: &str) -> env_logger::Logger {
+    env_logger::Builder::from_env(Env::new().write_style(env_logger::DEFAULT_WRITE_STYLE_ENV))
+        .parse_filters(filter)
+        .build()
+}
+
+/// `PUT /admin/log-level` request body
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LogLevelRequest {
+    /// `RUST_LOG` syntax, e.g. `debug` or `info,auto_batching_proxy::batch_processor=trace`
+    pub level: String,
+}
+
+/// `PUT /admin/log-level` response
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LogLevelChange {
+    pub previous_level: String,
+    pub level: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogLevelError {
+    Invalid(String),
+    /// Running embedded, without the proxy's own logger (see `init`)
+    NotInitialized,
+}
+
+/// Installs the global logger, `RUST_LOG` takes precedence over `default_filter`.
+/// Returns the effective filter
+pub fn init(default_filter: &str) -> String {
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| default_filter.to_string());
+    let logger = build_logger(&filter);
+    let max_level = logger.filter();
+    match log::set_logger(&RELOADABLE_LOGGER) {
+        Ok(()) => {
+            // the max level stays `Off` until here, i.e., no record is dropped in between
+            let _ = LOGGER.set(RwLock::new((filter.clone(), logger)));
+            log::set_max_level(max_level);
+        }
+        Err(e) => log::warn!("Logger was already initialized, `{filter}` isn't applied: {e}"),
+    }
+    filter
+}
+
+/// Replaces the filter of the logger installed by `init`
+pub fn set(filter: &str) -> Result<LogLevelChange, LogLevelError> {
+    let filter = filter.trim();
+    if filter.is_empty() {
+        return Err(LogLevelError::Invalid(
+            "level must not be empty".to_string(),
+        ));
+    }
+    // `env_logger` silently skips invalid directives, so they're validated upfront
+    env_filter::Builder::new()
+        .try_parse(filter)
+        .map_err(|e| LogLevelError::Invalid(format!("Invalid level `{filter}`: {e}")))?;
+    let inner = LOGGER.get().ok_or(LogLevelError::NotInitialized)?;
+
+    let replacement = build_logger(filter);
+    let max_level = replacement.filter();
+    let previous_level = {
+        let mut inner = inner.write().unwrap();
+        std::mem::replace(&mut *inner, (filter.to_string(), replacement)).0
+    };
+    log::set_max_level(max_level);
+    log::info!("Log level changed from `{previous_level}` to `{filter}`");
+
+    Ok(LogLevelChange {
+        previous_level,
+        level: filter.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct OtherLogger;
+
+    impl Log for OtherLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            false
+        }
+
+        fn log(&self, _: &Record) {}
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_set_without_own_logger() {
+        // e.g. Rocket's logger installed first when embedded, ignored if one already is
+        let _ = log::set_logger(&OtherLogger);
+        init("info");
+        assert_eq!(set("debug"), Err(LogLevelError::NotInitialized));
+    }
+}
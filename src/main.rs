@@ -1,55 +1,337 @@
 use auto_batching_proxy::{
-    build_rocket,
-    config::{AppConfig, Args},
+    bench, build_rockets,
+    config::{AppConfig, Args, Command},
+    replay,
 };
 use clap::Parser;
-use log::info;
-use rocket::{Build, Rocket, launch};
+use log::{info, warn};
+use rocket::Rocket;
+use rocket::futures::future::try_join_all;
 
-#[launch]
-async fn rocket() -> Rocket<Build> {
-    let args = Args::parse();
+fn main() {
+    let mut args = Args::parse();
+    let command = args.command.take();
+    if command == Some(Command::PrintConfig) {
+        print!("{}", Args::example_config_file());
+        return;
+    }
+    let check_config = args.check_config;
     let config = AppConfig::build(Some(args)).unwrap_or_else(|err| {
         println!("Configuration error: {err:?}");
         std::process::exit(1);
     });
 
+    // instead of `#[rocket::main]`, which sizes its runtime before `config.workers` is known
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = config.workers {
+        runtime.worker_threads(workers);
+    }
+    let runtime = runtime
+        .thread_name("rocket-worker-thread")
+        .enable_all()
+        .build()
+        .unwrap_or_else(|err| {
+            println!("Runtime error: {err}");
+            std::process::exit(1);
+        });
+    runtime.block_on(run(config, command, check_config));
+}
+
+async fn run(config: AppConfig, command: Option<Command>, check_config: bool) {
+    if check_config {
+        print_config(&config);
+        let problems = config.check();
+        for problem in &problems {
+            println!("Configuration problem: {problem}");
+        }
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+        println!("Configuration OK");
+        return;
+    }
+
+    if let Some(Command::Bench(bench_args)) = command {
+        if let Err(err) = bench::run(config, bench_args).await {
+            println!("Benchmark error: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::Replay(replay_args)) = command {
+        if let Err(err) = replay::run(replay_args).await {
+            println!("Replay error: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Initialize logging and get effective log level
     let _effective_log_level = config.init_logging();
 
     info!("🚀 Starting auto-batching proxy server...");
 
+    for problem in config.check() {
+        warn!("Configuration problem: {problem}");
+    }
+    print_config(&config);
+
+    // a listener failing (e.g. to bind) stops the others too
+    let listeners = build_rockets(config).await.into_iter().map(Rocket::launch);
+    if let Err(err) = try_join_all(listeners).await {
+        println!("Launch error: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// Effective configuration, secrets redacted
+fn print_config(config: &AppConfig) {
     // single print syscall
     println!(
         "Server Configuration:
   port: {}
+  address: {}
+  listen: {:?}
+  admin_listen: {:?}
+  tls_cert_path: {:?}
+  route_prefix: {}
+  max_request_body_kb: {}
+  max_bulk_body_mb: {}
+  keep_alive_secs: {}
+  data_limits: {:?}
+  workers: {:?}
+  batch_runtime_threads: {:?}
   Batch Settings:
     max_batch_size: {}
     max_wait_time_ms: {}
     batch_check_interval_ms: {}
+    scheduling_mode: {:?}
+    fair_scheduling: {}
+    deadline_flush_margin_ms: {}
   Inference:
-    inference_url: {}
+    inference_urls: {:?}
+    inference_protocol: {:?}
+    inference_api_key: {}
+    inference_headers: {:?}
+    inference_client_cert_path: {:?}
+    inference_ca_cert_path: {:?}
+    inference_tls_insecure: {}
+    inference_proxy_url: {}
+    inference_http2_prior_knowledge: {}
+    inference_pool_max_idle_per_host: {:?}
+    inference_pool_idle_timeout_secs: {:?}
+    inference_tcp_keepalive_secs: {:?}
     inference_timeout_secs: {}
+    upstream_connect_timeout_ms: {:?}
+    upstream_request_timeout_ms: {:?}
+    upstream_request_timeout_per_input_ms: {}
+    inference_max_retries: {}
+    inference_retry_backoff_ms: {}
+    retry_budget_percent: {:?}
+    hedge_requests: {}
+    shadow_inference_url: {:?}
+    canary_inference_url: {:?}
+    canary_percent: {}
+    compare_inference_url: {:?}
+    models: {:?}
+    endpoints: {:?}
+    model_aliases: {:?}
+    default_model: {:?}
+    wait_for_upstream: {}
+    wait_for_upstream_timeout_secs: {}
+    warmup_calls: {}
+    health_check_interval_secs: {}
+    upstream_dns_refresh_secs: {:?}
+    health_check_healthy_threshold: {}
+    circuit_breaker_error_rate: {:?}
+    circuit_breaker_cooldown_secs: {}
+    max_in_flight_per_backend: {:?}
     max_inference_inputs: {}
+    split_oversized_requests: {}
+    dedup_batch_inputs: {}
+    max_input_chars: {:?}
+    input_overflow: {:?}
+    preprocess: {:?}
+    preprocess_max_chars: {:?}
+    profanity_words: {}
+    postprocess: {:?}
+    projection_matrix_path: {:?}
+    postprocess_decimals: {:?}
+    max_batch_tokens: {:?}
+    discover_upstream_limits: {}
+    tokenizer_path: {:?}
+    latency_slo_ms: {:?}
+    slow_log_threshold_ms: {:?}
+  Queue:
+    load_shed_queue_depth: {:?}
+    load_shed_max_age_ms: {:?}
+    max_queue_age_ms: {:?}
+    rate_limit_requests_per_sec: {:?}
+    rate_limit_inputs_per_sec: {:?}
+    quota_daily_inputs: {:?}
+    quota_monthly_inputs: {:?}
+    usage_window_secs: {}
+    audit_log_path: {:?}
+    audit_log_max_size_mb: {}
+    audit_log_max_files: {}
+    debug_capture_path: {:?}
+    debug_capture_sample_rate: {}
+    debug_capture_redact_pii: {}
+    dead_letter_path: {:?}
+    dead_letter_max_size_mb: {}
+    record_traffic_path: {:?}
+    mirror_url: {:?}
+    mirror_include_inputs: {}
+    mirror_queue_size: {}
+    request_timeout_secs: {}
+    shutdown_drain_timeout_secs: {}
+    job_ttl_secs: {}
+    max_jobs: {}
+    job_queue_path: {:?}
+    webhook_secret: {}
+    webhook_max_retries: {}
+    webhook_include_embeddings: {}
+    kafka_brokers: {:?}
+    kafka_input_topic: {:?}
+    kafka_output_topic: {:?}
+    kafka_group_id: {}
   Options:
     include_batch_info: {}
     log_level: {}
+    access_log: {:?}
     quiet_mode: {}
+    api_keys: {} configured
+    admin_api_key: {}
 ",
         config.port,
+        config.address,
+        config.listen,
+        config.admin_listen,
+        config.tls_cert_path,
+        config.route_prefix,
+        config.max_request_body_kb,
+        config.max_bulk_body_mb,
+        config.keep_alive_secs,
+        config.data_limits,
+        config.workers,
+        config.batch_runtime_threads,
         //
         config.max_batch_size,
         config.max_wait_time_ms,
         config.batch_check_interval_ms,
+        config.scheduling_mode,
+        config.fair_scheduling,
+        config.deadline_flush_margin_ms,
         //
-        config.inference_url,
+        config.inference_urls,
+        config.inference_protocol,
+        if config.inference_api_key.is_some() {
+            "<redacted>"
+        } else {
+            "None"
+        },
+        config.inference_headers.keys().collect::<Vec<_>>(),
+        config.inference_client_cert_path,
+        config.inference_ca_cert_path,
+        config.inference_tls_insecure,
+        // may contain proxy credentials
+        if config.inference_proxy_url.is_some() {
+            "<redacted>"
+        } else {
+            "None"
+        },
+        config.inference_http2_prior_knowledge,
+        config.inference_pool_max_idle_per_host,
+        config.inference_pool_idle_timeout_secs,
+        config.inference_tcp_keepalive_secs,
         config.inference_timeout_secs,
+        config.upstream_connect_timeout_ms,
+        config.upstream_request_timeout_ms,
+        config.upstream_request_timeout_per_input_ms,
+        config.inference_max_retries,
+        config.inference_retry_backoff_ms,
+        config.retry_budget_percent,
+        config.hedge_requests,
+        config.shadow_inference_url,
+        config.canary_inference_url,
+        config.canary_percent,
+        config.compare_inference_url,
+        config.models,
+        config.endpoints,
+        config.model_aliases,
+        config.default_model,
+        config.wait_for_upstream,
+        config.wait_for_upstream_timeout_secs,
+        config.warmup_calls,
+        config.health_check_interval_secs,
+        config.upstream_dns_refresh_secs,
+        config.health_check_healthy_threshold,
+        config.circuit_breaker_error_rate,
+        config.circuit_breaker_cooldown_secs,
+        config.max_in_flight_per_backend,
         config.max_inference_inputs,
+        config.split_oversized_requests,
+        config.dedup_batch_inputs,
+        config.max_input_chars,
+        config.input_overflow,
+        config.preprocess,
+        config.preprocess_max_chars,
+        config.profanity_words.len(),
+        config.postprocess,
+        config.projection_matrix_path,
+        config.postprocess_decimals,
+        config.max_batch_tokens,
+        config.discover_upstream_limits,
+        config.tokenizer_path,
+        config.latency_slo_ms,
+        config.slow_log_threshold_ms,
+        //
+        config.load_shed_queue_depth,
+        config.load_shed_max_age_ms,
+        config.max_queue_age_ms,
+        config.rate_limit_requests_per_sec,
+        config.rate_limit_inputs_per_sec,
+        config.quota_daily_inputs,
+        config.quota_monthly_inputs,
+        config.usage_window_secs,
+        config.audit_log_path,
+        config.audit_log_max_size_mb,
+        config.audit_log_max_files,
+        config.debug_capture_path,
+        config.debug_capture_sample_rate,
+        config.debug_capture_redact_pii,
+        config.dead_letter_path,
+        config.dead_letter_max_size_mb,
+        config.record_traffic_path,
+        config.mirror_url,
+        config.mirror_include_inputs,
+        config.mirror_queue_size,
+        config.request_timeout_secs,
+        config.shutdown_drain_timeout_secs,
+        config.job_ttl_secs,
+        config.max_jobs,
+        config.job_queue_path,
+        if config.webhook_secret.is_some() {
+            "<redacted>"
+        } else {
+            "None"
+        },
+        config.webhook_max_retries,
+        config.webhook_include_embeddings,
+        config.kafka_brokers,
+        config.kafka_input_topic,
+        config.kafka_output_topic,
+        config.kafka_group_id,
         //
         config.include_batch_info,
         config.log_level,
-        config.quiet_mode
+        config.access_log,
+        config.quiet_mode,
+        config.api_keys.len(),
+        if config.admin_api_key.is_some() {
+            "<redacted>"
+        } else {
+            "None"
+        }
     );
-
-    build_rocket(config).await
 }
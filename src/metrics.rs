@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Process wide counters, rendered in Prometheus text format by `GET /metrics`
+pub static METRICS: Metrics = Metrics::new();
+
+/// Powers of two up to well above any sensible `max_batch_size` / `max_inference_inputs`
+const BATCH_SIZE_BUCKETS: [f64; 10] = [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0, 512.0];
+/// Seconds, `max_wait_time_ms` is typically in the 10-1000ms range
+const WAIT_TIME_BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+/// Distinct `client_tag` label values, requests of further tags are counted as `OTHER_CLIENT_TAGS`
+const MAX_CLIENT_TAGS: usize = 100;
+/// Not a valid `client_tag` itself, so it can't collide with one
+const OTHER_CLIENT_TAGS: &str = "(other)";
+/// Seconds, inference calls of a batch
+const INFERENCE_TIME_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug)]
+pub struct Metrics {
+    /// Batches where the inference service returned a different number of embeddings than inputs
+    pub embedding_count_mismatches: AtomicU64,
+    /// Requests per dispatched batch
+    pub batch_size: Histogram<10>,
+    /// Inputs per dispatched batch
+    pub batch_inputs: Histogram<10>,
+    /// How long the oldest request of a batch waited until the batch was dispatched
+    pub batch_wait_seconds: Histogram<11>,
+    /// Repeated inputs of batches that were sent upstream only once, see `config.dedup_batch_inputs`
+    pub deduplicated_inputs: AtomicU64,
+    /// Retried upstream calls of batches
+    pub inference_retries: AtomicU64,
+    /// Retries not sent, because `config.retry_budget_percent` was used up
+    pub retry_budget_exhausted: AtomicU64,
+    /// Batches sent to `config.shadow_inference_url`
+    pub shadow_batches: AtomicU64,
+    /// Shadow batches that failed, no client saw these
+    pub shadow_errors: AtomicU64,
+    /// Batches not shadowed, because too many shadow calls were already in flight
+    pub shadow_skipped: AtomicU64,
+    /// Successful shadow calls only
+    pub shadow_latency_seconds: Histogram<11>,
+    /// Batches sent to `config.inference_urls` while canary routing is on
+    pub canary_primary: TargetMetrics,
+    /// Batches routed to `config.canary_inference_url`
+    pub canary: TargetMetrics,
+    /// Finished requests per `EmbedRequest::client_tag`
+    client_tag_requests: Mutex<BTreeMap<String, u64>>,
+}
+
+/// Per upstream target (`target` label) counters
+#[derive(Debug)]
+pub struct TargetMetrics {
+    pub batches: AtomicU64,
+    pub errors: AtomicU64,
+    /// Successful calls only
+    pub latency_seconds: Histogram<11>,
+}
+
+impl TargetMetrics {
+    const fn new() -> Self {
+        Self {
+            batches: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            latency_seconds: Histogram::new(INFERENCE_TIME_BUCKETS),
+        }
+    }
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            embedding_count_mismatches: AtomicU64::new(0),
+            batch_size: Histogram::new(BATCH_SIZE_BUCKETS),
+            batch_inputs: Histogram::new(BATCH_SIZE_BUCKETS),
+            batch_wait_seconds: Histogram::new(WAIT_TIME_BUCKETS),
+            deduplicated_inputs: AtomicU64::new(0),
+            inference_retries: AtomicU64::new(0),
+            retry_budget_exhausted: AtomicU64::new(0),
+            shadow_batches: AtomicU64::new(0),
+            shadow_errors: AtomicU64::new(0),
+            shadow_skipped: AtomicU64::new(0),
+            shadow_latency_seconds: Histogram::new(INFERENCE_TIME_BUCKETS),
+            canary_primary: TargetMetrics::new(),
+            canary: TargetMetrics::new(),
+            client_tag_requests: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_client_tag(&self, client_tag: &str) {
+        let mut client_tag_requests = self.client_tag_requests.lock().unwrap();
+        let client_tag = if client_tag_requests.contains_key(client_tag)
+            || client_tag_requests.len() < MAX_CLIENT_TAGS
+        {
+            client_tag
+        } else {
+            OTHER_CLIENT_TAGS
+        };
+        *client_tag_requests
+            .entry(client_tag.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        Self::write_counter(
+            &mut output,
+            "proxy_embedding_count_mismatches_total",
+            "Batches failed because the embedding count didn't match the input count",
+            &self.embedding_count_mismatches,
+        );
+        self.batch_size.write(
+            &mut output,
+            "proxy_batch_size",
+            "Requests per dispatched batch",
+        );
+        self.batch_inputs.write(
+            &mut output,
+            "proxy_batch_inputs",
+            "Inputs per dispatched batch",
+        );
+        self.batch_wait_seconds.write(
+            &mut output,
+            "proxy_batch_wait_seconds",
+            "Time the oldest request of a batch waited until dispatch",
+        );
+        Self::write_counter(
+            &mut output,
+            "proxy_deduplicated_inputs_total",
+            "Repeated inputs of batches not sent upstream again",
+            &self.deduplicated_inputs,
+        );
+        Self::write_counter(
+            &mut output,
+            "proxy_inference_retries_total",
+            "Upstream calls of batches retried after transient failures",
+            &self.inference_retries,
+        );
+        Self::write_counter(
+            &mut output,
+            "proxy_retry_budget_exhausted_total",
+            "Retries not sent because the retry budget was used up",
+            &self.retry_budget_exhausted,
+        );
+        Self::write_counter(
+            &mut output,
+            "proxy_shadow_batches_total",
+            "Batches mirrored to the shadow inference backend",
+            &self.shadow_batches,
+        );
+        Self::write_counter(
+            &mut output,
+            "proxy_shadow_errors_total",
+            "Shadow inference calls that failed",
+            &self.shadow_errors,
+        );
+        Self::write_counter(
+            &mut output,
+            "proxy_shadow_skipped_total",
+            "Batches not shadowed because too many shadow calls were in flight",
+            &self.shadow_skipped,
+        );
+        self.shadow_latency_seconds.write(
+            &mut output,
+            "proxy_shadow_latency_seconds",
+            "Latency of successful shadow inference calls",
+        );
+        self.write_targets(&mut output);
+        self.write_client_tags(&mut output);
+        output
+    }
+
+    fn write_client_tags(&self, output: &mut String) {
+        let name = "proxy_client_tag_requests_total";
+        Self::write_header(
+            output,
+            name,
+            "Finished requests per client_tag of the request body",
+            "counter",
+        );
+        // validated to be safe as label values, see `EmbedRequest::is_valid_client_tag`
+        for (client_tag, requests) in self.client_tag_requests.lock().unwrap().iter() {
+            let _ = writeln!(output, "{name}{{client_tag=\"{client_tag}\"}} {requests}");
+        }
+    }
+
+    fn write_targets(&self, output: &mut String) {
+        let targets = [("primary", &self.canary_primary), ("canary", &self.canary)];
+        let name = "proxy_target_batches_total";
+        Self::write_header(
+            output,
+            name,
+            "Batches per upstream target with canary routing",
+            "counter",
+        );
+        for (label, target) in targets {
+            let batches = target.batches.load(Ordering::Relaxed);
+            let _ = writeln!(output, "{name}{{target=\"{label}\"}} {batches}");
+        }
+
+        let name = "proxy_target_errors_total";
+        Self::write_header(
+            output,
+            name,
+            "Failed batches per upstream target with canary routing",
+            "counter",
+        );
+        for (label, target) in targets {
+            let errors = target.errors.load(Ordering::Relaxed);
+            let _ = writeln!(output, "{name}{{target=\"{label}\"}} {errors}");
+        }
+
+        let name = "proxy_target_latency_seconds";
+        Self::write_header(
+            output,
+            name,
+            "Latency of successful calls per upstream target with canary routing",
+            "histogram",
+        );
+        for (label, target) in targets {
+            target
+                .latency_seconds
+                .write_series(output, name, &format!("target=\"{label}\","));
+        }
+    }
+
+    fn write_header(output: &mut String, name: &str, help: &str, metric_type: &str) {
+        let _ = writeln!(output, "# HELP {name} {help}");
+        let _ = writeln!(output, "# TYPE {name} {metric_type}");
+    }
+
+    fn write_counter(output: &mut String, name: &str, help: &str, counter: &AtomicU64) {
+        Self::write_header(output, name, help, "counter");
+        let _ = writeln!(output, "{name} {}", counter.load(Ordering::Relaxed));
+    }
+}
+
+/// Prometheus histogram with fixed upper bounds, observations above the last one
+/// only show up in the implicit `+Inf` bucket
+#[derive(Debug)]
+pub struct Histogram<const N: usize> {
+    upper_bounds: [f64; N],
+    /// Non-cumulative, cumulated on render
+    bucket_counts: [AtomicU64; N],
+    count: AtomicU64,
+    /// `f64` bits
+    sum: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    const fn new(upper_bounds: [f64; N]) -> Self {
+        Self {
+            upper_bounds,
+            bucket_counts: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        if let Some(index) = self.upper_bounds.iter().position(|bound| value <= *bound) {
+            self.bucket_counts[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    fn write(&self, output: &mut String, name: &str, help: &str) {
+        Metrics::write_header(output, name, help, "histogram");
+        self.write_series(output, name, "");
+    }
+
+    /// `labels` (e.g. `target="canary",`) are prepended to the `le` label
+    fn write_series(&self, output: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket_count) in self.upper_bounds.iter().zip(&self.bucket_counts) {
+            cumulative += bucket_count.load(Ordering::Relaxed);
+            let _ = writeln!(
+                output,
+                "{name}_bucket{{{labels}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(output, "{name}_bucket{{{labels}le=\"+Inf\"}} {count}");
+        let sum = f64::from_bits(self.sum.load(Ordering::Relaxed));
+        let labels = labels.trim_end_matches(',');
+        let labels = match labels.is_empty() {
+            true => String::new(),
+            false => format!("{{{labels}}}"),
+        };
+        let _ = writeln!(output, "{name}_sum{labels} {sum}");
+        let _ = writeln!(output, "{name}_count{labels} {count}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text_format() {
+        let metrics = Metrics::new();
+        metrics
+            .embedding_count_mismatches
+            .fetch_add(2, Ordering::Relaxed);
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE proxy_embedding_count_mismatches_total counter\n"));
+        assert!(output.contains("proxy_embedding_count_mismatches_total 2\n"));
+        assert!(output.contains("# TYPE proxy_batch_size histogram\n"));
+        assert!(output.contains("# TYPE proxy_batch_wait_seconds histogram\n"));
+    }
+
+    #[test]
+    fn test_client_tag_labels_are_capped() {
+        let metrics = Metrics::new();
+        metrics.record_client_tag("search");
+        for index in 0..MAX_CLIENT_TAGS {
+            metrics.record_client_tag(&format!("job-{index}"));
+        }
+        metrics.record_client_tag("search");
+
+        let output = metrics.render();
+        assert!(output.contains(
+            "proxy_client_tag_requests_total{client_tag=\"search\"} 2
+"
+        ));
+        assert!(output.contains(
+            "proxy_client_tag_requests_total{client_tag=\"job-0\"} 1
+"
+        ));
+        assert!(output.contains(
+            "proxy_client_tag_requests_total{client_tag=\"(other)\"} 1
+"
+        ));
+    }
+
+    #[test]
+    fn test_render_target_labels() {
+        let metrics = Metrics::new();
+        metrics.canary.batches.fetch_add(3, Ordering::Relaxed);
+        metrics.canary.latency_seconds.observe(0.02);
+
+        let output = metrics.render();
+        assert!(output.contains("proxy_target_batches_total{target=\"primary\"} 0\n"));
+        assert!(output.contains("proxy_target_batches_total{target=\"canary\"} 3\n"));
+        assert!(
+            output.contains(
+                "proxy_target_latency_seconds_bucket{target=\"canary\",le=\"0.025\"} 1\n"
+            )
+        );
+        assert!(output.contains("proxy_target_latency_seconds_count{target=\"canary\"} 1\n"));
+        assert_eq!(
+            output
+                .matches("# TYPE proxy_target_latency_seconds ")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new([1.0, 4.0, 16.0]);
+        for value in [1.0, 3.0, 4.0, 10.0, 100.0] {
+            histogram.observe(value);
+        }
+
+        let mut output = String::new();
+        histogram.write(&mut output, "test", "Test histogram");
+        assert!(output.contains("test_bucket{le=\"1\"} 1\n"));
+        assert!(output.contains("test_bucket{le=\"4\"} 3\n"));
+        assert!(output.contains("test_bucket{le=\"16\"} 4\n"));
+        assert!(output.contains("test_bucket{le=\"+Inf\"} 5\n"));
+        assert!(output.contains("test_sum 118\n"));
+        assert!(output.contains("test_count 5\n"));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::config::AppConfig;
+use crate::types::Priority;
+use log::{debug, warn};
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// `kafka://<brokers>/<topic>` mirror sink, brokers comma separated
+pub const MIRROR_KAFKA_SCHEME: &str = "kafka://";
+/// Records sent per HTTP POST at most
+const MIRROR_BATCH_SIZE: usize = 500;
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Mirrored per `/embed` request that reached the queue
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MirrorRecord {
+    /// Unix epoch milliseconds
+    pub timestamp_ms: u64,
+    pub request_id: String,
+    /// Client IP or masked API key, same as in `GET /admin/usage`
+    pub caller: String,
+    pub priority: Priority,
+    pub input_count: usize,
+    /// Total over all inputs
+    pub input_chars: usize,
+    pub status: u16,
+    /// Queue wait + inference
+    pub latency_ms: u64,
+    pub batch_id: Option<u64>,
+    /// Only with `config.mirror_include_inputs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<Vec<String>>,
+}
+
+impl MirrorRecord {
+    pub fn timestamp_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+/// `(brokers, topic)` of a `kafka://<brokers>/<topic>` URL
+pub fn parse_kafka_url(url: &str) -> Result<(&str, &str), String> {
+    url.strip_prefix(MIRROR_KAFKA_SCHEME)
+        .and_then(|rest| rest.rsplit_once('/'))
+        .filter(|(brokers, topic)| !brokers.is_empty() && !topic.is_empty())
+        .ok_or_else(|| format!("`{url}` must be {MIRROR_KAFKA_SCHEME}<brokers>/<topic>"))
+}
+
+enum Sink {
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+    #[cfg(feature = "kafka")]
+    Kafka {
+        producer: rdkafka::producer::FutureProducer,
+        topic: String,
+    },
+}
+
+impl Sink {
+    fn new(url: &str) -> Result<Self, String> {
+        if url.starts_with(MIRROR_KAFKA_SCHEME) {
+            #[cfg(feature = "kafka")]
+            {
+                let (brokers, topic) = parse_kafka_url(url)?;
+                let producer = rdkafka::ClientConfig::new()
+                    .set("bootstrap.servers", brokers)
+                    .create()
+                    .map_err(|e| format!("Failed to create mirror Kafka producer: {e}"))?;
+                return Ok(Self::Kafka {
+                    producer,
+                    topic: topic.to_string(),
+                });
+            }
+            #[cfg(not(feature = "kafka"))]
+            return Err(format!(
+                "{MIRROR_KAFKA_SCHEME} mirror_url requires the `kafka` cargo feature"
+            ));
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(MIRROR_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create mirror client: {e}"))?;
+        Ok(Self::Http {
+            client,
+            url: url.to_string(),
+        })
+    }
+
+    /// Best effort, failed records aren't retried
+    async fn send(&self, records: &[MirrorRecord]) -> Result<(), String> {
+        match self {
+            Self::Http { client, url } => {
+                let body: String = records
+                    .iter()
+                    .filter_map(|record| serde_json::to_string(record).ok())
+                    .map(|line| line + "\n")
+                    .collect();
+                let response = client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if !response.status().is_success() {
+                    return Err(format!("HTTP {}", response.status()));
+                }
+                Ok(())
+            }
+            #[cfg(feature = "kafka")]
+            Self::Kafka { producer, topic } => {
+                use rdkafka::producer::FutureRecord;
+
+                let payloads: Vec<_> = records
+                    .iter()
+                    .map(|record| serde_json::to_vec(record).unwrap_or_default())
+                    .collect();
+                let deliveries = records.iter().zip(&payloads).map(|(record, payload)| {
+                    producer.send(
+                        FutureRecord::to(topic)
+                            .key(&record.request_id)
+                            .payload(payload),
+                        MIRROR_TIMEOUT,
+                    )
+                });
+                let failed = rocket::futures::future::join_all(deliveries)
+                    .await
+                    .into_iter()
+                    .filter(Result::is_err)
+                    .count();
+                if failed > 0 {
+                    return Err(format!("{failed} records weren't produced"));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Mirrors request metadata to an analytics sink (`config.mirror_url`) for offline analysis
+///
+/// Records are sent from a background task, a slow or unreachable sink never delays requests:
+/// once `config.mirror_queue_size` records are buffered, further ones are dropped
+pub struct Mirror {
+    sender: mpsc::Sender<MirrorRecord>,
+    include_inputs: bool,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Mirror {
+    pub fn new(config: &AppConfig) -> Result<Option<Self>, String> {
+        let Some(url) = &config.mirror_url else {
+            return Ok(None);
+        };
+        let sink = Sink::new(url)?;
+        let (sender, receiver) = mpsc::channel(config.mirror_queue_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(Self::run(sink, receiver, dropped.clone()));
+
+        Ok(Some(Self {
+            sender,
+            include_inputs: config.mirror_include_inputs,
+            dropped,
+        }))
+    }
+
+    /// Whether callers should pass inputs along (they're cloned before queueing)
+    pub fn include_inputs(&self) -> bool {
+        self.include_inputs
+    }
+
+    pub fn record(&self, record: MirrorRecord) {
+        if self.sender.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records queued meanwhile are sent together, up to `MIRROR_BATCH_SIZE` at once
+    async fn run(sink: Sink, mut receiver: mpsc::Receiver<MirrorRecord>, dropped: Arc<AtomicU64>) {
+        let mut records = Vec::with_capacity(MIRROR_BATCH_SIZE);
+        while receiver.recv_many(&mut records, MIRROR_BATCH_SIZE).await > 0 {
+            match sink.send(&records).await {
+                Ok(()) => debug!("Mirrored {} records", records.len()),
+                Err(e) => warn!("Failed to mirror {} records: {e}", records.len()),
+            }
+            records.clear();
+
+            let dropped = dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("Mirror queue was full, dropped {dropped} records");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kafka_url() {
+        assert_eq!(
+            parse_kafka_url("kafka://broker-1:9092,broker-2:9092/embed-requests"),
+            Ok(("broker-1:9092,broker-2:9092", "embed-requests"))
+        );
+        assert!(parse_kafka_url("kafka://broker-1:9092").is_err());
+        assert!(parse_kafka_url("kafka:///embed-requests").is_err());
+        assert!(parse_kafka_url("https://broker-1/embed-requests").is_err());
+    }
+}
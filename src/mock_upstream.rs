@@ -0,0 +1,190 @@
+use crate::types::{BatchRequest, BatchResponse, SparseEmbedding, SparseValue, TokenEmbeddings};
+use serde_json::json;
+use std::time::Duration;
+
+pub const MOCK_SCHEME: &str = "mock://";
+/// Used by `--mock-upstream true`, same dimension as e.g. `all-MiniLM-L6-v2`
+pub const DEFAULT_MOCK_URL: &str = "mock://dims=384";
+
+/// In-process stand-in for the inference service (`inference_url = "mock://dims=384&latency_ms=20"`),
+/// returns deterministic synthetic embeddings, so the proxy runs without a TEI instance
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockUpstream {
+    dims: usize,
+    /// Simulated inference time per batch
+    latency: Duration,
+}
+
+impl MockUpstream {
+    /// `None` for non-mock URLs
+    pub fn parse(url: &str) -> Option<Result<Self, String>> {
+        let params = url.strip_prefix(MOCK_SCHEME)?;
+        Some(Self::parse_params(params).map_err(|e| format!("Invalid mock URL `{url}`: {e}")))
+    }
+
+    fn parse_params(params: &str) -> Result<Self, String> {
+        let mut mock = Self {
+            dims: 384,
+            latency: Duration::ZERO,
+        };
+        for param in params.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("`{param}` must be key=value"))?;
+            let value: u64 = value
+                .parse()
+                .map_err(|_| format!("`{key}` must be a non-negative number"))?;
+            match key {
+                "dims" if value > 0 => mock.dims = value as usize,
+                "dims" => return Err("`dims` must be > 0".to_string()),
+                "latency_ms" => mock.latency = Duration::from_millis(value),
+                _ => return Err(format!("unknown parameter `{key}`")),
+            }
+        }
+        Ok(mock)
+    }
+
+    pub async fn embed(&self, request: &BatchRequest) -> BatchResponse {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        request
+            .inputs
+            .iter()
+            .map(|input| self.embedding(input))
+            .collect()
+    }
+
+    /// Positive dimensions of `embed`'s vectors, like the ReLU'd term weights of SPLADE models
+    pub async fn embed_sparse(&self, request: &BatchRequest) -> Vec<SparseEmbedding> {
+        self.embed(request)
+            .await
+            .into_iter()
+            .map(|embedding| {
+                embedding
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, value)| *value > 0.0)
+                    .map(|(index, value)| SparseValue { index, value })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// One vector per whitespace separated word (at least one per input), standing in for tokens
+    pub async fn embed_all(&self, request: &BatchRequest) -> Vec<TokenEmbeddings> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        request
+            .inputs
+            .iter()
+            .map(|input| {
+                let mut words = input.split_whitespace().peekable();
+                if words.peek().is_none() {
+                    return vec![self.embedding(input)];
+                }
+                words.map(|word| self.embedding(word)).collect()
+            })
+            .collect()
+    }
+
+    /// Same input always maps to the same unit length vector
+    fn embedding(&self, input: &str) -> Vec<f32> {
+        // FNV-1a, stable across Rust versions unlike `DefaultHasher`
+        let mut state = input.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        let mut embedding: Vec<f32> = (0..self.dims)
+            .map(|_| {
+                // xorshift64, `state` is never 0 for FNV-1a output in practice
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state as f64 / u64::MAX as f64 * 2.0 - 1.0) as f32
+            })
+            .collect();
+
+        let norm = embedding
+            .iter()
+            .map(|value| value * value)
+            .sum::<f32>()
+            .sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|value| *value /= norm);
+        }
+        embedding
+    }
+
+    /// Mimics TEI `/info`
+    pub fn info(&self) -> serde_json::Value {
+        json!({
+            "model_id": "mock",
+            "dims": self.dims,
+            "latency_ms": self.latency.as_millis() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(MockUpstream::parse("http://127.0.0.1:8080/embed").is_none());
+        assert_eq!(
+            MockUpstream::parse("mock://dims=8&latency_ms=20").unwrap(),
+            Ok(MockUpstream {
+                dims: 8,
+                latency: Duration::from_millis(20),
+            })
+        );
+        assert_eq!(MockUpstream::parse("mock://").unwrap().unwrap().dims, 384);
+        for invalid in [
+            "mock://dims=0",
+            "mock://dims=abc",
+            "mock://size=8",
+            "mock://dims",
+        ] {
+            assert!(MockUpstream::parse(invalid).unwrap().is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_is_deterministic_and_normalized() {
+        let mock = MockUpstream::parse("mock://dims=16").unwrap().unwrap();
+        let request = BatchRequest {
+            inputs: vec!["Hello".into(), "World".into(), "Hello".into()],
+        };
+        let embeddings = mock.embed(&request).await;
+
+        assert_eq!(embeddings.len(), 3);
+        assert!(embeddings.iter().all(|embedding| embedding.len() == 16));
+        assert_eq!(embeddings[0], embeddings[2]);
+        assert_ne!(embeddings[0], embeddings[1]);
+        let norm: f32 = embeddings[0].iter().map(|value| value * value).sum();
+        assert!((norm - 1.0).abs() < 1e-5);
+
+        let token_embeddings = mock
+            .embed_all(&BatchRequest {
+                inputs: vec!["Hello World".into(), "".into()],
+            })
+            .await;
+        assert_eq!(
+            token_embeddings[0],
+            vec![embeddings[0].clone(), embeddings[1].clone()]
+        );
+        assert_eq!(token_embeddings[1].len(), 1);
+
+        let sparse_embeddings = mock.embed_sparse(&request).await;
+        assert_eq!(sparse_embeddings.len(), 3);
+        for (sparse, dense) in sparse_embeddings.iter().zip(&embeddings) {
+            assert!(sparse.iter().all(|entry| entry.value == dense[entry.index]));
+            assert_eq!(
+                sparse.len(),
+                dense.iter().filter(|value| **value > 0.0).count()
+            );
+        }
+    }
+}
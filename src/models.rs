@@ -0,0 +1,188 @@
+//! Multi-model routing: requests naming a `model` are queued & batched separately from
+//! the default ones, against that model's own TEI deployment & limits (`config.models`)
+use crate::batch_processor::BatchProcessor;
+use crate::batch_stats::BatchStats;
+use crate::config::AppConfig;
+use crate::inference_client::{InferenceBackend, InferenceServiceClient};
+use crate::postprocessing::PostprocessorChain;
+use crate::queue_state::QueueState;
+use crate::types::{ControlMessage, Endpoint, PendingRequest};
+use crate::upstream_limits;
+use crate::usage::UsageTracker;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::mpsc;
+
+/// Reserved model name of the requests without `model`, i.e., `config.default_model`
+/// (or `config.inference_urls`)
+pub const DEFAULT_MODEL: &str = "default";
+
+/// Channels into one running `BatchProcessor`, plus the snapshot of its pending queue
+pub struct ModelQueue {
+    /// Effective config of this queue's requests, see `AppConfig::for_model`
+    pub config: AppConfig,
+    pub request_sender: mpsc::UnboundedSender<PendingRequest>,
+    pub control_sender: mpsc::UnboundedSender<ControlMessage>,
+    pub queue_state: Arc<QueueState>,
+}
+
+impl ModelQueue {
+    /// Launches the batch loop of `inference_backend` as a background task on `runtime`,
+    /// batching requests for `endpoint` of the inference service
+    pub fn spawn(
+        config: AppConfig,
+        endpoint: Endpoint,
+        inference_backend: Arc<dyn InferenceBackend>,
+        batch_stats: Arc<BatchStats>,
+        usage: Arc<UsageTracker>,
+        postprocessors: Arc<PostprocessorChain>,
+        runtime: &Handle,
+    ) -> Self {
+        let (request_sender, request_receiver) = mpsc::unbounded_channel();
+        let (control_sender, control_receiver) = mpsc::unbounded_channel();
+        let queue_state = Arc::new(QueueState::new());
+        let batch_processor = BatchProcessor::new(
+            config.clone(),
+            inference_backend,
+            queue_state.clone(),
+            batch_stats,
+            usage,
+        )
+        .with_endpoint(endpoint)
+        .with_postprocessors(postprocessors);
+        runtime.spawn(batch_processor.run(request_receiver, control_receiver));
+
+        Self {
+            config,
+            request_sender,
+            control_sender,
+            queue_state,
+        }
+    }
+}
+
+/// `config.batch_runtime_threads` runtime, batches spawned from its batch loops run there too
+pub struct BatchRuntime {
+    /// Only taken on drop
+    runtime: Option<Runtime>,
+    handle: Handle,
+}
+
+impl BatchRuntime {
+    pub fn new(threads: usize) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(threads)
+            .thread_name("batch-runtime")
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            handle: runtime.handle().clone(),
+            runtime: Some(runtime),
+        })
+    }
+
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+}
+
+impl Drop for BatchRuntime {
+    /// Dropping a `Runtime` blocks until its tasks are done, which panics within async code
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Name of the `config.models` entry serving a request's `model`, after resolving
+/// `config.model_aliases` & `config.default_model`. `None` for the default queue
+pub fn resolve_model<'a>(config: &'a AppConfig, model: Option<&'a str>) -> Option<&'a str> {
+    let model = model.map(|model| {
+        config
+            .model_aliases
+            .get(model)
+            .map_or(model, String::as_str)
+    });
+    match model {
+        None | Some(DEFAULT_MODEL) => config.default_model.as_deref(),
+        model => model,
+    }
+}
+
+/// One queue per `config.models` entry, each with its own (health checked) client & limits
+/// discovered from its own upstream (`config` is expected without the default upstream's).
+/// Batch stats, usage & postprocessors are shared with the default queue
+pub async fn spawn_model_queues(
+    config: &AppConfig,
+    batch_stats: &Arc<BatchStats>,
+    usage: &Arc<UsageTracker>,
+    postprocessors: &Arc<PostprocessorChain>,
+    runtime: &Handle,
+) -> Result<HashMap<String, ModelQueue>, String> {
+    let mut model_queues = HashMap::new();
+    for (model, model_config) in &config.models {
+        let mut model_config = config.for_model(model_config);
+        let inference_client = Arc::new(
+            InferenceServiceClient::new(&model_config)
+                .map_err(|e| format!("Model `{model}`: {}", e.message()))?,
+        );
+        upstream_limits::discover(&inference_client, model, &mut model_config).await;
+        inference_client.spawn_background_tasks();
+        if model_config.discover_upstream_limits {
+            let inference_client = Arc::downgrade(&inference_client);
+            tokio::spawn(upstream_limits::watch(
+                move || inference_client.upgrade(),
+                model.clone(),
+                model_config.clone(),
+            ));
+        }
+        let model_queue = ModelQueue::spawn(
+            model_config,
+            Endpoint::Embed,
+            inference_client,
+            batch_stats.clone(),
+            usage.clone(),
+            postprocessors.clone(),
+            runtime,
+        );
+        model_queues.insert(model.clone(), model_queue);
+    }
+    Ok(model_queues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelConfig;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_resolve_model() {
+        let mut config = AppConfig {
+            models: BTreeMap::from([("bge-small".to_string(), ModelConfig::default())]),
+            model_aliases: BTreeMap::from([
+                (
+                    "text-embedding-3-small".to_string(),
+                    "bge-small".to_string(),
+                ),
+                ("legacy".to_string(), DEFAULT_MODEL.to_string()),
+            ]),
+            ..AppConfig::default()
+        };
+        assert_eq!(resolve_model(&config, None), None);
+        assert_eq!(resolve_model(&config, Some("default")), None);
+        assert_eq!(resolve_model(&config, Some("legacy")), None);
+        assert_eq!(
+            resolve_model(&config, Some("text-embedding-3-small")),
+            Some("bge-small")
+        );
+        // unknown models are left to the caller
+        assert_eq!(resolve_model(&config, Some("e5-large")), Some("e5-large"));
+
+        config.default_model = Some("bge-small".to_string());
+        assert_eq!(resolve_model(&config, None), Some("bge-small"));
+        assert_eq!(resolve_model(&config, Some("legacy")), Some("bge-small"));
+    }
+}
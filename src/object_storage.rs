@@ -0,0 +1,103 @@
+//! Bulk jobs reading their JSONL corpus from & writing results to object storage
+//! (`s3://bucket/key`, `gs://bucket/key` or `file:///path`), so multi-GB corpora never flow
+//! through HTTP request bodies. Credentials come from the usual environment variables
+//! (`AWS_ACCESS_KEY_ID`, `AWS_REGION`, `GOOGLE_SERVICE_ACCOUNT`, ...)
+use crate::bulk;
+use crate::request_context::RequestContext;
+use crate::request_handler::RequestHandler;
+use crate::types::Priority;
+use object_store::aws::AmazonS3Builder;
+use object_store::buffered::{BufReader, BufWriter};
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::{ObjectStore, ObjectStoreScheme};
+use rocket::futures::StreamExt;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+/// Parsed `input_uri` / `output_uri`
+pub struct ObjectLocation {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+}
+
+impl ObjectLocation {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let invalid = |e: String| format!("Invalid object store URI `{uri}`: {e}");
+        let url = reqwest::Url::parse(uri).map_err(|e| invalid(e.to_string()))?;
+        let (scheme, path) = ObjectStoreScheme::parse(&url).map_err(|e| invalid(e.to_string()))?;
+        let store: Arc<dyn ObjectStore> = match scheme {
+            ObjectStoreScheme::AmazonS3 => Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_url(uri)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?,
+            ),
+            ObjectStoreScheme::GoogleCloudStorage => Arc::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_url(uri)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?,
+            ),
+            ObjectStoreScheme::Local => Arc::new(LocalFileSystem::new()),
+            scheme => return Err(invalid(format!("{scheme:?} isn't supported"))),
+        };
+        Ok(Self { store, path })
+    }
+}
+
+/// Records processed by a bulk job
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BulkSummary {
+    pub records: usize,
+    pub failed_records: usize,
+}
+
+/// Streams `input` through the batcher at `Priority::Low`, results are uploaded to `output`
+/// as they come (multipart upload, completed only once the whole input is processed)
+pub async fn run_bulk_job(
+    handler: Arc<RequestHandler>,
+    context: RequestContext,
+    input: ObjectLocation,
+    output: ObjectLocation,
+) -> Result<BulkSummary, String> {
+    let meta = input
+        .store
+        .head(&input.path)
+        .await
+        .map_err(|e| format!("Failed to read input: {e}"))?;
+    let reader = BufReader::new(input.store, &meta);
+    let mut writer = BufWriter::new(output.store, output.path);
+
+    let mut summary = BulkSummary::default();
+    let mut results = std::pin::pin!(bulk::embed_lines(handler, context, Priority::Low, reader));
+    while let Some(result) = results.next().await {
+        summary.records += 1;
+        if result.error.is_some() {
+            summary.failed_records += 1;
+        }
+        if let Err(e) = writer.write_all(result.to_line().as_bytes()).await {
+            let _ = writer.abort().await;
+            return Err(format!("Failed to write output: {e}"));
+        }
+    }
+    writer
+        .shutdown()
+        .await
+        .map_err(|e| format!("Failed to write output: {e}"))?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(ObjectLocation::parse("file:///tmp/corpus.jsonl").is_ok());
+        assert!(ObjectLocation::parse("s3://bucket/corpus.jsonl").is_ok());
+        assert!(ObjectLocation::parse("memory:///corpus.jsonl").is_err());
+        assert!(ObjectLocation::parse("corpus.jsonl").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! In-process embedding model exported to ONNX (e.g. `optimum-cli export onnx --model
+//! sentence-transformers/all-MiniLM-L6-v2 <dir>`), run on CPU by ONNX Runtime, for
+//! `onnx:///path/to/model` inference URLs. The directory needs `model.onnx` & `tokenizer.json`
+//!
+//! ONNX Runtime itself is loaded on startup from `ORT_DYLIB_PATH` (`libonnxruntime.so` otherwise)
+use crate::inference_client::{InferenceError, ONNX_SCHEME};
+use crate::types::{BatchRequest, BatchResponse};
+use ort::session::{Session, SessionInputValue};
+use ort::value::Tensor;
+use serde_json::json;
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+/// Pooled output of sentence-transformers exports, used as-is when present
+const SENTENCE_EMBEDDING_OUTPUT: &str = "sentence_embedding";
+/// Token embeddings (batch, tokens, hidden) of plain transformer exports, mean pooled
+const LAST_HIDDEN_STATE_OUTPUT: &str = "last_hidden_state";
+
+/// Loaded session & tokenizer, shared by all batches
+pub struct OnnxModel {
+    /// `Session::run` needs exclusive access, parallelism happens inside ONNX Runtime
+    session: Mutex<Session>,
+    tokenizer: Tokenizer,
+    /// BERT-style exports take it, others (e.g. DistilBERT) don't
+    takes_token_type_ids: bool,
+    /// Model directory name
+    model_id: String,
+}
+
+impl OnnxModel {
+    /// `url` is `onnx://` followed by the model directory
+    pub fn load(url: &str) -> Result<Self, InferenceError> {
+        let dir = Path::new(url.strip_prefix(ONNX_SCHEME).unwrap_or(url));
+        let invalid = |e: String| InferenceError::InvalidConfig(format!("{}: {e}", dir.display()));
+
+        let model_path = dir.join("model.onnx");
+        if !model_path.is_file() {
+            return Err(invalid("model.onnx doesn't exist".to_string()));
+        }
+        let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| invalid(format!("tokenizer.json: {e}")))?;
+        // padding is done per batch below
+        tokenizer.with_padding(None);
+
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(&model_path))
+            .map_err(|e| invalid(format!("model.onnx: {e}")))?;
+        let takes_token_type_ids = session
+            .inputs
+            .iter()
+            .any(|input| input.name == "token_type_ids");
+
+        Ok(Self {
+            session: Mutex::new(session),
+            tokenizer,
+            takes_token_type_ids,
+            model_id: dir.file_name().map_or_else(
+                || dir.display().to_string(),
+                |name| name.to_string_lossy().to_string(),
+            ),
+        })
+    }
+
+    /// Runs on the blocking thread pool, calls aren't cancelled on timeout
+    pub async fn embed(
+        self: Arc<Self>,
+        request: &BatchRequest,
+    ) -> Result<BatchResponse, InferenceError> {
+        let inputs = request.inputs.clone();
+        tokio::task::spawn_blocking(move || self.embed_blocking(&inputs))
+            .await
+            .map_err(|e| InferenceError::BackendError(e.to_string()))?
+            .map_err(InferenceError::BackendError)
+    }
+
+    fn embed_blocking(&self, inputs: &[Arc<str>]) -> Result<BatchResponse, String> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let encodings = inputs
+            .iter()
+            .map(|input| self.tokenizer.encode(&**input, true))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Tokenization failed: {e}"))?;
+        let max_length = encodings
+            .iter()
+            .map(|encoding| encoding.get_ids().len())
+            .max()
+            .unwrap_or_default();
+
+        // row-major (batch, tokens), padded with 0 (`[PAD]` of BERT vocabularies, masked anyway)
+        let mut input_ids = vec![0i64; encodings.len() * max_length];
+        let mut attention_mask = vec![0i64; encodings.len() * max_length];
+        for (row, encoding) in encodings.iter().enumerate() {
+            let offset = row * max_length;
+            for (column, (id, mask)) in encoding
+                .get_ids()
+                .iter()
+                .zip(encoding.get_attention_mask())
+                .enumerate()
+            {
+                input_ids[offset + column] = *id as i64;
+                attention_mask[offset + column] = *mask as i64;
+            }
+        }
+
+        let shape = vec![encodings.len() as i64, max_length as i64];
+        let tensor = |values: Vec<i64>| -> Result<SessionInputValue<'static>, String> {
+            Tensor::from_array((shape.clone(), values))
+                .map(SessionInputValue::from)
+                .map_err(|e| e.to_string())
+        };
+        let mut session_inputs: Vec<(Cow<str>, SessionInputValue)> = vec![
+            ("input_ids".into(), tensor(input_ids)?),
+            ("attention_mask".into(), tensor(attention_mask.clone())?),
+        ];
+        if self.takes_token_type_ids {
+            session_inputs.push(("token_type_ids".into(), tensor(vec![0; shape_len(&shape)])?));
+        }
+
+        let mut session = self.session.lock().unwrap();
+        let outputs = session
+            .run(session_inputs)
+            .map_err(|e| format!("Inference failed: {e}"))?;
+
+        if let Some(pooled) = outputs.get(SENTENCE_EMBEDDING_OUTPUT) {
+            let (shape, values) = pooled
+                .try_extract_tensor::<f32>()
+                .map_err(|e| e.to_string())?;
+            let dims = shape[1] as usize;
+            return Ok(values
+                .chunks(dims)
+                .map(|embedding| normalize(embedding.to_vec()))
+                .collect());
+        }
+
+        let hidden = outputs
+            .get(LAST_HIDDEN_STATE_OUTPUT)
+            .ok_or_else(|| format!("Model has neither `{SENTENCE_EMBEDDING_OUTPUT}` nor `{LAST_HIDDEN_STATE_OUTPUT}` output"))?;
+        let (shape, values) = hidden
+            .try_extract_tensor::<f32>()
+            .map_err(|e| e.to_string())?;
+        let (tokens, dims) = (shape[1] as usize, shape[2] as usize);
+        Ok(attention_mask
+            .chunks(tokens)
+            .zip(values.chunks(tokens * dims))
+            .map(|(mask, token_embeddings)| mean_pool(mask, token_embeddings, dims))
+            .map(normalize)
+            .collect())
+    }
+
+    /// Mimics TEI `/info`
+    pub fn info(&self) -> serde_json::Value {
+        json!({
+            "model_id": self.model_id,
+            "model_type": "onnx",
+        })
+    }
+}
+
+impl std::fmt::Debug for OnnxModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnnxModel")
+            .field("model_id", &self.model_id)
+            .finish()
+    }
+}
+
+fn shape_len(shape: &[i64]) -> usize {
+    shape.iter().product::<i64>() as usize
+}
+
+/// Average of the (non-padding) token embeddings of a single input
+fn mean_pool(mask: &[i64], token_embeddings: &[f32], dims: usize) -> Vec<f32> {
+    let mut pooled = vec![0.0; dims];
+    let mut tokens = 0.0;
+    for (token_embedding, _) in token_embeddings
+        .chunks(dims)
+        .zip(mask)
+        .filter(|(_, mask)| **mask == 1)
+    {
+        pooled
+            .iter_mut()
+            .zip(token_embedding)
+            .for_each(|(sum, value)| *sum += value);
+        tokens += 1.0;
+    }
+    if tokens > 0.0 {
+        pooled.iter_mut().for_each(|sum| *sum /= tokens);
+    }
+    pooled
+}
+
+/// L2 normalized, like TEI defaults
+fn normalize(mut embedding: Vec<f32>) -> Vec<f32> {
+    let norm = embedding
+        .iter()
+        .map(|value| value * value)
+        .sum::<f32>()
+        .sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|value| *value /= norm);
+    }
+    embedding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_fails_without_model_file() {
+        let dir = std::env::temp_dir().join("auto-batching-proxy-onnx-missing");
+        let _ = std::fs::create_dir_all(&dir);
+
+        let Err(InferenceError::InvalidConfig(error)) =
+            OnnxModel::load(&format!("{ONNX_SCHEME}{}", dir.display()))
+        else {
+            panic!("load must fail");
+        };
+        assert!(error.contains("model.onnx"), "{error}");
+    }
+
+    #[test]
+    fn test_mean_pool_skips_padding() {
+        // 3 tokens of 2 dims, the last one is padding
+        let pooled = mean_pool(&[1, 1, 0], &[1.0, 2.0, 3.0, 4.0, 100.0, 100.0], 2);
+        assert_eq!(pooled, vec![2.0, 3.0]);
+        assert_eq!(normalize(vec![3.0, 4.0]), vec![0.6, 0.8]);
+    }
+}
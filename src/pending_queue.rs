@@ -0,0 +1,202 @@
+use crate::types::{PendingRequest, Priority};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Pending requests split into priority tiers, each tier is FIFO.
+/// Iteration order is the scheduling order: ALL high priority requests come before normal ones,
+/// which come before low (async job) ones
+#[derive(Debug, Default)]
+pub struct PendingQueue {
+    high: VecDeque<PendingRequest>,
+    normal: VecDeque<PendingRequest>,
+    low: VecDeque<PendingRequest>,
+}
+
+impl PendingQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_back(&mut self, request: PendingRequest) {
+        match request.priority {
+            Priority::High => self.high.push_back(request),
+            Priority::Normal => self.normal.push_back(request),
+            Priority::Low => self.low.push_back(request),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.high.len() + self.normal.len() + self.low.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.high.is_empty() && self.normal.is_empty() && self.low.is_empty()
+    }
+
+    /// Oldest across all tiers (a normal request could have been waiting longer than any high one)
+    pub fn oldest_received_at(&self) -> Option<Instant> {
+        self.high
+            .front()
+            .into_iter()
+            .chain(self.normal.front())
+            .chain(self.low.front())
+            .map(|request| request.received_at)
+            .min()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PendingRequest> {
+        self.high
+            .iter()
+            .chain(self.normal.iter())
+            .chain(self.low.iter())
+    }
+
+    /// Removes first `count` requests in scheduling order
+    pub fn take_front(&mut self, count: usize) -> Vec<PendingRequest> {
+        let from_high = count.min(self.high.len());
+        let from_normal = (count - from_high).min(self.normal.len());
+        let from_low = (count - from_high - from_normal).min(self.low.len());
+
+        self.high
+            .drain(..from_high)
+            .chain(self.normal.drain(..from_normal))
+            .chain(self.low.drain(..from_low))
+            .collect()
+    }
+
+    /// Removes requests at given positions (in scheduling order, as returned by `iter()`),
+    /// returned in the same order as `positions`
+    pub fn take(&mut self, positions: &[usize]) -> Vec<PendingRequest> {
+        // common case (plain FIFO), no need to rebuild the tiers
+        if positions
+            .iter()
+            .enumerate()
+            .all(|(i, &position)| i == position)
+        {
+            return self.take_front(positions.len());
+        }
+
+        let mut all: Vec<Option<PendingRequest>> = self
+            .high
+            .drain(..)
+            .chain(self.normal.drain(..))
+            .chain(self.low.drain(..))
+            .map(Some)
+            .collect();
+
+        let taken = positions
+            .iter()
+            .filter_map(|&position| all.get_mut(position).and_then(Option::take))
+            .collect();
+
+        // `push_back` routes each request back into its own tier, order is preserved
+        for request in all.into_iter().flatten() {
+            self.push_back(request);
+        }
+        taken
+    }
+
+    /// Removes (& returns) all requests matching `predicate`, keeping the order of the rest
+    pub fn remove_where(
+        &mut self,
+        predicate: impl Fn(&PendingRequest) -> bool,
+    ) -> Vec<PendingRequest> {
+        let mut removed = Vec::new();
+        for tier in [&mut self.high, &mut self.normal, &mut self.low] {
+            let (matching, remaining): (VecDeque<_>, VecDeque<_>) =
+                tier.drain(..).partition(|request| predicate(request));
+            *tier = remaining;
+            removed.extend(matching);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ResponseSender;
+    use tokio::sync::oneshot;
+
+    fn build_request(input: &str, priority: Priority) -> PendingRequest {
+        let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+        PendingRequest::new(vec![input.to_string()], response_sender).with_priority(priority)
+    }
+
+    #[test]
+    fn test_high_priority_requests_come_first() {
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("normal 1", Priority::Normal));
+        queue.push_back(build_request("high 1", Priority::High));
+        queue.push_back(build_request("normal 2", Priority::Normal));
+        queue.push_back(build_request("high 2", Priority::High));
+        assert_eq!(queue.len(), 4);
+
+        let order: Vec<&str> = queue.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(order, vec!["high 1", "high 2", "normal 1", "normal 2"]);
+
+        let taken = queue.take_front(3);
+        let taken: Vec<&str> = taken.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(taken, vec!["high 1", "high 2", "normal 1"]);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_low_priority_requests_come_last() {
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("low 1", Priority::Low));
+        queue.push_back(build_request("normal 1", Priority::Normal));
+        queue.push_back(build_request("high 1", Priority::High));
+        queue.push_back(build_request("low 2", Priority::Low));
+
+        let order: Vec<&str> = queue.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(order, vec!["high 1", "normal 1", "low 1", "low 2"]);
+
+        let taken = queue.take_front(3);
+        let taken: Vec<&str> = taken.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(taken, vec!["high 1", "normal 1", "low 1"]);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_oldest_received_at_considers_both_tiers() {
+        let mut queue = PendingQueue::new();
+        assert!(queue.oldest_received_at().is_none());
+
+        let normal = build_request("normal", Priority::Normal);
+        let normal_received_at = normal.received_at;
+        queue.push_back(normal);
+        queue.push_back(build_request("high", Priority::High));
+
+        assert_eq!(queue.oldest_received_at(), Some(normal_received_at));
+    }
+
+    #[test]
+    fn test_take_positions() {
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("normal 1", Priority::Normal));
+        queue.push_back(build_request("normal 2", Priority::Normal));
+        queue.push_back(build_request("high 1", Priority::High));
+
+        // scheduling order: high 1, normal 1, normal 2
+        let taken = queue.take(&[2, 0]);
+        let taken: Vec<&str> = taken.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(taken, vec!["normal 2", "high 1"]);
+
+        let remaining: Vec<&str> = queue.iter().map(|r| r.inputs[0].as_ref()).collect();
+        assert_eq!(remaining, vec!["normal 1"]);
+    }
+
+    #[test]
+    fn test_remove_where() {
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("drop", Priority::High));
+        queue.push_back(build_request("keep", Priority::High));
+        queue.push_back(build_request("drop", Priority::Normal));
+
+        let removed = queue.remove_where(|r| &*r.inputs[0] == "drop");
+        assert_eq!(removed.len(), 2);
+        assert_eq!(queue.len(), 1);
+        assert!(!queue.is_empty());
+    }
+}
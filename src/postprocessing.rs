@@ -0,0 +1,169 @@
+use crate::config::{AppConfig, EmbeddingPostprocessing};
+use std::sync::RwLock;
+
+/// Applied to the dense embeddings of every `/embed` batch of the default model in
+/// `BatchProcessor::handle_batch_success`, i.e., once per batch rather than by every client.
+/// Built-in implementations are selected via `config.postprocess`, custom ones can be
+/// plugged in with `RequestHandler::with_postprocessor`
+pub trait ResponsePostprocessor: Send + Sync {
+    /// Transforms the embeddings of a batch in place, `Err` fails the batch (500 `postprocessing_failed`)
+    fn postprocess(&self, embeddings: &mut [Vec<f32>]) -> Result<(), String>;
+}
+
+/// Postprocessors shared by the batch loop & `RequestHandler`, which can still add to them
+#[derive(Default)]
+pub struct PostprocessorChain {
+    postprocessors: RwLock<Vec<Box<dyn ResponsePostprocessor>>>,
+}
+
+impl PostprocessorChain {
+    /// Built-ins of `config.postprocess` in a fixed order, regardless of how they're listed:
+    /// projection, normalization, rounding
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        let steps = &config.postprocess;
+        let mut postprocessors: Vec<Box<dyn ResponsePostprocessor>> = Vec::new();
+        // `AppConfig::build` requires the settings of each step
+        if steps.contains(&EmbeddingPostprocessing::Project)
+            && let Some(path) = &config.projection_matrix_path
+        {
+            postprocessors.push(Box::new(Projection::load(path)?));
+        }
+        if steps.contains(&EmbeddingPostprocessing::Normalize) {
+            postprocessors.push(Box::new(Normalize));
+        }
+        if steps.contains(&EmbeddingPostprocessing::Round)
+            && let Some(decimals) = config.postprocess_decimals
+        {
+            postprocessors.push(Box::new(Round::new(decimals)));
+        }
+        Ok(Self {
+            postprocessors: RwLock::new(postprocessors),
+        })
+    }
+
+    /// Runs after those added before
+    pub fn push(&self, postprocessor: Box<dyn ResponsePostprocessor>) {
+        self.postprocessors.write().unwrap().push(postprocessor);
+    }
+
+    pub fn apply(&self, embeddings: &mut [Vec<f32>]) -> Result<(), String> {
+        for postprocessor in self.postprocessors.read().unwrap().iter() {
+            postprocessor.postprocess(embeddings)?;
+        }
+        Ok(())
+    }
+}
+
+/// `normalize`, scales each embedding to unit (L2) length, e.g. for dot product similarity
+pub struct Normalize;
+
+impl ResponsePostprocessor for Normalize {
+    fn postprocess(&self, embeddings: &mut [Vec<f32>]) -> Result<(), String> {
+        for embedding in embeddings {
+            let norm = embedding
+                .iter()
+                .map(|value| value * value)
+                .sum::<f32>()
+                .sqrt();
+            if norm > 0.0 {
+                embedding.iter_mut().for_each(|value| *value /= norm);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `project`, multiplies each embedding with a matrix (`config.projection_matrix_path`),
+/// e.g. a PCA reducing the dimensions clients store
+pub struct Projection {
+    /// One row per output dimension, each as long as the model's embeddings
+    rows: Vec<Vec<f32>>,
+}
+
+impl Projection {
+    pub fn new(rows: Vec<Vec<f32>>) -> Result<Self, String> {
+        let input_dims = rows.first().map(Vec::len).unwrap_or_default();
+        if input_dims == 0 || rows.iter().any(|row| row.len() != input_dims) {
+            return Err("Projection matrix rows must be non-empty & equally long".to_string());
+        }
+        Ok(Self { rows })
+    }
+
+    /// JSON array of rows
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read projection matrix `{path}`: {e}"))?;
+        let rows = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid projection matrix `{path}`: {e}"))?;
+        Self::new(rows).map_err(|e| format!("{e}: `{path}`"))
+    }
+}
+
+impl ResponsePostprocessor for Projection {
+    fn postprocess(&self, embeddings: &mut [Vec<f32>]) -> Result<(), String> {
+        let input_dims = self.rows[0].len();
+        for embedding in embeddings {
+            if embedding.len() != input_dims {
+                return Err(format!(
+                    "Projection expects {input_dims} dimensions, got {}",
+                    embedding.len()
+                ));
+            }
+            *embedding = self
+                .rows
+                .iter()
+                .map(|row| row.iter().zip(embedding.iter()).map(|(a, b)| a * b).sum())
+                .collect();
+        }
+        Ok(())
+    }
+}
+
+/// `round`, to `config.postprocess_decimals` decimal places, which mostly shrinks responses
+pub struct Round {
+    scale: f32,
+}
+
+impl Round {
+    pub fn new(decimals: u32) -> Self {
+        Self {
+            scale: 10f32.powi(decimals as i32),
+        }
+    }
+}
+
+impl ResponsePostprocessor for Round {
+    fn postprocess(&self, embeddings: &mut [Vec<f32>]) -> Result<(), String> {
+        for value in embeddings.iter_mut().flatten() {
+            *value = (*value * self.scale).round() / self.scale;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_postprocessors() {
+        let mut embeddings = vec![vec![3.0, 4.0, 1.0], vec![0.0, 0.0, 0.0]];
+        let chain = PostprocessorChain::default();
+        chain.push(Box::new(
+            Projection::new(vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]]).unwrap(),
+        ));
+        chain.push(Box::new(Normalize));
+        chain.push(Box::new(Round::new(1)));
+        chain.apply(&mut embeddings).unwrap();
+        assert_eq!(embeddings, vec![vec![0.6, 0.8], vec![0.0, 0.0]]);
+
+        // already projected
+        assert!(chain.apply(&mut embeddings).is_err());
+    }
+
+    #[test]
+    fn test_projection_matrix_must_be_rectangular() {
+        assert!(Projection::new(vec![]).is_err());
+        assert!(Projection::new(vec![vec![1.0, 0.0], vec![1.0]]).is_err());
+    }
+}
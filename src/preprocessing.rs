@@ -0,0 +1,221 @@
+use crate::config::{AppConfig, InputPreprocessing};
+use std::collections::HashSet;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+
+/// Applied to the inputs of every request before batching, see `RequestHandler::process_request_for`.
+/// Built-in implementations are selected via `config.preprocess`, custom ones can be
+/// plugged in with `RequestHandler::with_preprocessor`
+pub trait RequestPreprocessor: Send + Sync {
+    /// Transformed input, `Err` with the reason rejects the request (422 `input_preprocess_rejected`),
+    /// or only that input with `EmbedRequest::partial_errors`
+    fn preprocess(&self, input: String) -> Result<String, String>;
+}
+
+/// Built-ins of `config.preprocess` in a fixed order, regardless of how they're listed:
+/// text cleanup, profanity stripping, lowercasing, truncation
+pub fn build_preprocessors(config: &AppConfig) -> Vec<Box<dyn RequestPreprocessor>> {
+    let steps = &config.preprocess;
+    let mut preprocessors: Vec<Box<dyn RequestPreprocessor>> = Vec::new();
+    let cleanup_steps: Vec<InputPreprocessing> = steps
+        .iter()
+        .copied()
+        .filter(|step| TextCleanup::handles(*step))
+        .collect();
+    if !cleanup_steps.is_empty() {
+        preprocessors.push(Box::new(TextCleanup {
+            steps: cleanup_steps,
+        }));
+    }
+    if steps.contains(&InputPreprocessing::StripProfanity) {
+        preprocessors.push(Box::new(ProfanityFilter::new(&config.profanity_words)));
+    }
+    if steps.contains(&InputPreprocessing::Lowercase) {
+        preprocessors.push(Box::new(Lowercase));
+    }
+    // `AppConfig::build` requires `preprocess_max_chars` along with `truncate`
+    if steps.contains(&InputPreprocessing::Truncate)
+        && let Some(max_chars) = config.preprocess_max_chars
+    {
+        preprocessors.push(Box::new(Truncate { max_chars }));
+    }
+    preprocessors
+}
+
+/// `nfc`, `trim`, `collapse_whitespace` & `strip_control`: control characters are stripped first,
+/// so they don't end up between collapsed whitespace, trimming comes last
+pub struct TextCleanup {
+    pub steps: Vec<InputPreprocessing>,
+}
+
+impl TextCleanup {
+    fn handles(step: InputPreprocessing) -> bool {
+        matches!(
+            step,
+            InputPreprocessing::Nfc
+                | InputPreprocessing::Trim
+                | InputPreprocessing::CollapseWhitespace
+                | InputPreprocessing::StripControl
+        )
+    }
+}
+
+impl RequestPreprocessor for TextCleanup {
+    fn preprocess(&self, mut input: String) -> Result<String, String> {
+        let steps = &self.steps;
+        if steps.contains(&InputPreprocessing::StripControl) && input.chars().any(is_stripped) {
+            input = input.chars().filter(|c| !is_stripped(*c)).collect();
+        }
+        if steps.contains(&InputPreprocessing::Nfc) && !is_nfc(&input) {
+            input = input.nfc().collect();
+        }
+        if steps.contains(&InputPreprocessing::CollapseWhitespace) {
+            input = collapse_whitespace(&input);
+        }
+        if steps.contains(&InputPreprocessing::Trim) && input.trim().len() != input.len() {
+            input = input.trim().to_string();
+        }
+        Ok(input)
+    }
+}
+
+/// Control characters other than whitespace (`\n`, `\t`, ...), which `CollapseWhitespace` handles
+fn is_stripped(c: char) -> bool {
+    c.is_control() && !c.is_whitespace()
+}
+
+/// Runs of whitespace become a single space, leading & trailing whitespace is kept (as one space)
+fn collapse_whitespace(input: &str) -> String {
+    let mut collapsed = String::with_capacity(input.len());
+    let mut in_whitespace = false;
+    for c in input.chars() {
+        if c.is_whitespace() {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+/// `lowercase`, for uncased models or case-insensitive deduplication
+pub struct Lowercase;
+
+impl RequestPreprocessor for Lowercase {
+    fn preprocess(&self, input: String) -> Result<String, String> {
+        if input.chars().any(char::is_uppercase) {
+            Ok(input.to_lowercase())
+        } else {
+            Ok(input)
+        }
+    }
+}
+
+/// `truncate`, cuts inputs to `config.preprocess_max_chars` characters (not bytes)
+pub struct Truncate {
+    pub max_chars: usize,
+}
+
+impl RequestPreprocessor for Truncate {
+    fn preprocess(&self, mut input: String) -> Result<String, String> {
+        if let Some((index, _)) = input.char_indices().nth(self.max_chars) {
+            input.truncate(index);
+        }
+        Ok(input)
+    }
+}
+
+/// `strip_profanity`, drops words of `config.profanity_words` (case-insensitive, ignoring
+/// surrounding punctuation). Words of such inputs are then joined by single spaces
+pub struct ProfanityFilter {
+    words: HashSet<String>,
+}
+
+impl ProfanityFilter {
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    fn is_profane(&self, word: &str) -> bool {
+        let word = word.trim_matches(|c: char| c.is_ascii_punctuation());
+        self.words.contains(&word.to_lowercase())
+    }
+}
+
+impl RequestPreprocessor for ProfanityFilter {
+    fn preprocess(&self, input: String) -> Result<String, String> {
+        if !input.split_whitespace().any(|word| self.is_profane(word)) {
+            return Ok(input);
+        }
+        Ok(input
+            .split_whitespace()
+            .filter(|word| !self.is_profane(word))
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(config: &AppConfig, input: &str) -> String {
+        build_preprocessors(config)
+            .iter()
+            .try_fold(input.to_string(), |input, preprocessor| {
+                preprocessor.preprocess(input)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_text_cleanup() {
+        let cleanup = TextCleanup {
+            steps: vec![
+                InputPreprocessing::Trim,
+                InputPreprocessing::CollapseWhitespace,
+                InputPreprocessing::Nfc,
+                InputPreprocessing::StripControl,
+            ],
+        };
+        // "é" as "e" + combining acute accent
+        let input = "  Caf\u{65}\u{301}\t\n au\u{0}\u{7}  lait \r\n".to_string();
+        assert_eq!(
+            cleanup.preprocess(input.clone()).unwrap(),
+            "Caf\u{e9} au lait"
+        );
+
+        let collapse = TextCleanup {
+            steps: vec![InputPreprocessing::CollapseWhitespace],
+        };
+        assert_eq!(
+            collapse.preprocess(input).unwrap(),
+            " Caf\u{65}\u{301} au\u{0}\u{7} lait "
+        );
+    }
+
+    #[test]
+    fn test_built_in_preprocessors() {
+        assert!(build_preprocessors(&AppConfig::default()).is_empty());
+
+        let config = AppConfig {
+            preprocess: vec![
+                InputPreprocessing::Truncate,
+                InputPreprocessing::Lowercase,
+                InputPreprocessing::StripProfanity,
+                InputPreprocessing::Trim,
+            ],
+            preprocess_max_chars: Some(12),
+            profanity_words: vec!["Darn".to_string()],
+            ..AppConfig::default()
+        };
+        assert_eq!(build_preprocessors(&config).len(), 4);
+        assert_eq!(preprocess(&config, " Darn, ÜBER  Cool! "), "über cool!");
+        assert_eq!(preprocess(&config, "A darned long input"), "a darned lon");
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Snapshot of the pending queue, shared between `BatchProcessor` (the only writer)
+/// & `RequestHandler` (reader), since `pending_requests` itself is owned by the processor
+#[derive(Debug)]
+pub struct QueueState {
+    depth: AtomicUsize,
+    oldest_received_at: Mutex<Option<Instant>>,
+    /// Heartbeat of the batch loop, i.e., last `update` call
+    updated_at: Mutex<Instant>,
+    /// Batch dispatch paused via `POST /admin/queue/pause`
+    paused: AtomicBool,
+}
+
+impl Default for QueueState {
+    fn default() -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            oldest_received_at: Mutex::new(None),
+            updated_at: Mutex::new(Instant::now()),
+            paused: AtomicBool::new(false),
+        }
+    }
+}
+
+impl QueueState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called by `BatchProcessor` on every batch loop iteration
+    pub fn update(&self, depth: usize, oldest_received_at: Option<Instant>) {
+        self.depth.store(depth, Ordering::Relaxed);
+        *self.oldest_received_at.lock().unwrap() = oldest_received_at;
+        *self.updated_at.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the batch loop last checked in, grows unbounded once it's stuck or gone
+    pub fn since_last_update(&self) -> Duration {
+        self.updated_at.lock().unwrap().elapsed()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// How long the oldest pending request has been waiting, `None` when the queue is empty
+    pub fn oldest_age(&self) -> Option<Duration> {
+        self.oldest_received_at
+            .lock()
+            .unwrap()
+            .map(|received_at| received_at.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_queue_has_no_oldest_age() {
+        let queue_state = QueueState::new();
+        assert_eq!(queue_state.depth(), 0);
+        assert!(queue_state.oldest_age().is_none());
+    }
+
+    #[test]
+    fn test_update_reflects_depth_and_oldest_age() {
+        let queue_state = QueueState::new();
+        let received_at = Instant::now() - Duration::from_millis(100);
+        queue_state.update(3, Some(received_at));
+
+        assert_eq!(queue_state.depth(), 3);
+        assert!(queue_state.oldest_age().unwrap() >= Duration::from_millis(100));
+
+        queue_state.update(0, None);
+        assert_eq!(queue_state.depth(), 0);
+        assert!(queue_state.oldest_age().is_none());
+        assert!(queue_state.since_last_update() < Duration::from_millis(100));
+    }
+}
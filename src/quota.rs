@@ -0,0 +1,307 @@
+use crate::config::AppConfig;
+use crate::types::ErrorResponse;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Inputs used within a single day or month (UTC), `id` identifies that period
+#[derive(Debug, Default, Clone, Copy)]
+struct PeriodUsage {
+    id: i64,
+    used: u64,
+}
+
+impl PeriodUsage {
+    /// Usage of an older period doesn't count anymore
+    fn used_in(&self, id: i64) -> u64 {
+        if self.id == id { self.used } else { 0 }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CallerQuota {
+    daily: PeriodUsage,
+    monthly: PeriodUsage,
+}
+
+/// Remaining inputs after the request was charged, `None` for unlimited periods
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotaStatus {
+    pub daily_remaining: Option<u64>,
+    pub monthly_remaining: Option<u64>,
+}
+
+impl QuotaStatus {
+    fn set_headers(&self, response: &mut Response<'_>) {
+        if let Some(daily_remaining) = self.daily_remaining {
+            response.set_raw_header("X-Quota-Daily-Remaining", daily_remaining.to_string());
+        }
+        if let Some(monthly_remaining) = self.monthly_remaining {
+            response.set_raw_header("X-Quota-Monthly-Remaining", monthly_remaining.to_string());
+        }
+    }
+}
+
+/// Wraps a successful response, adding the caller's remaining quota headers
+pub struct WithQuotaHeaders<R>(pub R, pub Option<QuotaStatus>);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for WithQuotaHeaders<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.0.respond_to(request)?;
+        if let Some(status) = self.1 {
+            status.set_headers(&mut response);
+        }
+        Ok(response)
+    }
+}
+
+/// Rendered as 429 with remaining quota headers & `Retry-After` (until the exhausted period resets)
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    /// "daily" or "monthly"
+    period: &'static str,
+    limit: u64,
+    status: QuotaStatus,
+    retry_after_secs: u64,
+}
+
+impl QuotaExceeded {
+    pub fn message(&self) -> String {
+        format!("{} quota of {} inputs exhausted", self.period, self.limit)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for QuotaExceeded {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let error = Json(ErrorResponse {
+            error: self.message(),
+            code: Some("quota_exceeded"),
+        });
+        let mut response = Response::build_from(error.respond_to(request)?)
+            .status(Status::TooManyRequests)
+            .raw_header("Retry-After", self.retry_after_secs.to_string())
+            .finalize();
+        self.status.set_headers(&mut response);
+        Ok(response)
+    }
+}
+
+/// Daily & monthly (UTC calendar) input quotas, tracked per caller (API key or client IP).
+/// Inputs are charged when a request is accepted & refunded if it fails,
+/// state is in memory only, i.e., it starts over on restart
+#[derive(Debug)]
+pub struct QuotaTracker {
+    daily_inputs: Option<u64>,
+    monthly_inputs: Option<u64>,
+    callers: Mutex<HashMap<String, CallerQuota>>,
+}
+
+impl QuotaTracker {
+    /// `None` when neither `quota_daily_inputs` nor `quota_monthly_inputs` is set
+    pub fn new(config: &AppConfig) -> Option<Self> {
+        if config.quota_daily_inputs.is_none() && config.quota_monthly_inputs.is_none() {
+            return None;
+        }
+        Some(Self {
+            daily_inputs: config.quota_daily_inputs,
+            monthly_inputs: config.quota_monthly_inputs,
+            callers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn charge(&self, caller: &str, inputs: usize) -> Result<QuotaStatus, QuotaExceeded> {
+        self.charge_at(caller, inputs as u64, unix_now())
+    }
+
+    pub fn refund(&self, caller: &str, inputs: usize) {
+        self.refund_at(caller, inputs as u64, unix_now())
+    }
+
+    fn charge_at(&self, caller: &str, inputs: u64, now: u64) -> Result<QuotaStatus, QuotaExceeded> {
+        let (day, month) = periods(now);
+        let mut callers = self.callers.lock().unwrap();
+        let quota = callers.entry(caller.to_string()).or_default();
+
+        let daily_used = quota.daily.used_in(day);
+        let monthly_used = quota.monthly.used_in(month);
+        let remaining =
+            |limit: Option<u64>, used: u64| limit.map(|limit| limit.saturating_sub(used));
+        let status = QuotaStatus {
+            daily_remaining: remaining(self.daily_inputs, daily_used),
+            monthly_remaining: remaining(self.monthly_inputs, monthly_used),
+        };
+
+        if let Some(limit) = self.daily_inputs
+            && daily_used + inputs > limit
+        {
+            return Err(QuotaExceeded {
+                period: "daily",
+                limit,
+                status,
+                retry_after_secs: SECS_PER_DAY - now % SECS_PER_DAY,
+            });
+        }
+        if let Some(limit) = self.monthly_inputs
+            && monthly_used + inputs > limit
+        {
+            return Err(QuotaExceeded {
+                period: "monthly",
+                limit,
+                status,
+                retry_after_secs: secs_until_next_month(now),
+            });
+        }
+
+        quota.daily = PeriodUsage {
+            id: day,
+            used: daily_used + inputs,
+        };
+        quota.monthly = PeriodUsage {
+            id: month,
+            used: monthly_used + inputs,
+        };
+        Ok(QuotaStatus {
+            daily_remaining: status.daily_remaining.map(|remaining| remaining - inputs),
+            monthly_remaining: status.monthly_remaining.map(|remaining| remaining - inputs),
+        })
+    }
+
+    /// Only within the same period, a new day/month has started from zero anyway
+    fn refund_at(&self, caller: &str, inputs: u64, now: u64) {
+        let (day, month) = periods(now);
+        let mut callers = self.callers.lock().unwrap();
+        if let Some(quota) = callers.get_mut(caller) {
+            if quota.daily.id == day {
+                quota.daily.used = quota.daily.used.saturating_sub(inputs);
+            }
+            if quota.monthly.id == month {
+                quota.monthly.used = quota.monthly.used.saturating_sub(inputs);
+            }
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or_default()
+}
+
+/// (days since epoch, months since epoch) of a Unix timestamp, UTC
+fn periods(unix_secs: u64) -> (i64, i64) {
+    let day = (unix_secs / SECS_PER_DAY) as i64;
+    let (year, month) = year_month(day);
+    (day, (year - 1970) * 12 + month as i64 - 1)
+}
+
+fn secs_until_next_month(unix_secs: u64) -> u64 {
+    let (year, month) = year_month((unix_secs / SECS_PER_DAY) as i64);
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let next_month_starts_at = days_from_civil(next_year, next_month) as u64 * SECS_PER_DAY;
+    next_month_starts_at - unix_secs
+}
+
+/// Year & month of given days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+fn year_month(days: i64) -> (i64, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+/// Days since epoch of the first day of given month
+fn days_from_civil(year: i64, month: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-02-29 12:00:00 UTC
+    const LEAP_DAY_NOON: u64 = 1_709_208_000;
+
+    fn build_tracker(daily: Option<u64>, monthly: Option<u64>) -> QuotaTracker {
+        let config = AppConfig {
+            quota_daily_inputs: daily,
+            quota_monthly_inputs: monthly,
+            ..AppConfig::default()
+        };
+        QuotaTracker::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_calendar_periods() {
+        assert_eq!(year_month(0), (1970, 1));
+        assert_eq!(year_month((LEAP_DAY_NOON / SECS_PER_DAY) as i64), (2024, 2));
+        assert_eq!(days_from_civil(1970, 1), 0);
+        assert_eq!(
+            days_from_civil(2024, 3) * SECS_PER_DAY as i64,
+            1_709_251_200
+        );
+        assert_eq!(secs_until_next_month(LEAP_DAY_NOON), 12 * 3600);
+    }
+
+    #[test]
+    fn test_daily_quota_resets_next_day() {
+        let tracker = build_tracker(Some(10), None);
+
+        let status = tracker.charge_at("a", 8, LEAP_DAY_NOON).unwrap();
+        assert_eq!(status.daily_remaining, Some(2));
+        assert_eq!(status.monthly_remaining, None);
+
+        let exceeded = tracker.charge_at("a", 3, LEAP_DAY_NOON).unwrap_err();
+        assert_eq!(exceeded.period, "daily");
+        assert_eq!(exceeded.status.daily_remaining, Some(2));
+        assert_eq!(exceeded.retry_after_secs, 12 * 3600);
+
+        // other callers have their own quota
+        assert!(tracker.charge_at("b", 10, LEAP_DAY_NOON).is_ok());
+
+        let next_day = LEAP_DAY_NOON + SECS_PER_DAY;
+        assert_eq!(
+            tracker.charge_at("a", 3, next_day).unwrap().daily_remaining,
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn test_monthly_quota_and_refund() {
+        let tracker = build_tracker(None, Some(5));
+
+        assert!(tracker.charge_at("a", 5, LEAP_DAY_NOON).is_ok());
+        let exceeded = tracker.charge_at("a", 1, LEAP_DAY_NOON).unwrap_err();
+        assert_eq!(exceeded.period, "monthly");
+
+        tracker.refund_at("a", 2, LEAP_DAY_NOON);
+        assert_eq!(
+            tracker
+                .charge_at("a", 2, LEAP_DAY_NOON)
+                .unwrap()
+                .monthly_remaining,
+            Some(0)
+        );
+    }
+}
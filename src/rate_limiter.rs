@@ -0,0 +1,280 @@
+use crate::backpressure::QueuePressure;
+use crate::config::AppConfig;
+use crate::types::ErrorResponse;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::status::Custom;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets of idle callers are only pruned once there are more than this many
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Refill rate & capacity of a token bucket
+#[derive(Debug, Clone, Copy)]
+struct Rate {
+    per_sec: f64,
+    capacity: f64,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(rate: Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.capacity,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_sec).min(rate.capacity);
+        self.updated_at = now;
+    }
+
+    /// How long until `cost` tokens are available (zero if they already are)
+    fn wait_for(&self, cost: f64, rate: Rate) -> Duration {
+        Duration::from_secs_f64((cost - self.tokens).max(0.0) / rate.per_sec)
+    }
+}
+
+/// Both buckets of a single caller
+#[derive(Debug)]
+struct CallerBuckets {
+    requests: TokenBucket,
+    inputs: TokenBucket,
+}
+
+/// Rendered as 429 with `RateLimit-*` (IETF draft) & `Retry-After` headers
+#[derive(Debug)]
+pub struct RateLimited {
+    /// "requests" or "inputs"
+    what: &'static str,
+    limit: f64,
+    retry_after: Duration,
+    /// State of the queue the request was meant for
+    queue: Option<QueuePressure>,
+}
+
+impl RateLimited {
+    /// Rounded up, so clients retrying right at `Retry-After` aren't limited again
+    fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    pub fn with_queue_pressure(mut self, queue: QueuePressure) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    fn error(&self) -> ErrorResponse {
+        ErrorResponse {
+            error: format!("Rate limit of {} {}/sec exceeded", self.limit, self.what),
+            code: Some("rate_limited"),
+        }
+    }
+}
+
+/// Without the headers, for failures after the request was accepted (e.g. a chunk of a split request)
+impl From<RateLimited> for Custom<Json<ErrorResponse>> {
+    fn from(limited: RateLimited) -> Self {
+        Custom(Status::TooManyRequests, Json(limited.error()))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for RateLimited {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let retry_after_secs = self.retry_after_secs().to_string();
+        let error = Json(self.error());
+        let mut response = Response::build_from(error.respond_to(request)?)
+            .status(Status::TooManyRequests)
+            .raw_header("RateLimit-Limit", self.limit.to_string())
+            .raw_header("RateLimit-Remaining", "0")
+            .raw_header("RateLimit-Reset", retry_after_secs.clone())
+            .raw_header("Retry-After", retry_after_secs)
+            .finalize();
+        if let Some(queue) = &self.queue {
+            queue.add_headers(&mut response);
+        }
+        Ok(response)
+    }
+}
+
+/// Token-bucket rate limiting per caller (API key or client IP, see `RequestContext::client_id`),
+/// on request count & input count independently. Buckets hold one second worth of tokens,
+/// the inputs bucket at least `max_inference_inputs`, so any valid request can pass eventually:
+/// split requests (`split_oversized_requests`) are charged per chunk, see `check_inputs`
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests: Option<Rate>,
+    inputs: Option<Rate>,
+    buckets: Mutex<HashMap<String, CallerBuckets>>,
+}
+
+impl RateLimiter {
+    /// `None` when neither `rate_limit_requests_per_sec` nor `rate_limit_inputs_per_sec` is set
+    pub fn new(config: &AppConfig) -> Option<Self> {
+        let requests = config.rate_limit_requests_per_sec.map(|per_sec| Rate {
+            per_sec,
+            capacity: per_sec.max(1.0),
+        });
+        let inputs = config.rate_limit_inputs_per_sec.map(|per_sec| Rate {
+            per_sec,
+            capacity: per_sec.max(config.max_inference_inputs as f64),
+        });
+        if requests.is_none() && inputs.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            requests,
+            inputs,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Takes one request & `input_count` inputs from the caller's buckets,
+    /// nothing is taken when either of them is short
+    pub fn check(&self, caller: &str, input_count: usize) -> Result<(), RateLimited> {
+        self.take(caller, 1.0, input_count)
+    }
+
+    /// Takes `input_count` inputs only, for the further chunks of a request that passed `check`
+    pub fn check_inputs(&self, caller: &str, input_count: usize) -> Result<(), RateLimited> {
+        self.take(caller, 0.0, input_count)
+    }
+
+    fn take(&self, caller: &str, request_cost: f64, input_count: usize) -> Result<(), RateLimited> {
+        let now = Instant::now();
+        // disabled limits never run out
+        let unlimited = Rate {
+            per_sec: f64::INFINITY,
+            capacity: f64::INFINITY,
+        };
+        let requests_rate = self.requests.unwrap_or(unlimited);
+        let inputs_rate = self.inputs.unwrap_or(unlimited);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, caller_buckets| {
+                caller_buckets.requests.refill(requests_rate, now);
+                caller_buckets.inputs.refill(inputs_rate, now);
+                caller_buckets.requests.tokens < requests_rate.capacity
+                    || caller_buckets.inputs.tokens < inputs_rate.capacity
+            });
+        }
+
+        let caller_buckets = buckets
+            .entry(caller.to_string())
+            .or_insert_with(|| CallerBuckets {
+                requests: TokenBucket::full(requests_rate, now),
+                inputs: TokenBucket::full(inputs_rate, now),
+            });
+        caller_buckets.requests.refill(requests_rate, now);
+        caller_buckets.inputs.refill(inputs_rate, now);
+
+        let input_cost = input_count as f64;
+        if let Some(rate) = self.requests
+            && caller_buckets.requests.tokens < request_cost
+        {
+            let retry_after = caller_buckets.requests.wait_for(request_cost, rate);
+            return Err(RateLimited {
+                what: "requests",
+                limit: rate.per_sec,
+                retry_after,
+                queue: None,
+            });
+        }
+        if let Some(rate) = self.inputs
+            && caller_buckets.inputs.tokens < input_cost
+        {
+            let retry_after = caller_buckets.inputs.wait_for(input_cost, rate);
+            return Err(RateLimited {
+                what: "inputs",
+                limit: rate.per_sec,
+                retry_after,
+                queue: None,
+            });
+        }
+
+        caller_buckets.requests.tokens -= request_cost;
+        caller_buckets.inputs.tokens -= input_cost;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_without_limits() {
+        assert!(RateLimiter::new(&AppConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_requests_limit_is_per_caller() {
+        let config = AppConfig {
+            rate_limit_requests_per_sec: Some(2.0),
+            ..AppConfig::default()
+        };
+        let rate_limiter = RateLimiter::new(&config).unwrap();
+
+        assert!(rate_limiter.check("a", 1).is_ok());
+        assert!(rate_limiter.check("a", 1).is_ok());
+        let limited = rate_limiter.check("a", 1).unwrap_err();
+        assert_eq!(limited.what, "requests");
+        assert_eq!(limited.retry_after_secs(), 1);
+
+        // own bucket
+        assert!(rate_limiter.check("b", 1).is_ok());
+    }
+
+    #[test]
+    fn test_inputs_limit_takes_nothing_when_exceeded() {
+        let config = AppConfig {
+            max_inference_inputs: 4,
+            rate_limit_requests_per_sec: Some(10.0),
+            rate_limit_inputs_per_sec: Some(1.0),
+            ..AppConfig::default()
+        };
+        let rate_limiter = RateLimiter::new(&config).unwrap();
+
+        // capacity is raised to `max_inference_inputs`
+        assert!(rate_limiter.check("a", 3).is_ok());
+        let limited = rate_limiter.check("a", 2).unwrap_err();
+        assert_eq!(limited.what, "inputs");
+        assert_eq!(limited.limit, 1.0);
+        // the rejected call took no tokens
+        assert!(rate_limiter.check("a", 1).is_ok());
+    }
+
+    #[test]
+    fn test_check_inputs_takes_no_request() {
+        let config = AppConfig {
+            max_inference_inputs: 4,
+            rate_limit_requests_per_sec: Some(1.0),
+            rate_limit_inputs_per_sec: Some(4.0),
+            ..AppConfig::default()
+        };
+        let rate_limiter = RateLimiter::new(&config).unwrap();
+
+        assert!(rate_limiter.check("a", 2).is_ok());
+        // the request bucket is empty, further chunks only need inputs
+        assert!(rate_limiter.check_inputs("a", 2).is_ok());
+        let limited = rate_limiter.check_inputs("a", 1).unwrap_err();
+        assert_eq!(limited.what, "inputs");
+    }
+}
@@ -0,0 +1,225 @@
+use crate::audit_log::RotatingWriter;
+use crate::batch_stats::percentile;
+use crate::config::AppConfig;
+use crate::mirror::MirrorRecord;
+use crate::types::EmbedRequest;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Recordings are for capacity tests, not retention, a single rotated file is kept
+const RECORDING_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// One JSON line per recorded `/embed` request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedRequest {
+    /// Unix epoch milliseconds, when the request was received
+    pub timestamp_ms: u64,
+    pub body: EmbedRequest,
+}
+
+/// Records `/embed` request bodies as received (`config.record_traffic_path`), to be sent again
+/// with the `replay` subcommand
+pub struct TrafficRecorder {
+    sender: mpsc::Sender<RecordedRequest>,
+}
+
+impl TrafficRecorder {
+    pub fn new(config: &AppConfig) -> Result<Option<Self>, String> {
+        let Some(path) = &config.record_traffic_path else {
+            return Ok(None);
+        };
+        let writer = RotatingWriter::open(PathBuf::from(path), RECORDING_MAX_BYTES, 1)
+            .map_err(|e| format!("Failed to open traffic recording `{path}`: {e}"))?;
+
+        Ok(Some(Self {
+            sender: writer.spawn("traffic-recorder")?,
+        }))
+    }
+
+    pub fn record(&self, body: &EmbedRequest) {
+        let record = RecordedRequest {
+            timestamp_ms: MirrorRecord::timestamp_ms(),
+            body: body.clone(),
+        };
+        if let Err(e) = self.sender.send(record) {
+            error!(
+                "Traffic recorder is gone, lost request {:?}",
+                e.0.timestamp_ms
+            );
+        }
+    }
+}
+
+/// `replay` subcommand, e.g. `auto-batching-proxy replay --file traffic.jsonl --target http://10.0.0.9:3000/embed`
+#[derive(clap::Args, Debug, Clone, PartialEq)]
+pub struct ReplayArgs {
+    /// Recording of `--record-traffic-path`
+    #[arg(long)]
+    pub file: String,
+
+    /// `/embed` URL requests are sent to
+    #[arg(long)]
+    pub target: String,
+
+    /// Pacing multiplier, e.g. `2` sends the recorded traffic twice as fast
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+
+    /// Sent as `Authorization: Bearer <key>`
+    #[arg(long)]
+    pub api_key: Option<String>,
+}
+
+/// When each recorded request is due, relative to the first one
+fn schedule(records: &[RecordedRequest], speed: f64) -> Vec<Duration> {
+    let first_ms = records.first().map_or(0, |record| record.timestamp_ms);
+    records
+        .iter()
+        .map(|record| {
+            let offset_ms = record.timestamp_ms.saturating_sub(first_ms) as f64 / speed;
+            Duration::from_secs_f64(offset_ms / 1000.0)
+        })
+        .collect()
+}
+
+fn read_recording(path: &str) -> Result<Vec<RecordedRequest>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read recording `{path}`: {e}"))?;
+    let mut records = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| format!("Line {}: {e}", index + 1))
+        })
+        .collect::<Result<Vec<RecordedRequest>, _>>()?;
+    // the writer thread keeps arrival order, but recordings might have been concatenated
+    records.sort_by_key(|record| record.timestamp_ms);
+    Ok(records)
+}
+
+/// Sends every recorded request to `args.target` at its original offset (scaled by `args.speed`),
+/// without waiting for earlier responses, then prints latency percentiles & errors
+pub async fn run(args: ReplayArgs) -> Result<(), String> {
+    if !args.speed.is_finite() || args.speed <= 0.0 {
+        return Err("speed must be > 0".to_string());
+    }
+    let records = read_recording(&args.file)?;
+    if records.is_empty() {
+        return Err(format!("Recording `{}` is empty", args.file));
+    }
+    let offsets = schedule(&records, args.speed);
+    println!(
+        "Replaying {} requests over {:.1}s against {}",
+        records.len(),
+        offsets.last().copied().unwrap_or_default().as_secs_f64(),
+        args.target
+    );
+
+    let client = reqwest::Client::new();
+    let started_at = Instant::now();
+    let mut requests = JoinSet::new();
+    for (record, offset) in records.into_iter().zip(offsets) {
+        tokio::time::sleep_until((started_at + offset).into()).await;
+        let mut request = client.post(&args.target).json(&record.body);
+        if let Some(api_key) = &args.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        requests.spawn(async move {
+            let sent_at = Instant::now();
+            let result = match request.send().await {
+                Ok(response) if response.status().is_success() => Ok(()),
+                Ok(response) => Err(format!("HTTP {}", response.status())),
+                Err(e) => Err(e.to_string()),
+            };
+            (sent_at.elapsed(), result)
+        });
+    }
+
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    let mut last_error = None;
+    for (latency, result) in requests.join_all().await {
+        match result {
+            Ok(()) => latencies.push(latency),
+            Err(e) => {
+                errors += 1;
+                last_error = Some(e);
+            }
+        }
+    }
+    latencies.sort_unstable();
+
+    println!(
+        "Done in {:.1}s: {} succeeded, {errors} failed",
+        started_at.elapsed().as_secs_f64(),
+        latencies.len()
+    );
+    if !latencies.is_empty() {
+        let ms = |p: f64| percentile(&latencies, p).as_secs_f64() * 1000.0;
+        println!(
+            "Latency ms: p50 {:.1}, p95 {:.1}, p99 {:.1}, max {:.1}",
+            ms(0.50),
+            ms(0.95),
+            ms(0.99),
+            ms(1.0)
+        );
+    }
+    if let Some(e) = last_error {
+        println!("Last error: {e}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(timestamp_ms: u64) -> RecordedRequest {
+        RecordedRequest {
+            timestamp_ms,
+            body: EmbedRequest {
+                inputs: vec![format!("sent at {timestamp_ms}")],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_schedule_keeps_original_pacing() {
+        let records = [recorded(1_000), recorded(1_250), recorded(3_000)];
+        assert_eq!(
+            schedule(&records, 1.0),
+            vec![
+                Duration::ZERO,
+                Duration::from_millis(250),
+                Duration::from_secs(2)
+            ]
+        );
+        assert_eq!(schedule(&records, 2.0)[2], Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_read_recording_sorts_by_timestamp() {
+        let dir = std::env::temp_dir().join("auto-batching-proxy-replay");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("traffic.jsonl");
+        let lines: Vec<String> = [recorded(2_000), recorded(1_000)]
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n") + "\n\n").unwrap();
+
+        let records = read_recording(path.to_str().unwrap()).unwrap();
+        let timestamps: Vec<u64> = records.iter().map(|record| record.timestamp_ms).collect();
+        assert_eq!(timestamps, vec![1_000, 2_000]);
+        assert_eq!(records[0].body.inputs, vec!["sent at 1000".to_string()]);
+
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(read_recording(path.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::auth::is_configured_api_key;
+use crate::request_handler::RequestHandler;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const REQUEST_DEADLINE_HEADER: &str = "X-Request-Deadline-Ms";
+pub const REQUEST_TIMEOUT_HEADER: &str = "X-Request-Timeout-Ms";
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Request-local cache entry, so every reader of the same request sees the same id
+struct RequestId(String);
+
+/// Client supplied `X-Request-Id`, generated (16 hex digits) otherwise
+pub fn request_id<'r>(request: &'r Request<'_>) -> &'r str {
+    let request_id = request.local_cache(|| {
+        RequestId(
+            request
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .map(String::from)
+                .unwrap_or_else(|| {
+                    // unique enough for log correlation, without pulling in a UUID dependency
+                    format!("{:016x}", RandomState::new().build_hasher().finish())
+                }),
+        )
+    });
+    &request_id.0
+}
+
+/// `X-Api-Key`, or `Authorization: Bearer ...` otherwise
+pub fn presented_api_key<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    request
+        .headers()
+        .get_one(API_KEY_HEADER)
+        .or_else(|| {
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+        })
+        .map(str::trim)
+}
+
+/// Per-request metadata taken from HTTP headers (not part of the JSON body)
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    /// Converted from `X-Request-Deadline-Ms` (absolute Unix epoch milliseconds)
+    /// to a local `Instant`, so it's unaffected by later wall clock changes
+    pub deadline: Option<Instant>,
+    /// From `X-Request-Timeout-Ms`, lets latency-sensitive callers fail faster than `config.request_timeout_secs`
+    pub timeout: Option<Duration>,
+    /// One of `config.api_keys` (`X-Api-Key` or `Authorization: Bearer ...`), falling back to client IP,
+    /// used to share each batch fairly between callers
+    pub client_id: Option<String>,
+    /// See `request_id`, for logs & the audit log
+    pub request_id: String,
+}
+
+impl RequestContext {
+    fn parse_deadline(value: &str) -> Result<Instant, String> {
+        let deadline_ms: u64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("`{REQUEST_DEADLINE_HEADER}` must be Unix epoch milliseconds"))?;
+
+        let now = Instant::now();
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+
+        // a deadline in the past maps to `now`, i.e., it's already expired
+        let remaining = Duration::from_millis(deadline_ms.saturating_sub(now_ms));
+        Ok(now + remaining)
+    }
+
+    /// Only configured keys identify a caller, an arbitrary one per request
+    /// would get a fresh rate limit bucket & quota every time
+    fn client_id(request: &Request<'_>) -> Option<String> {
+        let api_keys = request
+            .rocket()
+            .state::<Arc<RequestHandler>>()
+            .map(|handler| handler.config.api_keys.as_slice())
+            .unwrap_or_default();
+        presented_api_key(request)
+            .filter(|api_key| is_configured_api_key(api_keys, api_key))
+            .map(String::from)
+            .or_else(|| request.client_ip().map(|ip| ip.to_string()))
+    }
+
+    fn parse_timeout(value: &str) -> Result<Duration, String> {
+        match value.trim().parse::<u64>() {
+            Ok(timeout_ms) if timeout_ms > 0 => Ok(Duration::from_millis(timeout_ms)),
+            _ => Err(format!(
+                "`{REQUEST_TIMEOUT_HEADER}` must be a positive number of milliseconds"
+            )),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestContext {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let deadline = match request.headers().get_one(REQUEST_DEADLINE_HEADER) {
+            Some(value) => match Self::parse_deadline(value) {
+                Ok(deadline) => Some(deadline),
+                Err(error) => return Outcome::Error((Status::BadRequest, error)),
+            },
+            None => None,
+        };
+
+        let timeout = match request.headers().get_one(REQUEST_TIMEOUT_HEADER) {
+            Some(value) => match Self::parse_timeout(value) {
+                Ok(timeout) => Some(timeout),
+                Err(error) => return Outcome::Error((Status::BadRequest, error)),
+            },
+            None => None,
+        };
+
+        Outcome::Success(RequestContext {
+            deadline,
+            timeout,
+            client_id: Self::client_id(request),
+            request_id: request_id(request).to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epoch_ms_from_now(offset_ms: i64) -> String {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        (now_ms + offset_ms).to_string()
+    }
+
+    #[test]
+    fn test_parse_deadline_in_future() {
+        let deadline = RequestContext::parse_deadline(&epoch_ms_from_now(5000)).unwrap();
+        let remaining = deadline - Instant::now();
+        assert!(remaining > Duration::from_millis(4000));
+        assert!(remaining <= Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn test_parse_deadline_in_past_is_expired() {
+        let deadline = RequestContext::parse_deadline(&epoch_ms_from_now(-5000)).unwrap();
+        assert!(deadline <= Instant::now());
+    }
+
+    #[test]
+    fn test_parse_deadline_invalid() {
+        assert!(RequestContext::parse_deadline("tomorrow").is_err());
+        assert!(RequestContext::parse_deadline("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(
+            RequestContext::parse_timeout("250").unwrap(),
+            Duration::from_millis(250)
+        );
+        assert!(RequestContext::parse_timeout("0").is_err());
+        assert!(RequestContext::parse_timeout("fast").is_err());
+    }
+}
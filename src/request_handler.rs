@@ -1,68 +1,1502 @@
-use crate::batch_processor::BatchProcessor;
-use crate::config::AppConfig;
-use crate::inference_client::InferenceServiceClient;
+use crate::audit_log::{AuditLog, AuditRecord};
+use crate::backpressure::{Overloaded, QueuePressure};
+use crate::batch_stats::{BatchStats, Stats};
+use crate::canary::CanaryBackend;
+use crate::comparison::{ComparisonBackend, DriftReport, DriftTracker};
+use crate::config::{AppConfig, InputOverflow};
+use crate::dead_letter::{DeadLetter, DeadLetterReason, DeadLetterRecord};
+use crate::debug_capture::DebugCapture;
+use crate::inference_client::{
+    ActiveClient, BackendStatus, InferenceBackend, InferenceServiceClient,
+};
+#[cfg(feature = "persistent-queue")]
+use crate::job_queue::{JobQueue, PersistedJob};
+use crate::jobs::{Job, JobStore};
+use crate::latency_stats::LATENCY;
+use crate::metrics::METRICS;
+use crate::mirror::{Mirror, MirrorRecord};
+use crate::models::{BatchRuntime, DEFAULT_MODEL, ModelQueue, resolve_model, spawn_model_queues};
+use crate::postprocessing::{PostprocessorChain, ResponsePostprocessor};
+use crate::preprocessing::{RequestPreprocessor, build_preprocessors};
+use crate::quota::{QuotaExceeded, QuotaStatus, QuotaTracker};
+use crate::rate_limiter::{RateLimited, RateLimiter};
+use crate::replay::TrafficRecorder;
+use crate::request_context::RequestContext;
+use crate::shadow::ShadowBackend;
+use crate::token_counter::TokenCounter;
+use crate::tuning::TuningReport;
 use crate::types::{
-    EmbedRequest, EmbedResponse, ErrorResponse, PendingRequest, ResponseReceiver, ResponseSender,
+    BackendSwitch, BuildInfo, ConfigSummary, ControlMessage, DeepHealth, EmbedError, EmbedRequest,
+    EmbedResponse, Endpoint, ErrorResponse, PendingRequest, Priority, ProxyInfo, QueueFlush,
+    QueuePause, Readiness, ResponseReceiver, ResponseSender,
 };
+use crate::upstream_limits;
+use crate::usage::{UsageReport, UsageTracker, caller_label};
+use crate::webhooks::WebhookSender;
+use log::{info, warn};
+use rocket::futures::future::join_all;
 use rocket::http::Status;
 use rocket::response::status::Custom;
 use rocket::serde::json::Json;
-use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::sync::{OnceCell, oneshot};
+use tokio::task::JoinSet;
 use tokio::time::timeout;
 
 pub struct RequestHandler {
     pub config: AppConfig,
-    request_sender: mpsc::UnboundedSender<PendingRequest>,
+    /// Requests without `model`
+    queue: ModelQueue,
+    /// `/embed_sparse` & `/embed_all` requests, batched apart from `/embed` ones
+    endpoint_queues: HashMap<Endpoint, ModelQueue>,
+    /// `config.models` queues, by model name
+    models: HashMap<String, ModelQueue>,
+    /// `None` unless `config.batch_runtime_threads` is set, shut down along with the handler
+    _batch_runtime: Option<BatchRuntime>,
+    /// Maintained by `BatchProcessor`
+    batch_stats: Arc<BatchStats>,
+    token_counter: TokenCounter,
+    /// Shared with `BatchProcessor`, kept here for backend status reporting
+    /// Replaced by `switch_backend`
+    inference_client: Arc<ActiveClient>,
+    /// Fed by `BatchProcessor` with every served request
+    usage: Arc<UsageTracker>,
+    /// `None` unless `config.quota_*` is set
+    quota: Option<QuotaTracker>,
+    /// `None` unless `config.rate_limit_*` is set
+    rate_limiter: Option<RateLimiter>,
+    /// `None` unless `config.audit_log_path` is set
+    audit_log: Option<AuditLog>,
+    /// `None` unless `config.debug_capture_path` is set
+    debug_capture: Option<DebugCapture>,
+    /// `None` unless `config.mirror_url` is set
+    mirror: Option<Mirror>,
+    /// `None` unless `config.dead_letter_path` is set
+    dead_letter: Option<DeadLetter>,
+    /// `None` unless `config.record_traffic_path` is set
+    traffic_recorder: Option<TrafficRecorder>,
+    /// `None` unless `config.compare_inference_url` is set
+    drift: Option<Arc<DriftTracker>>,
+    /// `POST /jobs/embed` submissions
+    jobs: JobStore,
+    /// `None` unless `config.job_queue_path` is set
+    #[cfg(feature = "persistent-queue")]
+    job_queue: Option<JobQueue>,
+    /// Notifies jobs' `callback_url`
+    webhooks: WebhookSender,
+    /// Set once shutdown (or `POST /admin/drain`) begins, new requests are rejected from then on
+    draining: AtomicBool,
+    /// Outcome of the single drain, shared by every caller
+    drained: OnceCell<bool>,
+    /// Built from `config.preprocess`, followed by those added via `with_preprocessor`
+    preprocessors: Vec<Box<dyn RequestPreprocessor>>,
+    /// Built from `config.postprocess`, shared with the `/embed` batch loops of every model
+    postprocessors: Arc<PostprocessorChain>,
 }
 
 impl RequestHandler {
-    pub async fn new(config: AppConfig) -> Result<Self, anyhow::Error> {
-        // setup mpsc channel
-        // - each request will be sent though it, hence `multiple producer`
-        // - receiver will be handling requests in tokio spawn`ed task
-        let (request_sender, request_receiver): (
-            mpsc::UnboundedSender<PendingRequest>,
-            mpsc::UnboundedReceiver<PendingRequest>,
-        ) = mpsc::unbounded_channel(); // non-blocking
-
+    pub async fn new(mut config: AppConfig) -> Result<Self, anyhow::Error> {
         // create this client once & return potential error
-        let inference_client =
-            InferenceServiceClient::new(&config).map_err(|e| anyhow::anyhow!(e.message()))?;
+        let inference_client = Arc::new(
+            InferenceServiceClient::new(&config).map_err(|e| anyhow::anyhow!(e.message()))?,
+        );
+        if config.wait_for_upstream
+            && !inference_client
+                .wait_until_reachable(Duration::from_secs(config.wait_for_upstream_timeout_secs))
+                .await
+        {
+            anyhow::bail!(
+                "No inference backend became ready within {}s",
+                config.wait_for_upstream_timeout_secs
+            );
+        }
+        // `config.models` are served by other deployments, whose limits are discovered separately
+        let configured = config.clone();
+        upstream_limits::discover(&inference_client, DEFAULT_MODEL, &mut config).await;
+        if config.warmup_calls > 0 {
+            let start_time = Instant::now();
+            inference_client
+                .warm_up(config.warmup_calls, config.max_inference_inputs)
+                .await;
+            info!("Warm-up completed in {:?}", start_time.elapsed());
+        }
+        inference_client.spawn_background_tasks();
+        let inference_client = Arc::new(ActiveClient::new(inference_client));
+
+        let token_counter = TokenCounter::new(&config).map_err(|e| anyhow::anyhow!(e))?;
 
-        let batch_processor = BatchProcessor::new(config.clone(), inference_client);
-        // launch `run` as a background task
-        tokio::spawn(batch_processor.run(request_receiver));
+        let rate_limiter = RateLimiter::new(&config);
+        let audit_log = AuditLog::new(&config).map_err(|e| anyhow::anyhow!(e))?;
+        let debug_capture = DebugCapture::new(&config).map_err(|e| anyhow::anyhow!(e))?;
+        let mirror = Mirror::new(&config).map_err(|e| anyhow::anyhow!(e))?;
+        let dead_letter = DeadLetter::new(&config).map_err(|e| anyhow::anyhow!(e))?;
+        let traffic_recorder = TrafficRecorder::new(&config).map_err(|e| anyhow::anyhow!(e))?;
+        #[cfg(feature = "persistent-queue")]
+        let job_queue = config
+            .job_queue_path
+            .as_deref()
+            .map(JobQueue::open)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!(e))?;
+        let quota = QuotaTracker::new(&config);
+        let preprocessors = build_preprocessors(&config);
+        let postprocessors =
+            Arc::new(PostprocessorChain::new(&config).map_err(|e| anyhow::anyhow!(e))?);
+        let jobs = JobStore::new(&config);
+        let webhooks = WebhookSender::new(&config).map_err(|e| anyhow::anyhow!(e))?;
+        let batch_stats = Arc::new(BatchStats::new());
+        let usage = Arc::new(UsageTracker::new(&config));
+        let inference_backend: Arc<dyn InferenceBackend> =
+            match CanaryBackend::new(&config, inference_client.clone())
+                .map_err(|e| anyhow::anyhow!(e.message()))?
+            {
+                Some(canary_backend) => Arc::new(canary_backend),
+                None => inference_client.clone(),
+            };
+        let comparison_backend = ComparisonBackend::new(&config, inference_backend.clone())
+            .map_err(|e| anyhow::anyhow!(e.message()))?;
+        let drift = comparison_backend.as_ref().map(ComparisonBackend::tracker);
+        let inference_backend: Arc<dyn InferenceBackend> = match comparison_backend {
+            Some(comparison_backend) => Arc::new(comparison_backend),
+            None => inference_backend,
+        };
+        let inference_backend: Arc<dyn InferenceBackend> =
+            match ShadowBackend::new(&config, inference_backend.clone())
+                .map_err(|e| anyhow::anyhow!(e.message()))?
+            {
+                Some(shadow_backend) => Arc::new(shadow_backend),
+                None => inference_backend,
+            };
+        // each request is sent through the queue's (non-blocking) mpsc channel,
+        // its receiver is handled by the batch loop in a tokio spawn`ed task
+        let batch_runtime = config
+            .batch_runtime_threads
+            .map(BatchRuntime::new)
+            .transpose()?;
+        let runtime = batch_runtime
+            .as_ref()
+            .map_or_else(Handle::current, BatchRuntime::handle);
+        let queue = ModelQueue::spawn(
+            config.clone(),
+            Endpoint::Embed,
+            inference_backend,
+            batch_stats.clone(),
+            usage.clone(),
+            postprocessors.clone(),
+            &runtime,
+        );
+        // canary, comparison & shadow backends only compare dense embeddings,
+        // which are also the only ones postprocessed
+        let endpoint_queues = [Endpoint::EmbedSparse, Endpoint::EmbedAll]
+            .into_iter()
+            .map(|endpoint| {
+                let queue = ModelQueue::spawn(
+                    config.for_endpoint(endpoint),
+                    endpoint,
+                    inference_client.clone(),
+                    batch_stats.clone(),
+                    usage.clone(),
+                    Arc::default(),
+                    &runtime,
+                );
+                (endpoint, queue)
+            })
+            .collect();
+        let models =
+            spawn_model_queues(&configured, &batch_stats, &usage, &postprocessors, &runtime)
+                .await
+                .map_err(|e| anyhow::anyhow!(e))?;
+        if config.discover_upstream_limits {
+            let inference_client = Arc::downgrade(&inference_client);
+            tokio::spawn(upstream_limits::watch(
+                move || inference_client.upgrade().map(|client| client.current()),
+                DEFAULT_MODEL.to_string(),
+                config.clone(),
+            ));
+        }
 
         Ok(Self {
             config,
-            request_sender,
+            queue,
+            endpoint_queues,
+            models,
+            _batch_runtime: batch_runtime,
+            batch_stats,
+            token_counter,
+            inference_client,
+            usage,
+            quota,
+            rate_limiter,
+            audit_log,
+            debug_capture,
+            mirror,
+            dead_letter,
+            traffic_recorder,
+            drift,
+            jobs,
+            #[cfg(feature = "persistent-queue")]
+            job_queue,
+            webhooks,
+            draining: AtomicBool::new(false),
+            drained: OnceCell::new(),
+            preprocessors,
+            postprocessors,
+        })
+    }
+
+    /// Runs after the built-in preprocessors of `config.preprocess`, in the order added
+    pub fn with_preprocessor(mut self, preprocessor: Box<dyn RequestPreprocessor>) -> Self {
+        self.preprocessors.push(preprocessor);
+        self
+    }
+
+    /// Runs after the built-in postprocessors of `config.postprocess`, in the order added.
+    /// Only `/embed` batches (of every model) are postprocessed
+    pub fn with_postprocessor(self, postprocessor: Box<dyn ResponsePostprocessor>) -> Self {
+        self.postprocessors.push(postprocessor);
+        self
+    }
+
+    /// Inputs of `request` through all `preprocessors`, 422 for the first rejected one.
+    /// Runs once on the whole request, i.e., before it's split into chunks or single inputs
+    fn preprocess(&self, request: &mut EmbedRequest) -> Result<(), Custom<Json<ErrorResponse>>> {
+        if self.preprocessors.is_empty() {
+            return Ok(());
+        }
+        for (index, input) in request.inputs.iter_mut().enumerate() {
+            *input = self
+                .preprocess_input(std::mem::take(input))
+                .map_err(|reason| {
+                    Custom(
+                        Status::UnprocessableEntity,
+                        Json(ErrorResponse {
+                            error: format!("`inputs` at index {index} rejected: {reason}"),
+                            code: Some("input_preprocess_rejected"),
+                        }),
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    fn preprocess_input(&self, input: String) -> Result<String, String> {
+        self.preprocessors
+            .iter()
+            .try_fold(input, |input, preprocessor| preprocessor.preprocess(input))
+    }
+
+    /// Checked before the request body is validated & queued, `model` picks the queue
+    /// whose state is reported along with the rejection. Split requests only pass the inputs
+    /// of their first chunk, the rest is charged by `process_request_in_chunks`
+    pub fn check_rate_limit(
+        &self,
+        context: &RequestContext,
+        input_count: usize,
+        model: Option<&str>,
+    ) -> Result<(), RateLimited> {
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter
+                .check(
+                    context.client_id.as_deref().unwrap_or_default(),
+                    input_count,
+                )
+                .map_err(|limited| limited.with_queue_pressure(self.queue_pressure(model))),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes `input_count` inputs (but no request) from the caller's rate limit,
+    /// for parts of a request that passed `check_rate_limit`
+    pub fn check_input_rate_limit(
+        &self,
+        context: &RequestContext,
+        input_count: usize,
+    ) -> Result<(), RateLimited> {
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.check_inputs(
+                context.client_id.as_deref().unwrap_or_default(),
+                input_count,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Waits until `input_count` inputs of the caller's rate are available, for the chunks after
+    /// the first one of a split request (see `check_rate_limit`). 429 when they wouldn't be before
+    /// the request times out, `request_timeout` being the one of the queue it's batched by
+    async fn wait_for_rate_limit(
+        &self,
+        context: &RequestContext,
+        input_count: usize,
+        request_timeout: Duration,
+    ) -> Result<(), Custom<Json<ErrorResponse>>> {
+        let request_timeout = context
+            .timeout
+            .map_or(request_timeout, |timeout| timeout.min(request_timeout));
+        let give_up_at = context
+            .deadline
+            .map_or(Instant::now() + request_timeout, |deadline| {
+                deadline.min(Instant::now() + request_timeout)
+            });
+        loop {
+            match self.check_input_rate_limit(context, input_count) {
+                Ok(()) => return Ok(()),
+                Err(limited) if Instant::now() + limited.retry_after() < give_up_at => {
+                    tokio::time::sleep(limited.retry_after()).await;
+                }
+                Err(limited) => return Err(limited.into()),
+            }
+        }
+    }
+
+    /// Depth & estimated wait of the `model` queue (the default one for unknown models)
+    pub fn queue_pressure(&self, model: Option<&str>) -> QueuePressure {
+        let queue = self.queue(model).unwrap_or(&self.queue);
+        QueuePressure::estimate(
+            queue.queue_state.depth(),
+            self.batch_stats.requests_per_sec(),
+            queue.queue_state.oldest_age(),
+            Duration::from_millis(queue.config.max_wait_time_ms),
+        )
+    }
+
+    /// Load shedding rejections get `Retry-After` & queue state headers, see `Overloaded`
+    pub fn backpressure(
+        &self,
+        error: Custom<Json<ErrorResponse>>,
+        model: Option<&str>,
+    ) -> EmbedError {
+        if Overloaded::is_load_shedding(&error) {
+            EmbedError::Overloaded(Overloaded {
+                error,
+                pressure: self.queue_pressure(model),
+            })
+        } else {
+            EmbedError::Rejected(error)
+        }
+    }
+
+    /// Charged once the request passed validation, `None` when quotas are disabled
+    pub fn charge_quota(
+        &self,
+        context: &RequestContext,
+        input_count: usize,
+    ) -> Result<Option<QuotaStatus>, QuotaExceeded> {
+        let Some(quota) = &self.quota else {
+            return Ok(None);
+        };
+        quota
+            .charge(
+                context.client_id.as_deref().unwrap_or_default(),
+                input_count,
+            )
+            .map(Some)
+    }
+
+    /// Failed requests don't count against the quota
+    pub fn refund_quota(&self, context: &RequestContext, input_count: usize) {
+        if let Some(quota) = &self.quota {
+            quota.refund(
+                context.client_id.as_deref().unwrap_or_default(),
+                input_count,
+            );
+        }
+    }
+
+    pub fn usage_report(&self) -> UsageReport {
+        self.usage.report()
+    }
+
+    /// `None` unless A/B comparison (`config.compare_inference_url`) is enabled
+    pub fn drift_report(&self) -> Option<DriftReport> {
+        self.drift.as_ref().map(|drift| drift.report())
+    }
+
+    /// Cheap (no upstream calls), meant to be polled by orchestrators, e.g., Kubernetes readiness probe
+    pub fn readiness(&self) -> Readiness {
+        // the batch loop ticks every `batch_check_interval_ms`, allow for some scheduling delay
+        let batch_loop_stall_limit =
+            Duration::from_millis(self.config.batch_check_interval_ms * 10)
+                .max(Duration::from_secs(1));
+
+        let accepting_requests = !self.draining.load(Ordering::SeqCst);
+        let queue_not_saturated = self
+            .queues()
+            .all(|queue| self.check_load_shedding(queue).is_ok());
+        let upstream_healthy = self
+            .inference_client
+            .current()
+            .backends()
+            .iter()
+            .any(|backend| backend.is_healthy());
+        let batch_loop_running = self.queues().all(|queue| {
+            !queue.request_sender.is_closed()
+                && queue.queue_state.since_last_update() < batch_loop_stall_limit
+        });
+
+        Readiness {
+            ready: accepting_requests
+                && queue_not_saturated
+                && upstream_healthy
+                && batch_loop_running,
+            accepting_requests,
+            queue_not_saturated,
+            upstream_healthy,
+            batch_loop_running,
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.batch_stats.snapshot(&self.queue.queue_state)
+    }
+
+    /// Judged against the default queue's batch settings, `config.models` queues count in too
+    pub fn tuning_report(&self) -> TuningReport {
+        TuningReport::new(&self.batch_stats.tuning_window(), &self.config)
+    }
+
+    pub async fn info(&self) -> ProxyInfo {
+        let inference_client = self.inference_client.current();
+        let (upstream, upstream_error) = match inference_client.upstream_info().await {
+            Ok(info) => (Some(info), None),
+            Err(e) => (None, Some(e)),
+        };
+        ProxyInfo {
+            proxy: BuildInfo::current(),
+            config: ConfigSummary {
+                // might have been switched since startup
+                inference_backends: inference_client.backends().len(),
+                ..ConfigSummary::from(&self.config)
+            },
+            upstream,
+            upstream_error,
+        }
+    }
+
+    pub async fn deep_health(&self) -> DeepHealth {
+        DeepHealth::from_probes(self.inference_client.current().probe_all().await)
+    }
+
+    pub fn backend_statuses(&self) -> Vec<BackendStatus> {
+        self.inference_client.current().backend_statuses()
+    }
+
+    /// Switches batches to `inference_url` (comma separated for replicas), once at least one of
+    /// the new backends passed a probe (& got its `config.warmup_calls`). Queued requests
+    /// are kept, batches in flight finish on the previous backends. Not persisted across restarts
+    pub async fn switch_backend(
+        &self,
+        inference_url: &str,
+    ) -> Result<BackendSwitch, Custom<Json<ErrorResponse>>> {
+        let invalid = |error: String| {
+            Custom(
+                Status::UnprocessableEntity,
+                Json(ErrorResponse {
+                    error,
+                    code: Some("invalid_inference_url"),
+                }),
+            )
+        };
+        let inference_urls: Vec<String> = inference_url
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        AppConfig::validate_inference_urls(&inference_urls).map_err(invalid)?;
+        let inference_client = InferenceServiceClient::new(&AppConfig {
+            inference_urls: inference_urls.clone(),
+            ..self.config.clone()
+        })
+        .map_err(|e| invalid(e.message()))?;
+
+        let probes = inference_client.probe_all().await;
+        if !probes.iter().any(|probe| probe.reachable) {
+            return Err(Custom(
+                Status::BadGateway,
+                Json(ErrorResponse {
+                    error: format!(
+                        "None of {inference_urls:?} is reachable: {:?}",
+                        probes
+                            .iter()
+                            .filter_map(|probe| probe.error.as_ref())
+                            .collect::<Vec<_>>()
+                    ),
+                    code: Some("backend_unreachable"),
+                }),
+            ));
+        }
+        if self.config.warmup_calls > 0 {
+            inference_client
+                .warm_up(self.config.warmup_calls, self.config.max_inference_inputs)
+                .await;
+        }
+
+        let inference_client = Arc::new(inference_client);
+        inference_client.spawn_background_tasks();
+        let previous = self.inference_client.replace(inference_client);
+        let previous_inference_urls: Vec<String> = previous
+            .backends()
+            .iter()
+            .map(|backend| backend.url.clone())
+            .collect();
+        info!("Switched inference backends from {previous_inference_urls:?} to {inference_urls:?}");
+
+        Ok(BackendSwitch {
+            previous_inference_urls,
+            inference_urls,
+            probes,
+        })
+    }
+
+    /// Stops (or resumes) batch dispatch of every model queue, e.g., during a brief upstream
+    /// maintenance window, so clients see added latency instead of errors. A drain on shutdown
+    /// still flushes everything
+    pub fn set_paused(&self, paused: bool) -> QueuePause {
+        for queue in self.queues() {
+            let message = if paused {
+                ControlMessage::Pause
+            } else {
+                ControlMessage::Resume
+            };
+            if queue.control_sender.send(message).is_err() {
+                warn!("Batch processor is not running, can't pause or resume it");
+            }
+        }
+        QueuePause {
+            paused,
+            queue_depth: self.queues().map(|queue| queue.queue_state.depth()).sum(),
+        }
+    }
+
+    /// Dispatches all pending requests of every model queue now, instead of waiting for
+    /// `config.max_wait_time_ms`, e.g., before maintenance
+    pub async fn flush(&self) -> QueueFlush {
+        let mut done_receivers = Vec::new();
+        for queue in self.queues() {
+            let (done_sender, done_receiver) = oneshot::channel();
+            if queue
+                .control_sender
+                .send(ControlMessage::Flush { done: done_sender })
+                .is_ok()
+            {
+                done_receivers.push(done_receiver);
+            }
+        }
+        QueueFlush {
+            flushed_requests: join_all(done_receivers)
+                .await
+                .into_iter()
+                .map(|flushed| flushed.unwrap_or(0))
+                .sum(),
+        }
+    }
+
+    /// Set once `drain` started
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Called on shutdown (& by `POST /admin/drain`), stops accepting new requests, then waits (up to
+    /// `config.shutdown_drain_timeout_secs`) until all queued & in-flight requests are served.
+    /// Only drains once, later callers wait for the same drain. `false` when its deadline passed
+    pub async fn drain(&self) -> bool {
+        *self.drained.get_or_init(|| self.drain_queues()).await
+    }
+
+    async fn drain_queues(&self) -> bool {
+        self.draining.store(true, Ordering::SeqCst);
+
+        let mut done_receivers = Vec::new();
+        for queue in self.queues() {
+            let (done_sender, done_receiver) = oneshot::channel();
+            if queue
+                .control_sender
+                .send(ControlMessage::Drain { done: done_sender })
+                .is_ok()
+            {
+                done_receivers.push(done_receiver);
+            }
+        }
+        if done_receivers.is_empty() {
+            warn!("Batch processor is not running, nothing to drain");
+            return true;
+        }
+
+        let drain_timeout = Duration::from_secs(self.config.shutdown_drain_timeout_secs);
+        match timeout(drain_timeout, join_all(done_receivers)).await {
+            Ok(_) => {
+                info!("All pending requests drained");
+                true
+            }
+            Err(_) => {
+                warn!(
+                    "Drain deadline of {}s exceeded, remaining requests will be dropped",
+                    self.config.shutdown_drain_timeout_secs
+                );
+                false
+            }
+        }
+    }
+
+    /// The default queues, followed by the `config.models` ones
+    fn queues(&self) -> impl Iterator<Item = &ModelQueue> {
+        std::iter::once(&self.queue)
+            .chain(self.endpoint_queues.values())
+            .chain(self.models.values())
+    }
+
+    /// Queue of the requested `model` (or its alias), 404 for models missing from `config.models`
+    fn queue(&self, model: Option<&str>) -> Result<&ModelQueue, Custom<Json<ErrorResponse>>> {
+        let Some(resolved) = resolve_model(&self.config, model) else {
+            return Ok(&self.queue);
+        };
+        self.models.get(resolved).ok_or_else(|| {
+            Custom(
+                Status::NotFound,
+                Json(ErrorResponse {
+                    error: format!("Unknown model `{}`", model.unwrap_or(resolved)),
+                    code: Some("model_not_found"),
+                }),
+            )
+        })
+    }
+
+    /// `queue` of `endpoint`, other endpoints than `/embed` are only served by the default model
+    fn queue_for(
+        &self,
+        endpoint: Endpoint,
+        model: Option<&str>,
+    ) -> Result<&ModelQueue, Custom<Json<ErrorResponse>>> {
+        if endpoint == Endpoint::Embed {
+            return self.queue(model);
+        }
+        match resolve_model(&self.config, model) {
+            None => Ok(&self.endpoint_queues[&endpoint]),
+            Some(_) => Err(Custom(
+                Status::NotImplemented,
+                Json(ErrorResponse {
+                    error: format!(
+                        "{} isn't served for model `{}`",
+                        endpoint.path(),
+                        model.unwrap_or_default()
+                    ),
+                    code: Some("unsupported_endpoint"),
+                }),
+            )),
+        }
+    }
+
+    /// `config` with the requested model's limits applied (see `AppConfig::for_model`),
+    /// 404 for unknown models
+    pub fn model_config(
+        &self,
+        model: Option<&str>,
+    ) -> Result<&AppConfig, Custom<Json<ErrorResponse>>> {
+        self.queue(model).map(|queue| &queue.config)
+    }
+
+    /// `model_config` of the queue batching `endpoint`, see `AppConfig::for_endpoint`
+    pub fn endpoint_config(
+        &self,
+        endpoint: Endpoint,
+        model: Option<&str>,
+    ) -> Result<&AppConfig, Custom<Json<ErrorResponse>>> {
+        self.queue_for(endpoint, model).map(|queue| &queue.config)
+    }
+
+    /// Rejects new requests early (instead of queueing them), when the queue is already too deep
+    /// or too stale, so upstream load balancers can fail over to another proxy instance
+    fn check_load_shedding(&self, queue: &ModelQueue) -> Result<(), Custom<Json<ErrorResponse>>> {
+        let shed = |code: &'static str, error: String| {
+            Err(Custom(
+                Status::ServiceUnavailable,
+                Json(ErrorResponse {
+                    error,
+                    code: Some(code),
+                }),
+            ))
+        };
+
+        if let Some(max_depth) = self.config.load_shed_queue_depth {
+            let depth = queue.queue_state.depth();
+            if depth >= max_depth {
+                return shed(
+                    "queue_depth_exceeded",
+                    format!("Queue depth {depth} reached limit of {max_depth}"),
+                );
+            }
+        }
+
+        if let Some(max_age_ms) = self.config.load_shed_max_age_ms
+            && let Some(oldest_age) = queue.queue_state.oldest_age()
+            && oldest_age.as_millis() as u64 >= max_age_ms
+        {
+            return shed(
+                "queue_age_exceeded",
+                format!(
+                    "Oldest queued request waited {}ms, limit is {max_age_ms}ms",
+                    oldest_age.as_millis()
+                ),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Queues the request at `Priority::Low` in the background, returns the `queued` job right away.
+    /// The job is processed like an `/embed` request (incl. `request_timeout_secs`), a failed job
+    /// refunds its quota. Once finished, the job is POSTed to `callback_url` (if any)
+    pub fn submit_job(
+        self: &Arc<Self>,
+        mut request: EmbedRequest,
+        callback_url: Option<String>,
+        context: RequestContext,
+    ) -> Result<Job, Custom<Json<ErrorResponse>>> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Custom(
+                Status::ServiceUnavailable,
+                Json(ErrorResponse {
+                    error: "Proxy is shutting down".to_string(),
+                    code: Some("shutting_down"),
+                }),
+            ));
+        }
+        let queue = self.queue(request.model.as_deref())?;
+        self.check_load_shedding(queue)?;
+        let job = self.jobs.create(None, context.client_id.clone())?;
+
+        request.priority = Priority::Low;
+        #[cfg(feature = "persistent-queue")]
+        self.persist_job(&job.job_id, || PersistedJob::Embed {
+            request: request.clone(),
+            callback_url: callback_url.clone(),
+            client_id: context.client_id.clone(),
+            request_id: context.request_id.clone(),
+        })?;
+        self.spawn_job(job.job_id.clone(), request, callback_url, context);
+        Ok(job)
+    }
+
+    fn spawn_job(
+        self: &Arc<Self>,
+        job_id: String,
+        request: EmbedRequest,
+        callback_url: Option<String>,
+        context: RequestContext,
+    ) {
+        let handler = self.clone();
+        tokio::spawn(async move {
+            let max_inference_inputs = handler
+                .model_config(request.model.as_deref())
+                .map_or(usize::MAX, |config| config.max_inference_inputs);
+            let input_count = request.inputs.len();
+            let result = if input_count > max_inference_inputs {
+                handler
+                    .process_request_in_chunks(Endpoint::Embed, request, context.clone())
+                    .await
+            } else {
+                handler.process_request(request, context.clone()).await
+            };
+            if result.is_err() {
+                handler.refund_quota(&context, input_count);
+            }
+            let finished = handler.jobs.finish(&job_id, result);
+            #[cfg(feature = "persistent-queue")]
+            handler.unpersist_job(&job_id);
+            handler.notify_job_finished(finished, callback_url).await;
+        });
+    }
+
+    /// Like `submit_job`, but the corpus is streamed from `input_uri` through `bulk::embed_lines`
+    /// & results are written to `output_uri`, see `object_storage`
+    #[cfg(feature = "object-store")]
+    pub fn submit_bulk_job(
+        self: &Arc<Self>,
+        input_uri: &str,
+        output_uri: String,
+        callback_url: Option<String>,
+        context: RequestContext,
+    ) -> Result<Job, Custom<Json<ErrorResponse>>> {
+        use crate::object_storage::ObjectLocation;
+
+        let invalid_uri = |error: String| {
+            Custom(
+                Status::UnprocessableEntity,
+                Json(ErrorResponse {
+                    error,
+                    code: Some("invalid_object_uri"),
+                }),
+            )
+        };
+        let input = ObjectLocation::parse(input_uri).map_err(invalid_uri)?;
+        let output = ObjectLocation::parse(&output_uri).map_err(invalid_uri)?;
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Custom(
+                Status::ServiceUnavailable,
+                Json(ErrorResponse {
+                    error: "Proxy is shutting down".to_string(),
+                    code: Some("shutting_down"),
+                }),
+            ));
+        }
+        self.check_load_shedding(&self.queue)?;
+        let job = self
+            .jobs
+            .create(Some(output_uri.clone()), context.client_id.clone())?;
+
+        #[cfg(feature = "persistent-queue")]
+        self.persist_job(&job.job_id, || PersistedJob::Bulk {
+            input_uri: input_uri.to_string(),
+            output_uri,
+            callback_url: callback_url.clone(),
+            client_id: context.client_id.clone(),
+            request_id: context.request_id.clone(),
+        })?;
+        self.spawn_bulk_job(job.job_id.clone(), input, output, callback_url, context);
+        Ok(job)
+    }
+
+    #[cfg(feature = "object-store")]
+    fn spawn_bulk_job(
+        self: &Arc<Self>,
+        job_id: String,
+        input: crate::object_storage::ObjectLocation,
+        output: crate::object_storage::ObjectLocation,
+        callback_url: Option<String>,
+        context: RequestContext,
+    ) {
+        let handler = self.clone();
+        tokio::spawn(async move {
+            let result =
+                crate::object_storage::run_bulk_job(handler.clone(), context, input, output)
+                    .await
+                    .map(|summary| (summary.records, summary.failed_records));
+            let finished = handler.jobs.finish_bulk(&job_id, result);
+            #[cfg(feature = "persistent-queue")]
+            handler.unpersist_job(&job_id);
+            handler.notify_job_finished(finished, callback_url).await;
+        });
+    }
+
+    /// Acknowledged jobs must be resumable, i.e., a job that can't be persisted is failed right away
+    #[cfg(feature = "persistent-queue")]
+    fn persist_job(
+        &self,
+        job_id: &str,
+        job: impl FnOnce() -> PersistedJob,
+    ) -> Result<(), Custom<Json<ErrorResponse>>> {
+        let Some(job_queue) = &self.job_queue else {
+            return Ok(());
+        };
+        job_queue.insert(job_id, &job()).map_err(|e| {
+            let error = Custom(
+                Status::InternalServerError,
+                Json(ErrorResponse {
+                    error: format!("Failed to persist job: {e}"),
+                    code: Some("job_not_persisted"),
+                }),
+            );
+            self.jobs.finish(job_id, Err(error.clone()));
+            error
         })
     }
 
+    /// A finished job is never resumed, even if its webhook wasn't delivered yet
+    #[cfg(feature = "persistent-queue")]
+    fn unpersist_job(&self, job_id: &str) {
+        if let Some(job_queue) = &self.job_queue
+            && let Err(e) = job_queue.remove(job_id)
+        {
+            warn!("Failed to remove finished job {job_id} from the job queue: {e}");
+        }
+    }
+
+    /// Queues the jobs persisted before the last shutdown again (`config.job_queue_path`),
+    /// under their previous job id. Returns how many were resumed
+    #[cfg(feature = "persistent-queue")]
+    pub fn resume_jobs(self: &Arc<Self>) -> Result<usize, String> {
+        let Some(job_queue) = &self.job_queue else {
+            return Ok(0);
+        };
+        let pending = job_queue.pending()?;
+        let count = pending.len();
+        let context = |client_id, request_id| RequestContext {
+            deadline: None,
+            timeout: None,
+            client_id,
+            request_id,
+        };
+        for (job_id, job) in pending {
+            match job {
+                PersistedJob::Embed {
+                    request,
+                    callback_url,
+                    client_id,
+                    request_id,
+                } => {
+                    self.jobs.restore(&job_id, None, client_id.clone());
+                    self.spawn_job(
+                        job_id,
+                        request,
+                        callback_url,
+                        context(client_id, request_id),
+                    );
+                }
+                #[cfg(feature = "object-store")]
+                PersistedJob::Bulk {
+                    input_uri,
+                    output_uri,
+                    callback_url,
+                    client_id,
+                    request_id,
+                } => {
+                    use crate::object_storage::ObjectLocation;
+
+                    match ObjectLocation::parse(&input_uri)
+                        .and_then(|input| Ok((input, ObjectLocation::parse(&output_uri)?)))
+                    {
+                        Ok((input, output)) => {
+                            self.jobs
+                                .restore(&job_id, Some(output_uri), client_id.clone());
+                            let context = context(client_id, request_id);
+                            self.spawn_bulk_job(job_id, input, output, callback_url, context);
+                        }
+                        Err(e) => warn!("Not resuming bulk job {job_id}: {e}"),
+                    }
+                }
+                #[cfg(not(feature = "object-store"))]
+                PersistedJob::Bulk { .. } => {
+                    warn!(
+                        "Not resuming bulk job {job_id}, it needs the `object-store` cargo feature"
+                    );
+                }
+            }
+        }
+        if count > 0 {
+            info!("Resumed {count} queued jobs");
+        }
+        Ok(count)
+    }
+
+    async fn notify_job_finished(&self, job: Option<Job>, callback_url: Option<String>) {
+        if let (Some(job), Some(callback_url)) = (job, callback_url) {
+            let job_id = job.job_id.clone();
+            if let Err(e) = self.webhooks.deliver(&callback_url, job).await {
+                warn!("Job {job_id} webhook to {callback_url} failed: {e}");
+            }
+        }
+    }
+
+    /// `/embed` bodies are recorded as received, i.e., before validation, rate limits & quotas
+    pub fn record_traffic(&self, request: &EmbedRequest) {
+        if let Some(traffic_recorder) = &self.traffic_recorder {
+            traffic_recorder.record(request);
+        }
+    }
+
+    /// `None` for unknown & expired jobs, and for jobs of other callers
+    pub fn job(&self, job_id: &str, context: &RequestContext) -> Option<Job> {
+        self.jobs.get(job_id, context.client_id.as_deref())
+    }
+
+    /// For requests with more than `config.max_inference_inputs` inputs (`config.split_oversized_requests`),
+    /// each chunk is queued as a separate request & embeddings are reassembled in input order.
+    /// Chunks after the first one wait for their inputs' share of the caller's rate limit.
+    /// Any failed chunk fails the whole request
+    pub async fn process_request_in_chunks(
+        self: &Arc<Self>,
+        endpoint: Endpoint,
+        mut request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        self.preprocess(&mut request)?;
+        self.process_preprocessed_in_chunks(endpoint, request, context)
+            .await
+    }
+
+    async fn process_preprocessed_in_chunks(
+        self: &Arc<Self>,
+        endpoint: Endpoint,
+        mut request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        let queue_config = self.endpoint_config(endpoint, request.model.as_deref())?;
+        let max_inference_inputs = queue_config.max_inference_inputs;
+        let request_timeout = queue_config.request_timeout();
+        let inputs = std::mem::take(&mut request.inputs);
+        let input_ids = request.input_ids.take();
+        let mut chunks = JoinSet::new();
+        for (index, inputs) in inputs.chunks(max_inference_inputs).enumerate() {
+            let handler = self.clone();
+            let chunk = EmbedRequest {
+                inputs: inputs.to_vec(),
+                ..request.clone()
+            };
+            let context = context.clone();
+            chunks.spawn(async move {
+                let result = async {
+                    if index > 0 {
+                        handler
+                            .wait_for_rate_limit(&context, chunk.inputs.len(), request_timeout)
+                            .await?;
+                    }
+                    handler
+                        .process_preprocessed(endpoint, chunk, context.clone())
+                        .await
+                };
+                (index, result.await)
+            });
+        }
+
+        let mut responses = vec![None; chunks.len()];
+        while let Some(joined) = chunks.join_next().await {
+            let (index, result) = joined.map_err(|e| {
+                Custom(
+                    Status::InternalServerError,
+                    Json(ErrorResponse {
+                        error: format!("Chunk task failed: {e}"),
+                        code: None,
+                    }),
+                )
+            })?;
+            // dropping `chunks` aborts the remaining ones
+            responses[index] = Some(result?);
+        }
+
+        let mut merged = Self::merge_responses(responses.into_iter().flatten());
+        merged.metadata = request.metadata;
+        merged.client_tag = request.client_tag;
+        merged.input_ids = input_ids;
+        Ok(merged)
+    }
+
+    /// Embeddings of `responses` (to parts of one request) in order, the first `batch_info`
+    fn merge_responses(responses: impl IntoIterator<Item = EmbedResponse>) -> EmbedResponse {
+        let mut merged = EmbedResponse::default();
+        for response in responses {
+            merged.embeddings.extend(response.embeddings);
+            merged.sparse_embeddings.extend(response.sparse_embeddings);
+            merged.token_embeddings.extend(response.token_embeddings);
+            merged.batch_info = merged.batch_info.or(response.batch_info);
+        }
+        merged
+    }
+
+    /// Already preprocessed `request`, in chunks when over the `max_inference_inputs` of `endpoint`
+    async fn process_preprocessed_of_any_size(
+        self: &Arc<Self>,
+        endpoint: Endpoint,
+        request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        let max_inference_inputs = self
+            .endpoint_config(endpoint, request.model.as_deref())?
+            .max_inference_inputs;
+        if request.inputs.len() > max_inference_inputs {
+            self.process_preprocessed_in_chunks(endpoint, request, context)
+                .await
+        } else {
+            self.process_preprocessed(endpoint, request, context).await
+        }
+    }
+
+    /// For `request.partial_errors`, inputs over `config.max_input_chars` (`input_overflow` `reject`)
+    /// & inputs rejected by a preprocessor aren't sent, the rest is batched as usual. When the inference service rejects those,
+    /// each input is queued on its own (still batched with other requests), so only the
+    /// offending ones fail. Other failures (e.g. upstream unavailable) fail the whole request
+    pub async fn process_request_partially(
+        self: &Arc<Self>,
+        endpoint: Endpoint,
+        mut request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        let mut input_errors = BTreeMap::new();
+        if let Some(max_input_chars) = self.config.max_input_chars
+            && self.config.input_overflow == InputOverflow::Reject
+        {
+            for index in request.inputs_longer_than(max_input_chars) {
+                let error = ErrorResponse {
+                    error: format!("Input exceeds {max_input_chars} characters"),
+                    code: Some("input_too_long"),
+                };
+                input_errors.insert(index, error);
+            }
+        }
+        if !self.preprocessors.is_empty() {
+            for (index, input) in request.inputs.iter_mut().enumerate() {
+                if input_errors.contains_key(&index) {
+                    continue;
+                }
+                match self.preprocess_input(std::mem::take(input)) {
+                    Ok(preprocessed) => *input = preprocessed,
+                    Err(reason) => {
+                        let error = ErrorResponse {
+                            error: format!("Input rejected: {reason}"),
+                            code: Some("input_preprocess_rejected"),
+                        };
+                        input_errors.insert(index, error);
+                    }
+                }
+            }
+        }
+        let input_ids = request.input_ids.take();
+        // input index of each input that's sent
+        let (sent, inputs): (Vec<usize>, Vec<String>) = std::mem::take(&mut request.inputs)
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !input_errors.contains_key(index))
+            .unzip();
+        let request = EmbedRequest {
+            inputs,
+            partial_errors: false,
+            ..request
+        };
+
+        let mut response = if sent.is_empty() {
+            EmbedResponse::default()
+        } else {
+            match self
+                .process_preprocessed_of_any_size(endpoint, request.clone(), context.clone())
+                .await
+            {
+                Err(Custom(_, error)) if error.code == Some("input_rejected") => {
+                    let results = if sent.len() == 1 {
+                        vec![Err(error.into_inner())]
+                    } else {
+                        self.process_inputs_separately(endpoint, &request, &context)
+                            .await?
+                    };
+                    let mut responses = Vec::new();
+                    for (index, result) in sent.into_iter().zip(results) {
+                        match result {
+                            Ok(response) => responses.push(response),
+                            Err(error) => {
+                                input_errors.insert(index, error);
+                            }
+                        }
+                    }
+                    Self::merge_responses(responses)
+                }
+                result => result?,
+            }
+        };
+        response.input_errors = input_errors;
+        response.input_ids = input_ids;
+        response.metadata = request.metadata;
+        response.client_tag = request.client_tag;
+        Ok(response)
+    }
+
+    /// Each input of `request` as a request of its own, the inference service's input
+    /// rejections are returned per input, any other failure fails them all
+    async fn process_inputs_separately(
+        self: &Arc<Self>,
+        endpoint: Endpoint,
+        request: &EmbedRequest,
+        context: &RequestContext,
+    ) -> Result<Vec<Result<EmbedResponse, ErrorResponse>>, Custom<Json<ErrorResponse>>> {
+        let mut inputs = JoinSet::new();
+        for (index, input) in request.inputs.iter().enumerate() {
+            let handler = self.clone();
+            let request = EmbedRequest {
+                inputs: vec![input.clone()],
+                ..request.clone()
+            };
+            let context = context.clone();
+            inputs.spawn(async move {
+                let result = handler
+                    .process_preprocessed(endpoint, request, context)
+                    .await;
+                (index, result)
+            });
+        }
+
+        let mut results = Vec::new();
+        results.resize_with(inputs.len(), || Ok(EmbedResponse::default()));
+        while let Some(joined) = inputs.join_next().await {
+            let (index, result) = joined.map_err(|e| {
+                Custom(
+                    Status::InternalServerError,
+                    Json(ErrorResponse {
+                        error: format!("Input task failed: {e}"),
+                        code: None,
+                    }),
+                )
+            })?;
+            results[index] = match result {
+                Ok(response) => Ok(response),
+                Err(Custom(_, error)) if error.code == Some("input_rejected") => {
+                    Err(error.into_inner())
+                }
+                // dropping `inputs` aborts the remaining ones
+                Err(error) => return Err(error),
+            };
+        }
+        Ok(results)
+    }
+
     /// This is further received by `/embed` route
     pub async fn process_request(
         &self,
         request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        self.process_request_for(Endpoint::Embed, request, context)
+            .await
+    }
+
+    /// `process_request` batched for the given inference service `endpoint`, e.g. by `/embed_sparse`
+    pub async fn process_request_for(
+        &self,
+        endpoint: Endpoint,
+        mut request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        self.preprocess(&mut request)?;
+        self.process_preprocessed(endpoint, request, context).await
+    }
+
+    /// `process_request_for` of a request whose inputs went through `preprocess` already
+    async fn process_preprocessed(
+        &self,
+        endpoint: Endpoint,
+        mut request: EmbedRequest,
+        context: RequestContext,
+    ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        let received_at = Instant::now();
+        let input_count = request.inputs.len();
+        let input_chars = request
+            .inputs
+            .iter()
+            .map(|input| input.chars().count())
+            .sum();
+        let priority = request.priority;
+        let metadata = request.metadata.clone();
+        let client_tag = request.client_tag.clone();
+        let input_ids = request.input_ids.take();
+        let mirrored_inputs = self
+            .mirror
+            .as_ref()
+            .filter(|mirror| mirror.include_inputs())
+            .map(|_| request.inputs.clone());
+        let captured_inputs = self
+            .debug_capture
+            .as_ref()
+            .filter(|debug_capture| debug_capture.should_sample())
+            .map(|_| request.inputs.clone());
+        // records the request as lost unless it finishes (successfully or with a client error)
+        let dead_letter_watch = self.dead_letter.as_ref().map(|dead_letter| {
+            let timestamp_ms = MirrorRecord::timestamp_ms();
+            let record = DeadLetterRecord {
+                timestamp_ms,
+                received_at_ms: timestamp_ms,
+                request_id: context.request_id.clone(),
+                caller: caller_label(context.client_id.as_deref()),
+                model: request.model.clone(),
+                input_count,
+                reason: DeadLetterReason::ClientGone,
+                status: None,
+                error: None,
+            };
+            dead_letter.watch(record, received_at)
+        });
+        let result = self
+            .queue_and_wait(endpoint, request, context.clone())
+            .await;
+        if result.is_ok() {
+            LATENCY.end_to_end.record(received_at.elapsed());
+        }
+        if let Some(client_tag) = &client_tag {
+            METRICS.record_client_tag(client_tag);
+        }
+        if let Some(dead_letter_watch) = dead_letter_watch {
+            match &result {
+                Ok(_) => dead_letter_watch.finish(Status::Ok, None, None),
+                Err(Custom(status, error)) => {
+                    dead_letter_watch.finish(*status, error.code, Some(&error.error))
+                }
+            }
+        }
+
+        if let (Some(debug_capture), Some(inputs)) = (&self.debug_capture, captured_inputs) {
+            debug_capture.capture(&context.request_id, &inputs, &result);
+        }
+
+        let (batch_id, status) = match &result {
+            Ok(response) => (
+                response.batch_info.as_ref().map(|info| info.batch_id),
+                Status::Ok.code,
+            ),
+            Err(Custom(status, _)) => (None, status.code),
+        };
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(AuditRecord::now(
+                &context.request_id,
+                caller_label(context.client_id.as_deref()),
+                input_count,
+                batch_id,
+                status,
+                client_tag.clone(),
+            ));
+        }
+
+        if let Some(mirror) = &self.mirror {
+            mirror.record(MirrorRecord {
+                timestamp_ms: MirrorRecord::timestamp_ms(),
+                request_id: context.request_id.clone(),
+                caller: caller_label(context.client_id.as_deref()),
+                priority,
+                input_count,
+                input_chars,
+                status,
+                latency_ms: received_at.elapsed().as_millis() as u64,
+                batch_id,
+                inputs: mirrored_inputs,
+            });
+        }
+
+        if let Some(threshold) = self.config.slow_log_threshold()
+            && received_at.elapsed() > threshold
+        {
+            Self::log_slow_request(
+                received_at.elapsed(),
+                input_count,
+                client_tag.as_deref(),
+                &result,
+            );
+        }
+        // might only be built for slow/audit logging, see `AppConfig::tracks_batch_info`
+        result.map(|mut response| {
+            if !self.config.include_batch_info {
+                response.batch_info = None;
+            }
+            response.metadata = metadata;
+            response.client_tag = client_tag;
+            response.input_ids = input_ids;
+            response
+        })
+    }
+
+    fn log_slow_request(
+        elapsed: Duration,
+        input_count: usize,
+        client_tag: Option<&str>,
+        result: &Result<EmbedResponse, Custom<Json<ErrorResponse>>>,
+    ) {
+        let tag = client_tag
+            .map(|client_tag| format!(" [{client_tag}]"))
+            .unwrap_or_default();
+        match result {
+            Ok(EmbedResponse {
+                batch_info: Some(info),
+                ..
+            }) => warn!(
+                "Slow request{tag}: {input_count} inputs, total {elapsed:?}, batch {} ({:?}) of {} requests, queue {:?}ms, serialization {:?}ms, inference {:?}ms",
+                info.batch_id,
+                info.batch_type,
+                info.batch_size.unwrap_or_default(),
+                info.queue_time_ms.unwrap_or_default(),
+                info.serialization_time_ms.unwrap_or_default(),
+                info.inference_time_ms.unwrap_or_default()
+            ),
+            Ok(_) => warn!("Slow request{tag}: {input_count} inputs, total {elapsed:?}"),
+            Err(Custom(status, error)) => warn!(
+                "Slow request{tag}: {input_count} inputs, failed with {status} after {elapsed:?}: {}",
+                error.error
+            ),
+        }
+    }
+
+    async fn queue_and_wait(
+        &self,
+        endpoint: Endpoint,
+        request: EmbedRequest,
+        context: RequestContext,
     ) -> Result<EmbedResponse, Custom<Json<ErrorResponse>>> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Custom(
+                Status::ServiceUnavailable,
+                Json(ErrorResponse {
+                    error: "Proxy is shutting down".to_string(),
+                    code: Some("shutting_down"),
+                }),
+            ));
+        }
+        let queue = self.queue_for(endpoint, request.model.as_deref())?;
+        self.check_load_shedding(queue)?;
+
+        let deadline_exceeded = || {
+            Custom(
+                Status::GatewayTimeout,
+                Json(ErrorResponse {
+                    error: "Request deadline exceeded".to_string(),
+                    code: Some("deadline_exceeded"),
+                }),
+            )
+        };
+        let deadline_budget = context
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if deadline_budget == Some(Duration::ZERO) {
+            return Err(deadline_exceeded());
+        }
+
+        let token_count = match queue.config.max_batch_tokens {
+            Some(max_batch_tokens) => {
+                let token_count = self.token_counter.count(&request.inputs);
+                // such request would never fit in any batch
+                if token_count > max_batch_tokens {
+                    return Err(Custom(
+                        Status::PayloadTooLarge,
+                        Json(ErrorResponse {
+                            error: format!(
+                                "`inputs` have {token_count} tokens, can't be greater than {max_batch_tokens}"
+                            ),
+                            code: Some("too_many_tokens"),
+                        }),
+                    ));
+                }
+                token_count
+            }
+            None => 0,
+        };
+
         // create oneshot channel (only for "this particular" request
         let (response_sender, response_receiver): (ResponseSender, ResponseReceiver) =
             oneshot::channel();
 
-        let pending_request = PendingRequest::new(request.inputs, response_sender);
+        let pending_request = PendingRequest::new(request.inputs, response_sender)
+            .with_priority(request.priority)
+            .with_deadline(context.deadline)
+            .with_client_id(context.client_id)
+            .with_token_count(token_count);
 
-        self.request_sender.send(pending_request).map_err(|err| {
+        queue.request_sender.send(pending_request).map_err(|err| {
             Custom(
                 Status::InternalServerError,
                 Json(ErrorResponse {
                     error: format!("Failed to queue request: {err:?}"),
+                    code: None,
                 }),
             )
         })?;
 
         // for individual request handling
         // this is different from `--max-wait-time-ms` which is for our proxy batch execution delay time
-        let request_timeout = self.config.max_wait_time_duration() + Duration::from_secs(30);
+        // per-request `X-Request-Timeout-Ms` can only shorten the configured timeout
+        let request_timeout = context
+            .timeout
+            .map_or(queue.config.request_timeout(), |timeout| {
+                timeout.min(queue.config.request_timeout())
+            });
+        // client deadline (if any) can only shorten the wait
+        let deadline_is_tighter = deadline_budget.is_some_and(|budget| budget < request_timeout);
+        let request_timeout =
+            deadline_budget.map_or(request_timeout, |budget| budget.min(request_timeout));
 
         // without `timeout`, requests could hang indefinitely, just in case:
         // batch processor gets stuck or downstream inference service becomes unresponsive
@@ -70,10 +1504,14 @@ impl RequestHandler {
         // Result<Result<Result<EmbedResponse, Custom<Json<ErrorResponse>>>, RecvError>, Elapsed>
         let timeout_result = timeout(request_timeout, response_receiver).await;
         let after_timeout_check = timeout_result.map_err(|_| {
+            if deadline_is_tighter {
+                return deadline_exceeded();
+            }
             Custom(
                 Status::RequestTimeout,
                 Json(ErrorResponse {
                     error: "Request timed out".to_string(),
+                    code: None,
                 }),
             )
         })?;
@@ -85,6 +1523,7 @@ impl RequestHandler {
                 Status::InternalServerError,
                 Json(ErrorResponse {
                     error: "Response channel closed".to_string(),
+                    code: None,
                 }),
             )
         })?
@@ -0,0 +1,95 @@
+use crate::config::AppConfig;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Retries available right away, e.g. after startup or a quiet period
+const MAX_TOKENS: f64 = 10.0;
+/// Refilled regardless of traffic, so a low traffic proxy can still retry now and then
+const MIN_RETRIES_PER_SEC: f64 = 1.0;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token bucket capping retries at `config.retry_budget_percent` of upstream calls.
+/// Every first attempt deposits a fraction of a token, every retry takes a whole one,
+/// so an upstream brownout isn't amplified into overload by the proxy's own retries
+#[derive(Debug)]
+pub struct RetryBudget {
+    /// Tokens per first attempt
+    ratio: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RetryBudget {
+    /// `None` when `config.retry_budget_percent` isn't set, retries are then only
+    /// bounded by `config.inference_max_retries`
+    pub fn new(config: &AppConfig) -> Option<Self> {
+        config.retry_budget_percent.map(|percent| Self {
+            ratio: percent / 100.0,
+            bucket: Mutex::new(Bucket {
+                tokens: MAX_TOKENS,
+                refilled_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// Called once per batch call, before its first attempt
+    pub fn deposit(&self) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.tokens = (bucket.tokens + self.ratio).min(MAX_TOKENS);
+    }
+
+    /// Whether a retry may be sent, takes a token if so
+    pub fn try_withdraw(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * MIN_RETRIES_PER_SEC).min(MAX_TOKENS);
+        bucket.refilled_at = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_retry_budget(percent: f64) -> RetryBudget {
+        let config = AppConfig {
+            retry_budget_percent: Some(percent),
+            ..AppConfig::default()
+        };
+        RetryBudget::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_disabled_without_percent() {
+        assert!(RetryBudget::new(&AppConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_retries_limited_to_share_of_calls() {
+        let retry_budget = build_retry_budget(25.0);
+        // initial burst
+        for _ in 0..MAX_TOKENS as usize {
+            assert!(retry_budget.try_withdraw());
+        }
+        assert!(!retry_budget.try_withdraw());
+
+        // 8 calls at 25% earn 2 retries (plus whatever the time based refill adds meanwhile)
+        for _ in 0..8 {
+            retry_budget.deposit();
+        }
+        assert!(retry_budget.try_withdraw());
+        assert!(retry_budget.try_withdraw());
+        assert!(!retry_budget.try_withdraw());
+    }
+}
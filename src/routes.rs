@@ -1,45 +1,387 @@
+use crate::access_log::InputCount;
+use crate::auth::{AdminAuth, ApiKeyAuth};
+use crate::batch_stats::Stats;
+use crate::bulk::{self, BULK_LIMIT};
+use crate::comparison::DriftReport;
+use crate::config::InputOverflow;
+use crate::inference_client::BackendStatus;
+use crate::jobs::{EmbedJobRequest, Job};
+use crate::json_body::JsonBody;
+use crate::latency_stats::{LATENCY, LatencyReport};
+use crate::log_level::{self, LogLevelChange, LogLevelError, LogLevelRequest};
+use crate::metrics::METRICS;
+use crate::quota::WithQuotaHeaders;
+use crate::request_context::RequestContext;
 use crate::request_handler::RequestHandler;
-use crate::types::{EmbedRequest, EmbedResponse, ErrorResponse};
-use rocket::http::Status;
+use crate::streamed_response::StreamedEmbedResponse;
+use crate::tuning::TuningReport;
+use crate::types::{
+    BackendSwitch, BackendSwitchRequest, DeepHealth, DrainReport, EmbedError, EmbedRequest,
+    EmbedResponse, Endpoint, ErrorResponse, MAX_CLIENT_TAG_CHARS, Priority, ProxyInfo, QueueFlush,
+    QueuePause, Readiness, SparseEmbedResponse, TokenEmbedResponse,
+};
+use crate::usage::UsageReport;
+use crate::webhooks::WebhookSender;
+use rocket::data::{Data, Limits};
+use rocket::futures::StreamExt;
+use rocket::http::{ContentType, Status};
 use rocket::response::status::Custom;
+use rocket::response::stream::TextStream;
 use rocket::serde::json::Json;
-use rocket::{State, get, post};
+use rocket::{State, get, post, put};
 use std::sync::Arc;
 
 /// POST /embed - Main embedding endpoint
 ///
 /// Accepts a JSON request with string inputs and returns embeddings.
 /// Requests are automatically batched for efficiency.
+/// Optional `X-Request-Deadline-Ms` header (Unix epoch ms) fails the request once passed,
+/// `X-Request-Timeout-Ms` shortens the configured `request_timeout_secs`.
+/// With `split_oversized_requests`, requests above `max_inference_inputs` are split (413 otherwise).
+/// With `max_input_chars` configured, over-long inputs are rejected with 422 or truncated.
+/// With `models` configured, `model` picks the backend & limits, unknown models are rejected with 404.
+/// With `api_keys` configured, responds 401 unless one of them is presented.
+/// With `rate_limit_*` configured, responds 429 once the caller exceeds its rate.
+/// Rejections by rate limiting & load shedding (503) come with `X-Queue-Depth` & `X-Estimated-Wait-Ms`
+/// headers (& `Retry-After`), so clients can back off.
+/// With `quota_*` configured, responds 429 once the caller's quota is exhausted,
+/// remaining quota is returned in `X-Quota-Daily-Remaining` & `X-Quota-Monthly-Remaining` headers.
 #[post("/embed", data = "<request>")]
 pub async fn embed(
-    request: Json<EmbedRequest>,
+    _auth: ApiKeyAuth,
+    request: JsonBody<EmbedRequest>,
+    context: RequestContext,
+    input_count: InputCount<'_>,
     request_handler: &State<Arc<RequestHandler>>,
-) -> Result<Json<EmbedResponse>, Custom<Json<ErrorResponse>>> {
+) -> Result<WithQuotaHeaders<StreamedEmbedResponse>, EmbedError> {
+    input_count.record(request.inputs.len());
+    request_handler.record_traffic(&request);
+    let WithQuotaHeaders(response, quota_status) = embed_for(
+        Endpoint::Embed,
+        request.into_inner(),
+        context,
+        request_handler,
+    )
+    .await?;
+    Ok(WithQuotaHeaders(
+        StreamedEmbedResponse(response),
+        quota_status,
+    ))
+}
+
+/// POST /embed_sparse - Sparse (e.g. SPLADE) embeddings from TEI `/embed_sparse`
+///
+/// Same request body, validation, rate limits & quotas as `/embed`, responds with
+/// `{"embeddings": [[{"index": ..., "value": ...}, ...], ...]}`. Batched in a queue of its own,
+/// so sparse & dense inputs never share an upstream call. Only served for the default model
+#[post("/embed_sparse", data = "<request>")]
+pub async fn embed_sparse(
+    _auth: ApiKeyAuth,
+    request: JsonBody<EmbedRequest>,
+    context: RequestContext,
+    input_count: InputCount<'_>,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<WithQuotaHeaders<Json<SparseEmbedResponse>>, EmbedError> {
+    input_count.record(request.inputs.len());
+    let WithQuotaHeaders(response, quota_status) = embed_for(
+        Endpoint::EmbedSparse,
+        request.into_inner(),
+        context,
+        request_handler,
+    )
+    .await?;
+    Ok(WithQuotaHeaders(Json(response.into()), quota_status))
+}
+
+/// POST /embed_all - Token level embeddings from TEI `/embed_all`
+///
+/// Same request body, validation, rate limits & quotas as `/embed`, responds with
+/// `{"embeddings": [[[...], ...], ...]}`, i.e., one vector per token of each input.
+/// Batched in a queue of its own & only served for the default model, like `/embed_sparse`
+#[post("/embed_all", data = "<request>")]
+pub async fn embed_all(
+    _auth: ApiKeyAuth,
+    request: JsonBody<EmbedRequest>,
+    context: RequestContext,
+    input_count: InputCount<'_>,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<WithQuotaHeaders<Json<TokenEmbedResponse>>, EmbedError> {
+    input_count.record(request.inputs.len());
+    let WithQuotaHeaders(response, quota_status) = embed_for(
+        Endpoint::EmbedAll,
+        request.into_inner(),
+        context,
+        request_handler,
+    )
+    .await?;
+    Ok(WithQuotaHeaders(Json(response.into()), quota_status))
+}
+
+/// The flow shared by `/embed`, `/embed_sparse` & `/embed_all`: rate limiting, validation, quota,
+/// then the request is batched for `endpoint`, as a whole, in chunks or with partial errors
+async fn embed_for(
+    endpoint: Endpoint,
+    request: EmbedRequest,
+    context: RequestContext,
+    request_handler: &Arc<RequestHandler>,
+) -> Result<WithQuotaHeaders<EmbedResponse>, EmbedError> {
+    let max_inference_inputs = request_handler
+        .endpoint_config(endpoint, request.model.as_deref())?
+        .max_inference_inputs;
+    // split requests are charged per chunk
+    request_handler.check_rate_limit(
+        &context,
+        request.inputs.len().min(max_inference_inputs),
+        request.model.as_deref(),
+    )?;
+    let request = validate_embed_request(request_handler, endpoint, request)?;
+
+    let input_count = request.inputs.len();
+    let model = request.model.clone();
+    let quota_status = request_handler.charge_quota(&context, input_count)?;
+    let embed_response = if request.partial_errors {
+        request_handler
+            .process_request_partially(endpoint, request, context.clone())
+            .await
+    } else if input_count > max_inference_inputs {
+        request_handler
+            .process_request_in_chunks(endpoint, request, context.clone())
+            .await
+    } else {
+        request_handler
+            .process_request_for(endpoint, request, context.clone())
+            .await
+    }
+    .map_err(|error| {
+        request_handler.refund_quota(&context, input_count);
+        request_handler.backpressure(error, model.as_deref())
+    })?;
+    Ok(WithQuotaHeaders(embed_response, quota_status))
+}
+
+/// POST /embed/bulk - Embeds a whole corpus in one request
+///
+/// JSONL body, one `{"id": ..., "input": "..."}` record per line, streamed through the batcher in chunks
+/// of `max_inference_inputs`. Responds with JSONL `{"id": ..., "embedding": [...]}` lines,
+/// or `{"id": ..., "error": "..."}` for invalid lines & failed chunks (the rest is still processed).
+/// The body is limited by `max_bulk_body_mb`, quotas & `rate_limit_inputs_per_sec` are charged per chunk
+/// (the stream ends with a `rate_limited` line once the latter is exhausted),
+/// the whole bulk request counts as one request against `rate_limit_requests_per_sec`.
+#[post("/embed/bulk", data = "<body>")]
+pub async fn embed_bulk<'r>(
+    _auth: ApiKeyAuth,
+    body: Data<'r>,
+    limits: &Limits,
+    context: RequestContext,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<(ContentType, TextStream![String + 'r]), EmbedError> {
+    request_handler.check_rate_limit(&context, 0, None)?;
+    let body = body.open(limits.get(BULK_LIMIT).unwrap_or(Limits::JSON));
+    let results = bulk::embed_lines(
+        request_handler.inner().clone(),
+        context,
+        Priority::Normal,
+        tokio::io::BufReader::new(body),
+    );
+    Ok((
+        ContentType::new("application", "x-ndjson"),
+        TextStream(results.map(|result| result.to_line())),
+    ))
+}
+
+/// Shared by `/embed` & `/jobs/embed` (& the other endpoints), against the limits of `endpoint`
+fn validate_embed_request(
+    request_handler: &RequestHandler,
+    endpoint: Endpoint,
+    mut request: EmbedRequest,
+) -> Result<EmbedRequest, EmbedError> {
     if request.inputs.is_empty() {
         return Err(Custom(
             Status::BadRequest,
             Json(ErrorResponse {
                 error: "`inputs` can't be empty".to_string(),
+                code: None,
             }),
-        ));
+        )
+        .into());
     }
 
-    if request.inputs.len() > request_handler.config.max_inference_inputs {
+    if let Some(client_tag) = &request.client_tag
+        && !EmbedRequest::is_valid_client_tag(client_tag)
+    {
         return Err(Custom(
-            Status::PayloadTooLarge,
+            Status::UnprocessableEntity,
             Json(ErrorResponse {
                 error: format!(
-                    "`inputs` can't be greater than {}",
-                    request_handler.config.max_inference_inputs
+                    "`client_tag` must be 1-{MAX_CLIENT_TAG_CHARS} ASCII letters, digits or `-_.:/`"
                 ),
+                code: Some("invalid_client_tag"),
+            }),
+        )
+        .into());
+    }
+
+    let max_inference_inputs = request_handler
+        .endpoint_config(endpoint, request.model.as_deref())?
+        .max_inference_inputs;
+    let is_oversized = request.inputs.len() > max_inference_inputs;
+    if is_oversized && !request_handler.config.split_oversized_requests {
+        return Err(Custom(
+            Status::PayloadTooLarge,
+            Json(ErrorResponse {
+                error: format!("`inputs` can't be greater than {max_inference_inputs}"),
+                code: None,
+            }),
+        )
+        .into());
+    }
+
+    if let Some(max_input_chars) = request_handler.config.max_input_chars {
+        match request_handler.config.input_overflow {
+            InputOverflow::Reject => {
+                let too_long = request.inputs_longer_than(max_input_chars);
+                // see `RequestHandler::process_request_partially`
+                if !too_long.is_empty() && !request.partial_errors {
+                    return Err(Custom(
+                        Status::UnprocessableEntity,
+                        Json(ErrorResponse {
+                            error: format!(
+                                "`inputs` at indices {too_long:?} exceed {max_input_chars} characters"
+                            ),
+                            code: Some("input_too_long"),
+                        }),
+                    )
+                    .into());
+                }
+            }
+            InputOverflow::Truncate => request.truncate_inputs(max_input_chars),
+        }
+    }
+    Ok(request)
+}
+
+/// POST /jobs/embed - Asynchronous embedding job
+///
+/// Same request body, validation, rate limits & quotas as `/embed`, but responds 202 right away
+/// with a `job_id` to poll via `GET /jobs/<job_id>`. Jobs are batched at the lowest priority,
+/// i.e., only fill batches once `/embed` traffic is served.
+/// With `callback_url`, the finished job is also POSTed there (signed with `webhook_secret`).
+/// Responds 503 once `max_jobs` unfinished or unexpired jobs are kept.
+#[post("/jobs/embed", data = "<request>")]
+pub async fn submit_embed_job(
+    _auth: ApiKeyAuth,
+    request: Json<EmbedJobRequest>,
+    context: RequestContext,
+    input_count: InputCount<'_>,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<WithQuotaHeaders<Custom<Json<Job>>>, EmbedError> {
+    let EmbedJobRequest {
+        request,
+        callback_url,
+    } = request.into_inner();
+    input_count.record(request.inputs.len());
+    let max_inference_inputs = request_handler
+        .endpoint_config(Endpoint::Embed, request.model.as_deref())?
+        .max_inference_inputs;
+    // split requests are charged per chunk
+    request_handler.check_rate_limit(
+        &context,
+        request.inputs.len().min(max_inference_inputs),
+        request.model.as_deref(),
+    )?;
+    validate_callback_url(callback_url.as_deref())?;
+    if request.partial_errors {
+        return Err(Custom(
+            Status::UnprocessableEntity,
+            Json(ErrorResponse {
+                error: "`partial_errors` isn't supported by jobs".to_string(),
+                code: Some("unsupported_option"),
             }),
-        ));
+        )
+        .into());
     }
+    let request = validate_embed_request(request_handler, Endpoint::Embed, request)?;
 
-    let embed_response = request_handler
-        .process_request(request.into_inner())
-        .await?;
-    Ok(Json(embed_response))
+    let input_count = request.inputs.len();
+    let model = request.model.clone();
+    let quota_status = request_handler.charge_quota(&context, input_count)?;
+    let job = request_handler
+        .submit_job(request, callback_url, context.clone())
+        .map_err(|error| {
+            request_handler.refund_quota(&context, input_count);
+            request_handler.backpressure(error, model.as_deref())
+        })?;
+    Ok(WithQuotaHeaders(
+        Custom(Status::Accepted, Json(job)),
+        quota_status,
+    ))
+}
+
+/// POST /jobs/embed/bulk - Asynchronous bulk job on object storage (`object-store` cargo feature)
+///
+/// Reads a JSONL corpus (`POST /embed/bulk` format) from `input_uri` & writes JSONL results
+/// to `output_uri` (`s3://`, `gs://` or `file://`), responds 202 with a `job_id` right away.
+/// Batched at the lowest priority like `/jobs/embed`, the finished job reports `records`
+/// & `failed_records`. Inputs are charged against `rate_limit_inputs_per_sec` per chunk,
+/// like `POST /embed/bulk`. Responds 422 for unsupported or invalid URIs.
+#[cfg(feature = "object-store")]
+#[post("/jobs/embed/bulk", data = "<request>")]
+pub fn submit_bulk_embed_job(
+    _auth: ApiKeyAuth,
+    request: Json<crate::jobs::BulkJobRequest>,
+    context: RequestContext,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<Custom<Json<Job>>, EmbedError> {
+    request_handler.check_rate_limit(&context, 0, None)?;
+    let request = request.into_inner();
+    validate_callback_url(request.callback_url.as_deref())?;
+    let job = request_handler.submit_bulk_job(
+        &request.input_uri,
+        request.output_uri,
+        request.callback_url,
+        context,
+    )?;
+    Ok(Custom(Status::Accepted, Json(job)))
+}
+
+fn validate_callback_url(callback_url: Option<&str>) -> Result<(), EmbedError> {
+    match callback_url.map(WebhookSender::validate_callback_url) {
+        Some(Err(error)) => Err(Custom(
+            Status::UnprocessableEntity,
+            Json(ErrorResponse {
+                error,
+                code: Some("invalid_callback_url"),
+            }),
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// GET /jobs/<job_id> - Status of an asynchronous embedding job
+///
+/// `queued`, `completed` (with `embeddings`) or `failed` (with `error`),
+/// responds 404 for unknown jobs, jobs submitted by another client (API key or client IP)
+/// and once a finished job is older than `job_ttl_secs`.
+#[get("/jobs/<job_id>")]
+pub fn embed_job(
+    _auth: ApiKeyAuth,
+    job_id: &str,
+    context: RequestContext,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<Json<Job>, Custom<Json<ErrorResponse>>> {
+    request_handler
+        .job(job_id, &context)
+        .map(Json)
+        .ok_or_else(|| {
+            Custom(
+                Status::NotFound,
+                Json(ErrorResponse {
+                    error: format!("Job `{job_id}` not found or expired"),
+                    code: Some("job_not_found"),
+                }),
+            )
+        })
 }
 
 /// GET /health - Health check endpoint
@@ -50,3 +392,220 @@ pub async fn embed(
 pub fn health() -> &'static str {
     "OK"
 }
+
+/// GET /livez - Liveness probe
+///
+/// The process is up & serving HTTP, a failing liveness probe means the proxy should be restarted.
+#[get("/livez")]
+pub fn livez() -> &'static str {
+    "OK"
+}
+
+/// GET /readyz - Readiness probe
+///
+/// Responds 503 while shutting down, load shedding, without any healthy inference backend
+/// or when the batch loop stopped ticking, i.e., the proxy should temporarily receive no traffic.
+#[get("/readyz")]
+pub fn readyz(request_handler: &State<Arc<RequestHandler>>) -> Custom<Json<Readiness>> {
+    let readiness = request_handler.readiness();
+    let status = if readiness.ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    Custom(status, Json(readiness))
+}
+
+/// GET /info - What clients are talking to
+///
+/// Proxy build (version, git sha), client-relevant settings and the upstream model info
+/// (TEI `/info`, cached for a minute). Responds 200 even if the upstream info can't be fetched.
+#[get("/info")]
+pub async fn info(request_handler: &State<Arc<RequestHandler>>) -> Json<ProxyInfo> {
+    Json(request_handler.info().await)
+}
+
+/// GET /stats - Live queue & batching statistics
+///
+/// Current queue depth, batches dispatched so far (by flush trigger), recent batch sizes
+/// and throughput over the last minute, meant for dashboards & tuning batch settings.
+#[get("/stats")]
+pub fn stats(request_handler: &State<Arc<RequestHandler>>) -> Json<Stats> {
+    Json(request_handler.stats())
+}
+
+/// GET /stats/tuning - Arrival rate, batch fill ratio & which trigger flushed batches over the last
+/// minute, with suggested `max_wait_time_ms` / `max_batch_size` adjustments & the reasoning behind them
+#[get("/stats/tuning")]
+pub fn tuning_stats(request_handler: &State<Arc<RequestHandler>>) -> Json<TuningReport> {
+    Json(request_handler.tuning_report())
+}
+
+/// GET /stats/latency - p50/p95/p99 & max (ms) since startup of end-to-end request latency,
+/// queue time & upstream time, for checking SLOs without a full metrics stack
+///
+/// Estimated in buckets, percentiles are at most 5% too high.
+#[get("/stats/latency")]
+pub fn latency_stats() -> Json<LatencyReport> {
+    Json(LATENCY.report())
+}
+
+/// GET /health/deep - Health check including upstream reachability
+///
+/// Probes every inference backend on each call, responds 503 when none of them is reachable,
+/// so load balancers can eject proxies whose backend is down.
+#[get("/health/deep")]
+pub async fn health_deep(request_handler: &State<Arc<RequestHandler>>) -> Custom<Json<DeepHealth>> {
+    let deep_health = request_handler.deep_health().await;
+    let status = if deep_health.upstream.reachable {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    Custom(status, Json(deep_health))
+}
+
+/// GET /health/backends - Per inference backend status
+///
+/// Unhealthy backends receive no batches until they pass enough health probes in a row.
+#[get("/health/backends")]
+pub fn health_backends(request_handler: &State<Arc<RequestHandler>>) -> Json<Vec<BackendStatus>> {
+    Json(request_handler.backend_statuses())
+}
+
+/// GET /metrics - Prometheus scrape endpoint
+#[get("/metrics")]
+pub fn metrics() -> String {
+    METRICS.render()
+}
+
+/// GET /admin/usage - Per caller usage (requests, inputs, inference time) for chargeback
+///
+/// Counters are reset every `usage_window_secs`, the last completed window is kept as `previous`.
+/// Responds 401 unless `admin_api_key` is presented.
+#[get("/admin/usage")]
+pub fn admin_usage(
+    _auth: AdminAuth,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Json<UsageReport> {
+    Json(request_handler.usage_report())
+}
+
+/// GET /admin/drift - A/B comparison (`compare_inference_url`) results: batches & inputs compared,
+/// count & dimension mismatches, cosine similarity between both backends' embeddings
+///
+/// 404 unless comparison is enabled. Responds 401 unless `admin_api_key` is presented.
+#[get("/admin/drift")]
+pub fn admin_drift(
+    _auth: AdminAuth,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<Json<DriftReport>, Custom<Json<ErrorResponse>>> {
+    request_handler.drift_report().map(Json).ok_or_else(|| {
+        Custom(
+            Status::NotFound,
+            Json(ErrorResponse {
+                error: "A/B comparison is disabled, see `compare_inference_url`".to_string(),
+                code: Some("comparison_disabled"),
+            }),
+        )
+    })
+}
+
+/// PUT /admin/backend - Switches batches to another `inference_url` at runtime (blue/green),
+/// without a restart & without dropping queued requests
+///
+/// The new backends are probed first, nothing changes unless one of them is reachable (502 otherwise).
+/// Responds 401 unless `admin_api_key` is presented, tenants' `api_keys` can't repoint everyone's traffic.
+#[put("/admin/backend", format = "json", data = "<request>")]
+pub async fn admin_switch_backend(
+    _auth: AdminAuth,
+    request: Json<BackendSwitchRequest>,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Result<Json<BackendSwitch>, Custom<Json<ErrorResponse>>> {
+    request_handler
+        .switch_backend(&request.inference_url)
+        .await
+        .map(Json)
+}
+
+/// PUT /admin/log-level - Replaces the log filter at runtime (`RUST_LOG` syntax, e.g. `debug`),
+/// e.g. during an incident, restarting with another `--log-level` would lose the queue.
+/// Not persisted across restarts
+///
+/// 422 for invalid levels, 401 unless `admin_api_key` is presented.
+#[put("/admin/log-level", format = "json", data = "<request>")]
+pub fn admin_log_level(
+    _auth: AdminAuth,
+    request: Json<LogLevelRequest>,
+) -> Result<Json<LogLevelChange>, Custom<Json<ErrorResponse>>> {
+    log_level::set(&request.level)
+        .map(Json)
+        .map_err(|e| match e {
+            LogLevelError::Invalid(error) => Custom(
+                Status::UnprocessableEntity,
+                Json(ErrorResponse {
+                    error,
+                    code: Some("invalid_log_level"),
+                }),
+            ),
+            LogLevelError::NotInitialized => Custom(
+                Status::Conflict,
+                Json(ErrorResponse {
+                    error: "Logging is managed by the embedding application".to_string(),
+                    code: Some("logging_not_initialized"),
+                }),
+            ),
+        })
+}
+
+/// POST /admin/queue/pause - Stops dispatching batches, e.g., during a brief upstream maintenance
+/// window. Requests keep queueing (up to load shedding limits & their deadlines), so clients see
+/// added latency rather than errors
+///
+/// Responds 401 unless `admin_api_key` is presented.
+#[post("/admin/queue/pause")]
+pub fn admin_pause_queue(
+    _auth: AdminAuth,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Json<QueuePause> {
+    Json(request_handler.set_paused(true))
+}
+
+/// POST /admin/queue/resume - Dispatches queued requests again, see `POST /admin/queue/pause`
+///
+/// Responds 401 unless `admin_api_key` is presented.
+#[post("/admin/queue/resume")]
+pub fn admin_resume_queue(
+    _auth: AdminAuth,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Json<QueuePause> {
+    Json(request_handler.set_paused(false))
+}
+
+/// POST /admin/drain - Drains the proxy ahead of a rolling restart, distinct from SIGTERM:
+/// `/readyz` fails & new requests are rejected with 503 from now on, while queued requests are
+/// served. Responds once they are (or `shutdown_drain_timeout_secs` passed), there's no way back
+///
+/// Responds 401 unless `admin_api_key` is presented.
+#[post("/admin/drain")]
+pub async fn admin_drain(
+    _auth: AdminAuth,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Json<DrainReport> {
+    Json(DrainReport {
+        completed: request_handler.drain().await,
+    })
+}
+
+/// POST /admin/queue/flush - Dispatches all pending requests right away, regardless of
+/// `max_wait_time_ms` (also while paused), e.g., before maintenance. Responds once they're
+/// dispatched, not once they're served
+///
+/// Responds 401 unless `admin_api_key` is presented.
+#[post("/admin/queue/flush")]
+pub async fn admin_flush_queue(
+    _auth: AdminAuth,
+    request_handler: &State<Arc<RequestHandler>>,
+) -> Json<QueueFlush> {
+    Json(request_handler.flush().await)
+}
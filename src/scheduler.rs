@@ -0,0 +1,352 @@
+use crate::adaptive_limit::BatchLimits;
+use crate::config::{AppConfig, SchedulingMode};
+use crate::pending_queue::PendingQueue;
+use crate::types::{BatchType, PendingRequest, Priority};
+use log::{debug, info};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Decides when pending requests are flushed & which of them go into the next batch.
+/// Built-in implementations are selected via `config.scheduling_mode`, custom ones can be
+/// plugged in with `BatchProcessor::with_scheduler`
+pub trait BatchScheduler: Send {
+    /// Checked after every batch loop iteration, `Some` flushes ALL pending requests (in batches)
+    fn should_flush(
+        &self,
+        queue: &PendingQueue,
+        limits: &BatchLimits,
+        config: &AppConfig,
+    ) -> Option<BatchType> {
+        flush_on_size_or_wait_time(queue, limits, config)
+    }
+
+    /// Positions (in `queue.iter()` order) of requests forming the next batch, each one must be
+    /// accepted by `budget.try_add`
+    fn select_batch(&self, queue: &PendingQueue, budget: &mut BatchBudget) -> Vec<usize>;
+}
+
+pub fn build_scheduler(config: &AppConfig) -> Box<dyn BatchScheduler> {
+    match config.scheduling_mode {
+        SchedulingMode::Fifo => Box::new(FifoScheduler {
+            fair: config.fair_scheduling,
+        }),
+        SchedulingMode::SizeClass => Box::new(SizeClassScheduler),
+        SchedulingMode::Deadline => Box::new(DeadlineScheduler {
+            request_timeout: config.request_timeout(),
+            flush_margin: Duration::from_millis(config.deadline_flush_margin_ms),
+        }),
+    }
+}
+
+/// `Max Batch Size` - flush as soon as enough requests are accumulated
+///
+/// ```Max Wait Time - maximal time user request can wait for other requests to be accumulated in a batch```
+///
+/// let's assume, we have such timeline, at 500th ms, we process all requests in single batch,
+/// (but also consider `max_inference_inputs` limitation)
+///
+/// User1 request with 10 inputs arrives at 0th ms
+/// User2 request with 20 inputs arrives at 100th ms
+/// User3 request with 10 inputs arrives at 300th ms // exceeds max_inference_inputs of e.g., 32
+/// User4 request with 5 inputs arrives at 500th ms
+pub fn flush_on_size_or_wait_time(
+    queue: &PendingQueue,
+    limits: &BatchLimits,
+    config: &AppConfig,
+) -> Option<BatchType> {
+    if queue.len() >= limits.max_batch_size {
+        return Some(BatchType::MaxBatchSize);
+    }
+
+    let elapsed = queue.oldest_received_at()?.elapsed();
+    if elapsed >= config.max_wait_time_duration() {
+        info!(
+            "Processing due to config.max_wait_time_ms: {} timeout",
+            config.max_wait_time_ms
+        );
+        debug!("Oldest request waited {elapsed:?}");
+        return Some(BatchType::MaxWaitTimeMs);
+    }
+    None
+}
+
+/// Arrival order, high priority tier first
+///
+/// With `fair`, requests are picked round-robin across callers (FIFO per caller), i.e., a client
+/// with 100 queued requests gets the same share of the batch as a client with 1.
+/// A caller whose next request doesn't fit in the remaining budget is skipped
+/// for the rest of this batch (rather than jumping ahead with its later requests)
+pub struct FifoScheduler {
+    pub fair: bool,
+}
+
+impl BatchScheduler for FifoScheduler {
+    fn select_batch(&self, queue: &PendingQueue, budget: &mut BatchBudget) -> Vec<usize> {
+        if !self.fair {
+            // `.iter()` - front-to-back, high priority tier first
+            return queue
+                .iter()
+                .take_while(|request| budget.try_add(request))
+                .enumerate()
+                .map(|(position, _)| position)
+                .collect();
+        }
+
+        // queue positions grouped per (priority, client), in order of first appearance
+        let mut groups: Vec<(Priority, Option<&str>, VecDeque<usize>)> = Vec::new();
+        for (position, request) in queue.iter().enumerate() {
+            let client_id = request.client_id.as_deref();
+            match groups
+                .iter_mut()
+                .find(|(priority, id, _)| *priority == request.priority && *id == client_id)
+            {
+                Some((_, _, positions)) => positions.push_back(position),
+                None => groups.push((request.priority, client_id, VecDeque::from([position]))),
+            }
+        }
+
+        let requests: Vec<&PendingRequest> = queue.iter().collect();
+        let mut selected = Vec::new();
+        // `groups` is in scheduling order, so high priority groups are exhausted before normal ones
+        for tier in [Priority::High, Priority::Normal, Priority::Low] {
+            loop {
+                let mut picked_any = false;
+                for (_, _, positions) in groups.iter_mut().filter(|(p, _, _)| *p == tier) {
+                    if budget.is_full() {
+                        break;
+                    }
+                    if let Some(&position) = positions.front() {
+                        if !budget.try_add(requests[position]) {
+                            positions.clear();
+                            continue;
+                        }
+                        selected.push(position);
+                        positions.pop_front();
+                        picked_any = true;
+                    }
+                }
+                if !picked_any || budget.is_full() {
+                    break;
+                }
+            }
+        }
+        selected
+    }
+}
+
+/// Oldest request (high priority first) anchors the batch, remaining slots are filled with
+/// requests of the same size class first, then progressively more distant classes,
+/// so short & long inputs are mixed only when there is nothing better to batch
+pub struct SizeClassScheduler;
+
+impl BatchScheduler for SizeClassScheduler {
+    fn select_batch(&self, queue: &PendingQueue, budget: &mut BatchBudget) -> Vec<usize> {
+        let Some(anchor_class) = queue.iter().next().map(|r| r.size_class()) else {
+            return Vec::new();
+        };
+
+        // priority tier always wins over size class, position keeps it FIFO otherwise
+        let candidates = sorted_candidates(queue, |position, request| {
+            (request.size_class().abs_diff(anchor_class), position)
+        });
+        pack_greedily(candidates, budget)
+    }
+}
+
+/// Earliest deadline first: requests without explicit deadline (`X-Request-Deadline-Ms`) are due
+/// at `received_at + request_timeout`. Besides size & wait time, pending requests are also
+/// flushed early once any deadline is within `config.deadline_flush_margin_ms`
+pub struct DeadlineScheduler {
+    pub request_timeout: Duration,
+    pub flush_margin: Duration,
+}
+
+impl DeadlineScheduler {
+    fn due_at(&self, request: &PendingRequest) -> Instant {
+        request
+            .deadline
+            .unwrap_or(request.received_at + self.request_timeout)
+    }
+}
+
+impl BatchScheduler for DeadlineScheduler {
+    fn should_flush(
+        &self,
+        queue: &PendingQueue,
+        limits: &BatchLimits,
+        config: &AppConfig,
+    ) -> Option<BatchType> {
+        flush_on_size_or_wait_time(queue, limits, config).or_else(|| {
+            let flush_before = Instant::now() + self.flush_margin;
+            queue
+                .iter()
+                .filter_map(|request| request.deadline)
+                .any(|deadline| deadline <= flush_before)
+                .then_some(BatchType::Deadline)
+        })
+    }
+
+    fn select_batch(&self, queue: &PendingQueue, budget: &mut BatchBudget) -> Vec<usize> {
+        let candidates =
+            sorted_candidates(queue, |position, request| (self.due_at(request), position));
+        pack_greedily(candidates, budget)
+    }
+}
+
+/// Queue positions sorted by priority tier first, then by `key`
+fn sorted_candidates<K: Ord>(
+    queue: &PendingQueue,
+    key: impl Fn(usize, &PendingRequest) -> K,
+) -> Vec<(usize, &PendingRequest)> {
+    let mut candidates: Vec<(Priority, K, usize, &PendingRequest)> = queue
+        .iter()
+        .enumerate()
+        .map(|(position, request)| (request.priority, key(position, request), position, request))
+        .collect();
+    candidates.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    candidates
+        .into_iter()
+        .map(|(_, _, position, request)| (position, request))
+        .collect()
+}
+
+/// Unlike FIFO, requests that don't fit are skipped (not stopping), since order is already relaxed
+fn pack_greedily(
+    candidates: Vec<(usize, &PendingRequest)>,
+    budget: &mut BatchBudget,
+) -> Vec<usize> {
+    let mut selected = Vec::new();
+    for (position, request) in candidates {
+        if budget.is_full() {
+            break;
+        }
+        if budget.try_add(request) {
+            selected.push(position);
+        }
+    }
+    selected
+}
+
+/// Running totals of a batch being built, checked against (possibly adapted) limits
+pub struct BatchBudget<'a> {
+    config: &'a AppConfig,
+    limits: BatchLimits,
+    requests: usize,
+    inputs: usize,
+    tokens: usize,
+}
+
+impl<'a> BatchBudget<'a> {
+    pub fn new(config: &'a AppConfig, limits: BatchLimits) -> Self {
+        Self {
+            config,
+            limits,
+            requests: 0,
+            inputs: 0,
+            tokens: 0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.requests >= self.limits.max_batch_size
+    }
+
+    /// Adds request to the totals only if the batch stays within ALL limits
+    ///
+    /// An empty batch accepts any request, since configured limits are already validated
+    /// per request (routes.rs, request_handler.rs), only adapted limits could be smaller
+    pub fn try_add(&mut self, request: &PendingRequest) -> bool {
+        let exceeds_tokens = self
+            .config
+            .max_batch_tokens
+            .is_some_and(|max_batch_tokens| self.tokens + request.token_count > max_batch_tokens);
+
+        if self.requests > 0
+            && (self.is_full()
+                || (self.inputs + request.inputs.len()) > self.limits.max_inference_inputs
+                || exceeds_tokens)
+        {
+            return false;
+        }
+
+        self.requests += 1;
+        self.inputs += request.inputs.len();
+        self.tokens += request.token_count;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ResponseSender;
+    use tokio::sync::oneshot;
+
+    fn build_request(input: &str, deadline_in: Option<Duration>) -> PendingRequest {
+        let (response_sender, _): (ResponseSender, _) = oneshot::channel();
+        PendingRequest::new(vec![input.to_string()], response_sender)
+            .with_deadline(deadline_in.map(|deadline_in| Instant::now() + deadline_in))
+    }
+
+    fn build_deadline_scheduler() -> DeadlineScheduler {
+        DeadlineScheduler {
+            request_timeout: Duration::from_secs(30),
+            flush_margin: Duration::from_millis(100),
+        }
+    }
+
+    #[test]
+    fn test_default_flush_on_max_batch_size() {
+        let config = AppConfig {
+            max_batch_size: 2,
+            ..AppConfig::default()
+        };
+        let limits = BatchLimits::from_config(&config);
+        let scheduler = FifoScheduler { fair: false };
+
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("1", None));
+        assert_eq!(scheduler.should_flush(&queue, &limits, &config), None);
+
+        queue.push_back(build_request("2", None));
+        assert_eq!(
+            scheduler.should_flush(&queue, &limits, &config),
+            Some(BatchType::MaxBatchSize)
+        );
+    }
+
+    #[test]
+    fn test_deadline_scheduler_flushes_when_deadline_is_near() {
+        let config = AppConfig::default();
+        let limits = BatchLimits::from_config(&config);
+        let scheduler = build_deadline_scheduler();
+
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("far", Some(Duration::from_secs(10))));
+        assert_eq!(scheduler.should_flush(&queue, &limits, &config), None);
+
+        queue.push_back(build_request("near", Some(Duration::from_millis(50))));
+        assert_eq!(
+            scheduler.should_flush(&queue, &limits, &config),
+            Some(BatchType::Deadline)
+        );
+    }
+
+    #[test]
+    fn test_deadline_scheduler_selects_earliest_deadline_first() {
+        let config = AppConfig {
+            max_batch_size: 2,
+            ..AppConfig::default()
+        };
+        let scheduler = build_deadline_scheduler();
+
+        let mut queue = PendingQueue::new();
+        queue.push_back(build_request("no deadline", None));
+        queue.push_back(build_request("late", Some(Duration::from_secs(10))));
+        queue.push_back(build_request("soon", Some(Duration::from_secs(1))));
+
+        let mut budget = BatchBudget::new(&config, BatchLimits::from_config(&config));
+        let selected = scheduler.select_batch(&queue, &mut budget);
+        assert_eq!(selected, vec![2, 1]);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::AppConfig;
+use crate::inference_client::{InferenceBackend, InferenceError, InferenceServiceClient};
+use crate::metrics::METRICS;
+use crate::types::{BatchRequest, BatchResponse};
+use log::debug;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Shadow calls in flight at once, batches beyond that aren't shadowed,
+/// so a slow shadow backend can't pile up tasks (& memory) in the proxy
+const MAX_IN_FLIGHT_SHADOW_CALLS: usize = 64;
+
+/// Sends every batch to the primary backend & a copy to `config.shadow_inference_url`.
+/// Only the primary's response is used, the shadow call runs in the background
+/// & just feeds the `proxy_shadow_*` metrics
+pub struct ShadowBackend {
+    primary: Arc<dyn InferenceBackend>,
+    shadow: Arc<InferenceServiceClient>,
+    in_flight: Arc<Semaphore>,
+}
+
+impl ShadowBackend {
+    /// `None` unless `config.shadow_inference_url` is set
+    pub fn new(
+        config: &AppConfig,
+        primary: Arc<dyn InferenceBackend>,
+    ) -> Result<Option<Self>, InferenceError> {
+        let Some(shadow_inference_url) = &config.shadow_inference_url else {
+            return Ok(None);
+        };
+        // failures are only recorded, neither retried nor hedged
+        let shadow = InferenceServiceClient::new(&AppConfig {
+            inference_urls: vec![shadow_inference_url.clone()],
+            inference_max_retries: 0,
+            hedge_requests: false,
+            circuit_breaker_error_rate: None,
+            ..config.clone()
+        })?;
+
+        Ok(Some(Self {
+            primary,
+            shadow: Arc::new(shadow),
+            in_flight: Arc::new(Semaphore::new(MAX_IN_FLIGHT_SHADOW_CALLS)),
+        }))
+    }
+
+    fn shadow(&self, request: BatchRequest) {
+        let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+            METRICS.shadow_skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        METRICS.shadow_batches.fetch_add(1, Ordering::Relaxed);
+
+        let shadow = self.shadow.clone();
+        tokio::spawn(async move {
+            let start_time = Instant::now();
+            match shadow.call_service(request, None).await {
+                Ok(_) => METRICS
+                    .shadow_latency_seconds
+                    .observe(start_time.elapsed().as_secs_f64()),
+                Err(e) => {
+                    debug!("Shadow inference call failed: {}", e.message());
+                    METRICS.shadow_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            drop(permit);
+        });
+    }
+}
+
+#[rocket::async_trait]
+impl InferenceBackend for ShadowBackend {
+    async fn embed(
+        &self,
+        request: BatchRequest,
+        timeout: Option<Duration>,
+    ) -> Result<BatchResponse, InferenceError> {
+        self.shadow(request.clone());
+        self.primary.embed(request, timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shadow_gets_a_copy_of_every_batch() {
+        let config = AppConfig {
+            inference_urls: vec!["mock://dims=4".to_string()],
+            shadow_inference_url: Some("mock://dims=8".to_string()),
+            ..AppConfig::default()
+        };
+        let primary = Arc::new(InferenceServiceClient::new(&config).unwrap());
+        let backend = ShadowBackend::new(&config, primary).unwrap().unwrap();
+
+        let shadow_batches = METRICS.shadow_batches.load(Ordering::Relaxed);
+        let request = BatchRequest {
+            inputs: vec!["Hello".into()],
+        };
+        let response = backend.embed(request, None).await.unwrap();
+        // primary's response, not the shadow's
+        assert_eq!(response[0].len(), 4);
+        assert!(METRICS.shadow_batches.load(Ordering::Relaxed) > shadow_batches);
+    }
+
+    #[test]
+    fn test_no_shadow_backend_by_default() {
+        let config = AppConfig::default();
+        let primary = Arc::new(InferenceServiceClient::new(&config).unwrap());
+        assert!(ShadowBackend::new(&config, primary).unwrap().is_none());
+    }
+}
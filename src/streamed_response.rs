@@ -0,0 +1,156 @@
+use crate::types::{BatchInfo, EmbedResponse, InputResult, KeyedEmbedding};
+use rocket::futures::stream;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::stream::ByteStream;
+use rocket::response::{self, Responder};
+use serde::Serialize;
+
+/// Fields of `EmbedResponse` written after the embeddings
+#[derive(Serialize)]
+struct ResponseTail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_info: Option<BatchInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_tag: Option<String>,
+}
+
+/// `/embed` response body, same JSON as `Json<EmbedResponse>`, but written one embedding at a time,
+/// so there's no second (serialized) copy of all embeddings in memory per response.
+/// Each embedding is dropped once it's written. Embeddings of ID-keyed inputs are written
+/// as `{"id": ..., "embedding": ...}` & failed inputs as error objects, like `Embeddings`
+pub struct StreamedEmbedResponse(pub EmbedResponse);
+
+impl<'r> Responder<'r, 'r> for StreamedEmbedResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        let EmbedResponse {
+            embeddings,
+            batch_info,
+            metadata,
+            client_tag,
+            input_ids,
+            input_errors,
+            ..
+        } = self.0;
+        let mut input_ids = input_ids.map(Vec::into_iter);
+        let tail = serde_json::to_string(&ResponseTail {
+            batch_info,
+            metadata,
+            client_tag,
+        })
+        .map_err(|_| rocket::http::Status::InternalServerError)?;
+        // `{"batch_info":...}` continues the object opened before the embeddings
+        let tail = match tail
+            .strip_prefix('{')
+            .and_then(|tail| tail.strip_suffix('}'))
+        {
+            Some("") | None => "]}".to_string(),
+            Some(fields) => format!("],{fields}}}"),
+        };
+
+        let chunks = std::iter::once(br#"{"embeddings":["#.to_vec())
+            .chain(
+                InputResult::merge(embeddings, input_errors)
+                    .enumerate()
+                    .map(move |(index, embedding)| {
+                        let mut chunk = if index == 0 { Vec::new() } else { vec![b','] };
+                        // floats can't fail to serialize (non-finite ones become `null`)
+                        let _ = match input_ids.as_mut().and_then(Iterator::next) {
+                            Some(id) => serde_json::to_writer(
+                                &mut chunk,
+                                &KeyedEmbedding::new(id, embedding),
+                            ),
+                            None => serde_json::to_writer(&mut chunk, &embedding),
+                        };
+                        chunk
+                    }),
+            )
+            .chain(std::iter::once(tail.into_bytes()));
+
+        let mut response = ByteStream(stream::iter(chunks)).respond_to(request)?;
+        response.set_header(ContentType::JSON);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::types::{BatchInfo, BatchType, InputId};
+    use rocket::get;
+    use rocket::local::asynchronous::Client;
+
+    fn embed_response(with_batch_info: bool) -> EmbedResponse {
+        let config = AppConfig {
+            include_batch_info: true,
+            ..AppConfig::default()
+        };
+        EmbedResponse {
+            embeddings: vec![vec![0.5, -1.0], vec![], vec![f32::NAN, 2.25]],
+            batch_info: BatchInfo::new(&config, BatchType::MaxBatchSize, 7)
+                .filter(|_| with_batch_info),
+            metadata: with_batch_info.then(|| serde_json::json!({"job": [1, "a"]})),
+            client_tag: with_batch_info.then(|| "backfill".to_string()),
+            ..EmbedResponse::default()
+        }
+    }
+
+    #[get("/?<with_batch_info>")]
+    fn streamed(with_batch_info: bool) -> StreamedEmbedResponse {
+        StreamedEmbedResponse(embed_response(with_batch_info))
+    }
+
+    #[get("/keyed")]
+    fn keyed() -> StreamedEmbedResponse {
+        StreamedEmbedResponse(EmbedResponse {
+            embeddings: vec![vec![0.5, -1.0], vec![]],
+            input_ids: Some(vec![InputId::Text("a".to_string()), InputId::Number(7)]),
+            ..EmbedResponse::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_keyed_embeddings() {
+        let rocket = rocket::build().mount("/", rocket::routes![keyed]);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        let body = client.get("/keyed").dispatch().await.into_string().await;
+        let body: serde_json::Value = serde_json::from_str(&body.unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"embeddings": [
+                {"id": "a", "embedding": [0.5, -1.0]},
+                {"id": 7, "embedding": []},
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_same_json_as_serde() {
+        let rocket = rocket::build().mount("/", rocket::routes![streamed]);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        for with_batch_info in [false, true] {
+            let response = client
+                .get(format!("/?with_batch_info={with_batch_info}"))
+                .dispatch()
+                .await;
+            assert_eq!(response.content_type(), Some(ContentType::JSON));
+            let body = response.into_string().await.unwrap();
+
+            // `batch_id`s differ between both responses
+            let mut body: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let mut expected = serde_json::to_value(embed_response(with_batch_info)).unwrap();
+            for json in [&mut body, &mut expected] {
+                if let Some(batch_info) = json.get_mut("batch_info") {
+                    batch_info["batch_id"] = serde_json::Value::Null;
+                }
+            }
+            assert_eq!(body, expected);
+            assert_eq!(body.get("batch_info").is_some(), with_batch_info);
+        }
+    }
+}
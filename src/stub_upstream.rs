@@ -0,0 +1,99 @@
+//! Minimal HTTP/1.1 inference service stand-in for unit tests that need a real connection
+//! (headers, proxies, retries, hung backends), unlike `mock_upstream` which never leaves the process.
+//! Integration tests have their own, see `tests/test_utils.rs`
+use rocket::http::Status;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A request received by `spawn`
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    /// Request line & headers
+    pub head: String,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    /// `inputs` of an `/embed` body, none for health checks & `/info`
+    pub fn inputs(&self) -> Vec<String> {
+        serde_json::from_str::<Value>(&self.body)
+            .ok()
+            .and_then(|request| serde_json::from_value(request["inputs"].clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Reads a whole request, `None` once the peer closed (or failed) before sending one
+async fn read_request(stream: &mut TcpStream) -> Option<StubRequest> {
+    let mut received = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        received.extend_from_slice(&buffer[..read]);
+        let Some(head_end) = received.windows(4).position(|window| window == b"\r\n\r\n") else {
+            continue;
+        };
+        let mut request = StubRequest {
+            head: String::from_utf8_lossy(&received[..head_end]).to_string(),
+            body: String::new(),
+        };
+        let content_length: usize = request
+            .header("content-length")
+            .map_or(0, |length| length.parse().unwrap());
+        let body = &received[head_end + 4..];
+        if body.len() >= content_length {
+            request.body = String::from_utf8_lossy(&body[..content_length]).to_string();
+            return Some(request);
+        }
+    }
+}
+
+/// Listens on an ephemeral port, one request per connection. `respond` answers each request
+/// with a status & JSON body, or never (`None`, the connection is kept open). Returns the `/embed` URL
+pub async fn spawn(
+    respond: impl Fn(StubRequest) -> Option<(Status, String)> + Send + Sync + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/embed", listener.local_addr().unwrap());
+    let respond = Arc::new(respond);
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let Some(request) = read_request(&mut stream).await else {
+                    return;
+                };
+                let Some((status, body)) = respond(request) else {
+                    return std::future::pending().await;
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    url
+}
+
+/// `spawn` answering health checks with 200 & `/embed` with 1 embedding (`[0.1]`) per input
+pub async fn spawn_embedding() -> String {
+    spawn(|request| {
+        let embeddings = vec![[0.1]; request.inputs().len()];
+        Some((Status::Ok, json!(embeddings).to_string()))
+    })
+    .await
+}
@@ -0,0 +1,92 @@
+use crate::config::AppConfig;
+
+/// Rough average for English text with WordPiece/BPE vocabularies
+const APPROX_CHARS_PER_TOKEN: usize = 4;
+
+/// Counts tokens of request inputs, used for `config.max_batch_tokens` batch packing.
+///
+/// Exact counts need the `tokenizer` cargo feature & `config.tokenizer_path` (HuggingFace
+/// `tokenizer.json` of the served model), otherwise it falls back to a characters based estimate
+pub struct TokenCounter {
+    #[cfg(feature = "tokenizer")]
+    tokenizer: Option<tokenizers::Tokenizer>,
+}
+
+impl TokenCounter {
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        #[cfg(feature = "tokenizer")]
+        {
+            let tokenizer = match &config.tokenizer_path {
+                Some(path) => Some(
+                    tokenizers::Tokenizer::from_file(path)
+                        .map_err(|e| format!("Failed to load tokenizer from {path}: {e}"))?,
+                ),
+                None => None,
+            };
+            Ok(Self { tokenizer })
+        }
+
+        #[cfg(not(feature = "tokenizer"))]
+        {
+            if config.tokenizer_path.is_some() {
+                return Err("tokenizer_path requires the `tokenizer` cargo feature".to_string());
+            }
+            Ok(Self {})
+        }
+    }
+
+    pub fn count(&self, inputs: &[String]) -> usize {
+        #[cfg(feature = "tokenizer")]
+        if let Some(tokenizer) = &self.tokenizer {
+            // includes special tokens (e.g. [CLS], [SEP]), since the model processes them too
+            let counted: Result<usize, _> = inputs
+                .iter()
+                .map(|input| {
+                    tokenizer
+                        .encode(input.as_str(), true)
+                        .map(|encoding| encoding.len())
+                })
+                .sum();
+            match counted {
+                Ok(tokens) => return tokens,
+                Err(e) => log::warn!("Tokenization failed, falling back to estimate: {e}"),
+            }
+        }
+
+        inputs.iter().map(|input| Self::estimate(input)).sum()
+    }
+
+    fn estimate(input: &str) -> usize {
+        input
+            .chars()
+            .count()
+            .div_ceil(APPROX_CHARS_PER_TOKEN)
+            .max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_without_tokenizer() {
+        let token_counter = TokenCounter::new(&AppConfig::default()).unwrap();
+        assert_eq!(token_counter.count(&["".to_string()]), 1);
+        assert_eq!(token_counter.count(&["What is ML ?".to_string()]), 3);
+        assert_eq!(
+            token_counter.count(&["What is ML ?".to_string(), "abcd".repeat(100)]),
+            103
+        );
+    }
+
+    #[cfg(not(feature = "tokenizer"))]
+    #[test]
+    fn test_tokenizer_path_requires_feature() {
+        let config = AppConfig {
+            tokenizer_path: Some("tokenizer.json".to_string()),
+            ..AppConfig::default()
+        };
+        assert!(TokenCounter::new(&config).is_err());
+    }
+}
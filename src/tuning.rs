@@ -0,0 +1,202 @@
+use crate::config::AppConfig;
+use serde::Serialize;
+
+/// Suggestions need a few batches to go by, a handful of requests says nothing about the traffic
+const MIN_BATCHES: usize = 10;
+/// A flush trigger "dominates" once it's behind this share of batches
+const DOMINANT_SHARE: f64 = 0.8;
+
+/// Arrivals & dispatched batches over the recent window, see `BatchStats::tuning_window`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TuningWindow {
+    pub window_secs: u64,
+    /// Requests that reached the batch loop
+    pub arrivals: u64,
+    pub batches: usize,
+    /// Requests dispatched in those batches
+    pub requests: usize,
+    /// Batches flushed because they reached `max_batch_size`
+    pub size_flushes: usize,
+    /// Batches flushed because their oldest request waited `max_wait_time_ms`
+    pub wait_flushes: usize,
+}
+
+/// `GET /stats/tuning` response
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TuningReport {
+    pub window_secs: u64,
+    pub arrival_rate_per_sec: f64,
+    pub batches: usize,
+    /// Average requests per batch relative to `max_batch_size`
+    pub avg_fill_ratio: f64,
+    /// Share of batches flushed on `max_batch_size`
+    pub size_flush_ratio: f64,
+    /// Share of batches flushed on `max_wait_time_ms`, the rest were deadline, drain or manual flushes
+    pub wait_flush_ratio: f64,
+    pub max_wait_time_ms: u64,
+    pub max_batch_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_max_wait_time_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_max_batch_size: Option<usize>,
+    /// Why something was (or wasn't) suggested
+    pub reasons: Vec<String>,
+}
+
+impl TuningReport {
+    pub fn new(window: &TuningWindow, config: &AppConfig) -> Self {
+        let ratio = |part: usize, total: usize| match total {
+            0 => 0.0,
+            _ => part as f64 / total as f64,
+        };
+        let avg_batch_size = ratio(window.requests, window.batches);
+        let mut report = Self {
+            window_secs: window.window_secs,
+            arrival_rate_per_sec: window.arrivals as f64 / window.window_secs.max(1) as f64,
+            batches: window.batches,
+            avg_fill_ratio: avg_batch_size / config.max_batch_size as f64,
+            size_flush_ratio: ratio(window.size_flushes, window.batches),
+            wait_flush_ratio: ratio(window.wait_flushes, window.batches),
+            max_wait_time_ms: config.max_wait_time_ms,
+            max_batch_size: config.max_batch_size,
+            suggested_max_wait_time_ms: None,
+            suggested_max_batch_size: None,
+            reasons: Vec::new(),
+        };
+        report.suggest(avg_batch_size, config);
+        report
+    }
+
+    fn suggest(&mut self, avg_batch_size: f64, config: &AppConfig) {
+        if self.batches < MIN_BATCHES {
+            self.reasons.push(format!(
+                "Only {} batches in the last {}s, too few to suggest anything",
+                self.batches, self.window_secs
+            ));
+            return;
+        }
+        let arrivals_per_wait = self.arrival_rate_per_sec * self.max_wait_time_ms as f64 / 1000.0;
+
+        if self.size_flush_ratio >= DOMINANT_SHARE {
+            // every request brings at least one input
+            let suggested = (self.max_batch_size * 2).min(config.max_inference_inputs);
+            if suggested > self.max_batch_size {
+                self.suggested_max_batch_size = Some(suggested);
+                self.reasons.push(format!(
+                    "{:.0}% of batches fill up before `max_wait_time_ms`, larger batches could raise throughput, as long as the inference service keeps up",
+                    self.size_flush_ratio * 100.0
+                ));
+            } else {
+                self.reasons.push(format!(
+                    "{:.0}% of batches fill up before `max_wait_time_ms`, but `max_batch_size` is already at `max_inference_inputs`",
+                    self.size_flush_ratio * 100.0
+                ));
+            }
+        } else if self.wait_flush_ratio >= DOMINANT_SHARE && self.avg_fill_ratio < 0.5 {
+            if arrivals_per_wait < 1.0 {
+                let suggested = (self.max_wait_time_ms / 2).max(1);
+                if suggested < self.max_wait_time_ms {
+                    self.suggested_max_wait_time_ms = Some(suggested);
+                }
+                self.reasons.push(format!(
+                    "Only {arrivals_per_wait:.2} requests arrive per `max_wait_time_ms`, waiting adds latency without batching much"
+                ));
+            } else {
+                // flushing once the usual batch is collected, rather than after the full wait
+                let suggested = (arrivals_per_wait.ceil() as usize).max(1);
+                if suggested < self.max_batch_size {
+                    self.suggested_max_batch_size = Some(suggested);
+                }
+                self.reasons.push(format!(
+                    "Batches leave after `max_wait_time_ms` with {avg_batch_size:.1} requests on average, a `max_batch_size` of about the {arrivals_per_wait:.1} requests arriving meanwhile flushes them sooner"
+                ));
+            }
+        } else {
+            self.reasons.push(format!(
+                "Batches are {:.0}% full on average & flushed by both triggers, no change suggested",
+                self.avg_fill_ratio * 100.0
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AppConfig {
+        AppConfig {
+            max_wait_time_ms: 100,
+            max_batch_size: 32,
+            max_inference_inputs: 128,
+            ..AppConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_too_few_batches_suggest_nothing() {
+        let window = TuningWindow {
+            window_secs: 60,
+            arrivals: 3,
+            batches: 3,
+            requests: 3,
+            wait_flushes: 3,
+            ..TuningWindow::default()
+        };
+        let report = TuningReport::new(&window, &config());
+        assert_eq!(report.arrival_rate_per_sec, 0.05);
+        assert_eq!(report.wait_flush_ratio, 1.0);
+        assert!(report.suggested_max_wait_time_ms.is_none());
+        assert!(report.suggested_max_batch_size.is_none());
+        assert_eq!(report.reasons.len(), 1);
+    }
+
+    #[test]
+    fn test_full_batches_suggest_larger_batch_size() {
+        let window = TuningWindow {
+            window_secs: 60,
+            arrivals: 6400,
+            batches: 200,
+            requests: 6400,
+            size_flushes: 190,
+            wait_flushes: 10,
+        };
+        let report = TuningReport::new(&window, &config());
+        assert_eq!(report.avg_fill_ratio, 1.0);
+        assert_eq!(report.size_flush_ratio, 0.95);
+        assert_eq!(report.suggested_max_batch_size, Some(64));
+        assert!(report.suggested_max_wait_time_ms.is_none());
+    }
+
+    #[test]
+    fn test_sparse_traffic_suggests_shorter_wait() {
+        // 0.5 requests/s, i.e., 0.05 per 100ms wait
+        let window = TuningWindow {
+            window_secs: 60,
+            arrivals: 30,
+            batches: 30,
+            requests: 30,
+            wait_flushes: 30,
+            ..TuningWindow::default()
+        };
+        let report = TuningReport::new(&window, &config());
+        assert_eq!(report.suggested_max_wait_time_ms, Some(50));
+        assert!(report.suggested_max_batch_size.is_none());
+    }
+
+    #[test]
+    fn test_timed_out_partial_batches_suggest_smaller_batch_size() {
+        // 60 requests/s, i.e., 6 per 100ms wait, never reaching 32
+        let window = TuningWindow {
+            window_secs: 60,
+            arrivals: 3600,
+            batches: 600,
+            requests: 3600,
+            wait_flushes: 600,
+            ..TuningWindow::default()
+        };
+        let report = TuningReport::new(&window, &config());
+        assert_eq!(report.suggested_max_batch_size, Some(6));
+        assert!(report.suggested_max_wait_time_ms.is_none());
+    }
+}
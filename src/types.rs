@@ -1,22 +1,363 @@
-use crate::config::AppConfig;
+use crate::backpressure::Overloaded;
+use crate::config::{AppConfig, InferenceProtocol, SchedulingMode};
+use crate::inference_client::BackendProbe;
+use crate::quota::QuotaExceeded;
+use crate::rate_limiter::RateLimited;
+use rocket::Responder;
 use rocket::response::status::Custom;
 use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::oneshot;
 
 pub type ResponseSender = oneshot::Sender<Result<EmbedResponse, Custom<Json<ErrorResponse>>>>;
 pub type ResponseReceiver = oneshot::Receiver<Result<EmbedResponse, Custom<Json<ErrorResponse>>>>;
 
+/// `/embed` failure, load shedding, rate limiting & quotas need their own response headers
+#[derive(Responder, Debug)]
+pub enum EmbedError {
+    Rejected(Custom<Json<ErrorResponse>>),
+    Overloaded(Overloaded),
+    RateLimited(RateLimited),
+    QuotaExceeded(QuotaExceeded),
+}
+
+impl From<Custom<Json<ErrorResponse>>> for EmbedError {
+    fn from(error: Custom<Json<ErrorResponse>>) -> Self {
+        EmbedError::Rejected(error)
+    }
+}
+
+impl From<RateLimited> for EmbedError {
+    fn from(error: RateLimited) -> Self {
+        EmbedError::RateLimited(error)
+    }
+}
+
+impl From<QuotaExceeded> for EmbedError {
+    fn from(error: QuotaExceeded) -> Self {
+        EmbedError::QuotaExceeded(error)
+    }
+}
+
+/// `GET /info` response
+#[derive(Serialize, Debug, Clone)]
+pub struct ProxyInfo {
+    pub proxy: BuildInfo,
+    pub config: ConfigSummary,
+    /// Upstream `/info` payload as-is (e.g., TEI `model_id`, `max_batch_tokens`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<serde_json::Value>,
+    /// Why `upstream` is missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_sha: &'static str,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("GIT_SHA"),
+        }
+    }
+}
+
+/// Settings relevant to clients, no upstream URLs or credentials
+#[derive(Serialize, Debug, Clone)]
+pub struct ConfigSummary {
+    pub max_batch_size: usize,
+    pub max_wait_time_ms: u64,
+    pub max_inference_inputs: usize,
+    pub max_batch_tokens: Option<usize>,
+    pub max_input_chars: Option<usize>,
+    pub scheduling_mode: SchedulingMode,
+    pub inference_protocol: InferenceProtocol,
+    pub inference_backends: usize,
+    /// Accepted `model` values, besides `model_aliases` & `default`
+    pub models: Vec<String>,
+    pub model_aliases: BTreeMap<String, String>,
+    pub default_model: Option<String>,
+}
+
+impl From<&AppConfig> for ConfigSummary {
+    fn from(config: &AppConfig) -> Self {
+        Self {
+            max_batch_size: config.max_batch_size,
+            max_wait_time_ms: config.max_wait_time_ms,
+            max_inference_inputs: config.max_inference_inputs,
+            max_batch_tokens: config.max_batch_tokens,
+            max_input_chars: config.max_input_chars,
+            scheduling_mode: config.scheduling_mode,
+            inference_protocol: config.inference_protocol,
+            inference_backends: config.inference_urls.len(),
+            models: config.models.keys().cloned().collect(),
+            model_aliases: config.model_aliases.clone(),
+            default_model: config.default_model.clone(),
+        }
+    }
+}
+
+/// `PUT /admin/backend` request body
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BackendSwitchRequest {
+    /// Same format as `--inference-url`, comma separated for multiple replicas
+    pub inference_url: String,
+}
+
+/// `PUT /admin/backend` response
+#[derive(Serialize, Debug, Clone)]
+pub struct BackendSwitch {
+    pub previous_inference_urls: Vec<String>,
+    pub inference_urls: Vec<String>,
+    /// Validation probes of the new backends, run before switching
+    pub probes: Vec<BackendProbe>,
+}
+
+/// `POST /admin/queue/pause` & `POST /admin/queue/resume` response
+#[derive(Serialize, Debug, Clone)]
+pub struct QueuePause {
+    pub paused: bool,
+    /// Requests waiting across all model queues
+    pub queue_depth: usize,
+}
+
+/// `POST /admin/drain` response
+#[derive(Serialize, Debug, Clone)]
+pub struct DrainReport {
+    /// `false` when `shutdown_drain_timeout_secs` passed before every queued request was served
+    pub completed: bool,
+}
+
+/// `POST /admin/queue/flush` response
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueFlush {
+    /// Requests dispatched across all model queues
+    pub flushed_requests: usize,
+}
+
+/// `GET /health/deep` response
+#[derive(Serialize, Debug, Clone)]
+pub struct DeepHealth {
+    /// "ok" while at least one inference backend is reachable, "unavailable" otherwise
+    pub status: &'static str,
+    pub upstream: UpstreamHealth,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UpstreamHealth {
+    pub reachable: bool,
+    /// Of the fastest reachable backend
+    pub latency_ms: Option<f64>,
+    pub backends: Vec<BackendProbe>,
+}
+
+impl DeepHealth {
+    pub fn from_probes(backends: Vec<BackendProbe>) -> Self {
+        let latency_ms = backends
+            .iter()
+            .filter(|probe| probe.reachable)
+            .map(|probe| probe.latency_ms)
+            .min_by(f64::total_cmp);
+        Self {
+            status: if latency_ms.is_some() {
+                "ok"
+            } else {
+                "unavailable"
+            },
+            upstream: UpstreamHealth {
+                reachable: latency_ms.is_some(),
+                latency_ms,
+                backends,
+            },
+        }
+    }
+}
+
+/// `GET /readyz` response, ready only when all checks pass
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Not shutting down
+    pub accepting_requests: bool,
+    /// Below load shedding limits (`load_shed_*`)
+    pub queue_not_saturated: bool,
+    /// At least one inference backend passes periodic health checks
+    pub upstream_healthy: bool,
+    pub batch_loop_running: bool,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct ErrorResponse {
     pub error: String,
+    /// Machine-readable error code, e.g. for upstream load balancers deciding whether to fail over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// Caller's id of an ID-keyed input (`{"id": ..., "text": ...}`), echoed back with its embedding
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum InputId {
+    Number(i64),
+    Text(String),
+}
+
+/// Element of `inputs` as sent
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Input {
+    Text(String),
+    Keyed { id: InputId, text: String },
+}
+
+/// `EmbedRequest` as sent, with `inputs` either all strings or all ID-keyed
+#[derive(Deserialize)]
+struct EmbedRequestBody {
+    inputs: Vec<Input>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    client_tag: Option<String>,
+    /// How `EmbedRequest` serializes ID-keyed inputs, e.g. in persisted jobs
+    #[serde(default)]
+    input_ids: Option<Vec<InputId>>,
+    #[serde(default)]
+    partial_errors: bool,
+}
+
+impl TryFrom<EmbedRequestBody> for EmbedRequest {
+    type Error = String;
+
+    fn try_from(body: EmbedRequestBody) -> Result<Self, Self::Error> {
+        let input_count = body.inputs.len();
+        let mut inputs = Vec::with_capacity(input_count);
+        let mut keyed_ids = Vec::new();
+        for input in body.inputs {
+            match input {
+                Input::Text(text) => inputs.push(text),
+                Input::Keyed { id, text } => {
+                    keyed_ids.push(id);
+                    inputs.push(text);
+                }
+            }
+        }
+        let input_ids = match (keyed_ids.len(), body.input_ids) {
+            (0, input_ids) => input_ids,
+            (keyed, None) if keyed == input_count => Some(keyed_ids),
+            _ => {
+                return Err(
+                    "`inputs` must be either all strings or all `{\"id\", \"text\"}` objects"
+                        .to_string(),
+                );
+            }
+        };
+        if input_ids
+            .as_ref()
+            .is_some_and(|input_ids| input_ids.len() != input_count)
+        {
+            return Err("`input_ids` must have one id per input".to_string());
+        }
+
+        Ok(Self {
+            inputs,
+            priority: body.priority,
+            model: body.model,
+            metadata: body.metadata,
+            client_tag: body.client_tag,
+            input_ids,
+            partial_errors: body.partial_errors,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(try_from = "EmbedRequestBody")]
 pub struct EmbedRequest {
-    /// Inference service supports both single & multiple inputs per user
+    /// Inference service supports both single & multiple inputs per user.
+    /// Also accepted as `[{"id": ..., "text": ...}, ...]`, see `input_ids`
     pub inputs: Vec<String>,
+    /// High priority requests are always packed into the next batch first
+    #[serde(default)]
+    pub priority: Priority,
+    /// One of `config.models` (or `config.model_aliases`), `config.default_model` otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Any JSON, echoed back in the response, e.g. to correlate responses of many logical jobs
+    /// multiplexed over one connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Short label (see `MAX_CLIENT_TAG_CHARS`), echoed back like `metadata`, also recorded in
+    /// the audit log, slow request logs & `proxy_client_tag_requests_total`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
+    /// Ids of ID-keyed `inputs`, in input order. Embeddings are then returned as
+    /// `[{"id": ..., "embedding": ...}, ...]`, so callers sharding & merging results
+    /// don't depend on positions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_ids: Option<Vec<InputId>>,
+    /// Inputs that are too long (`config.input_overflow` `reject`) or rejected by the inference
+    /// service fail on their own, with an error object in place of their embedding, instead of
+    /// failing the whole request
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial_errors: bool,
+}
+
+/// Keeps `client_tag`s usable as log fields & metric labels
+pub const MAX_CLIENT_TAG_CHARS: usize = 64;
+
+impl EmbedRequest {
+    /// `client_tag` is limited to `MAX_CLIENT_TAG_CHARS` ASCII letters, digits & `-_.:/`
+    pub fn is_valid_client_tag(client_tag: &str) -> bool {
+        !client_tag.is_empty()
+            && client_tag.len() <= MAX_CLIENT_TAG_CHARS
+            && client_tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c))
+    }
+
+    /// Indices of inputs longer than `max_chars` characters (not bytes)
+    pub fn inputs_longer_than(&self, max_chars: usize) -> Vec<usize> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| input.chars().nth(max_chars).is_some())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Cuts every input to at most `max_chars` characters (at a char boundary)
+    pub fn truncate_inputs(&mut self, max_chars: usize) {
+        for input in &mut self.inputs {
+            if let Some((byte_index, _)) = input.char_indices().nth(max_chars) {
+                input.truncate(byte_index);
+            }
+        }
+    }
+}
+
+/// e.g., interactive search traffic (`high`) sharing the proxy with offline backfill jobs (`normal`),
+/// `POST /jobs/embed` submissions are always `low`. Declaration order is the scheduling order
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
@@ -25,6 +366,15 @@ pub enum BatchType {
     MaxBatchSize,
     #[serde(rename = "max_wait_time_ms")]
     MaxWaitTimeMs,
+    /// Flushed early by `SchedulingMode::Deadline`, a client deadline was about to expire
+    #[serde(rename = "deadline")]
+    Deadline,
+    /// Final flush of the pending queue on shutdown
+    #[serde(rename = "drain")]
+    Drain,
+    /// Flushed on demand via `POST /admin/queue/flush`
+    #[serde(rename = "flush")]
+    Flush,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -34,7 +384,18 @@ pub struct BatchInfo {
     pub batch_size: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub batch_wait_time_ms: Option<u64>,
+    /// Time this request actually spent queued, from arrival until its batch was dispatched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_time_ms: Option<f64>,
+    /// Upstream call only
     pub inference_time_ms: Option<f64>,
+    /// Proxy's own overhead around the upstream call, i.e., building the batch request
+    /// & splitting embeddings back into per-request responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serialization_time_ms: Option<f64>,
+    /// From arrival until the response was handed back to the route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processing_time_ms: Option<f64>,
 }
 
 pub static BATCH_COUNTER: AtomicU64 = AtomicU64::new(1);
@@ -49,39 +410,360 @@ impl BatchInfo {
             None
         };
 
-        if config.include_batch_info {
+        // `RequestHandler` strips it from responses unless `include_batch_info` is set
+        if config.tracks_batch_info() {
             return Some(BatchInfo {
                 batch_id: BATCH_COUNTER.fetch_add(1, Ordering::Relaxed),
                 batch_type,
                 batch_size: Some(batch_size),
                 batch_wait_time_ms,
-                inference_time_ms: None, // filled later in `process_batch`
+                // timings are filled later in `process_batch` & `handle_batch_success`
+                queue_time_ms: None,
+                inference_time_ms: None,
+                serialization_time_ms: None,
+                processing_time_ms: None,
             });
         }
         None
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct EmbedResponse {
     pub embeddings: Vec<Vec<f32>>,
     #[serde(skip_serializing_if = "Option::is_none")] // hide when None
     pub batch_info: Option<BatchInfo>,
+    /// `EmbedRequest::metadata`, as sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// `EmbedRequest::client_tag`, as sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
+    /// `EmbedRequest::input_ids`, the routes then pair each embedding with its id
+    #[serde(skip)]
+    pub input_ids: Option<Vec<InputId>>,
+    /// Failed inputs of `EmbedRequest::partial_errors` requests by input index, written in place
+    /// of their embeddings, which only cover the inputs that succeeded
+    #[serde(skip)]
+    pub input_errors: BTreeMap<usize, ErrorResponse>,
+    /// Filled instead of `embeddings` for `Endpoint::EmbedSparse` requests,
+    /// returned by `/embed_sparse` as `SparseEmbedResponse`
+    #[serde(skip)]
+    pub sparse_embeddings: Vec<SparseEmbedding>,
+    /// Filled instead of `embeddings` for `Endpoint::EmbedAll` requests,
+    /// returned by `/embed_all` as `TokenEmbedResponse`
+    #[serde(skip)]
+    pub token_embeddings: Vec<TokenEmbeddings>,
+}
+
+/// Embedding of an input, or why it failed (see `EmbedRequest::partial_errors`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum InputResult<E> {
+    Embedding(E),
+    Error(ErrorResponse),
+}
+
+impl<E> InputResult<E> {
+    /// `embeddings` of the inputs that succeeded, with `input_errors` (by input index) in between
+    pub fn merge(
+        embeddings: Vec<E>,
+        mut input_errors: BTreeMap<usize, ErrorResponse>,
+    ) -> impl Iterator<Item = Self> {
+        let mut embeddings = embeddings.into_iter();
+        (0..).map_while(move |index| match input_errors.remove(&index) {
+            Some(error) => Some(Self::Error(error)),
+            None => embeddings.next().map(Self::Embedding),
+        })
+    }
+}
+
+/// Result of an ID-keyed input, `{"id": ..., "embedding": ...}` or `{"id": ..., "error": ...}`
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyedEmbedding<E> {
+    pub id: InputId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<E>,
+    #[serde(flatten)]
+    pub error: Option<ErrorResponse>,
+}
+
+impl<E> KeyedEmbedding<E> {
+    pub fn new(id: InputId, result: InputResult<E>) -> Self {
+        match result {
+            InputResult::Embedding(embedding) => Self {
+                id,
+                embedding: Some(embedding),
+                error: None,
+            },
+            InputResult::Error(error) => Self {
+                id,
+                embedding: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+/// Embeddings in input order, or paired with `EmbedRequest::input_ids`
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Embeddings<E> {
+    Keyed(Vec<KeyedEmbedding<E>>),
+    Positional(Vec<InputResult<E>>),
+}
+
+impl<E> Embeddings<E> {
+    pub fn new(
+        embeddings: Vec<E>,
+        input_ids: Option<Vec<InputId>>,
+        input_errors: BTreeMap<usize, ErrorResponse>,
+    ) -> Self {
+        let results = InputResult::merge(embeddings, input_errors);
+        match input_ids {
+            Some(input_ids) => Self::Keyed(
+                input_ids
+                    .into_iter()
+                    .zip(results)
+                    .map(|(id, result)| KeyedEmbedding::new(id, result))
+                    .collect(),
+            ),
+            None => Self::Positional(results.collect()),
+        }
+    }
+}
+
+/// `POST /embed_sparse` response
+#[derive(Debug, Clone, Serialize)]
+pub struct SparseEmbedResponse {
+    pub embeddings: Embeddings<SparseEmbedding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_info: Option<BatchInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
+}
+
+impl From<EmbedResponse> for SparseEmbedResponse {
+    fn from(response: EmbedResponse) -> Self {
+        Self {
+            embeddings: Embeddings::new(
+                response.sparse_embeddings,
+                response.input_ids,
+                response.input_errors,
+            ),
+            batch_info: response.batch_info,
+            metadata: response.metadata,
+            client_tag: response.client_tag,
+        }
+    }
+}
+
+/// `POST /embed_all` response
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenEmbedResponse {
+    pub embeddings: Embeddings<TokenEmbeddings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_info: Option<BatchInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
+}
+
+impl From<EmbedResponse> for TokenEmbedResponse {
+    fn from(response: EmbedResponse) -> Self {
+        Self {
+            embeddings: Embeddings::new(
+                response.token_embeddings,
+                response.input_ids,
+                response.input_errors,
+            ),
+            batch_info: response.batch_info,
+            metadata: response.metadata,
+            client_tag: response.client_tag,
+        }
+    }
+}
+
+/// One vector per token of an input, as returned by TEI `/embed_all`, the token count varies by input
+pub type TokenEmbeddings = Vec<Vec<f32>>;
+
+/// Non-zero dimension of a sparse (e.g. SPLADE) embedding, as returned by TEI `/embed_sparse`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct SparseValue {
+    pub index: usize,
+    pub value: f32,
+}
+
+pub type SparseEmbedding = Vec<SparseValue>;
+
+/// TEI endpoint a queue batches for, requests of different endpoints never share a batch
+#[derive(
+    Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+    #[default]
+    Embed,
+    EmbedSparse,
+    EmbedAll,
+}
+
+impl Endpoint {
+    /// Path on the inference service, next to the configured `/embed`
+    pub fn path(self) -> &'static str {
+        match self {
+            Endpoint::Embed => "/embed",
+            Endpoint::EmbedSparse => "/embed_sparse",
+            Endpoint::EmbedAll => "/embed_all",
+        }
+    }
+}
+
+/// Upstream response of a batch, in the shape of its `Endpoint`, one entry per input
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOutput {
+    Dense(BatchResponse),
+    Sparse(Vec<SparseEmbedding>),
+    Tokens(Vec<TokenEmbeddings>),
 }
 
+impl BatchOutput {
+    pub fn len(&self) -> usize {
+        match self {
+            BatchOutput::Dense(embeddings) => embeddings.len(),
+            BatchOutput::Sparse(embeddings) => embeddings.len(),
+            BatchOutput::Tokens(embeddings) => embeddings.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Consecutive parts of `sizes` entries each (moved, not copied), e.g. one per request of the batch.
+    /// An entry is everything returned for one input, e.g. all of its token vectors
+    pub fn split(self, sizes: impl IntoIterator<Item = usize>) -> Vec<BatchOutput> {
+        fn split<T>(entries: Vec<T>, sizes: impl IntoIterator<Item = usize>) -> Vec<Vec<T>> {
+            let mut entries = entries.into_iter();
+            sizes
+                .into_iter()
+                .map(|size| entries.by_ref().take(size).collect())
+                .collect()
+        }
+        match self {
+            BatchOutput::Dense(embeddings) => split(embeddings, sizes)
+                .into_iter()
+                .map(BatchOutput::Dense)
+                .collect(),
+            BatchOutput::Sparse(embeddings) => split(embeddings, sizes)
+                .into_iter()
+                .map(BatchOutput::Sparse)
+                .collect(),
+            BatchOutput::Tokens(embeddings) => split(embeddings, sizes)
+                .into_iter()
+                .map(BatchOutput::Tokens)
+                .collect(),
+        }
+    }
+
+    /// Reverts `BatchRequest::dedup`, i.e., one entry per original input again. Left as is
+    /// without an entry per unique input, `BatchProcessor` then fails the batch on the count mismatch.
+    /// Entries are moved to their first occurrence, only repeated inputs get a copy
+    pub fn expand(self, positions: &[usize]) -> BatchOutput {
+        fn expand<T: Clone>(entries: Vec<T>, positions: &[usize]) -> Vec<T> {
+            if positions
+                .iter()
+                .enumerate()
+                .all(|(index, position)| index == *position)
+            {
+                return entries;
+            }
+            // `dedup` keeps first occurrences in order, i.e., an entry is either the next one
+            // or was already moved to the output
+            let mut first_occurrences: Vec<usize> = Vec::with_capacity(entries.len());
+            let mut entries = entries.into_iter();
+            let mut expanded: Vec<T> = Vec::with_capacity(positions.len());
+            for (index, position) in positions.iter().enumerate() {
+                match first_occurrences.get(*position) {
+                    Some(first) => expanded.push(expanded[*first].clone()),
+                    None => {
+                        first_occurrences.push(index);
+                        expanded.extend(entries.next());
+                    }
+                }
+            }
+            expanded
+        }
+        let unique_inputs = positions.iter().max().map_or(0, |position| position + 1);
+        if self.len() != unique_inputs {
+            return self;
+        }
+        match self {
+            BatchOutput::Dense(embeddings) => BatchOutput::Dense(expand(embeddings, positions)),
+            BatchOutput::Sparse(embeddings) => BatchOutput::Sparse(expand(embeddings, positions)),
+            BatchOutput::Tokens(embeddings) => BatchOutput::Tokens(expand(embeddings, positions)),
+        }
+    }
+
+    /// Response of a single request, `batch_info` is filled by the caller
+    pub fn into_response(self) -> EmbedResponse {
+        match self {
+            BatchOutput::Dense(embeddings) => EmbedResponse {
+                embeddings,
+                ..EmbedResponse::default()
+            },
+            BatchOutput::Sparse(sparse_embeddings) => EmbedResponse {
+                sparse_embeddings,
+                ..EmbedResponse::default()
+            },
+            BatchOutput::Tokens(token_embeddings) => EmbedResponse {
+                token_embeddings,
+                ..EmbedResponse::default()
+            },
+        }
+    }
+}
+
+/// Inputs are shared with the batch's `PendingRequest`s, so building (& cloning, e.g., for
+/// shadow or comparison backends) a batch only bumps reference counts
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BatchRequest {
-    pub inputs: Vec<String>,
+    pub inputs: Vec<Arc<str>>,
 }
 impl BatchRequest {
     pub fn prepare_request(batch: &[PendingRequest]) -> BatchRequest {
-        let all_inputs: Vec<String> = batch
+        let all_inputs: Vec<Arc<str>> = batch
             .iter()
             .flat_map(|request| &request.inputs)
             .cloned()
             .collect();
         BatchRequest { inputs: all_inputs }
     }
+
+    /// Keeps only the first occurrence of every input, `None` when there are no repeated ones.
+    /// Otherwise returns the position of every original input among the remaining ones
+    pub fn dedup(&mut self) -> Option<Vec<usize>> {
+        let mut unique_inputs = Vec::new();
+        let mut unique_positions: HashMap<Arc<str>, usize> = HashMap::new();
+        let positions: Vec<usize> = self
+            .inputs
+            .iter()
+            .map(|input| {
+                *unique_positions.entry(input.clone()).or_insert_with(|| {
+                    unique_inputs.push(input.clone());
+                    unique_inputs.len() - 1
+                })
+            })
+            .collect();
+        if unique_inputs.len() == self.inputs.len() {
+            return None;
+        }
+        self.inputs = unique_inputs;
+        Some(positions)
+    }
 }
 
 // TEI returns embeddings directly as an array, not wrapped in an object
@@ -89,19 +771,84 @@ pub type BatchResponse = Vec<Vec<f32>>;
 
 #[derive(Debug)]
 pub struct PendingRequest {
-    pub inputs: Vec<String>,
+    pub inputs: Vec<Arc<str>>,
     pub response_sender: ResponseSender,
     pub received_at: std::time::Instant,
+    /// Client supplied deadline (`X-Request-Deadline-Ms`), request is failed instead of batched once passed
+    pub deadline: Option<std::time::Instant>,
+    pub priority: Priority,
+    /// Caller identity, used by `config.fair_scheduling`
+    pub client_id: Option<String>,
+    /// Total tokens across `inputs`, only counted when `config.max_batch_tokens` is set
+    pub token_count: usize,
 }
 
 impl PendingRequest {
     pub fn new(inputs: Vec<String>, response_sender: ResponseSender) -> Self {
         Self {
-            inputs,
+            inputs: inputs.into_iter().map(Arc::from).collect(),
             response_sender,
             received_at: std::time::Instant::now(),
+            deadline: None,
+            priority: Priority::Normal,
+            client_id: None,
+            token_count: 0,
         }
     }
+
+    pub fn with_token_count(mut self, token_count: usize) -> Self {
+        self.token_count = token_count;
+        self
+    }
+
+    pub fn with_client_id(mut self, client_id: Option<String>) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<std::time::Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Power-of-two bucket of the longest input (in characters), i.e., inputs of 33..=64 chars share a class
+    pub fn size_class(&self) -> u32 {
+        let longest = self
+            .inputs
+            .iter()
+            .map(|input| input.chars().count())
+            .max()
+            .unwrap_or_default();
+        longest.max(1).next_power_of_two().trailing_zeros()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= std::time::Instant::now())
+    }
+}
+
+/// Out-of-band instructions for `BatchProcessor`, delivered through its own channel
+/// (separate from incoming requests)
+#[derive(Debug)]
+pub enum ControlMessage {
+    /// Flush all pending requests & notify once every in-flight batch has completed
+    Drain {
+        done: oneshot::Sender<()>,
+    },
+    /// Stop dispatching batches, requests keep queueing (within load shedding limits & deadlines)
+    Pause,
+    Resume,
+    /// Dispatch all pending requests now, regardless of wait time (even while paused),
+    /// replies with how many were flushed
+    Flush {
+        done: oneshot::Sender<usize>,
+    },
 }
 
 #[cfg(test)]
@@ -110,44 +857,184 @@ mod tests {
     use std::time::Instant;
     use tokio::sync::oneshot;
 
+    #[test]
+    fn test_inputs_longer_than_and_truncate() {
+        let mut request = EmbedRequest {
+            inputs: vec![
+                "short".to_string(),
+                "ünïcödé".to_string(),
+                "12345".to_string(),
+            ],
+            ..EmbedRequest::default()
+        };
+        assert_eq!(request.inputs_longer_than(5), vec![1]);
+
+        request.truncate_inputs(5);
+        assert_eq!(request.inputs, vec!["short", "ünïcö", "12345"]);
+        assert!(request.inputs_longer_than(5).is_empty());
+    }
+
     #[test]
     fn test_prepare_request_can_handle_duplicates_for_multiple_users() {
         let (response_sender, _response_receiver) = oneshot::channel();
         let req1 = PendingRequest {
-            inputs: vec!["Hello".to_string()],
+            inputs: vec!["Hello".into()],
             response_sender,
             received_at: Instant::now(),
+            deadline: None,
+            priority: Priority::Normal,
+            client_id: None,
+            token_count: 0,
         };
 
         let (response_sender, _response_receiver) = oneshot::channel();
         let req2 = PendingRequest {
-            inputs: vec!["Hello".to_string()],
+            inputs: vec!["Hello".into()],
             response_sender,
             received_at: Instant::now(),
+            deadline: None,
+            priority: Priority::Normal,
+            client_id: None,
+            token_count: 0,
         };
 
         let batch: Vec<PendingRequest> = vec![req1, req2];
         let prepared = BatchRequest::prepare_request(&batch);
 
         assert_eq!(prepared.inputs.len(), 2);
-        assert_eq!(prepared.inputs[0], "Hello");
-        assert_eq!(prepared.inputs[1], "Hello");
+        assert_eq!(&*prepared.inputs[0], "Hello");
+        assert_eq!(&*prepared.inputs[1], "Hello");
+    }
+
+    #[test]
+    fn test_id_keyed_inputs() {
+        let request: EmbedRequest = serde_json::from_value(serde_json::json!({
+            "inputs": [{"id": "doc-1", "text": "Hello"}, {"id": 2, "text": "World"}]
+        }))
+        .unwrap();
+        assert_eq!(request.inputs, vec!["Hello", "World"]);
+        let input_ids = vec![InputId::Text("doc-1".to_string()), InputId::Number(2)];
+        assert_eq!(request.input_ids.as_ref(), Some(&input_ids));
+
+        // e.g. persisted jobs
+        let serialized = serde_json::to_value(&request).unwrap();
+        let request: EmbedRequest = serde_json::from_value(serialized).unwrap();
+        assert_eq!(request.inputs, vec!["Hello", "World"]);
+        assert_eq!(request.input_ids, Some(input_ids));
+
+        let request: EmbedRequest =
+            serde_json::from_value(serde_json::json!({"inputs": ["Hello"]})).unwrap();
+        assert!(request.input_ids.is_none());
+
+        let mixed = serde_json::json!({"inputs": ["Hello", {"id": 2, "text": "World"}]});
+        assert!(serde_json::from_value::<EmbedRequest>(mixed).is_err());
+        let missing_id = serde_json::json!({"inputs": ["Hello", "World"], "input_ids": [1]});
+        assert!(serde_json::from_value::<EmbedRequest>(missing_id).is_err());
+    }
+
+    #[test]
+    fn test_embeddings_with_input_errors() {
+        let error = |code| ErrorResponse {
+            error: "failed".to_string(),
+            code: Some(code),
+        };
+        let input_errors =
+            BTreeMap::from([(0, error("input_too_long")), (2, error("input_rejected"))]);
+        let embeddings = Embeddings::new(vec![vec![1.0], vec![3.0]], None, input_errors.clone());
+        assert_eq!(
+            serde_json::to_value(embeddings).unwrap(),
+            serde_json::json!([
+                {"error": "failed", "code": "input_too_long"},
+                [1.0],
+                {"error": "failed", "code": "input_rejected"},
+                [3.0],
+            ])
+        );
+
+        let input_ids = (1..=4).map(InputId::Number).collect();
+        let embeddings = Embeddings::new(vec![vec![1.0], vec![3.0]], Some(input_ids), input_errors);
+        assert_eq!(
+            serde_json::to_value(embeddings).unwrap(),
+            serde_json::json!([
+                {"id": 1, "error": "failed", "code": "input_too_long"},
+                {"id": 2, "embedding": [1.0]},
+                {"id": 3, "error": "failed", "code": "input_rejected"},
+                {"id": 4, "embedding": [3.0]},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_dedup_and_expand() {
+        let mut request = BatchRequest {
+            inputs: vec!["Hello".into(), "World".into(), "Hello".into()],
+        };
+        let positions = request.dedup().unwrap();
+        assert_eq!(request.inputs, vec![Arc::from("Hello"), Arc::from("World")]);
+        assert_eq!(positions, vec![0, 1, 0]);
+
+        let output = BatchOutput::Dense(vec![vec![0.1], vec![0.2]]).expand(&positions);
+        assert_eq!(
+            output,
+            BatchOutput::Dense(vec![vec![0.1], vec![0.2], vec![0.1]])
+        );
+        let output =
+            BatchOutput::Dense(vec![vec![0.1], vec![0.2], vec![0.3]]).expand(&[0, 1, 1, 0, 2, 1]);
+        assert_eq!(
+            output,
+            BatchOutput::Dense(vec![
+                vec![0.1],
+                vec![0.2],
+                vec![0.2],
+                vec![0.1],
+                vec![0.3],
+                vec![0.2]
+            ])
+        );
+        // a miscounted response is left for the embedding count check
+        let output = BatchOutput::Dense(vec![vec![0.1]]).expand(&positions);
+        assert_eq!(output.len(), 1);
+
+        let mut request = BatchRequest {
+            inputs: vec!["Hello".into(), "World".into()],
+        };
+        assert!(request.dedup().is_none());
+        assert_eq!(request.inputs.len(), 2);
     }
 
     #[test]
     fn test_prepare_request_can_handle_multiple_inputs_per_user() {
         let (response_sender, _response_receiver) = oneshot::channel();
         let req = PendingRequest {
-            inputs: vec!["Hello".to_string(), "World".to_string()],
+            inputs: vec!["Hello".into(), "World".into()],
             response_sender,
             received_at: Instant::now(),
+            deadline: None,
+            priority: Priority::Normal,
+            client_id: None,
+            token_count: 0,
         };
 
         let batch: Vec<PendingRequest> = vec![req];
         let prepared = BatchRequest::prepare_request(&batch);
 
         assert_eq!(prepared.inputs.len(), 2);
-        assert_eq!(prepared.inputs[0], "Hello");
-        assert_eq!(prepared.inputs[1], "World");
+        assert_eq!(&*prepared.inputs[0], "Hello");
+        assert_eq!(&*prepared.inputs[1], "World");
+    }
+
+    #[test]
+    fn test_size_class_uses_longest_input() {
+        let size_class = |inputs: Vec<&str>| {
+            let (response_sender, _response_receiver) = oneshot::channel();
+            let inputs = inputs.into_iter().map(String::from).collect();
+            PendingRequest::new(inputs, response_sender).size_class()
+        };
+
+        assert_eq!(size_class(vec!["a"]), 0);
+        assert_eq!(size_class(vec!["abc", "a"]), 2);
+        assert_eq!(size_class(vec!["abcd"]), 2);
+        assert_eq!(size_class(vec![&"a".repeat(33), "a"]), 6);
+        assert_eq!(size_class(vec![&"a".repeat(64)]), 6);
     }
 }
@@ -0,0 +1,174 @@
+use crate::config::AppConfig;
+use crate::inference_client::InferenceServiceClient;
+use log::{info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Upstream `/info` is cached for a minute anyway, see `InferenceServiceClient::upstream_info`
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Per call limits the inference service enforces itself, as reported by TEI's `/info`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UpstreamLimits {
+    /// TEI `max_client_batch_size`
+    pub max_inputs: Option<usize>,
+    /// TEI `max_batch_tokens`
+    pub max_batch_tokens: Option<usize>,
+}
+
+impl UpstreamLimits {
+    pub fn from_info(info: &serde_json::Value) -> Self {
+        let limit = |key: &str| {
+            info.get(key)
+                .and_then(serde_json::Value::as_u64)
+                .filter(|limit| *limit > 0)
+                .map(|limit| limit as usize)
+        };
+        Self {
+            max_inputs: limit("max_client_batch_size"),
+            max_batch_tokens: limit("max_batch_tokens"),
+        }
+    }
+
+    /// Replaces the limits of `config` that weren't set explicitly, returns what changed
+    pub fn apply(&self, config: &mut AppConfig) -> Vec<String> {
+        let mut changes = Vec::new();
+        if let Some(max_inputs) = self.max_inputs
+            && !config.max_inference_inputs_explicit
+            && max_inputs != config.max_inference_inputs
+        {
+            changes.push(format!(
+                "max_inference_inputs {} -> {max_inputs}",
+                config.max_inference_inputs
+            ));
+            config.max_inference_inputs = max_inputs;
+        }
+        if let Some(max_batch_tokens) = self.max_batch_tokens
+            && !config.max_batch_tokens_explicit
+            && Some(max_batch_tokens) != config.max_batch_tokens
+        {
+            changes.push(format!(
+                "max_batch_tokens {:?} -> {max_batch_tokens}",
+                config.max_batch_tokens
+            ));
+            config.max_batch_tokens = Some(max_batch_tokens);
+        }
+        changes
+    }
+
+    /// Effective limits of `config` the inference service would reject batches over
+    pub fn conflicts(&self, config: &AppConfig) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        if let Some(max_inputs) = self.max_inputs
+            && config.max_inference_inputs > max_inputs
+        {
+            conflicts.push(format!(
+                "max_inference_inputs ({}) > upstream max_client_batch_size ({max_inputs})",
+                config.max_inference_inputs
+            ));
+        }
+        if let (Some(upstream), Some(max_batch_tokens)) =
+            (self.max_batch_tokens, config.max_batch_tokens)
+            && max_batch_tokens > upstream
+        {
+            conflicts.push(format!(
+                "max_batch_tokens ({max_batch_tokens}) > upstream max_batch_tokens ({upstream})"
+            ));
+        }
+        conflicts
+    }
+}
+
+/// With `config.discover_upstream_limits`, applies the limits of the `/info` of `client` to `config`
+/// (of the queue of `model`). An unreachable `/info` keeps the configured limits
+pub async fn discover(client: &InferenceServiceClient, model: &str, config: &mut AppConfig) {
+    if !config.discover_upstream_limits {
+        return;
+    }
+    match client.upstream_info().await {
+        Ok(info) => {
+            let changes = UpstreamLimits::from_info(&info).apply(config);
+            if changes.is_empty() {
+                info!("Upstream limits of `{model}` match the configured ones");
+            } else {
+                info!(
+                    "Applied upstream limits of `{model}`: {}",
+                    changes.join(", ")
+                );
+            }
+        }
+        Err(e) => {
+            warn!("Failed to discover upstream limits of `{model}`, keeping configured ones: {e}")
+        }
+    }
+}
+
+/// Re-reads upstream `/info` every `RECHECK_INTERVAL`, warning when the inference service
+/// (e.g. redeployed with another model) no longer accepts the effective limits. Those are fixed
+/// for the proxy's lifetime, a restart picks up the new ones. Stops once `client` returns `None`,
+/// i.e., the queue of `model` is gone
+pub async fn watch(
+    client: impl Fn() -> Option<Arc<InferenceServiceClient>>,
+    model: String,
+    config: AppConfig,
+) {
+    let mut interval = tokio::time::interval(RECHECK_INTERVAL);
+    // the first tick completes right away, limits were just discovered
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let Some(client) = client() else {
+            return;
+        };
+        let Ok(info) = client.upstream_info().await else {
+            continue;
+        };
+        for conflict in UpstreamLimits::from_info(&info).conflicts(&config) {
+            warn!("Upstream limits of `{model}` changed, {conflict}, restart to apply them");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tei_info() -> serde_json::Value {
+        json!({
+            "model_id": "sentence-transformers/all-MiniLM-L6-v2",
+            "max_client_batch_size": 32,
+            "max_batch_tokens": 16384,
+        })
+    }
+
+    #[test]
+    fn test_apply_keeps_explicit_limits() {
+        let limits = UpstreamLimits::from_info(&tei_info());
+        let mut config = AppConfig {
+            max_inference_inputs: 64,
+            ..AppConfig::default()
+        };
+        assert_eq!(limits.apply(&mut config).len(), 2);
+        assert_eq!(config.max_inference_inputs, 32);
+        assert_eq!(config.max_batch_tokens, Some(16384));
+        assert!(limits.conflicts(&config).is_empty());
+
+        let mut config = AppConfig {
+            max_inference_inputs: 64,
+            max_inference_inputs_explicit: true,
+            ..AppConfig::default()
+        };
+        assert_eq!(limits.apply(&mut config).len(), 1);
+        assert_eq!(config.max_inference_inputs, 64);
+        assert_eq!(limits.conflicts(&config).len(), 1);
+    }
+
+    #[test]
+    fn test_from_info_without_limits() {
+        let limits = UpstreamLimits::from_info(&json!({"model_id": "custom"}));
+        assert_eq!(limits, UpstreamLimits::default());
+        let mut config = AppConfig::default();
+        assert!(limits.apply(&mut config).is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::AppConfig;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Callers without any identity (no API key & no client IP)
+const ANONYMOUS_CALLER: &str = "anonymous";
+/// Hex digits of an API key's SHA-256 in its label, tells keys apart without revealing them
+const KEY_HASH_DIGITS: usize = 12;
+/// Trailing characters of an API key in its label, only shown for keys 3 times as long
+const KEY_SUFFIX_CHARS: usize = 4;
+
+/// Served (successful) traffic of a single caller
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct CallerUsage {
+    pub requests: u64,
+    pub inputs: u64,
+    /// Each batch's inference time is split between its requests by input count
+    pub inference_time_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageWindow {
+    /// Unix epoch seconds
+    pub started_at: u64,
+    /// Unix epoch seconds, `None` for the current window
+    pub ended_at: Option<u64>,
+    /// Keyed by `caller_label`, i.e., client IP or masked API key.
+    /// Tracked by full API key, only masked in `UsageTracker::report`
+    pub callers: BTreeMap<String, CallerUsage>,
+}
+
+impl UsageWindow {
+    fn starting_now() -> Self {
+        Self {
+            started_at: unix_now(),
+            ended_at: None,
+            callers: BTreeMap::new(),
+        }
+    }
+
+    fn masked(&self) -> Self {
+        let mut callers: BTreeMap<String, CallerUsage> = BTreeMap::new();
+        for (client_id, usage) in &self.callers {
+            let masked = callers.entry(caller_label(Some(client_id))).or_default();
+            masked.requests += usage.requests;
+            masked.inputs += usage.inputs;
+            masked.inference_time_ms += usage.inference_time_ms;
+        }
+        Self {
+            callers,
+            ..self.clone()
+        }
+    }
+}
+
+/// `GET /admin/usage` response, `previous` keeps the last completed window around
+/// so periodic collection doesn't lose usage at window boundaries
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub window_secs: u64,
+    pub current: UsageWindow,
+    pub previous: Option<UsageWindow>,
+}
+
+#[derive(Debug)]
+struct UsageState {
+    current: UsageWindow,
+    current_started: Instant,
+    previous: Option<UsageWindow>,
+}
+
+/// Per caller usage counters for internal chargeback, reset every `config.usage_window_secs`
+#[derive(Debug)]
+pub struct UsageTracker {
+    window: Duration,
+    state: Mutex<UsageState>,
+}
+
+impl UsageTracker {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            window: Duration::from_secs(config.usage_window_secs),
+            state: Mutex::new(UsageState {
+                current: UsageWindow::starting_now(),
+                current_started: Instant::now(),
+                previous: None,
+            }),
+        }
+    }
+
+    pub fn record(&self, client_id: Option<&str>, inputs: usize, inference_time: Duration) {
+        let mut state = self.state.lock().unwrap();
+        self.rotate_if_due(&mut state);
+
+        let usage = state
+            .current
+            .callers
+            // anonymous callers under "", see `caller_label`
+            .entry(client_id.unwrap_or_default().to_string())
+            .or_default();
+        usage.requests += 1;
+        usage.inputs += inputs as u64;
+        usage.inference_time_ms += inference_time.as_secs_f64() * 1000.0;
+    }
+
+    pub fn report(&self) -> UsageReport {
+        let mut state = self.state.lock().unwrap();
+        self.rotate_if_due(&mut state);
+
+        UsageReport {
+            window_secs: self.window.as_secs(),
+            current: state.current.masked(),
+            previous: state.previous.as_ref().map(UsageWindow::masked),
+        }
+    }
+
+    fn rotate_if_due(&self, state: &mut UsageState) {
+        if state.current_started.elapsed() < self.window {
+            return;
+        }
+        let mut completed = std::mem::replace(&mut state.current, UsageWindow::starting_now());
+        completed.ended_at = Some(state.current.started_at);
+        state.previous = Some(completed);
+        state.current_started = Instant::now();
+    }
+}
+
+/// API keys are secrets, so they're labeled by a truncated SHA-256 (distinct per key),
+/// followed by their last 4 characters (of keys of at least 12) to be recognizable,
+/// e.g. `key:3f1c2a9b07de…1234`
+pub(crate) fn caller_label(client_id: Option<&str>) -> String {
+    let Some(client_id) = client_id.filter(|client_id| !client_id.is_empty()) else {
+        return ANONYMOUS_CALLER.to_string();
+    };
+    if client_id.parse::<IpAddr>().is_ok() {
+        return client_id.to_string();
+    }
+
+    let hash: String = Sha256::digest(client_id.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let hash = &hash[..KEY_HASH_DIGITS];
+    let chars: Vec<char> = client_id.chars().collect();
+    if chars.len() < 3 * KEY_SUFFIX_CHARS {
+        return format!("key:{hash}");
+    }
+    let suffix: String = chars[chars.len() - KEY_SUFFIX_CHARS..].iter().collect();
+    format!("key:{hash}…{suffix}")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caller_label_masks_api_keys() {
+        assert_eq!(caller_label(Some("127.0.0.1")), "127.0.0.1");
+        let label = caller_label(Some("sk-team-a-1234"));
+        assert!(label.starts_with("key:") && label.ends_with("…1234"));
+        assert!(!label.contains("team"));
+        // same suffix, still told apart
+        assert_ne!(label, caller_label(Some("sk-team-b-1234")));
+        // short keys show none of their characters
+        assert_eq!(caller_label(Some("abcd1234")).len(), "key:".len() + 12);
+        assert_eq!(caller_label(None), ANONYMOUS_CALLER);
+    }
+
+    #[test]
+    fn test_record_accumulates_per_caller() {
+        let tracker = UsageTracker::new(&AppConfig::default());
+        tracker.record(Some("10.0.0.1"), 3, Duration::from_millis(30));
+        tracker.record(Some("10.0.0.1"), 1, Duration::from_millis(10));
+        tracker.record(Some("10.0.0.2"), 2, Duration::from_millis(20));
+
+        let report = tracker.report();
+        assert!(report.previous.is_none());
+        assert_eq!(
+            report.current.callers["10.0.0.1"],
+            CallerUsage {
+                requests: 2,
+                inputs: 4,
+                inference_time_ms: 40.0,
+            }
+        );
+        assert_eq!(report.current.callers["10.0.0.2"].requests, 1);
+    }
+
+    #[test]
+    fn test_record_keeps_keys_with_the_same_suffix_apart() {
+        let tracker = UsageTracker::new(&AppConfig::default());
+        tracker.record(Some("sk-team-a-1234"), 3, Duration::from_millis(30));
+        tracker.record(Some("sk-team-b-1234"), 1, Duration::from_millis(10));
+        tracker.record(None, 1, Duration::from_millis(10));
+
+        let callers = tracker.report().current.callers;
+        assert_eq!(callers.len(), 3);
+        assert_eq!(callers[&caller_label(Some("sk-team-a-1234"))].inputs, 3);
+        assert_eq!(callers[ANONYMOUS_CALLER].inputs, 1);
+    }
+
+    #[test]
+    fn test_window_rotation() {
+        let tracker = UsageTracker {
+            window: Duration::ZERO,
+            ..UsageTracker::new(&AppConfig::default())
+        };
+        tracker.record(Some("10.0.0.1"), 1, Duration::from_millis(10));
+
+        // zero window, so every call starts a new one
+        let report = tracker.report();
+        assert!(report.current.callers.is_empty());
+        let previous = report.previous.unwrap();
+        assert!(previous.ended_at.is_some());
+    }
+}
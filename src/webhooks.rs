@@ -0,0 +1,138 @@
+use crate::config::AppConfig;
+use crate::jobs::Job;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// `sha256=<hex HMAC-SHA256 of the raw body>`, only sent with `config.webhook_secret`
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Per delivery attempt
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// POSTs finished `POST /jobs/embed` jobs to their `callback_url`
+pub struct WebhookSender {
+    client: reqwest::Client,
+    secret: Option<String>,
+    max_retries: u32,
+    /// Doubled on every retry
+    retry_backoff: Duration,
+    include_embeddings: bool,
+    /// Prepended to `/jobs/<job_id>` result URLs
+    route_prefix: String,
+}
+
+impl WebhookSender {
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create webhook client: {e}"))?;
+        Ok(Self {
+            client,
+            secret: config.webhook_secret.clone(),
+            max_retries: config.webhook_max_retries,
+            retry_backoff: Duration::from_secs(1),
+            include_embeddings: config.webhook_include_embeddings,
+            route_prefix: config.route_prefix.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Callbacks must be absolute http(s) URLs
+    pub fn validate_callback_url(callback_url: &str) -> Result<(), String> {
+        match reqwest::Url::parse(callback_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+            _ => Err(format!(
+                "`callback_url` `{callback_url}` must be an absolute http(s) URL"
+            )),
+        }
+    }
+
+    pub fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+        mac.update(body);
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("sha256={signature}")
+    }
+
+    /// Network errors, 429 & 5xx responses are retried up to `config.webhook_max_retries` times,
+    /// other responses are final. Results stay pollable either way
+    pub async fn deliver(&self, callback_url: &str, mut job: Job) -> Result<(), String> {
+        if !self.include_embeddings {
+            job.embeddings = None;
+            job.result_url = Some(format!("{}/jobs/{}", self.route_prefix, job.job_id));
+        }
+        let body = serde_json::to_vec(&job).map_err(|e| e.to_string())?;
+        let signature = self
+            .secret
+            .as_deref()
+            .map(|secret| Self::sign(secret, &body));
+
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(callback_url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(WEBHOOK_SIGNATURE_HEADER, signature);
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!("Job {} delivered to {callback_url}", job.job_id);
+                    return Ok(());
+                }
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    format!("HTTP {}", response.status())
+                }
+                Ok(response) => return Err(format!("HTTP {}", response.status())),
+                Err(e) => e.to_string(),
+            };
+            if attempt >= self.max_retries {
+                return Err(error);
+            }
+
+            let backoff = self
+                .retry_backoff
+                .saturating_mul(2u32.saturating_pow(attempt));
+            attempt += 1;
+            warn!(
+                "Job {} webhook failed ({error}), retry {attempt}/{} in {backoff:?}",
+                job.job_id, self.max_retries
+            );
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            WebhookSender::sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_validate_callback_url() {
+        assert!(WebhookSender::validate_callback_url("https://example.com/hooks/embed").is_ok());
+        assert!(WebhookSender::validate_callback_url("/hooks/embed").is_err());
+        assert!(WebhookSender::validate_callback_url("ftp://example.com/hooks").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+mod test_utils;
+
+use crate::test_utils::{build_inputs, get_client, get_client_with_defaults, spawn_stub_upstream};
+use auto_batching_proxy::config::{AccessLogFormat, AppConfig};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_access_log_echoes_request_id() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        access_log: Some(AccessLogFormat::Json),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Request-Id", "req-123"))
+        .body(json!({"inputs": build_inputs(2, None)}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("X-Request-Id"), Some("req-123"));
+
+    // generated when the client doesn't send one
+    let response = client.get("/health").dispatch().await;
+    let request_id = response.headers().get_one("X-Request-Id").unwrap();
+    assert_eq!(request_id.len(), 16);
+}
+
+#[tokio::test]
+async fn test_access_log_disabled_by_default() {
+    let client = get_client_with_defaults().await;
+    let response = client.get("/health").dispatch().await;
+    assert!(response.headers().get_one("X-Request-Id").is_none());
+}
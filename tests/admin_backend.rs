@@ -0,0 +1,70 @@
+mod test_utils;
+
+use crate::test_utils::{ADMIN_API_KEY, admin_auth, get_client, post_json};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{Value, json};
+
+async fn embedding_dims(client: &Client) -> usize {
+    let response = post_json(client, "/embed", json!({"inputs": ["Hello"]}).to_string()).await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    json["embeddings"][0].as_array().unwrap().len()
+}
+
+async fn switch_backend(client: &Client, inference_url: &str) -> (Status, Value) {
+    let response = client
+        .put("/admin/backend")
+        .header(admin_auth())
+        .header(ContentType::JSON)
+        .body(json!({"inference_url": inference_url}).to_string())
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_switch_backend_at_runtime() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(embedding_dims(&client).await, 8);
+
+    let (status, json) = switch_backend(&client, "mock://dims=4").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["previous_inference_urls"], json!(["mock://dims=8"]));
+    assert_eq!(json["inference_urls"], json!(["mock://dims=4"]));
+    assert_eq!(json["probes"][0]["reachable"], true);
+    assert_eq!(embedding_dims(&client).await, 4);
+
+    let response = client.get("/health/backends").dispatch().await;
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json[0]["url"], "mock://dims=4");
+}
+
+#[tokio::test]
+async fn test_switch_backend_keeps_current_one_on_failed_probe() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    // nothing listens on port 1
+    let (status, json) = switch_backend(&client, "http://127.0.0.1:1/embed").await;
+    assert_eq!(status, Status::BadGateway);
+    assert_eq!(json["code"], "backend_unreachable");
+
+    let (status, json) = switch_backend(&client, "not a url").await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(json["code"], "invalid_inference_url");
+
+    assert_eq!(embedding_dims(&client).await, 8);
+}
@@ -0,0 +1,156 @@
+mod test_utils;
+
+use crate::test_utils::{ADMIN_API_KEY, admin_auth, get_client, post_json};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+async fn get_stats(client: &Client) -> Value {
+    client
+        .get("/stats")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_paused_queue_holds_requests_until_resumed() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    let response = client
+        .post("/admin/queue/pause")
+        .header(admin_auth())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["paused"], true);
+
+    let embed = post_json(&client, "/embed", json!({"inputs": ["Hello"]}).to_string());
+    let resume = async {
+        // well past `max_wait_time_ms`, the request would have been served by now
+        for _ in 0..50 {
+            if get_stats(&client).await["queue_depth"] == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let stats = get_stats(&client).await;
+        assert_eq!(stats["paused"], true);
+        assert_eq!(stats["queue_depth"], 1);
+        assert_eq!(stats["batches_total"], 0);
+
+        let response = client
+            .post("/admin/queue/resume")
+            .header(admin_auth())
+            .dispatch()
+            .await;
+        let json: Value = response.into_json().await.unwrap();
+        assert_eq!(json["paused"], false);
+        assert_eq!(json["queue_depth"], 1);
+    };
+    let (response, _) = tokio::join!(embed, resume);
+    assert_eq!(response.status(), Status::Ok);
+
+    let stats = get_stats(&client).await;
+    assert_eq!(stats["paused"], false);
+    assert_eq!(stats["batches_total"], 1);
+}
+
+#[tokio::test]
+async fn test_flush_dispatches_before_max_wait_time() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 60_000,
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    let embed = post_json(&client, "/embed", json!({"inputs": ["Hello"]}).to_string());
+    let flush = async {
+        for _ in 0..50 {
+            if get_stats(&client).await["queue_depth"] == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let response = client
+            .post("/admin/queue/flush")
+            .header(admin_auth())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json: Value = response.into_json().await.unwrap();
+        assert_eq!(json["flushed_requests"], 1);
+    };
+    let (response, _) =
+        tokio::time::timeout(Duration::from_secs(5), async { tokio::join!(embed, flush) })
+            .await
+            .expect("flushed request should be served long before max_wait_time_ms");
+    assert_eq!(response.status(), Status::Ok);
+
+    let stats = get_stats(&client).await;
+    assert_eq!(stats["batches_by_type"]["flush"], 1);
+}
+
+#[tokio::test]
+async fn test_drain_serves_queued_requests_then_rejects_new_ones() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 60_000,
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(client.get("/readyz").dispatch().await.status(), Status::Ok);
+
+    let embed = post_json(&client, "/embed", json!({"inputs": ["Hello"]}).to_string());
+    let drain = async {
+        for _ in 0..50 {
+            if get_stats(&client).await["queue_depth"] == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let response = client
+            .post("/admin/drain")
+            .header(admin_auth())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json: Value = response.into_json().await.unwrap();
+        assert_eq!(json["completed"], true);
+    };
+    let (response, _) = tokio::join!(embed, drain);
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(
+        client.get("/readyz").dispatch().await.status(),
+        Status::ServiceUnavailable
+    );
+    let response = post_json(&client, "/embed", json!({"inputs": ["Hello"]}).to_string()).await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "shutting_down");
+
+    // a second drain (e.g. SIGTERM after the drain) doesn't wait again
+    let response = client
+        .post("/admin/drain")
+        .header(admin_auth())
+        .dispatch()
+        .await;
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["completed"], true);
+}
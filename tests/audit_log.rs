@@ -0,0 +1,57 @@
+mod test_utils;
+
+use crate::test_utils::{get_client, spawn_stub_upstream};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_embed_requests_are_audited_without_input_text() {
+    let dir = std::env::temp_dir().join("auto-batching-proxy-audit-integration");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.log");
+
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        audit_log_path: Some(path.to_string_lossy().to_string()),
+        api_keys: vec!["sk-team-a-1234".to_string()],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Api-Key", "sk-team-a-1234"))
+        .header(Header::new("X-Request-Id", "audit-1"))
+        .body(json!({"inputs": ["top secret", "also secret"]}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.unwrap();
+    // only tracked internally
+    assert!(body.get("batch_info").is_none());
+
+    // written from a background thread
+    let mut content = String::new();
+    for _ in 0..50 {
+        content = std::fs::read_to_string(&path).unwrap_or_default();
+        if !content.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert!(!content.contains("secret"));
+    let record: Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(record["request_id"], "audit-1");
+    let caller = record["caller"].as_str().unwrap();
+    assert!(caller.starts_with("key:") && caller.ends_with("…1234"));
+    assert_eq!(record["input_count"], 2);
+    assert_eq!(record["status"], 200);
+    assert!(record["batch_id"].is_u64());
+    assert!(record["timestamp_ms"].is_u64());
+}
@@ -0,0 +1,105 @@
+mod test_utils;
+
+use crate::test_utils::{ADMIN_API_KEY, admin_auth, build_inputs, get_client, spawn_stub_upstream};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{Value, json};
+
+async fn get_protected_client() -> Client {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        api_keys: vec!["key-1".to_string(), "key-2".to_string()],
+        ..Default::default()
+    };
+    get_client(config).await
+}
+
+async fn embed_status(client: &Client, header: Option<Header<'static>>) -> Status {
+    let mut request = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .body(json!({"inputs": build_inputs(1, None)}).to_string());
+    if let Some(header) = header {
+        request = request.header(header);
+    }
+    request.dispatch().await.status()
+}
+
+#[tokio::test]
+async fn test_embed_requires_api_key() {
+    let client = get_protected_client().await;
+
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .body(json!({"inputs": build_inputs(1, None)}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(body["error"], "Unauthorized");
+
+    let wrong_key = Header::new("X-Api-Key", "key-3");
+    assert_eq!(
+        embed_status(&client, Some(wrong_key)).await,
+        Status::Unauthorized
+    );
+}
+
+#[tokio::test]
+async fn test_embed_accepts_configured_api_keys() {
+    let client = get_protected_client().await;
+
+    let api_key = Header::new("X-Api-Key", "key-1");
+    assert_eq!(embed_status(&client, Some(api_key)).await, Status::Ok);
+
+    let bearer = Header::new("Authorization", "Bearer key-2");
+    assert_eq!(embed_status(&client, Some(bearer)).await, Status::Ok);
+}
+
+async fn switch_backend_status(client: &Client, header: Header<'static>) -> Status {
+    client
+        .put("/admin/backend")
+        .header(ContentType::JSON)
+        .header(header)
+        .body(json!({"inference_url": "mock://dims=4"}).to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+#[tokio::test]
+async fn test_admin_routes_require_admin_api_key() {
+    // disabled for everyone without an admin key
+    let client = get_protected_client().await;
+    let tenant_key = || Header::new("X-Api-Key", "key-1");
+    assert_eq!(
+        switch_backend_status(&client, tenant_key()).await,
+        Status::Unauthorized
+    );
+
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        api_keys: vec!["key-1".to_string()],
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(
+        switch_backend_status(&client, tenant_key()).await,
+        Status::Unauthorized
+    );
+    assert_eq!(
+        switch_backend_status(&client, admin_auth()).await,
+        Status::Ok
+    );
+}
+
+#[tokio::test]
+async fn test_health_is_not_protected() {
+    let client = get_protected_client().await;
+    let response = client.get("/health").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
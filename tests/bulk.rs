@@ -0,0 +1,126 @@
+mod test_utils;
+
+use crate::test_utils::get_client;
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::Status;
+use serde_json::{Value, json};
+
+#[tokio::test]
+async fn test_embed_bulk_jsonl() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        max_inference_inputs: 4,
+        max_input_chars: Some(20),
+        ..Default::default()
+    })
+    .await;
+
+    let mut body: Vec<String> = (0..10)
+        .map(|id| json!({"id": id, "input": format!("document {id}")}).to_string())
+        .collect();
+    body.push(String::new());
+    body.push("not json".to_string());
+    body.push(json!({"id": "long", "input": "x".repeat(21)}).to_string());
+
+    let response = client
+        .post("/embed/bulk")
+        .body(body.join("\n"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.content_type().unwrap().to_string(),
+        "application/x-ndjson"
+    );
+
+    let results: Vec<Value> = response
+        .into_string()
+        .await
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(results.len(), 12);
+
+    let embedded: Vec<&Value> = results
+        .iter()
+        .filter(|result| result.get("embedding").is_some())
+        .collect();
+    // chunks complete in input order
+    let ids: Vec<i64> = embedded
+        .iter()
+        .map(|result| result["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids, (0..10).collect::<Vec<_>>());
+    assert!(
+        embedded
+            .iter()
+            .all(|result| result["embedding"].as_array().unwrap().len() == 8)
+    );
+
+    let failed: Vec<&Value> = results
+        .iter()
+        .filter(|result| result.get("error").is_some())
+        .collect();
+    assert_eq!(failed.len(), 2);
+    assert!(failed.iter().any(|result| result["id"].is_null()));
+    assert!(failed.iter().any(|result| result["id"] == "long"));
+}
+
+#[tokio::test]
+async fn test_embed_bulk_body_limit() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_bulk_body_mb: 1,
+        ..Default::default()
+    })
+    .await;
+
+    // a single 2 MiB line is cut at the limit, i.e., can't be parsed
+    let body = json!({"id": 1, "input": "x".repeat(2 * 1024 * 1024)}).to_string();
+    let response = client.post("/embed/bulk").body(body).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let result: Value = serde_json::from_str(response.into_string().await.unwrap().trim()).unwrap();
+    assert!(result["id"].is_null());
+    assert!(result["error"].is_string());
+}
+
+#[tokio::test]
+async fn test_embed_bulk_stops_once_inputs_rate_is_exhausted() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        max_inference_inputs: 4,
+        rate_limit_inputs_per_sec: Some(4.0),
+        ..Default::default()
+    })
+    .await;
+
+    let body: Vec<String> = (0..12)
+        .map(|id| json!({"id": id, "input": format!("document {id}")}).to_string())
+        .collect();
+    let response = client
+        .post("/embed/bulk")
+        .body(body.join("\n"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let results: Vec<Value> = response
+        .into_string()
+        .await
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // the first chunk fits the bucket, the second one ends the stream
+    assert_eq!(results.len(), 5);
+    assert!(
+        results[..4]
+            .iter()
+            .all(|result| result.get("embedding").is_some())
+    );
+    assert!(results[4]["id"].is_null());
+    assert_eq!(results[4]["code"], "rate_limited");
+}
@@ -0,0 +1,76 @@
+mod test_utils;
+
+use crate::test_utils::{ADMIN_API_KEY, admin_auth, get_client, post_json};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::Status;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+async fn drift_report_after_one_request(compare_inference_url: &str) -> Value {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        compare_inference_url: Some(compare_inference_url.to_string()),
+        max_wait_time_ms: 10,
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    let body = json!({"inputs": ["Hello", "World"]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    // always the primary's embeddings
+    assert_eq!(json["embeddings"][0].as_array().unwrap().len(), 8);
+
+    // compared in the background
+    let mut report = Value::Null;
+    for _ in 0..50 {
+        let response = client
+            .get("/admin/drift")
+            .header(admin_auth())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        report = response.into_json().await.unwrap();
+        if report["batches_compared"] != 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    report
+}
+
+#[tokio::test]
+async fn test_identical_backends_dont_drift() {
+    let report = drift_report_after_one_request("mock://dims=8").await;
+    assert_eq!(report["batches_compared"], 1);
+    assert_eq!(report["inputs_compared"], 2);
+    assert_eq!(report["dimension_mismatches"], 0);
+    assert!(report["cosine_similarity"]["min"].as_f64().unwrap() > 0.999);
+}
+
+#[tokio::test]
+async fn test_dimension_mismatches_are_reported() {
+    let report = drift_report_after_one_request("mock://dims=4").await;
+    assert_eq!(report["batches_compared"], 1);
+    assert_eq!(report["dimension_mismatches"], 2);
+    assert!(report["cosine_similarity"].is_null());
+}
+
+#[tokio::test]
+async fn test_drift_report_requires_comparison() {
+    let client = get_client(AppConfig {
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    })
+    .await;
+    let response = client
+        .get("/admin/drift")
+        .header(admin_auth())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "comparison_disabled");
+}
@@ -0,0 +1,54 @@
+mod test_utils;
+
+use crate::test_utils::get_client;
+use auto_batching_proxy::config::AppConfig;
+use auto_batching_proxy::request_context::{REQUEST_ID_HEADER, REQUEST_TIMEOUT_HEADER};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_timed_out_requests_are_dead_lettered() {
+    let dir = std::env::temp_dir().join("auto-batching-proxy-dead-letter-integration");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dead-letter.jsonl");
+
+    let config = AppConfig {
+        inference_urls: vec!["mock://dims=8&latency_ms=300".to_string()],
+        max_wait_time_ms: 10,
+        dead_letter_path: Some(path.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    let embed = |request_id: &'static str, timeout_ms: &'static str| {
+        client
+            .post("/embed")
+            .header(ContentType::JSON)
+            .header(Header::new(REQUEST_ID_HEADER, request_id))
+            .header(Header::new(REQUEST_TIMEOUT_HEADER, timeout_ms))
+            .body(json!({"inputs": ["Hello"]}).to_string())
+            .dispatch()
+    };
+    assert_eq!(embed("served", "5000").await.status(), Status::Ok);
+    assert_eq!(embed("lost", "50").await.status(), Status::RequestTimeout);
+
+    // written from a background thread
+    let mut content = String::new();
+    for _ in 0..50 {
+        content = std::fs::read_to_string(&path).unwrap_or_default();
+        if !content.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(content.lines().count(), 1, "{content}");
+    let record: Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(record["request_id"], "lost");
+    assert_eq!(record["reason"], "timed_out");
+    assert_eq!(record["status"], 408);
+    assert_eq!(record["input_count"], 1);
+    assert!(record["timestamp_ms"].as_u64().unwrap() >= record["received_at_ms"].as_u64().unwrap());
+}
@@ -0,0 +1,51 @@
+mod test_utils;
+
+use crate::test_utils::{get_client, spawn_stub_upstream};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_sampled_requests_are_captured_with_redaction() {
+    let dir = std::env::temp_dir().join("auto-batching-proxy-debug-capture-integration");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("capture.jsonl");
+
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        debug_capture_path: Some(path.to_string_lossy().to_string()),
+        debug_capture_sample_rate: 1.0,
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Request-Id", "capture-1"))
+        .body(json!({"inputs": ["order 12345 for jane@example.com"]}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // written from a background thread
+    let mut content = String::new();
+    for _ in 0..50 {
+        content = std::fs::read_to_string(&path).unwrap_or_default();
+        if !content.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let record: Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(record["request_id"], "capture-1");
+    assert_eq!(record["inputs"], json!(["order ##### for <email>"]));
+    assert_eq!(record["status"], 200);
+    assert_eq!(record["embedding_count"], 1);
+    assert!(record["embedding_dims"].as_u64().unwrap() > 0);
+    assert!(record["batch_info"]["batch_id"].is_u64());
+}
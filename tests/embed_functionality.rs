@@ -156,6 +156,82 @@ mod embed_functionality_tests {
         }
     }
 
+    mod batch_info_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_batch_info_latency_breakdown() {
+            let config = AppConfig {
+                inference_urls: vec!["mock://dims=8".to_string()],
+                include_batch_info: true,
+                max_wait_time_ms: 200,
+                ..Default::default()
+            };
+
+            let client = get_client(config).await;
+            let response = post_json(
+                &client,
+                "/embed",
+                json!({"inputs": build_inputs(2, None)}).to_string(),
+            )
+            .await;
+            let json: Value = response.into_json().await.expect("Valid JSON response");
+
+            let batch_info = &json["batch_info"];
+            let queue_time_ms = batch_info["queue_time_ms"].as_f64().unwrap();
+            let inference_time_ms = batch_info["inference_time_ms"].as_f64().unwrap();
+            let processing_time_ms = batch_info["processing_time_ms"].as_f64().unwrap();
+            assert!(batch_info["serialization_time_ms"].as_f64().unwrap() >= 0.0);
+            // only flushed once `max_wait_time_ms` passed
+            assert!(queue_time_ms >= 150.0);
+            assert!(processing_time_ms >= queue_time_ms + inference_time_ms);
+        }
+
+        #[tokio::test]
+        async fn test_batch_info_hidden_with_slow_logging_only() {
+            let config = AppConfig {
+                inference_urls: vec!["mock://dims=8".to_string()],
+                include_batch_info: false,
+                // batch info is tracked internally for the slow request log
+                slow_log_threshold_ms: Some(1),
+                max_wait_time_ms: 50,
+                ..Default::default()
+            };
+
+            let client = get_client(config).await;
+            let response = post_json(
+                &client,
+                "/embed",
+                json!({"inputs": build_inputs(1, None)}).to_string(),
+            )
+            .await;
+            let json: Value = response.into_json().await.expect("Valid JSON response");
+
+            assert_eq!(json["embeddings"].as_array().unwrap().len(), 1);
+            assert!(json.get("batch_info").is_none());
+        }
+    }
+
+    mod batch_runtime_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_batches_on_dedicated_runtime() {
+            let config = AppConfig {
+                inference_urls: vec!["mock://dims=8".to_string()],
+                batch_runtime_threads: Some(2),
+                max_batch_size: 3,
+                include_batch_info: true,
+                ..Default::default()
+            };
+
+            let client = Arc::new(get_client(config).await);
+            let batches_info =
+                launch_threads_with_tests(client.clone(), 3, build_inputs(1, None), true).await;
+            assert_eq!(count_batch(&batches_info, BatchType::MaxBatchSize, 3), 3);
+        }
+    }
+
     mod benchmark_tests {
         use super::*;
 
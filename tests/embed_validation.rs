@@ -2,11 +2,15 @@ mod test_utils;
 
 use crate::test_utils::{
     build_inputs, direct_call_to_inference_service, get_client, get_client_with_defaults,
-    get_proxy_embeddings, post_json,
+    get_proxy_embeddings, post_json, spawn_stub_server, spawn_stub_upstream,
 };
-use auto_batching_proxy::config::AppConfig;
-use rocket::http::{ContentType, Status};
+use auto_batching_proxy::config::{AppConfig, InputOverflow};
+use auto_batching_proxy::request_context::{REQUEST_DEADLINE_HEADER, REQUEST_TIMEOUT_HEADER};
+use rocket::data::ByteUnit;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
 use serde_json::{Value, json};
+use std::collections::BTreeMap;
 
 #[tokio::test]
 async fn test_embed_endpoint_plain_text_request() {
@@ -75,6 +79,35 @@ async fn test_embed_endpoint_fails_when_inputs_exceed_config_max_inference_input
     // inference service returns `413 Payload Too Large error!`
 }
 
+#[tokio::test]
+async fn test_embed_endpoint_splits_oversized_request() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        max_inference_inputs: 10,
+        split_oversized_requests: true,
+        ..Default::default()
+    };
+    let embed = |client: Client, inputs: Vec<String>| async move {
+        let response = post_json(&client, "/embed", json!({"inputs": inputs}).to_string()).await;
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().await.expect("Valid JSON");
+        serde_json::from_value::<Vec<Vec<f32>>>(body["embeddings"].clone()).unwrap()
+    };
+
+    // distinct inputs, so a misplaced chunk can't go unnoticed
+    let inputs: Vec<String> = (0..25).map(|i| format!("input {i}")).collect();
+    let embeddings = embed(get_client(config.clone()).await, inputs.clone()).await;
+
+    // reassembled in input order, same as when sent in one batch
+    let unsplit_config = AppConfig {
+        max_inference_inputs: 32,
+        ..config
+    };
+    let expected = embed(get_client(unsplit_config).await, inputs).await;
+    assert_eq!(embeddings, expected);
+}
+
 #[tokio::test]
 async fn test_embed_endpoint_succeeds_when_inputs_equals_config_max_inference_inputs() {
     // let's try with defaults this time
@@ -94,6 +127,152 @@ async fn test_embed_endpoint_succeeds_when_inputs_equals_config_max_inference_in
     // skip the embeddings part this time, checked somewhere else
 }
 
+#[tokio::test]
+async fn test_embed_endpoint_fails_when_body_exceeds_max_request_body_kb() {
+    let config = AppConfig {
+        max_request_body_kb: 1,
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": ["x".repeat(2048)]}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let json: Value = response.into_json().await.expect("Valid JSON");
+    assert!(json["error"].is_string());
+}
+
+#[tokio::test]
+async fn test_rocket_config_applies_keep_alive_and_data_limits() {
+    let rocket = auto_batching_proxy::build_rocket(AppConfig {
+        keep_alive_secs: 30,
+        data_limits: BTreeMap::from([("string".to_string(), 2048)]),
+        max_request_body_kb: 4,
+        ..Default::default()
+    })
+    .await;
+    let config = rocket::Config::from(rocket.figment());
+    assert_eq!(config.keep_alive, 30);
+    assert_eq!(config.limits.get("string"), Some(ByteUnit::Byte(2048)));
+    assert_eq!(config.limits.get("json"), Some(ByteUnit::Kibibyte(4)));
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_rejects_inputs_exceeding_max_input_chars() {
+    let config = AppConfig {
+        max_input_chars: Some(10),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": ["short", "x".repeat(11), "ok", "y".repeat(20)]}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let json: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(json["code"], "input_too_long");
+    assert!(json["error"].as_str().unwrap().contains("[1, 3]"));
+}
+
+/// TEI stand-in, rejects (413) batches with inputs above `max_input_chars` like TEI does inputs
+/// above its token limit, embeds every other input as `[length]`. Returns the `/embed` URL
+async fn spawn_upstream_rejecting_inputs_over(max_input_chars: usize) -> String {
+    let addr = spawn_stub_server(move |request| {
+        // health checks & `/info` come without inputs
+        let inputs = request.inputs();
+        if inputs.iter().any(|input| input.len() > max_input_chars) {
+            let error = "Input validation error: inputs must have less than 512 tokens";
+            (Status::PayloadTooLarge, json!({"error": error}).to_string())
+        } else {
+            let embeddings: Vec<[f32; 1]> =
+                inputs.iter().map(|input| [input.len() as f32]).collect();
+            (Status::Ok, json!(embeddings).to_string())
+        }
+    })
+    .await;
+    format!("http://{addr}/embed")
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_partial_errors() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_upstream_rejecting_inputs_over(1000).await],
+        max_wait_time_ms: 10,
+        max_input_chars: Some(2000),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    // too long for `max_input_chars` & too many tokens for the inference service respectively
+    let too_long = "x".repeat(2001);
+    let poison = "word ".repeat(300);
+    let inputs = json!([
+        {"id": "a", "text": "Hello"},
+        {"id": "b", "text": too_long},
+        {"id": "c", "text": poison},
+        {"id": "d", "text": "World"},
+    ]);
+    let body = json!({"inputs": inputs, "partial_errors": true}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let json: Value = response.into_json().await.expect("Valid JSON");
+    let results = json["embeddings"].as_array().unwrap();
+    let ids: Vec<&str> = results
+        .iter()
+        .map(|result| result["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["a", "b", "c", "d"]);
+    assert_eq!(results[1]["code"], "input_too_long");
+    assert_eq!(results[2]["code"], "input_rejected");
+    let embedding = |index: usize| -> Vec<f32> {
+        serde_json::from_value(results[index]["embedding"].clone()).unwrap()
+    };
+    assert_eq!(vec![embedding(0), embedding(3)], vec![vec![5.0], vec![5.0]]);
+
+    // without it, any failed input fails the whole request
+    let body = json!({"inputs": ["Hello", poison]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let body = json!({"inputs": ["Hello", poison], "partial_errors": true}).to_string();
+    let json: Value = post_json(&client, "/embed", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert!(json["embeddings"][0].is_array());
+    assert_eq!(json["embeddings"][1]["code"], "input_rejected");
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_truncates_inputs_exceeding_max_input_chars() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        max_input_chars: Some(10),
+        input_overflow: InputOverflow::Truncate,
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": ["short", "x".repeat(11)]}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let json: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(json["embeddings"].as_array().unwrap().len(), 2);
+}
+
 #[tokio::test]
 async fn test_embed_endpoint_invalid_json_plain_text() {
     let client = get_client_with_defaults().await;
@@ -146,3 +325,122 @@ async fn test_verify_direct_and_proxy_return_similar_results_for_2_inputs() {
     let inputs = vec!["What is ML ?".to_string(), "What is NLP ?".to_string()];
     verify_direct_and_proxy_return_similar_results(&inputs).await;
 }
+
+fn epoch_ms_from_now(offset_ms: i64) -> String {
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    (now_ms + offset_ms).to_string()
+}
+
+async fn post_with_deadline(client: &Client, deadline: String) -> (Status, Value) {
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .header(Header::new(REQUEST_DEADLINE_HEADER, deadline))
+        .body(json!({"inputs": build_inputs(1, None)}).to_string())
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.expect("Valid JSON"))
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_invalid_deadline_header() {
+    let client = get_client_with_defaults().await;
+    let (status, _) = post_with_deadline(&client, "soon".to_string()).await;
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_rejects_already_passed_deadline() {
+    let client = get_client_with_defaults().await;
+    let (status, body) = post_with_deadline(&client, epoch_ms_from_now(-1000)).await;
+    assert_eq!(status, Status::GatewayTimeout);
+    assert_eq!(body["code"], "deadline_exceeded");
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_fails_request_whose_deadline_passes_in_queue() {
+    let config = AppConfig {
+        max_wait_time_ms: 2000, // request would wait longer than its deadline
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let (status, body) = post_with_deadline(&client, epoch_ms_from_now(300)).await;
+    assert_eq!(status, Status::GatewayTimeout);
+    assert_eq!(body["code"], "deadline_exceeded");
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_succeeds_within_deadline() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let (status, body) = post_with_deadline(&client, epoch_ms_from_now(10_000)).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["embeddings"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_request_timeout_header_fails_fast() {
+    let config = AppConfig {
+        max_wait_time_ms: 2000,
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let start_time = std::time::Instant::now();
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .header(Header::new(REQUEST_TIMEOUT_HEADER, "300"))
+        .body(json!({"inputs": build_inputs(1, None)}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::RequestTimeout);
+    assert!(start_time.elapsed() < std::time::Duration::from_millis(2000));
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_priority_field() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    for (priority, expected_status) in [
+        ("high", Status::Ok),
+        ("normal", Status::Ok),
+        ("urgent", Status::UnprocessableEntity),
+    ] {
+        let response = post_json(
+            &client,
+            "/embed",
+            json!({"inputs": build_inputs(1, None), "priority": priority}).to_string(),
+        )
+        .await;
+        assert_eq!(response.status(), expected_status, "priority: {priority}");
+    }
+}
+
+#[tokio::test]
+async fn test_embed_endpoint_fails_when_inputs_exceed_config_max_batch_tokens() {
+    let config = AppConfig {
+        max_batch_tokens: Some(10),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": ["What is Vector search ? ".repeat(10)]}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(body["code"], "too_many_tokens");
+}
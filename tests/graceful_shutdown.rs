@@ -0,0 +1,64 @@
+mod test_utils;
+
+use crate::test_utils::{build_inputs, spawn_stub_upstream};
+use auto_batching_proxy::config::AppConfig;
+use auto_batching_proxy::request_context::RequestContext;
+use auto_batching_proxy::request_handler::RequestHandler;
+use auto_batching_proxy::types::EmbedRequest;
+use rocket::http::Status;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn test_drain_flushes_pending_requests_and_rejects_new_ones() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_batch_size: 100,
+        max_wait_time_ms: 5000, // would normally keep requests queued for 5s
+        ..Default::default()
+    };
+    let handler = Arc::new(RequestHandler::new(config).await.unwrap());
+
+    let mut handles = Vec::new();
+    for _ in 1..=3 {
+        let handler = handler.clone();
+        handles.push(tokio::spawn(async move {
+            handler
+                .process_request(
+                    EmbedRequest {
+                        inputs: build_inputs(2, None),
+                        ..Default::default()
+                    },
+                    RequestContext::default(),
+                )
+                .await
+        }));
+    }
+    // let the batch processor queue them
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let start_time = Instant::now();
+    handler.drain().await;
+    assert!(start_time.elapsed() < Duration::from_secs(5));
+
+    for handle in handles {
+        let response = handle
+            .await
+            .unwrap()
+            .expect("Drained request should be served");
+        assert_eq!(response.embeddings.len(), 2);
+    }
+
+    let rejected = handler
+        .process_request(
+            EmbedRequest {
+                inputs: build_inputs(1, None),
+                ..Default::default()
+            },
+            RequestContext::default(),
+        )
+        .await
+        .expect_err("New requests should be rejected while draining");
+    assert_eq!(rejected.0, Status::ServiceUnavailable);
+    assert_eq!(rejected.1.code, Some("shutting_down"));
+}
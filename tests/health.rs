@@ -1,7 +1,13 @@
 mod test_utils;
 
+use auto_batching_proxy::config::{AppConfig, ModelConfig};
 use rocket::http::Status;
-use test_utils::get_client_with_defaults;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use test_utils::{
+    get_client, get_client_with_defaults, post_json, spawn_stub_server, spawn_stub_upstream,
+};
 
 #[tokio::test]
 async fn test_health_endpoint() {
@@ -13,6 +19,273 @@ async fn test_health_endpoint() {
     assert_eq!(body, "OK");
 }
 
+#[tokio::test]
+async fn test_health_backends_endpoint() {
+    let config = AppConfig {
+        inference_urls: vec![
+            spawn_stub_upstream().await,
+            // nothing listens on port 9
+            "http://127.0.0.1:9/embed".to_string(),
+        ],
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+    // first probe runs right after startup
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let response = client.get("/health/backends").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let backends: Vec<Value> = response.into_json().await.expect("valid JSON");
+    assert_eq!(backends.len(), 2);
+    assert_eq!(backends[0]["healthy"], true);
+    assert_eq!(backends[1]["url"], "http://127.0.0.1:9/embed");
+    assert_eq!(backends[1]["healthy"], false);
+    assert!(backends[1]["last_error"].is_string());
+}
+
+#[tokio::test]
+async fn test_health_deep_endpoint() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = client.get("/health/deep").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["upstream"]["reachable"], true);
+    assert!(body["upstream"]["latency_ms"].is_number());
+
+    let config = AppConfig {
+        // nothing listens on port 9
+        inference_urls: vec!["http://127.0.0.1:9/embed".to_string()],
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+    let response = client.get("/health/deep").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["upstream"]["reachable"], false);
+    assert!(body["upstream"]["backends"][0]["error"].is_string());
+}
+
+#[tokio::test]
+async fn test_info_endpoint() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = client.get("/info").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["proxy"]["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["proxy"]["git_sha"].is_string());
+    assert_eq!(body["config"]["inference_backends"], 1);
+    assert!(body["upstream"]["model_id"].is_string());
+    assert!(body.get("upstream_error").is_none());
+
+    let config = AppConfig {
+        inference_urls: vec!["http://127.0.0.1:9/embed".to_string()],
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+    let response = client.get("/info").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert!(body.get("upstream").is_none());
+    assert!(body["upstream_error"].is_string());
+}
+
+/// TEI stand-in answering every request (health checks, `/info`) with `info`,
+/// returns the `/embed` URL
+async fn spawn_upstream_with_info(info: Value) -> String {
+    let addr = spawn_stub_server(move |_| (Status::Ok, info.to_string())).await;
+    format!("http://{addr}/embed")
+}
+
+#[tokio::test]
+async fn test_discovered_upstream_limits() {
+    let info = json!({"model_id": "stub", "max_client_batch_size": 4, "max_batch_tokens": 2048});
+    let config = AppConfig {
+        inference_urls: vec![spawn_upstream_with_info(info).await],
+        max_inference_inputs: 8,
+        discover_upstream_limits: true,
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+    let response = client.get("/info").dispatch().await;
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["config"]["max_inference_inputs"], 4);
+    assert_eq!(body["config"]["max_batch_tokens"], 2048);
+    assert_eq!(body["upstream"]["max_client_batch_size"], 4);
+}
+
+#[tokio::test]
+async fn test_discovered_upstream_limits_per_model() {
+    let info = json!({"model_id": "small", "max_client_batch_size": 2});
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_inference_inputs: 8,
+        discover_upstream_limits: true,
+        models: BTreeMap::from([(
+            "small".to_string(),
+            ModelConfig {
+                inference_url: spawn_upstream_with_info(info).await,
+                ..ModelConfig::default()
+            },
+        )]),
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+
+    // limited by the model's own upstream, the default one has none
+    let body = json!({"inputs": ["a", "b", "c"], "model": "small"}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    let body = json!({"inputs": ["a", "b", "c"]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[tokio::test]
+async fn test_stats_endpoint() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(&client, "/embed", json!({"inputs": ["a", "b"]}).to_string()).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/stats").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["queue_depth"], 0);
+    assert!(body["oldest_pending_age_ms"].is_null());
+    assert_eq!(body["batches_total"], 1);
+    assert_eq!(body["batches_by_type"]["max_wait_time_ms"], 1);
+    assert_eq!(body["batch_size"]["max"], 1);
+    assert_eq!(body["throughput"]["window_secs"], 60);
+    assert!(body["throughput"]["inputs_per_sec"].as_f64().unwrap() > 0.0);
+}
+
+#[tokio::test]
+async fn test_tuning_stats_endpoint() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(&client, "/embed", json!({"inputs": ["a"]}).to_string()).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/stats/tuning").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["window_secs"], 60);
+    assert_eq!(body["batches"], 1);
+    assert_eq!(body["wait_flush_ratio"], 1.0);
+    assert!(body["arrival_rate_per_sec"].as_f64().unwrap() > 0.0);
+    // a single batch is too little to go by
+    assert!(body.get("suggested_max_wait_time_ms").is_none());
+    assert_eq!(body["reasons"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_latency_stats_endpoint() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(&client, "/embed", json!({"inputs": ["a"]}).to_string()).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/stats/latency").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // process wide, other tests' requests count as well
+    let body: Value = response.into_json().await.expect("valid JSON");
+    for summary in ["end_to_end", "queue", "upstream"] {
+        let summary = &body[summary];
+        assert!(summary["count"].as_u64().unwrap() >= 1, "{body}");
+        let p50 = summary["p50_ms"].as_f64().unwrap();
+        let p99 = summary["p99_ms"].as_f64().unwrap();
+        assert!(
+            p50 <= p99 && p99 <= summary["max_ms"].as_f64().unwrap(),
+            "{body}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_livez_and_readyz_endpoints() {
+    let client = get_client_with_defaults().await;
+    let response = client.get("/livez").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/readyz").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["ready"], true);
+    assert_eq!(body["batch_loop_running"], true);
+
+    let config = AppConfig {
+        // nothing listens on port 9
+        inference_urls: vec!["http://127.0.0.1:9/embed".to_string()],
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+    // first probe runs right after startup
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let response = client.get("/livez").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/readyz").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["upstream_healthy"], false);
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let client = get_client_with_defaults().await;
+    let response = client.get("/metrics").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.into_string().await.expect("valid response body");
+    assert!(body.contains("proxy_embedding_count_mismatches_total"));
+    assert!(body.contains("proxy_batch_size_bucket{le=\"+Inf\"}"));
+    assert!(body.contains("proxy_batch_inputs_count"));
+    assert!(body.contains("proxy_batch_wait_seconds_sum"));
+}
+
+#[tokio::test]
+async fn test_routes_mounted_under_prefix() {
+    let config = AppConfig {
+        route_prefix: "/embeddings/v1".to_string(),
+        ..AppConfig::default()
+    };
+    let client = get_client(config).await;
+
+    let response = client.get("/embeddings/v1/health").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/health").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
 #[tokio::test]
 async fn test_404_not_found() {
     let client = get_client_with_defaults().await;
@@ -0,0 +1,246 @@
+mod test_utils;
+
+use crate::test_utils::{get_client, post_json, spawn_stub_server};
+use auto_batching_proxy::config::AppConfig;
+use auto_batching_proxy::webhooks::{WEBHOOK_SIGNATURE_HEADER, WebhookSender};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+fn mock_config() -> AppConfig {
+    AppConfig {
+        inference_urls: vec!["mock://dims=8&latency_ms=5".to_string()],
+        max_wait_time_ms: 10,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_submit_and_poll_embed_job() {
+    let client = get_client(mock_config()).await;
+
+    let body = json!({"inputs": ["Hello", "World"]}).to_string();
+    let response = post_json(&client, "/jobs/embed", body.clone()).await;
+    assert_eq!(response.status(), Status::Accepted);
+    let job: Value = response.into_json().await.unwrap();
+    assert_eq!(job["status"], "queued");
+    let job_id = job["job_id"].as_str().unwrap().to_string();
+
+    let mut job = job;
+    for _ in 0..100 {
+        let response = client.get(format!("/jobs/{job_id}")).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        job = response.into_json().await.unwrap();
+        if job["status"] != "queued" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(job["status"], "completed", "{job}");
+
+    // same embeddings as the synchronous endpoint
+    let response = post_json(&client, "/embed", body).await;
+    let embedded: Value = response.into_json().await.unwrap();
+    assert_eq!(job["embeddings"], embedded["embeddings"]);
+}
+
+#[tokio::test]
+async fn test_embed_job_webhook_is_signed() {
+    let (sender, mut webhooks) = tokio::sync::mpsc::unbounded_channel();
+    let addr = spawn_stub_server(move |request| {
+        let _ = sender.send(request);
+        (Status::Ok, String::new())
+    })
+    .await;
+    let callback_url = format!("http://{addr}/hooks/embed");
+    let client = get_client(AppConfig {
+        webhook_secret: Some("webhook-secret".to_string()),
+        ..mock_config()
+    })
+    .await;
+
+    let body = json!({"inputs": ["Hello"], "callback_url": callback_url}).to_string();
+    let response = post_json(&client, "/jobs/embed", body).await;
+    assert_eq!(response.status(), Status::Accepted);
+    let job: Value = response.into_json().await.unwrap();
+
+    let webhook = tokio::time::timeout(Duration::from_secs(5), webhooks.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        webhook.header(WEBHOOK_SIGNATURE_HEADER),
+        Some(WebhookSender::sign("webhook-secret", webhook.body.as_bytes()).as_str())
+    );
+    let delivered: Value = serde_json::from_str(&webhook.body).unwrap();
+    assert_eq!(delivered["job_id"], job["job_id"]);
+    assert_eq!(delivered["status"], "completed");
+    assert_eq!(delivered["embeddings"][0].as_array().unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn test_embed_job_validation_and_unknown_job() {
+    let client = get_client(mock_config()).await;
+
+    let response = post_json(&client, "/jobs/embed", json!({"inputs": []}).to_string()).await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = json!({"inputs": ["Hello"], "callback_url": "/relative"}).to_string();
+    let response = post_json(&client, "/jobs/embed", body).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client.get("/jobs/0123456789abcdef").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "job_not_found");
+}
+
+#[tokio::test]
+async fn test_embed_job_is_only_visible_to_its_submitter() {
+    let client = get_client(AppConfig {
+        api_keys: vec!["key-1".to_string(), "key-2".to_string()],
+        ..mock_config()
+    })
+    .await;
+
+    let response = client
+        .post("/jobs/embed")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Api-Key", "key-1"))
+        .body(json!({"inputs": ["Hello"]}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Accepted);
+    let job: Value = response.into_json().await.unwrap();
+    let job_id = job["job_id"].as_str().unwrap();
+
+    let response = client
+        .get(format!("/jobs/{job_id}"))
+        .header(Header::new("X-Api-Key", "key-2"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get(format!("/jobs/{job_id}"))
+        .header(Header::new("X-Api-Key", "key-1"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[tokio::test]
+async fn test_submit_embed_job_above_max_jobs() {
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://latency_ms=500".to_string()],
+        max_jobs: 1,
+        ..Default::default()
+    })
+    .await;
+
+    let body = json!({"inputs": ["Hello"]}).to_string();
+    let response = post_json(&client, "/jobs/embed", body.clone()).await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let response = post_json(&client, "/jobs/embed", body).await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "too_many_jobs");
+}
+
+#[cfg(feature = "persistent-queue")]
+#[tokio::test]
+async fn test_persisted_jobs_are_resumed_on_startup() {
+    use auto_batching_proxy::job_queue::{JobQueue, PersistedJob};
+    use auto_batching_proxy::types::EmbedRequest;
+
+    let dir = std::env::temp_dir().join("auto-batching-proxy-job-queue-integration");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("jobs.redb").to_string_lossy().to_string();
+    // left behind by a previous instance
+    {
+        let job_queue = JobQueue::open(&path).unwrap();
+        let job = PersistedJob::Embed {
+            request: EmbedRequest {
+                inputs: vec!["Hello".to_string()],
+                ..Default::default()
+            },
+            callback_url: None,
+            client_id: None,
+            request_id: "before-restart".to_string(),
+        };
+        job_queue.insert("0123456789abcdef", &job).unwrap();
+    }
+
+    let client = get_client(AppConfig {
+        job_queue_path: Some(path.clone()),
+        ..mock_config()
+    })
+    .await;
+    let mut job = Value::Null;
+    for _ in 0..100 {
+        let response = client.get("/jobs/0123456789abcdef").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        job = response.into_json().await.unwrap();
+        if job["status"] != "queued" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(job["status"], "completed", "{job}");
+    assert_eq!(job["embeddings"][0].as_array().unwrap().len(), 8);
+}
+
+#[cfg(feature = "object-store")]
+#[tokio::test]
+async fn test_bulk_embed_job_on_object_store() {
+    let dir = std::env::temp_dir().join("auto-batching-proxy-bulk-job");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("corpus.jsonl");
+    let output = dir.join("embeddings.jsonl");
+    let _ = std::fs::remove_file(&output);
+    let corpus: Vec<String> = (0..5)
+        .map(|id| json!({"id": id, "input": format!("document {id}")}).to_string())
+        .chain(["not json".to_string()])
+        .collect();
+    std::fs::write(&input, corpus.join("\n")).unwrap();
+
+    let client = get_client(mock_config()).await;
+    let body = json!({
+        "input_uri": format!("file://{}", input.display()),
+        "output_uri": format!("file://{}", output.display()),
+    })
+    .to_string();
+    let response = post_json(&client, "/jobs/embed/bulk", body).await;
+    assert_eq!(response.status(), Status::Accepted);
+    let job: Value = response.into_json().await.unwrap();
+    let job_id = job["job_id"].as_str().unwrap().to_string();
+
+    let mut job = job;
+    for _ in 0..100 {
+        job = client
+            .get(format!("/jobs/{job_id}"))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        if job["status"] != "queued" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(job["status"], "completed", "{job}");
+    assert_eq!(job["records"], 6);
+    assert_eq!(job["failed_records"], 1);
+
+    let results = std::fs::read_to_string(&output).unwrap();
+    assert_eq!(results.lines().count(), 6);
+
+    let body =
+        json!({"input_uri": "ftp://host/corpus.jsonl", "output_uri": "s3://bucket/out.jsonl"})
+            .to_string();
+    let response = post_json(&client, "/jobs/embed/bulk", body).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
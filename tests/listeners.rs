@@ -0,0 +1,82 @@
+use auto_batching_proxy::build_rockets;
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use std::net::SocketAddr;
+
+fn mock_config() -> AppConfig {
+    AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_one_rocket_per_listener() {
+    let ipv6: SocketAddr = "[::1]:3000".parse().unwrap();
+    let admin: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+    let rockets = build_rockets(AppConfig {
+        listen: vec![ipv6],
+        admin_listen: Some(admin),
+        keep_alive_secs: 30,
+        ..mock_config()
+    })
+    .await;
+    assert_eq!(rockets.len(), 3);
+
+    let addresses: Vec<SocketAddr> = rockets
+        .iter()
+        .map(|rocket| {
+            let config = rocket::Config::from(rocket.figment());
+            // listeners only differ in their address
+            assert_eq!(config.keep_alive, 30);
+            SocketAddr::new(config.address, config.port)
+        })
+        .collect();
+    assert_eq!(
+        addresses,
+        vec!["127.0.0.1:3000".parse().unwrap(), ipv6, admin]
+    );
+}
+
+#[tokio::test]
+async fn test_admin_routes_only_on_admin_listener() {
+    let mut rockets = build_rockets(AppConfig {
+        admin_listen: Some("127.0.0.1:9000".parse().unwrap()),
+        admin_api_key: Some("admin-key".to_string()),
+        ..mock_config()
+    })
+    .await;
+    let admin = Client::untracked(rockets.pop().unwrap()).await.unwrap();
+    let main = Client::untracked(rockets.pop().unwrap()).await.unwrap();
+
+    for uri in ["/metrics", "/stats", "/admin/usage"] {
+        for (client, status) in [(&main, Status::NotFound), (&admin, Status::Ok)] {
+            let response = client
+                .get(uri)
+                .header(Header::new("X-Api-Key", "admin-key"))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), status);
+        }
+    }
+    for client in [&main, &admin] {
+        assert_eq!(client.get("/health").dispatch().await.status(), Status::Ok);
+    }
+
+    let response = main
+        .post("/embed")
+        .header(rocket::http::ContentType::JSON)
+        .body(r#"{"inputs": ["Hello"]}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = admin
+        .post("/embed")
+        .header(rocket::http::ContentType::JSON)
+        .body(r#"{"inputs": ["Hello"]}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
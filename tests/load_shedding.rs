@@ -0,0 +1,95 @@
+mod test_utils;
+
+use crate::test_utils::{build_inputs, get_client, post_json, spawn_stub_upstream};
+use auto_batching_proxy::backpressure::{ESTIMATED_WAIT_HEADER, QUEUE_DEPTH_HEADER};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::Status;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+
+// first request sits in the queue (large `max_wait_time_ms`), so the next one should be shed
+async fn second_request_is_shed(config: AppConfig, expected_code: &str) {
+    let client = Arc::new(get_client(config).await);
+
+    let first_client = client.clone();
+    let first = tokio::spawn(async move {
+        let response = post_json(
+            first_client.as_ref(),
+            "/embed",
+            json!({"inputs": build_inputs(1, None)}).to_string(),
+        )
+        .await;
+        response.status()
+    });
+
+    // give the batch processor some time to pick up the first request
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": build_inputs(1, None)}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    // the first request is still queued, nothing was dispatched yet
+    assert_eq!(response.headers().get_one(QUEUE_DEPTH_HEADER), Some("1"));
+    let estimated_wait_ms: u64 = response
+        .headers()
+        .get_one(ESTIMATED_WAIT_HEADER)
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(estimated_wait_ms >= 200, "{estimated_wait_ms}");
+    assert_eq!(response.headers().get_one("Retry-After"), Some("1"));
+
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert!(body["error"].is_string());
+    assert_eq!(body["code"], expected_code);
+
+    // queued request is still served normally
+    assert_eq!(first.await.unwrap(), Status::Ok);
+}
+
+#[tokio::test]
+async fn test_load_shedding_by_queue_depth() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_batch_size: 100,
+        max_wait_time_ms: 1000,
+        load_shed_queue_depth: Some(1),
+        ..Default::default()
+    };
+    second_request_is_shed(config, "queue_depth_exceeded").await;
+}
+
+#[tokio::test]
+async fn test_load_shedding_by_oldest_request_age() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_batch_size: 100,
+        max_wait_time_ms: 1000,
+        load_shed_max_age_ms: Some(100),
+        ..Default::default()
+    };
+    second_request_is_shed(config, "queue_age_exceeded").await;
+}
+
+#[tokio::test]
+async fn test_no_load_shedding_below_limits() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        load_shed_queue_depth: Some(10),
+        load_shed_max_age_ms: Some(5000),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": build_inputs(2, None)}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::Ok);
+}
@@ -0,0 +1,43 @@
+mod test_utils;
+
+use crate::test_utils::{ADMIN_API_KEY, admin_auth, get_client};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{ContentType, Status};
+use serde_json::{Value, json};
+
+// single test, the logger is process wide
+#[tokio::test]
+async fn test_change_log_level_at_runtime() {
+    let config = AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        log_level: "warn".to_string(),
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    };
+    // before Rocket installs its own logger, same as in `main`
+    config.init_logging();
+    assert_eq!(log::max_level(), log::LevelFilter::Warn);
+    let client = get_client(config).await;
+
+    let put_level = |level: &'static str| {
+        client
+            .put("/admin/log-level")
+            .header(admin_auth())
+            .header(ContentType::JSON)
+            .body(json!({ "level": level }).to_string())
+            .dispatch()
+    };
+    let response = put_level("debug").await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["previous_level"], "warn");
+    assert_eq!(json["level"], "debug");
+    assert_eq!(log::max_level(), log::LevelFilter::Debug);
+    assert!(log::log_enabled!(target: "auto_batching_proxy", log::Level::Debug));
+
+    let response = put_level("info,auto_batching_proxy=loud").await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "invalid_log_level");
+    assert_eq!(log::max_level(), log::LevelFilter::Debug);
+}
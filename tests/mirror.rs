@@ -0,0 +1,44 @@
+mod test_utils;
+
+use crate::test_utils::{get_client, post_json, spawn_stub_server};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::Status;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_requests_are_mirrored_to_http_sink() {
+    let (sender, mut mirrored) = tokio::sync::mpsc::unbounded_channel();
+    let addr = spawn_stub_server(move |request| {
+        let _ = sender.send(request.body);
+        (Status::Ok, String::new())
+    })
+    .await;
+    let client = get_client(AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        mirror_url: Some(format!("http://{addr}/mirror")),
+        mirror_include_inputs: true,
+        ..Default::default()
+    })
+    .await;
+
+    let body = json!({"inputs": ["Hello", "World!"]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    // only tracked internally
+    assert!(json.get("batch_info").is_none());
+
+    let body = tokio::time::timeout(Duration::from_secs(5), mirrored.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let record: Value = serde_json::from_str(body.lines().next().unwrap()).unwrap();
+    assert_eq!(record["input_count"], 2);
+    assert_eq!(record["input_chars"], 11);
+    assert_eq!(record["priority"], "normal");
+    assert_eq!(record["status"], 200);
+    assert!(record["batch_id"].is_u64());
+    assert_eq!(record["inputs"], json!(["Hello", "World!"]));
+}
@@ -0,0 +1,363 @@
+mod test_utils;
+
+use crate::test_utils::{get_client, post_json};
+use auto_batching_proxy::build_rockets_with_handler;
+use auto_batching_proxy::config::{
+    AppConfig, EmbeddingPostprocessing, EndpointConfig, InputPreprocessing, ModelConfig,
+};
+use auto_batching_proxy::postprocessing::ResponsePostprocessor;
+use auto_batching_proxy::preprocessing::RequestPreprocessor;
+use auto_batching_proxy::request_handler::RequestHandler;
+use auto_batching_proxy::types::Endpoint;
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+fn mock_config(url: &str) -> AppConfig {
+    AppConfig {
+        inference_urls: vec![url.to_string()],
+        max_wait_time_ms: 10,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_embed_with_mock_upstream() {
+    let client = get_client(mock_config("mock://dims=8&latency_ms=5")).await;
+
+    let body = json!({"inputs": ["Hello", "World", "Hello"]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let json: Value = response.into_json().await.unwrap();
+    let embeddings = json["embeddings"].as_array().unwrap();
+    assert_eq!(embeddings.len(), 3);
+    assert_eq!(embeddings[0].as_array().unwrap().len(), 8);
+    // deterministic
+    assert_eq!(embeddings[0], embeddings[2]);
+    assert_ne!(embeddings[0], embeddings[1]);
+}
+
+#[tokio::test]
+async fn test_mock_upstream_health_and_info() {
+    let client = get_client(mock_config("mock://dims=8")).await;
+
+    let response = client.get("/health/deep").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/info").dispatch().await;
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["upstream"]["model_id"], "mock");
+    assert_eq!(json["upstream"]["dims"], 8);
+}
+
+#[tokio::test]
+async fn test_embed_sparse_with_mock_upstream() {
+    let client = get_client(mock_config("mock://dims=8")).await;
+
+    let body = json!({"inputs": ["Hello", "World", "Hello"]}).to_string();
+    let response = post_json(&client, "/embed_sparse", body).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let json: Value = response.into_json().await.unwrap();
+    let embeddings = json["embeddings"].as_array().unwrap();
+    assert_eq!(embeddings.len(), 3);
+    assert_eq!(embeddings[0], embeddings[2]);
+    assert_ne!(embeddings[0], embeddings[1]);
+    for entry in embeddings[0].as_array().unwrap() {
+        assert!(entry["index"].as_u64().unwrap() < 8);
+        assert!(entry["value"].as_f64().unwrap() > 0.0);
+    }
+}
+
+#[tokio::test]
+async fn test_metadata_and_client_tag_are_echoed() {
+    let client = get_client(mock_config("mock://dims=8")).await;
+
+    let metadata = json!({"job_id": 42, "shard": ["a", "b"]});
+    for path in ["/embed", "/embed_sparse", "/embed_all"] {
+        let body = json!({"inputs": ["Hello"], "metadata": metadata, "client_tag": "backfill-v2"});
+        let response = post_json(&client, path, body.to_string()).await;
+        assert_eq!(response.status(), Status::Ok);
+        let json: Value = response.into_json().await.unwrap();
+        assert_eq!(json["metadata"], metadata);
+        assert_eq!(json["client_tag"], "backfill-v2");
+    }
+
+    // omitted unless sent
+    let body = json!({"inputs": ["Hello"]}).to_string();
+    let json: Value = post_json(&client, "/embed", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert!(json.get("metadata").is_none());
+    assert!(json.get("client_tag").is_none());
+
+    let body = json!({"inputs": ["Hello"], "client_tag": "has spaces"}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "invalid_client_tag");
+}
+
+#[tokio::test]
+async fn test_id_keyed_inputs_across_chunks() {
+    let config = AppConfig {
+        max_inference_inputs: 2,
+        split_oversized_requests: true,
+        ..mock_config("mock://dims=8")
+    };
+    let client = get_client(config).await;
+
+    let texts = ["a", "b", "c", "d", "e"];
+    let body = json!({"inputs": texts}).to_string();
+    let plain: Value = post_json(&client, "/embed", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+
+    let inputs: Vec<Value> = texts
+        .iter()
+        .rev()
+        .map(|text| json!({"id": format!("doc-{text}"), "text": text}))
+        .collect();
+    let body = json!({"inputs": inputs}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    let embeddings = json["embeddings"].as_array().unwrap();
+    assert_eq!(embeddings.len(), texts.len());
+    for (index, text) in texts.iter().rev().enumerate() {
+        assert_eq!(embeddings[index]["id"], format!("doc-{text}"));
+        let position = texts.len() - 1 - index;
+        assert_eq!(
+            embeddings[index]["embedding"],
+            plain["embeddings"][position]
+        );
+    }
+
+    let body = json!({"inputs": [{"id": 1, "text": "a"}]}).to_string();
+    let json: Value = post_json(&client, "/embed_sparse", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(json["embeddings"][0]["id"], 1);
+    assert!(json["embeddings"][0]["embedding"].is_array());
+
+    let body = json!({"inputs": ["a", {"id": 1, "text": "b"}]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[tokio::test]
+async fn test_preprocessed_inputs_embed_the_same() {
+    let config = AppConfig {
+        preprocess: vec![
+            InputPreprocessing::Nfc,
+            InputPreprocessing::Trim,
+            InputPreprocessing::CollapseWhitespace,
+        ],
+        ..mock_config("mock://dims=8")
+    };
+    let client = get_client(config).await;
+
+    // precomposed vs. combining accent, extra whitespace
+    let body = json!({"inputs": ["Caf\u{e9} au lait", " Cafe\u{301}  au\nlait "]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["embeddings"][0], json["embeddings"][1]);
+}
+
+/// Rejects inputs mentioning secrets, masks numbers
+struct SecretsPreprocessor;
+
+impl RequestPreprocessor for SecretsPreprocessor {
+    fn preprocess(&self, input: String) -> Result<String, String> {
+        if input.contains("password") {
+            return Err("looks like a secret".to_string());
+        }
+        Ok(input.replace(|c: char| c.is_ascii_digit(), "#"))
+    }
+}
+
+#[tokio::test]
+async fn test_custom_preprocessor() {
+    let config = AppConfig {
+        preprocess: vec![InputPreprocessing::Lowercase],
+        max_inference_inputs: 2,
+        split_oversized_requests: true,
+        ..mock_config("mock://dims=8")
+    };
+    let handler = RequestHandler::new(config)
+        .await
+        .unwrap()
+        .with_preprocessor(Box::new(SecretsPreprocessor));
+    let rocket = build_rockets_with_handler(handler).remove(0);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let body = json!({"inputs": ["Order 12", "order 99"]}).to_string();
+    let json: Value = post_json(&client, "/embed", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(json["embeddings"][0], json["embeddings"][1]);
+
+    let body = json!({"inputs": ["Hello", "my password is hunter2"]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "input_preprocess_rejected");
+
+    // the index is the one within the whole request, not within its chunk
+    let body = json!({"inputs": ["Hello", "World", "my password is hunter2"]}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let json: Value = response.into_json().await.unwrap();
+    assert!(
+        json["error"].as_str().unwrap().contains("index 2"),
+        "{json}"
+    );
+
+    let inputs = json!(["Hello", "my password is hunter2", "World"]);
+    let body = json!({"inputs": inputs, "partial_errors": true});
+    let json: Value = post_json(&client, "/embed", body.to_string())
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert!(json["embeddings"][0].is_array());
+    assert_eq!(json["embeddings"][1]["code"], "input_preprocess_rejected");
+    assert!(json["embeddings"][2].is_array());
+}
+
+/// Keeps the leading dimensions, as for Matryoshka embeddings
+struct TruncateDims(usize);
+
+impl ResponsePostprocessor for TruncateDims {
+    fn postprocess(&self, embeddings: &mut [Vec<f32>]) -> Result<(), String> {
+        embeddings
+            .iter_mut()
+            .for_each(|embedding| embedding.truncate(self.0));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_postprocessed_embeddings() {
+    let config = AppConfig {
+        postprocess: vec![
+            EmbeddingPostprocessing::Round,
+            EmbeddingPostprocessing::Normalize,
+        ],
+        postprocess_decimals: Some(2),
+        models: BTreeMap::from([(
+            "small".to_string(),
+            ModelConfig {
+                inference_url: "mock://dims=6".to_string(),
+                ..ModelConfig::default()
+            },
+        )]),
+        ..mock_config("mock://dims=8")
+    };
+    let handler = RequestHandler::new(config)
+        .await
+        .unwrap()
+        .with_postprocessor(Box::new(TruncateDims(4)));
+    let rocket = build_rockets_with_handler(handler).remove(0);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let body = json!({"inputs": ["Hello", "World"]}).to_string();
+    let json: Value = post_json(&client, "/embed", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    for embedding in json["embeddings"].as_array().unwrap() {
+        let values: Vec<f64> = serde_json::from_value(embedding.clone()).unwrap();
+        assert_eq!(values.len(), 4);
+        // normalized before being rounded, regardless of the configured order
+        assert!(values.iter().map(|value| value * value).sum::<f64>() <= 1.01);
+        assert!(
+            values
+                .iter()
+                .all(|value| ((value * 100.0).round() - value * 100.0).abs() < 1e-3)
+        );
+    }
+
+    // other models' batches as well
+    let body = json!({"inputs": ["Hello"], "model": "small"}).to_string();
+    let json: Value = post_json(&client, "/embed", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(json["embeddings"][0].as_array().unwrap().len(), 4);
+
+    // only dense `/embed` batches are postprocessed
+    let body = json!({"inputs": ["Hello"]}).to_string();
+    let json: Value = post_json(&client, "/embed_all", body)
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(json["embeddings"][0][0].as_array().unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn test_embed_all_with_mock_upstream() {
+    let client = get_client(mock_config("mock://dims=8")).await;
+
+    let body = json!({"inputs": ["Hello World", "Hello"]}).to_string();
+    let response = post_json(&client, "/embed_all", body).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let json: Value = response.into_json().await.unwrap();
+    let embeddings = json["embeddings"].as_array().unwrap();
+    assert_eq!(embeddings.len(), 2);
+    // one vector per (whitespace separated) token
+    assert_eq!(embeddings[0].as_array().unwrap().len(), 2);
+    assert_eq!(embeddings[1].as_array().unwrap().len(), 1);
+    assert_eq!(embeddings[0][0], embeddings[1][0]);
+    assert_eq!(embeddings[0][0].as_array().unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn test_endpoint_queues_batch_independently() {
+    let config = AppConfig {
+        endpoints: BTreeMap::from([(
+            Endpoint::EmbedAll,
+            EndpointConfig {
+                max_wait_time_ms: Some(1000),
+                ..EndpointConfig::default()
+            },
+        )]),
+        ..mock_config("mock://dims=8")
+    };
+    let client = get_client(config).await;
+
+    let start_time = Instant::now();
+    let body = json!({"inputs": ["Hello World"]}).to_string();
+    let embed_all = async {
+        let response = post_json(&client, "/embed_all", body.clone()).await;
+        (response.status(), start_time.elapsed())
+    };
+    let embed = async {
+        let response = post_json(&client, "/embed", body.clone()).await;
+        (response.status(), start_time.elapsed())
+    };
+    let ((embed_all_status, embed_all_elapsed), (embed_status, embed_elapsed)) =
+        tokio::join!(embed_all, embed);
+
+    // `/embed` isn't held back by the pending `/embed_all` request
+    assert_eq!(embed_status, Status::Ok);
+    assert!(embed_elapsed < Duration::from_millis(500));
+    assert_eq!(embed_all_status, Status::Ok);
+    assert!(embed_all_elapsed >= Duration::from_millis(1000));
+}
@@ -0,0 +1,96 @@
+mod test_utils;
+
+use crate::test_utils::{get_client, post_json};
+use auto_batching_proxy::config::{AppConfig, ModelConfig};
+use rocket::http::Status;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+fn multi_model_config() -> AppConfig {
+    AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        models: BTreeMap::from([(
+            "small".to_string(),
+            ModelConfig {
+                inference_url: "mock://dims=4".to_string(),
+                max_inference_inputs: Some(2),
+                ..ModelConfig::default()
+            },
+        )]),
+        max_wait_time_ms: 10,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_requests_are_routed_by_model() {
+    let client = get_client(multi_model_config()).await;
+
+    let dims = |json: &Value| json["embeddings"][0].as_array().unwrap().len();
+    let response = post_json(&client, "/embed", json!({"inputs": ["Hello"]}).to_string()).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(dims(&response.into_json().await.unwrap()), 8);
+
+    let body = json!({"inputs": ["Hello"], "model": "small"}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(dims(&response.into_json().await.unwrap()), 4);
+
+    let response = client.get("/info").dispatch().await;
+    let info: Value = response.into_json().await.unwrap();
+    assert_eq!(info["config"]["models"], json!(["small"]));
+}
+
+#[tokio::test]
+async fn test_model_limits_apply_to_its_requests_only() {
+    let client = get_client(multi_model_config()).await;
+
+    let inputs = json!(["one", "two", "three"]);
+    let response = post_json(&client, "/embed", json!({"inputs": inputs}).to_string()).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = json!({"inputs": inputs, "model": "small"}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
+#[tokio::test]
+async fn test_aliases_and_default_model() {
+    let client = get_client(AppConfig {
+        model_aliases: BTreeMap::from([
+            ("text-embedding-3-small".to_string(), "small".to_string()),
+            ("legacy".to_string(), "default".to_string()),
+        ]),
+        default_model: Some("small".to_string()),
+        ..multi_model_config()
+    })
+    .await;
+
+    for model in [
+        None,
+        Some("default"),
+        Some("legacy"),
+        Some("text-embedding-3-small"),
+    ] {
+        let body = json!({"inputs": ["Hello"], "model": model}).to_string();
+        let response = post_json(&client, "/embed", body).await;
+        assert_eq!(response.status(), Status::Ok);
+        let json: Value = response.into_json().await.unwrap();
+        assert_eq!(
+            json["embeddings"][0].as_array().unwrap().len(),
+            4,
+            "{model:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_model_is_rejected() {
+    let client = get_client(multi_model_config()).await;
+
+    let body = json!({"inputs": ["Hello"], "model": "large"}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::NotFound);
+    let json: Value = response.into_json().await.unwrap();
+    assert_eq!(json["code"], "model_not_found");
+}
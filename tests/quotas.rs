@@ -0,0 +1,50 @@
+mod test_utils;
+
+use crate::test_utils::{build_inputs, get_client, post_json, spawn_stub_upstream};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::Status;
+use serde_json::{Value, json};
+
+#[tokio::test]
+async fn test_quota_exhausted_gets_429() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        quota_daily_inputs: Some(5),
+        quota_monthly_inputs: Some(100),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": build_inputs(3, None)}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("X-Quota-Daily-Remaining"),
+        Some("2")
+    );
+    assert_eq!(
+        response.headers().get_one("X-Quota-Monthly-Remaining"),
+        Some("97")
+    );
+
+    let response = post_json(
+        &client,
+        "/embed",
+        json!({"inputs": build_inputs(3, None)}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(
+        response.headers().get_one("X-Quota-Daily-Remaining"),
+        Some("2")
+    );
+    assert!(response.headers().get_one("Retry-After").is_some());
+
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(body["code"], "quota_exceeded");
+}
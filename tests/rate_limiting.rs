@@ -0,0 +1,106 @@
+mod test_utils;
+
+use crate::test_utils::{build_inputs, get_client, post_json, spawn_stub_upstream};
+use auto_batching_proxy::backpressure::QUEUE_DEPTH_HEADER;
+use auto_batching_proxy::config::{AppConfig, ModelConfig};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+#[tokio::test]
+async fn test_rate_limited_request_gets_429_with_headers() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        rate_limit_requests_per_sec: Some(1.0),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+    let body = json!({"inputs": build_inputs(1, None)}).to_string();
+
+    let response = post_json(&client, "/embed", body.clone()).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(response.headers().get_one("RateLimit-Limit"), Some("1"));
+    assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some("0"));
+    assert_eq!(response.headers().get_one("Retry-After"), Some("1"));
+    assert_eq!(response.headers().get_one(QUEUE_DEPTH_HEADER), Some("0"));
+
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(body["code"], "rate_limited");
+}
+
+#[tokio::test]
+async fn test_unknown_api_keys_share_the_client_ip_limit() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        rate_limit_requests_per_sec: Some(1.0),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    // not one of `api_keys`, so a new key per request doesn't get a new bucket
+    for (api_key, status) in [
+        ("made-up-1", Status::Ok),
+        ("made-up-2", Status::TooManyRequests),
+    ] {
+        let response = client
+            .post("/embed")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Api-Key", api_key))
+            .remote("10.0.0.1:40000".parse().unwrap())
+            .body(json!({"inputs": build_inputs(1, None)}).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), status);
+    }
+}
+
+#[tokio::test]
+async fn test_split_request_above_inputs_capacity_is_paced_not_rejected() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        max_inference_inputs: 4,
+        split_oversized_requests: true,
+        rate_limit_inputs_per_sec: Some(8.0),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    // 12 inputs, more than the bucket holds, charged in chunks of 4
+    let body = json!({"inputs": build_inputs(12, None)}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(body["embeddings"].as_array().unwrap().len(), 12);
+}
+
+#[tokio::test]
+async fn test_split_request_pacing_gives_up_at_the_model_timeout() {
+    let config = AppConfig {
+        inference_urls: vec!["mock://dims=8".to_string()],
+        max_wait_time_ms: 10,
+        max_inference_inputs: 4,
+        split_oversized_requests: true,
+        rate_limit_inputs_per_sec: Some(4.0),
+        models: BTreeMap::from([(
+            "short-timeout".to_string(),
+            ModelConfig {
+                inference_url: "mock://dims=8".to_string(),
+                request_timeout_secs: Some(1),
+                ..ModelConfig::default()
+            },
+        )]),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    // the later chunks would wait for 2s, beyond the model's (not the default 30s) timeout
+    let body = json!({"inputs": build_inputs(12, None), "model": "short-timeout"}).to_string();
+    let response = post_json(&client, "/embed", body).await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+}
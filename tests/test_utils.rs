@@ -2,10 +2,20 @@
 
 use auto_batching_proxy::types::{BatchInfo, BatchType};
 use auto_batching_proxy::{build_rocket, config::AppConfig};
-use rocket::http::ContentType;
+use rocket::http::{ContentType, Header, Status};
 use rocket::local::asynchronous::{Client, LocalResponse};
 use serde_json::{Value, json};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// `AppConfig::admin_api_key` of tests calling `/admin/*` routes
+pub const ADMIN_API_KEY: &str = "admin-key";
+
+pub fn admin_auth() -> Header<'static> {
+    Header::new("X-Api-Key", ADMIN_API_KEY)
+}
 
 pub async fn get_client(config: AppConfig) -> Client {
     let rocket = build_rocket(config).await;
@@ -68,10 +78,7 @@ pub async fn launch_threads_with_tests(
 
                 let mut first_embedding_len = 0;
                 for (i, embedding) in embeddings.iter().enumerate() {
-                    assert!(
-                        embedding.is_array(),
-                        "Embedding {i} should be an array"
-                    );
+                    assert!(embedding.is_array(), "Embedding {i} should be an array");
 
                     let embedding_values = embedding.as_array().unwrap();
                     assert!(
@@ -127,7 +134,7 @@ pub async fn direct_call_to_inference_service(inputs: &Vec<String>) -> Vec<Vec<f
     // compare this with `post_json` which uses Rocket test client
     let inference_client = reqwest::Client::new();
     let response = inference_client
-        .post(&AppConfig::default().inference_url) // bypasses our proxy
+        .post(&AppConfig::default().inference_urls[0]) // bypasses our proxy
         .header("Content-Type", "application/json")
         .json(&json!({
             "inputs": inputs
@@ -140,7 +147,7 @@ pub async fn direct_call_to_inference_service(inputs: &Vec<String>) -> Vec<Vec<f
     embeddings
 }
 
-pub fn count_batch(batches_info: &Vec<Value>, batch_type: BatchType, size: usize) -> usize {
+pub fn count_batch(batches_info: &[Value], batch_type: BatchType, size: usize) -> usize {
     batches_info
         .iter()
         .filter(|batch_info| {
@@ -164,3 +171,110 @@ pub fn get_proxy_embeddings(json: Value) -> Vec<Vec<f32>> {
         serde_json::from_value(json["embeddings"].clone()).expect("Should parse embeddings");
     proxy_embeddings
 }
+
+/// A request received by `spawn_stub_server`
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    /// Request line & headers
+    pub head: String,
+    pub body: String,
+}
+
+impl StubRequest {
+    /// e.g. `/embed`
+    pub fn path(&self) -> &str {
+        self.head.split(' ').nth(1).unwrap_or_default()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    /// `inputs` of an `/embed` body, none for health checks & `/info`
+    pub fn inputs(&self) -> Vec<String> {
+        serde_json::from_str::<Value>(&self.body)
+            .ok()
+            .and_then(|request| serde_json::from_value(request["inputs"].clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Reads a whole request, `None` once the peer closed (or failed) before sending one
+async fn read_request(stream: &mut TcpStream) -> Option<StubRequest> {
+    let mut received = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        received.extend_from_slice(&buffer[..read]);
+        let Some(head_end) = received.windows(4).position(|window| window == b"\r\n\r\n") else {
+            continue;
+        };
+        let mut request = StubRequest {
+            head: String::from_utf8_lossy(&received[..head_end]).to_string(),
+            body: String::new(),
+        };
+        let content_length: usize = request
+            .header("content-length")
+            .map_or(0, |length| length.parse().unwrap());
+        let body = &received[head_end + 4..];
+        if body.len() >= content_length {
+            request.body = String::from_utf8_lossy(&body[..content_length]).to_string();
+            return Some(request);
+        }
+    }
+}
+
+/// Minimal HTTP/1.1 server on an ephemeral port, one request per connection.
+/// `respond` answers each request with a status & JSON body, e.g. to stand in for the
+/// inference service or to receive webhooks
+pub async fn spawn_stub_server(
+    respond: impl Fn(StubRequest) -> (Status, String) + Send + Sync + 'static,
+) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let respond = Arc::new(respond);
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let Some(request) = read_request(&mut stream).await else {
+                    return;
+                };
+                let (status, body) = respond(request);
+                let response = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    addr
+}
+
+/// Inference service stand-in, 8 dimensional embeddings that only depend on the input.
+/// Health checks & `/info` are answered as well. Returns the `/embed` URL
+pub async fn spawn_stub_upstream() -> String {
+    let addr = spawn_stub_server(|request| match request.path() {
+        "/info" => (Status::Ok, json!({"model_id": "stub"}).to_string()),
+        _ => {
+            let embeddings: Vec<Vec<f32>> = request
+                .inputs()
+                .iter()
+                .map(|input| {
+                    let seed = input.bytes().map(u32::from).sum::<u32>();
+                    (0..8).map(|i| ((seed + i) % 97) as f32 / 97.0).collect()
+                })
+                .collect();
+            (Status::Ok, json!(embeddings).to_string())
+        }
+    })
+    .await;
+    format!("http://{addr}/embed")
+}
@@ -0,0 +1,48 @@
+use auto_batching_proxy::build_rocket;
+use auto_batching_proxy::config::AppConfig;
+use std::time::Duration;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+#[tokio::test]
+async fn test_serves_https_with_tls_config() {
+    // free port for a real (not local client) launch, TLS is handled by the listener
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = AppConfig {
+        port,
+        tls_cert_path: Some(format!("{FIXTURES}/server.pem")),
+        tls_key_path: Some(format!("{FIXTURES}/server.key")),
+        quiet_mode: true,
+        ..Default::default()
+    };
+    let rocket = build_rocket(config).await.ignite().await.unwrap();
+    let shutdown = rocket.shutdown();
+    let server = tokio::spawn(rocket.launch());
+
+    let ca = std::fs::read(format!("{FIXTURES}/ca.pem")).unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+        .build()
+        .unwrap();
+
+    let mut body = None;
+    for _ in 0..50 {
+        if let Ok(response) = client
+            .get(format!("https://localhost:{port}/health"))
+            .send()
+            .await
+        {
+            body = Some(response.text().await.unwrap());
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(body.as_deref(), Some("OK"));
+
+    shutdown.notify();
+    let _ = server.await;
+}
@@ -0,0 +1,55 @@
+mod test_utils;
+
+use crate::test_utils::{ADMIN_API_KEY, admin_auth, build_inputs, get_client, spawn_stub_upstream};
+use auto_batching_proxy::config::AppConfig;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::{Value, json};
+
+#[tokio::test]
+async fn test_admin_usage_reports_served_requests_per_key() {
+    let config = AppConfig {
+        inference_urls: vec![spawn_stub_upstream().await],
+        max_wait_time_ms: 10,
+        api_keys: vec!["team-a-key-1234".to_string()],
+        admin_api_key: Some(ADMIN_API_KEY.to_string()),
+        ..Default::default()
+    };
+    let client = get_client(config).await;
+
+    let response = client
+        .post("/embed")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Api-Key", "team-a-key-1234"))
+        .body(json!({"inputs": build_inputs(3, None)}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/admin/usage").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .get("/admin/usage")
+        .header(Header::new("X-Api-Key", "team-a-key-1234"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .get("/admin/usage")
+        .header(admin_auth())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.expect("Valid JSON");
+    assert_eq!(body["window_secs"], 3600);
+    let callers = body["current"]["callers"].as_object().unwrap();
+    let (label, usage) = callers.iter().next().unwrap();
+    assert_eq!(callers.len(), 1);
+    assert!(label.starts_with("key:") && label.ends_with("…1234"));
+    assert!(!label.contains("team-a"));
+    assert_eq!(usage["requests"], 1);
+    assert_eq!(usage["inputs"], 3);
+    assert!(usage["inference_time_ms"].as_f64().unwrap() > 0.0);
+}